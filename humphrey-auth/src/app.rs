@@ -3,6 +3,7 @@
 use crate::database::AuthDatabase;
 use crate::AuthProvider;
 
+use humphrey::http::identity::PeerIdentity;
 use humphrey::http::{Request, Response, StatusCode};
 use humphrey::App;
 
@@ -47,6 +48,24 @@ pub trait AuthRequestHandler<S>: Fn(Request, Arc<S>, String) -> Response + Send
 impl<T, S> AuthRequestHandler<S> for T where T: Fn(Request, Arc<S>, String) -> Response + Send + Sync
 {}
 
+/// Represents a function able to handle a request from a client which authenticated with a TLS client
+///   certificate. This is passed the request, the state, and the verified identity of the client.
+///
+/// # Example
+/// ```
+/// fn cert_auth_req_handler(_: Request, state: Arc<MyState>, identity: PeerIdentity) -> Response {
+///     Response::new(StatusCode::OK, identity.subject)
+/// }
+/// ```
+pub trait CertAuthRequestHandler<S>:
+    Fn(Request, Arc<S>, PeerIdentity) -> Response + Send + Sync
+{
+}
+impl<T, S> CertAuthRequestHandler<S> for T where
+    T: Fn(Request, Arc<S>, PeerIdentity) -> Response + Send + Sync
+{
+}
+
 /// Represents a Humphrey application with authentication enabled.
 /// This is implemented on Humphrey's `App` type provided that the state implements `AuthState`
 ///   and the database implements `AuthDatabase`.
@@ -60,6 +79,13 @@ where
     fn with_auth_route<T>(self, route: &str, handler: T) -> Self
     where
         T: AuthRequestHandler<S> + 'static;
+
+    /// Adds a route which requires the client to authenticate with a TLS client certificate, and associated
+    ///   handler to the server. Client authentication must be enabled on the app with `with_client_auth`.
+    /// Routes can include wildcards, such as `/blog/*`.
+    fn with_cert_auth_route<T>(self, route: &str, handler: T) -> Self
+    where
+        T: CertAuthRequestHandler<S> + 'static;
 }
 
 impl<S, D> AuthApp<S, D> for App<S>
@@ -83,6 +109,19 @@ where
             forbidden()
         })
     }
+
+    fn with_cert_auth_route<T>(self, route: &str, handler: T) -> Self
+    where
+        T: CertAuthRequestHandler<S> + 'static,
+    {
+        self.with_route(route, move |mut request: Request, state: Arc<S>| {
+            if let Some(identity) = request.peer_identity.take() {
+                return (handler)(request, state, identity);
+            }
+
+            forbidden()
+        })
+    }
 }
 
 fn forbidden() -> Response {
@@ -1,10 +1,15 @@
 //! Provides the core configuration functionality.
 
+use crate::config::default::{DEFAULT_EJECT_TIME, DEFAULT_MAX_FAILURES};
 use crate::config::extended_hashmap::ExtendedMap;
 use crate::config::tree::{parse_conf, ConfigNode};
 use crate::logger::LogLevel;
 use crate::proxy::{EqMutex, LoadBalancer};
-use crate::rand::Lcg;
+
+use humphrey::http::compression::DEFAULT_MIN_SIZE;
+use humphrey::http::proxy::{HostRewrite, ProxyHeaders};
+#[cfg(feature = "tls")]
+use humphrey::tls::ClientAuth;
 
 use std::collections::HashMap;
 use std::env::{args, var};
@@ -12,6 +17,7 @@ use std::fs::File;
 use std::io::Read;
 use std::net::IpAddr;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Represents the parsed and validated configuration.
@@ -23,6 +29,9 @@ pub struct Config {
     pub address: String,
     /// The port to host the server on
     pub port: u16,
+    /// The addresses to accept connections on, derived from the address, port and TLS configuration if no
+    ///   `listen` entries are configured
+    pub listeners: Vec<ListenConfig>,
     /// The number of threads to host the server on
     pub threads: usize,
     /// The TLS configuration to use
@@ -41,10 +50,14 @@ pub struct Config {
     pub logging: LoggingConfig,
     /// Cache configuration
     pub cache: CacheConfig,
+    /// Compression configuration
+    pub compression: CompressionConfig,
     /// Blacklist configuration
     pub blacklist: BlacklistConfig,
     /// The amount of time to wait between requests
     pub connection_timeout: Option<Duration>,
+    /// Whether to reload the configuration when the file changes
+    pub watch: bool,
 }
 
 /// Represents the configuration for a specific host.
@@ -52,6 +65,9 @@ pub struct Config {
 pub struct HostConfig {
     /// Wildcard string specifying what hosts to match, e.g. `*.example.com`
     pub matches: String,
+    /// The certificate to use for this host, selected using the host name requested with SNI
+    #[cfg(feature = "tls")]
+    pub tls: Option<HostTlsConfig>,
     /// The routes to use for this host
     pub routes: Vec<RouteConfig>,
 }
@@ -81,9 +97,13 @@ pub struct RouteConfig {
     /// The path to the file, directory or redirect target
     pub path: Option<String>,
     /// The load balancer to use for proxying
-    pub load_balancer: Option<EqMutex<LoadBalancer>>,
+    pub load_balancer: Option<Arc<EqMutex<LoadBalancer>>>,
     /// The WebSocket proxy target for WebSocket connections to this route
     pub websocket_proxy: Option<String>,
+    /// The configuration for caching responses from the proxy targets
+    pub cache: Option<RouteCacheConfig>,
+    /// The configuration for how requests are forwarded to the proxy targets
+    pub forwarding: Option<ForwardingConfig>,
 }
 
 /// Represents configuration for the logger.
@@ -106,6 +126,61 @@ pub struct CacheConfig {
     pub time_limit: usize,
 }
 
+/// Represents configuration for caching the responses of a proxied route.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RouteCacheConfig {
+    /// The maximum total size of the cached responses, in bytes
+    pub size_limit: usize,
+    /// How long a stale response can be served while it is revalidated in the background, in seconds, unless specified by the backend
+    pub stale_while_revalidate: u64,
+    /// How long a stale response can be served if the backend fails, in seconds, unless specified by the backend
+    pub stale_if_error: u64,
+}
+
+/// Represents configuration for how requests to a proxied route are forwarded to its targets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardingConfig {
+    /// Whether to remove the part of the path matched by the route before the wildcard
+    pub strip_prefix: bool,
+    /// The path to add to the start of the forwarded path
+    pub add_prefix: Option<String>,
+    /// The headers to add to forwarded requests, and how to set their `Host` header
+    pub headers: ProxyHeaders,
+}
+
+/// Represents configuration for detecting which targets of a proxied route are unavailable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// The number of consecutive failed requests after which a target is taken out of rotation, or `0` to never take it out
+    pub max_failures: usize,
+    /// How long a target is taken out of rotation for before it is tried again
+    pub eject_time: Duration,
+    /// The configuration for actively checking the health of the targets, if enabled
+    pub check: Option<HealthCheckConfig>,
+}
+
+/// Represents configuration for actively checking the health of the targets of a proxied route.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthCheckConfig {
+    /// The path to request from each target
+    pub path: String,
+    /// How often to check each target
+    pub interval: Duration,
+    /// The status code which a healthy target responds with
+    pub status: u16,
+    /// How long to wait for a response before considering the target unhealthy
+    pub timeout: Duration,
+}
+
+/// Represents configuration for response compression.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Whether to compress responses
+    pub enabled: bool,
+    /// The minimum size of a response to compress, in bytes
+    pub min_size: usize,
+}
+
 /// Represents configuration for the blacklist.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BlacklistConfig {
@@ -125,6 +200,28 @@ pub struct TlsConfig {
     pub key_file: String,
     /// Whether to force clients to use HTTPS.
     pub force: bool,
+    /// Authentication of clients with TLS client certificates, if enabled.
+    pub client_auth: Option<ClientAuthConfig>,
+}
+
+/// Represents configuration for authenticating clients with TLS client certificates.
+#[cfg(feature = "tls")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientAuthConfig {
+    /// The path of the file containing the CAs which client certificates must be signed by.
+    pub ca_file: String,
+    /// Whether clients must send a certificate.
+    pub mode: ClientAuth,
+}
+
+/// Represents the TLS certificate of a specific host.
+#[cfg(feature = "tls")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostTlsConfig {
+    /// The TLS certificate file path.
+    pub cert_file: String,
+    /// The TLS key file path.
+    pub key_file: String,
 }
 
 /// Represents configuration for a plugin.
@@ -140,12 +237,18 @@ pub struct PluginConfig {
 }
 
 /// Represents an algorithm for load balancing.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum LoadBalancerMode {
     /// Evenly distributes load in a repeating pattern
     RoundRobin,
     /// Randomly distributes load
     Random,
+    /// Sends each request to the target handling the fewest requests
+    LeastConnections,
+    /// Consistently sends requests from the same client address to the same target
+    IpHash,
+    /// Consistently sends requests with the same value of the given cookie to the same target
+    CookieHash(String),
 }
 
 /// Represents a method of applying the blacklist.
@@ -157,6 +260,27 @@ pub enum BlacklistMode {
     Forbidden,
 }
 
+/// Represents an address on which the server accepts connections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListenConfig {
+    /// The address to listen on, including the port
+    pub address: String,
+    /// The way in which connections to the address are handled
+    pub mode: ListenMode,
+}
+
+/// Represents the way in which connections to a listener are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ListenMode {
+    /// Serves requests over plain HTTP
+    Http,
+    /// Serves requests over HTTPS with the certificates of the `tls` section
+    #[cfg(feature = "tls")]
+    Https,
+    /// Redirects every request to HTTPS on the given port
+    Redirect(u16),
+}
+
 /// Represents the source of the configuration.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ConfigSource {
@@ -170,10 +294,37 @@ pub enum ConfigSource {
     Default,
 }
 
+impl ConfigSource {
+    /// Gets the path to the configuration file from where it was specified, or `None` if the default configuration was used.
+    pub fn path(&self) -> Option<String> {
+        match self {
+            Self::Argument => args().nth(1),
+            Self::EnvironmentVariable => var("HUMPHREY_CONF").ok(),
+            Self::CurrentDirectory => Some("humphrey.conf".into()),
+            Self::Default => None,
+        }
+    }
+}
+
 impl Config {
     /// Attempts to load the configuration.
     pub fn load() -> Result<Self, String> {
-        let (path, source) = if let Some(arg_path) = args().nth(1) {
+        let (path, source) = Self::locate();
+
+        if let Ok((filename, config_string)) = load_config_file(path) {
+            let mut config = Self::parse(&config_string, &filename)?;
+            config.source = source;
+
+            Ok(config)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    /// Finds the path to the configuration file and where it was specified.
+    /// If no configuration file could be found, the path is empty and the source is `ConfigSource::Default`.
+    fn locate() -> (String, ConfigSource) {
+        if let Some(arg_path) = args().nth(1) {
             (arg_path, ConfigSource::Argument)
         } else if Path::new("humphrey.conf").exists() {
             ("humphrey.conf".into(), ConfigSource::CurrentDirectory)
@@ -181,17 +332,26 @@ impl Config {
             (env_path, ConfigSource::EnvironmentVariable)
         } else {
             ("".into(), ConfigSource::Default)
-        };
+        }
+    }
 
-        if let Ok((filename, config_string)) = load_config_file(path) {
-            let tree = parse_conf(&config_string, &filename).map_err(|e| e.to_string())?;
-            let mut config = Self::from_tree(tree)?;
-            config.source = source;
+    /// Attempts to load the configuration from the given path.
+    /// Unlike `Config::load`, this fails if the file cannot be read instead of falling back to the default.
+    pub fn load_from(path: impl AsRef<str>, source: ConfigSource) -> Result<Self, String> {
+        let (filename, config_string) = load_config_file(path.as_ref())
+            .map_err(|_| format!("Could not read configuration file {}", path.as_ref()))?;
 
-            Ok(config)
-        } else {
-            Ok(Config::default())
-        }
+        let mut config = Self::parse(&config_string, &filename)?;
+        config.source = source;
+
+        Ok(config)
+    }
+
+    /// Parses and validates the configuration from the contents of a configuration file.
+    fn parse(config_string: &str, filename: &str) -> Result<Self, String> {
+        let tree = parse_conf(config_string, filename).map_err(|e| e.to_string())?;
+
+        Ok(Self::from_tree(tree)?)
     }
 
     /// Parses the config from the config tree.
@@ -212,6 +372,8 @@ impl Config {
         } else {
             None
         };
+        let watch =
+            hashmap.get_optional_parsed("server.watch", false, "server.watch must be a boolean")?;
 
         if threads < 1 {
             return Err("You cannot specify less than 1 thread");
@@ -248,14 +410,22 @@ impl Config {
             let cert_file = hashmap.get_owned("server.tls.cert_file");
             let key_file = hashmap.get_owned("server.tls.key_file");
             let force = hashmap.get_optional("server.tls.force", "false".into());
-
-            if force == "true" && threads < 2 {
-                return Err("A minimum of two threads are required to force HTTPS");
-            }
-
-            if force == "true" && port != 443 {
-                return Err("Forcing HTTPS redirects requires the port to be 443");
-            }
+            let client_ca = hashmap.get_owned("server.tls.client_ca");
+            let client_auth = hashmap.get_owned("server.tls.client_auth");
+
+            let client_auth = match (client_ca, client_auth.as_deref()) {
+                (Some(ca_file), None | Some("required")) => Some(ClientAuthConfig {
+                    ca_file,
+                    mode: ClientAuth::Required,
+                }),
+                (Some(ca_file), Some("optional")) => Some(ClientAuthConfig {
+                    ca_file,
+                    mode: ClientAuth::Optional,
+                }),
+                (Some(_), Some(_)) => return Err("Invalid TLS client authentication mode"),
+                (None, Some(_)) => return Err("Missing client CA file for TLS"),
+                (None, None) => None,
+            };
 
             if let Some(cert_file) = cert_file {
                 if let Some(key_file) = key_file {
@@ -263,6 +433,7 @@ impl Config {
                         cert_file,
                         key_file,
                         force: force == "true",
+                        client_auth,
                     })
                 } else {
                     return Err("Missing key file for TLS");
@@ -272,6 +443,85 @@ impl Config {
             }
         };
 
+        // Get and validate the listeners
+        let listeners = {
+            let mut listeners: Vec<ListenConfig> = Vec::new();
+            let mut redirects: Vec<usize> = Vec::new();
+
+            for entry in tree.get_listeners() {
+                let mut parts = entry.split_whitespace();
+                let address = parts.next().ok_or("Invalid listen address")?.to_string();
+                let mode = parts.next().unwrap_or("http");
+
+                if parts.next().is_some()
+                    || address
+                        .rsplit_once(':')
+                        .and_then(|(_, port)| port.parse::<u16>().ok())
+                        .is_none()
+                {
+                    return Err("Invalid listen address");
+                }
+
+                let mode = match mode {
+                    "http" => ListenMode::Http,
+                    #[cfg(feature = "tls")]
+                    "https" if tls_config.is_some() => ListenMode::Https,
+                    "https" => return Err("HTTPS listeners require the `tls` section"),
+                    "redirect" => {
+                        redirects.push(listeners.len());
+                        ListenMode::Redirect(443)
+                    }
+                    _ => return Err("Invalid listener mode"),
+                };
+
+                listeners.push(ListenConfig { address, mode });
+            }
+
+            // Redirects go to the port of the first HTTPS listener
+            #[cfg(feature = "tls")]
+            let https_port = listeners
+                .iter()
+                .find(|listener| listener.mode == ListenMode::Https)
+                .and_then(|listener| listener.address.rsplit_once(':'))
+                .and_then(|(_, port)| port.parse::<u16>().ok());
+            #[cfg(not(feature = "tls"))]
+            let https_port: Option<u16> = None;
+
+            if !redirects.is_empty() {
+                let https_port =
+                    https_port.ok_or("Redirecting listeners require an HTTPS listener")?;
+                for index in redirects {
+                    listeners[index].mode = ListenMode::Redirect(https_port);
+                }
+            }
+
+            #[cfg(feature = "tls")]
+            if !listeners.is_empty() && tls_config.as_ref().is_some_and(|tls| tls.force) {
+                return Err("Use a `redirect` listener instead of forcing HTTPS when listeners are configured");
+            }
+
+            if listeners.is_empty() {
+                listeners.push(ListenConfig {
+                    address: format!("{}:{}", address, port),
+                    mode: ListenMode::Http,
+                });
+
+                #[cfg(feature = "tls")]
+                if let Some(tls_config) = &tls_config {
+                    listeners[0].mode = ListenMode::Https;
+
+                    if tls_config.force {
+                        listeners.push(ListenConfig {
+                            address: "0.0.0.0:80".into(),
+                            mode: ListenMode::Redirect(port),
+                        });
+                    }
+                }
+            }
+
+            listeners
+        };
+
         // Get and validate the logging configuration
         let logging = {
             let log_level = hashmap.get_optional_parsed(
@@ -306,6 +556,22 @@ impl Config {
             }
         };
 
+        // Get and validate the compression configuration
+        let compression = {
+            let enabled = hashmap.get_optional_parsed(
+                "server.compression.enabled",
+                false,
+                "server.compression.enabled must be a boolean",
+            )?;
+            let min_size = hashmap.get_optional_parsed(
+                "server.compression.min_size",
+                DEFAULT_MIN_SIZE,
+                "Invalid minimum compression size",
+            )?;
+
+            CompressionConfig { enabled, min_size }
+        };
+
         // Get and validate the configuration for the different routes
         let default_host = parse_host("*", &tree)?;
 
@@ -320,6 +586,11 @@ impl Config {
             hosts
         };
 
+        #[cfg(feature = "tls")]
+        if tls_config.is_none() && hosts.iter().any(|host| host.tls.is_some()) {
+            return Err("Host certificates require a default certificate in the `tls` section");
+        }
+
         // Get and validate plugin configuration
         #[cfg(feature = "plugins")]
         let plugins = {
@@ -349,6 +620,7 @@ impl Config {
             source: ConfigSource::Default,
             address,
             port,
+            listeners,
             threads,
             #[cfg(feature = "tls")]
             tls_config,
@@ -359,19 +631,26 @@ impl Config {
             plugins,
             logging,
             cache,
+            compression,
             blacklist,
             connection_timeout,
+            watch,
         })
     }
 
-    /// Get the route at the given host and route indices.
-    pub fn get_route(&self, host: usize, route: usize) -> &RouteConfig {
+    /// Get the host at the given host index, where `0` is the default host.
+    pub fn get_host(&self, host: usize) -> &HostConfig {
         if host == 0 {
-            &self.default_host.routes[route]
+            &self.default_host
         } else {
-            &self.hosts[host - 1].routes[route]
+            &self.hosts[host - 1]
         }
     }
+
+    /// Get the route at the given host and route indices.
+    pub fn get_route(&self, host: usize, route: usize) -> &RouteConfig {
+        &self.get_host(host).routes[route]
+    }
 }
 
 /// Loads the configuration file.
@@ -420,8 +699,35 @@ fn parse_host(wild: &str, node: &ConfigNode) -> Result<HostConfig, &'static str>
         routes.extend(parse_route(&wild, conf)?);
     }
 
+    // Only `host` blocks have their own certificates, the default host uses the server's certificate
+    #[cfg(feature = "tls")]
+    let tls = match node {
+        ConfigNode::Host(_, children) => {
+            let mut hashmap: HashMap<String, ConfigNode> = HashMap::new();
+            for child in children {
+                child.flatten(&mut hashmap, &Vec::new());
+            }
+
+            match (
+                hashmap.get_owned("tls.cert_file"),
+                hashmap.get_owned("tls.key_file"),
+            ) {
+                (Some(cert_file), Some(key_file)) => Some(HostTlsConfig {
+                    cert_file,
+                    key_file,
+                }),
+                (Some(_), None) => return Err("Missing key file for TLS"),
+                (None, Some(_)) => return Err("Missing certificate file for TLS"),
+                (None, None) => None,
+            }
+        }
+        _ => None,
+    };
+
     Ok(HostConfig {
         matches: wild.to_string(),
+        #[cfg(feature = "tls")]
+        tls,
         routes,
     })
 }
@@ -433,6 +739,12 @@ fn parse_route(
 ) -> Result<Vec<RouteConfig>, &'static str> {
     let mut routes: Vec<RouteConfig> = Vec::new();
 
+    let cache = parse_route_cache(&conf)?;
+
+    if cache.is_some() && !conf.contains_key("proxy") {
+        return Err("Response caching is only supported on proxy routes");
+    }
+
     for wild in wild.split(',').map(|s| s.trim()) {
         let websocket_proxy = conf.get_owned("websocket");
 
@@ -447,6 +759,8 @@ fn parse_route(
                 path: Some(file),
                 load_balancer: None,
                 websocket_proxy,
+                cache: None,
+                forwarding: None,
             });
         } else if conf.contains_key("directory") {
             // This is a regular directory-serving route
@@ -459,33 +773,24 @@ fn parse_route(
                 path: Some(directory),
                 load_balancer: None,
                 websocket_proxy,
+                cache: None,
+                forwarding: None,
             });
         } else if conf.contains_key("proxy") {
             // This is a proxy route
 
-            let targets: Vec<String> = conf
-                .get_compulsory("proxy", "")
-                .unwrap()
-                .split(',')
-                .map(|s| s.to_owned())
-                .collect();
+            let (targets, weights) =
+                parse_proxy_targets(&conf.get_compulsory("proxy", "").unwrap())?;
 
             let load_balancer_mode = conf.get_optional("load_balancer_mode", "round-robin".into());
-            let load_balancer_mode =
-                match load_balancer_mode.as_str() {
-                    "round-robin" => LoadBalancerMode::RoundRobin,
-                    "random" => LoadBalancerMode::Random,
-                    _ => return Err(
-                        "Invalid load balancer mode, valid options are `round-robin` or `random`",
-                    ),
-                };
+            let load_balancer_mode = parse_load_balancer_mode(&load_balancer_mode)?;
 
-            let load_balancer = EqMutex::new(LoadBalancer {
-                targets,
-                mode: load_balancer_mode,
-                lcg: Lcg::new(),
-                index: 0,
-            });
+            let health = parse_health(&conf)?;
+            let forwarding = parse_forwarding(&conf)?;
+
+            let load_balancer = Arc::new(EqMutex::new(
+                LoadBalancer::new(targets, load_balancer_mode, health).with_weights(weights),
+            ));
 
             routes.push(RouteConfig {
                 route_type: RouteType::Proxy,
@@ -493,6 +798,8 @@ fn parse_route(
                 path: None,
                 load_balancer: Some(load_balancer),
                 websocket_proxy,
+                cache,
+                forwarding: Some(forwarding),
             });
         } else if conf.contains_key("redirect") {
             // This is a redirect route
@@ -505,6 +812,8 @@ fn parse_route(
                 path: Some(target),
                 load_balancer: None,
                 websocket_proxy,
+                cache: None,
+                forwarding: None,
             });
         } else if !conf.contains_key("websocket") {
             return Err("Invalid route configuration, every route must contain either the `file`, `directory`, `proxy` or `redirect` field, unless it defines a WebSocket proxy with the `websocket` field");
@@ -515,9 +824,163 @@ fn parse_route(
                 path: None,
                 load_balancer: None,
                 websocket_proxy,
+                cache: None,
+                forwarding: None,
             });
         }
     }
 
     Ok(routes)
 }
+
+/// Parses the response cache configuration of a route, if it has any.
+fn parse_route_cache(
+    conf: &HashMap<String, ConfigNode>,
+) -> Result<Option<RouteCacheConfig>, &'static str> {
+    if !conf.keys().any(|key| key.starts_with("cache.")) {
+        return Ok(None);
+    }
+
+    let size_limit =
+        conf.get_compulsory_parsed("cache.size", "Invalid or missing route cache size")?;
+    let stale_while_revalidate = conf.get_optional_parsed(
+        "cache.stale_while_revalidate",
+        0,
+        "Invalid route cache stale_while_revalidate time",
+    )?;
+    let stale_if_error = conf.get_optional_parsed(
+        "cache.stale_if_error",
+        0,
+        "Invalid route cache stale_if_error time",
+    )?;
+
+    Ok(Some(RouteCacheConfig {
+        size_limit,
+        stale_while_revalidate,
+        stale_if_error,
+    }))
+}
+
+/// Parses a comma-separated list of proxy targets, each of which may be followed by `=` and its weight.
+fn parse_proxy_targets(targets: &str) -> Result<(Vec<String>, Vec<usize>), &'static str> {
+    targets
+        .split(',')
+        .map(|target| match target.rsplit_once('=') {
+            Some((target, weight)) => match weight.parse::<usize>() {
+                Ok(weight) if weight > 0 => Ok((target.to_string(), weight)),
+                _ => Err("Invalid proxy target weight, weights must be positive integers"),
+            },
+            None => Ok((target.to_string(), 1)),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|targets| targets.into_iter().unzip())
+}
+
+/// Parses a load balancer mode.
+fn parse_load_balancer_mode(mode: &str) -> Result<LoadBalancerMode, &'static str> {
+    match mode {
+        "round-robin" => Ok(LoadBalancerMode::RoundRobin),
+        "random" => Ok(LoadBalancerMode::Random),
+        "least-connections" => Ok(LoadBalancerMode::LeastConnections),
+        "ip-hash" => Ok(LoadBalancerMode::IpHash),
+        _ => match mode.strip_prefix("cookie-hash:") {
+            Some(cookie) if !cookie.is_empty() => Ok(LoadBalancerMode::CookieHash(cookie.to_string())),
+            _ => Err("Invalid load balancer mode, valid options are `round-robin`, `random`, `least-connections`, `ip-hash` or `cookie-hash:<cookie name>`"),
+        },
+    }
+}
+
+/// Parses the configuration for how requests to a proxied route are forwarded.
+fn parse_forwarding(conf: &HashMap<String, ConfigNode>) -> Result<ForwardingConfig, &'static str> {
+    let strip_prefix =
+        conf.get_optional_parsed("strip_prefix", true, "Invalid strip prefix setting")?;
+
+    let add_prefix = conf.get_owned("add_prefix");
+    if add_prefix
+        .as_ref()
+        .is_some_and(|prefix| !prefix.starts_with('/'))
+    {
+        return Err("Added path prefix must start with `/`");
+    }
+
+    let host = match conf.get_owned("host").as_deref() {
+        None | Some("preserve") => HostRewrite::Preserve,
+        Some("target") => HostRewrite::Target,
+        Some(host) => HostRewrite::Custom(host.to_string()),
+    };
+
+    let defaults = ProxyHeaders::default();
+
+    let x_forwarded = conf.get_optional_parsed(
+        "forwarding.x_forwarded",
+        defaults.x_forwarded,
+        "Invalid `X-Forwarded-*` header setting",
+    )?;
+    let forwarded = conf.get_optional_parsed(
+        "forwarding.forwarded",
+        defaults.forwarded,
+        "Invalid `Forwarded` header setting",
+    )?;
+    let via =
+        match conf.get_optional_parsed("forwarding.via", true, "Invalid `Via` header setting")? {
+            true => defaults.via,
+            false => None,
+        };
+
+    Ok(ForwardingConfig {
+        strip_prefix,
+        add_prefix,
+        headers: ProxyHeaders {
+            x_forwarded,
+            forwarded,
+            via,
+            host,
+        },
+    })
+}
+
+/// Parses the configuration for detecting unavailable targets of a proxied route.
+fn parse_health(conf: &HashMap<String, ConfigNode>) -> Result<HealthConfig, &'static str> {
+    let max_failures = conf.get_optional_parsed(
+        "health.max_failures",
+        DEFAULT_MAX_FAILURES,
+        "Invalid maximum number of failures",
+    )?;
+    let eject_time = conf.get_optional_parsed(
+        "health.eject_time",
+        DEFAULT_EJECT_TIME,
+        "Invalid health eject time",
+    )?;
+
+    let check = if let Some(path) = conf.get_owned("health.path") {
+        if !path.starts_with('/') {
+            return Err("Health check path must start with `/`");
+        }
+
+        let interval: u64 =
+            conf.get_optional_parsed("health.interval", 10, "Invalid health check interval")?;
+        let status =
+            conf.get_optional_parsed("health.status", 200, "Invalid health check status")?;
+        let timeout: u64 =
+            conf.get_optional_parsed("health.timeout", 2, "Invalid health check timeout")?;
+
+        if interval == 0 || timeout == 0 {
+            return Err("Health check interval and timeout must be at least one second");
+        }
+
+        Some(HealthCheckConfig {
+            path,
+            interval: Duration::from_secs(interval),
+            status,
+            timeout: Duration::from_secs(timeout),
+        })
+    } else {
+        None
+    };
+
+    Ok(HealthConfig {
+        max_failures,
+        eject_time: Duration::from_secs(eject_time),
+        check,
+    })
+}
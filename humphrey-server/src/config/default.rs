@@ -1,17 +1,26 @@
 //! Provides default values for the configuration.
 
 use crate::config::{
-    BlacklistConfig, BlacklistMode, Config, ConfigSource, HostConfig, LoggingConfig, RouteConfig,
-    RouteType,
+    BlacklistConfig, BlacklistMode, CompressionConfig, Config, ConfigSource, ForwardingConfig,
+    HealthConfig, HostConfig, ListenConfig, ListenMode, LoggingConfig, RouteConfig, RouteType,
 };
 use crate::server::logger::LogLevel;
 
+use humphrey::http::compression::DEFAULT_MIN_SIZE;
+use humphrey::http::proxy::ProxyHeaders;
+
+use std::time::Duration;
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             source: ConfigSource::Default,
             address: "0.0.0.0".into(),
             port: 80,
+            listeners: vec![ListenConfig {
+                address: "0.0.0.0:80".into(),
+                mode: ListenMode::Http,
+            }],
             threads: 32,
             #[cfg(feature = "tls")]
             tls_config: None,
@@ -22,8 +31,10 @@ impl Default for Config {
             plugins: Vec::new(),
             logging: Default::default(),
             cache: Default::default(),
+            compression: Default::default(),
             blacklist: Default::default(),
             connection_timeout: Default::default(),
+            watch: false,
         }
     }
 }
@@ -32,6 +43,8 @@ impl Default for HostConfig {
     fn default() -> Self {
         Self {
             matches: "*".into(),
+            #[cfg(feature = "tls")]
+            tls: None,
             routes: vec![Default::default()],
         }
     }
@@ -45,6 +58,34 @@ impl Default for RouteConfig {
             path: Some('.'.into()),
             load_balancer: None,
             websocket_proxy: None,
+            cache: None,
+            forwarding: None,
+        }
+    }
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        Self {
+            strip_prefix: true,
+            add_prefix: None,
+            headers: ProxyHeaders::default(),
+        }
+    }
+}
+
+/// The default number of consecutive failed requests after which a proxy target is taken out of rotation.
+pub const DEFAULT_MAX_FAILURES: usize = 3;
+
+/// The default number of seconds for which a proxy target is taken out of rotation.
+pub const DEFAULT_EJECT_TIME: u64 = 10;
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: DEFAULT_MAX_FAILURES,
+            eject_time: Duration::from_secs(DEFAULT_EJECT_TIME),
+            check: None,
         }
     }
 }
@@ -59,6 +100,15 @@ impl Default for LoggingConfig {
     }
 }
 
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+}
+
 impl Default for BlacklistConfig {
     fn default() -> Self {
         Self {
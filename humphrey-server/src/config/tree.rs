@@ -123,6 +123,24 @@ impl ConfigNode {
         plugins
     }
 
+    /// Get the values of the `listen` entries directly under this node, in the order they are configured.
+    /// These are read separately since flattening would only keep the last of them.
+    pub fn get_listeners(&self) -> Vec<String> {
+        let mut listeners: Vec<String> = Vec::new();
+
+        if let ConfigNode::Section(_, children) = self {
+            for child in children {
+                if let ConfigNode::String(key, value) = child {
+                    if key == "listen" {
+                        listeners.push(value.clone());
+                    }
+                }
+            }
+        }
+
+        listeners
+    }
+
     /// Get this node's value as a string, or `None` if this is not possible.
     pub fn get_string(&self) -> Option<String> {
         match self {
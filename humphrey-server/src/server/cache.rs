@@ -2,17 +2,44 @@
 
 use crate::config::Config;
 
+use humphrey::http::compression::Encoding;
+use humphrey::http::conditional::Validators;
 use humphrey::http::mime::MimeType;
-use std::{collections::VecDeque, time::SystemTime};
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::metadata;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 
 /// Represents the server's cache.
+///
+/// Items are looked up by their route and host, and when the cache is full, the least recently used items
+///   are evicted first. Items are kept in a doubly-linked list ordered by when they were last used, which is
+///   stored in a vector and linked by index so that every operation takes constant time.
+///
+/// When the cache is shared between threads, `peek` only needs a read lock, so the file of an item can be checked
+///   without holding the lock before the lookup is recorded with `touch` or `invalidate`.
 #[derive(Default)]
 pub struct Cache {
     /// The cache's maximum size.
     pub cache_limit: usize,
     cache_time_limit: u64,
     cache_size: usize,
-    data: VecDeque<CachedItem>,
+    index: HashMap<(String, usize), usize>,
+    entries: Vec<Option<Entry>>,
+    free: Vec<usize>,
+    most_recent: Option<usize>,
+    least_recent: Option<usize>,
+    stats: StatCounters,
+}
+
+/// An item in the cache, along with its position in the list of items ordered by when they were last used.
+struct Entry {
+    item: CachedItem,
+    more_recent: Option<usize>,
+    less_recent: Option<usize>,
 }
 
 /// Represents a cached item.
@@ -21,69 +48,290 @@ pub struct CachedItem {
     pub route: String,
     /// The host that this item was served at.
     pub host: usize,
+    /// The path of the file which the item was read from.
+    pub path: PathBuf,
     /// The MIME type of the item.
     pub mime_type: MimeType,
+    /// The validators of the item, used to answer conditional requests and to detect changes to the file.
+    pub validators: Validators,
     /// The time at which the item was cached.
     pub cache_time: u64,
     /// The item's data.
     pub data: Vec<u8>,
+    /// Compressed versions of the item's data, along with their encodings.
+    pub variants: Vec<(Encoding, Vec<u8>)>,
 }
 
-impl Cache {
-    /// Attempts to get an item from the cache.
-    /// If the item is not present, or it is stale, returns `None`.
-    pub fn get(&self, route: &str, host: usize) -> Option<&CachedItem> {
-        let time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        let index = self
-            .data
+/// Counts how the cache has been used since it was created.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of lookups which found a valid item.
+    pub hits: u64,
+    /// The number of lookups which did not find a valid item.
+    pub misses: u64,
+    /// The number of items removed to make space for new items.
+    pub evictions: u64,
+    /// The number of items removed because they were too old or their file had changed.
+    pub invalidations: u64,
+}
+
+/// The counters behind `CacheStats`, which can be updated while the cache is only borrowed immutably.
+#[derive(Default)]
+struct StatCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl CachedItem {
+    /// Gets the version of the item's data compressed with the given encoding, if it is cached.
+    pub fn variant(&self, encoding: Encoding) -> Option<&Vec<u8>> {
+        self.variants
             .iter()
-            .position(|item| item.route == route && item.host == host);
+            .find(|(variant_encoding, _)| *variant_encoding == encoding)
+            .map(|(_, data)| data)
+    }
 
-        if let Some(index) = index {
-            let item = &self.data[index];
-            if time - item.cache_time > self.cache_time_limit {
+    /// Returns the total size of the item's data, including its compressed versions.
+    pub fn size(&self) -> usize {
+        self.data.len()
+            + self
+                .variants
+                .iter()
+                .map(|(_, data)| data.len())
+                .sum::<usize>()
+    }
+
+    /// Checks whether the file which the item was read from has changed since it was cached, by comparing
+    ///   its modification time and size with those when it was read.
+    pub fn is_modified(&self) -> bool {
+        is_file_modified(&self.path, &self.validators)
+    }
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} evictions, {} invalidations",
+            self.hits, self.misses, self.evictions, self.invalidations
+        )
+    }
+}
+
+impl Cache {
+    /// Attempts to get an item from the cache, marking it as the most recently used item.
+    /// If the item is not present, or it is stale or its file has changed, returns `None`.
+    pub fn get(&mut self, route: &str, host: usize) -> Option<&CachedItem> {
+        let item = self.peek(route, host)?;
+
+        if self.is_expired(item) || item.is_modified() {
+            self.invalidate(route, host);
+            return None;
+        }
+
+        self.touch(route, host);
+        self.peek(route, host)
+    }
+
+    /// Looks up an item without marking it as used or checking whether it is still valid, counting a miss if it
+    ///   is not present.
+    ///
+    /// If an item is found, the lookup must be completed with `touch` if it is still valid, or `invalidate` if not.
+    pub fn peek(&self, route: &str, host: usize) -> Option<&CachedItem> {
+        match self.index.get(&(route.to_string(), host)) {
+            Some(index) => Some(&self.entry(*index).item),
+            None => {
+                self.stats.misses.fetch_add(1, Ordering::Relaxed);
                 None
-            } else {
-                Some(item)
             }
-        } else {
-            None
+        }
+    }
+
+    /// Returns whether the item was cached longer ago than the cache's time limit.
+    pub fn is_expired(&self, item: &CachedItem) -> bool {
+        now().saturating_sub(item.cache_time) > self.cache_time_limit
+    }
+
+    /// Marks an item as the most recently used item, counting a hit.
+    /// Does nothing if the item is not cached, for example if it was removed since it was found.
+    pub fn touch(&mut self, route: &str, host: usize) {
+        if let Some(index) = self.index.get(&(route.to_string(), host)).copied() {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+            self.unlink(index);
+            self.push_most_recent(index);
+        }
+    }
+
+    /// Removes an item which is stale or whose file has changed, counting a miss.
+    pub fn invalidate(&mut self, route: &str, host: usize) {
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(index) = self.index.get(&(route.to_string(), host)).copied() {
+            self.remove(index);
+            self.stats.invalidations.fetch_add(1, Ordering::Relaxed);
         }
     }
 
     /// Sets an item in the cache.
-    /// Overwrites older versions if needed.
-    pub fn set(&mut self, route: &str, host: usize, value: Vec<u8>, mime_type: MimeType) {
-        while self.cache_size + value.len() > self.cache_limit {
-            self.cache_size -= self.data[0].data.len();
-            self.data.pop_front();
+    /// Overwrites older versions if needed, and evicts the least recently used items to make space for it.
+    ///
+    /// Does nothing if the item is larger than the cache.
+    pub fn set(
+        &mut self,
+        route: &str,
+        host: usize,
+        path: PathBuf,
+        value: Vec<u8>,
+        mime_type: MimeType,
+        validators: Validators,
+    ) {
+        if value.len() > self.cache_limit {
+            return;
         }
 
-        if let Some(existing_item) = self
-            .data
-            .iter()
-            .position(|item| item.route == route && item.host == host)
-        {
-            self.cache_size -= self.data[existing_item].data.len();
-            self.data.remove(existing_item);
+        let key = (route.to_string(), host);
+
+        if let Some(existing_item) = self.index.get(&key) {
+            self.remove(*existing_item);
+        }
+
+        while self.cache_size + value.len() > self.cache_limit {
+            let least_recent = self.least_recent.unwrap();
+            self.remove(least_recent);
+            self.stats.evictions.fetch_add(1, Ordering::Relaxed);
         }
 
         self.cache_size += value.len();
 
-        self.data.push_back(CachedItem {
-            route: route.into(),
-            host,
-            data: value,
-            mime_type,
-            cache_time: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        });
+        let entry = Entry {
+            item: CachedItem {
+                route: route.into(),
+                host,
+                path,
+                data: value,
+                mime_type,
+                validators,
+                variants: Vec::new(),
+                cache_time: now(),
+            },
+            more_recent: None,
+            less_recent: None,
+        };
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = Some(entry);
+                index
+            }
+            None => {
+                self.entries.push(Some(entry));
+                self.entries.len() - 1
+            }
+        };
+
+        self.index.insert(key, index);
+        self.push_most_recent(index);
+    }
+
+    /// Stores a compressed version of a cached item's data.
+    /// Does nothing if the item is not cached, or if there is not enough space in the cache.
+    pub fn set_variant(&mut self, route: &str, host: usize, encoding: Encoding, value: Vec<u8>) {
+        let index = match self.index.get(&(route.to_string(), host)) {
+            Some(index) => *index,
+            None => return,
+        };
+
+        let cache_size = self.cache_size;
+        let cache_limit = self.cache_limit;
+        let item = &mut self.entry_mut(index).item;
+
+        let existing_size = item.variant(encoding).map_or(0, |data| data.len());
+
+        let new_size = cache_size - existing_size + value.len();
+
+        if new_size > cache_limit {
+            return;
+        }
+
+        item.variants
+            .retain(|(variant_encoding, _)| *variant_encoding != encoding);
+        item.variants.push((encoding, value));
+        self.cache_size = new_size;
+    }
+
+    /// Gets the number of items in the cache.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns `true` if the cache contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Gets the total size of the items in the cache, in bytes.
+    pub fn size(&self) -> usize {
+        self.cache_size
+    }
+
+    /// Gets the hit, miss, eviction and invalidation counts of the cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            evictions: self.stats.evictions.load(Ordering::Relaxed),
+            invalidations: self.stats.invalidations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes the item at the given index from the cache.
+    fn remove(&mut self, index: usize) {
+        self.unlink(index);
+
+        let entry = self.entries[index].take().unwrap();
+        self.index
+            .remove(&(entry.item.route.clone(), entry.item.host));
+        self.cache_size -= entry.item.size();
+        self.free.push(index);
+    }
+
+    /// Removes the entry at the given index from the list of items ordered by when they were last used.
+    fn unlink(&mut self, index: usize) {
+        let (more_recent, less_recent) = {
+            let entry = self.entry_mut(index);
+            (entry.more_recent.take(), entry.less_recent.take())
+        };
+
+        match more_recent {
+            Some(more_recent) => self.entry_mut(more_recent).less_recent = less_recent,
+            None => self.most_recent = less_recent,
+        }
+
+        match less_recent {
+            Some(less_recent) => self.entry_mut(less_recent).more_recent = more_recent,
+            None => self.least_recent = more_recent,
+        }
+    }
+
+    /// Adds the unlinked entry at the given index to the list as the most recently used item.
+    fn push_most_recent(&mut self, index: usize) {
+        let previous = self.most_recent.replace(index);
+        self.entry_mut(index).less_recent = previous;
+
+        match previous {
+            Some(previous) => self.entry_mut(previous).more_recent = Some(index),
+            None => self.least_recent = Some(index),
+        }
+    }
+
+    fn entry(&self, index: usize) -> &Entry {
+        self.entries[index].as_ref().unwrap()
+    }
+
+    fn entry_mut(&mut self, index: usize) -> &mut Entry {
+        self.entries[index].as_mut().unwrap()
     }
 }
 
@@ -92,8 +340,23 @@ impl From<&Config> for Cache {
         Self {
             cache_limit: config.cache.size_limit,
             cache_time_limit: config.cache.time_limit as u64,
-            cache_size: 0,
-            data: VecDeque::new(),
+            ..Default::default()
         }
     }
 }
+
+/// Checks whether a file has changed since the given validators were taken from it, by comparing its modification
+///   time and size with those in the validators.
+pub fn is_file_modified(path: &Path, validators: &Validators) -> bool {
+    metadata(path).map_or(true, |metadata| {
+        Validators::from_metadata(&metadata) != *validators
+    })
+}
+
+/// Gets the current time as a UNIX timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
@@ -0,0 +1,103 @@
+//! Provides active health checking of proxy targets.
+
+use crate::config::HealthCheckConfig;
+use crate::proxy::{log_health_change, EqMutex, LoadBalancer};
+use crate::server::server::AppState;
+
+use humphrey::http::Response;
+
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Weak};
+use std::thread::{sleep, spawn};
+
+/// Starts a thread for each proxied route of the current configuration which has active health checks enabled.
+///
+/// Each thread stops once its route's load balancer is dropped, which happens when the configuration is reloaded,
+///   so this should be called again after every reload.
+pub fn start_health_checks(state: &Arc<AppState>) {
+    let config = state.config();
+
+    for host in config.hosts.iter().chain(Some(&config.default_host)) {
+        for route in &host.routes {
+            let load_balancer = match &route.load_balancer {
+                Some(load_balancer) => load_balancer,
+                None => continue,
+            };
+
+            let check = match &load_balancer.lock().unwrap().health.check {
+                Some(check) => check.clone(),
+                None => continue,
+            };
+
+            let load_balancer = Arc::downgrade(load_balancer);
+            let state = state.clone();
+
+            spawn(move || health_check_thread(state, load_balancer, check));
+        }
+    }
+}
+
+/// Periodically checks every target of the load balancer until the load balancer is dropped.
+fn health_check_thread(
+    state: Arc<AppState>,
+    load_balancer: Weak<EqMutex<LoadBalancer>>,
+    check: HealthCheckConfig,
+) {
+    loop {
+        let targets = match load_balancer.upgrade() {
+            Some(load_balancer) => load_balancer.lock().unwrap().targets.clone(),
+            None => return,
+        };
+
+        for target in targets {
+            let healthy = check_target(&target, &check);
+
+            let change = match load_balancer.upgrade() {
+                Some(load_balancer) => load_balancer.lock().unwrap().report_check(&target, healthy),
+                None => return,
+            };
+
+            log_health_change(&state, &target, change);
+        }
+
+        sleep(check.interval);
+    }
+}
+
+/// Checks whether the target responds to the health check request with the expected status code.
+pub fn check_target(target: &str, check: &HealthCheckConfig) -> bool {
+    let target_sock = match target
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+    {
+        Some(target_sock) => target_sock,
+        None => return false,
+    };
+
+    let mut stream = match TcpStream::connect_timeout(&target_sock, check.timeout) {
+        Ok(stream) => stream,
+        Err(_) => return false,
+    };
+
+    if stream.set_read_timeout(Some(check.timeout)).is_err()
+        || stream.set_write_timeout(Some(check.timeout)).is_err()
+    {
+        return false;
+    }
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        check.path, target
+    );
+
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+
+    match Response::from_stream(&mut stream) {
+        Ok(response) => u16::from(response.status_code) == check.status,
+        Err(_) => false,
+    }
+}
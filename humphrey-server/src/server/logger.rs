@@ -4,7 +4,7 @@ use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::str::FromStr;
 use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 
 use humphrey::http::date::DateTime;
 use humphrey::monitor::event::{Event, EventType, ToEventMask};
@@ -35,7 +35,7 @@ pub const INTERNAL_MASK_DEBUG: u32 = INTERNAL_MASK_INFO
 
 /// Encapsulates logging methods and configuration.
 pub struct Logger {
-    level: LogLevel,
+    level: RwLock<LogLevel>,
     console: bool,
     file: Option<Mutex<File>>,
 }
@@ -56,7 +56,7 @@ pub enum LogLevel {
 impl Default for Logger {
     fn default() -> Self {
         Self {
-            level: LogLevel::Warn,
+            level: RwLock::new(LogLevel::Warn),
             console: true,
             file: None,
         }
@@ -77,7 +77,7 @@ impl From<&Config> for Logger {
         });
 
         Self {
-            level: config.logging.level.clone(),
+            level: RwLock::new(config.logging.level.clone()),
             console: config.logging.console,
             file,
         }
@@ -94,7 +94,7 @@ impl Logger {
 
     /// Logs a warning, provided that the log level allows this.
     pub fn warn(&self, message: impl AsRef<str>) {
-        if self.level >= LogLevel::Warn {
+        if self.level() >= LogLevel::Warn {
             let string = format!("{} [WARN]  {}", Logger::time_format(), message.as_ref());
             self.log_to_console(&string);
             self.log_to_file(&string);
@@ -103,7 +103,7 @@ impl Logger {
 
     /// Logs information, provided that the log level allows this.
     pub fn info(&self, message: impl AsRef<str>) {
-        if self.level >= LogLevel::Info {
+        if self.level() >= LogLevel::Info {
             let string = format!("{} [INFO]  {}", Logger::time_format(), message.as_ref());
             self.log_to_console(&string);
             self.log_to_file(&string);
@@ -112,13 +112,23 @@ impl Logger {
 
     /// Logs debug information, provided that the log level allows this.
     pub fn debug(&self, message: impl AsRef<str>) {
-        if self.level == LogLevel::Debug {
+        if self.level() == LogLevel::Debug {
             let string = format!("{} [DEBUG] {}", Logger::time_format(), message.as_ref());
             self.log_to_console(&string);
             self.log_to_file(&string);
         }
     }
 
+    /// Gets the current log level.
+    pub fn level(&self) -> LogLevel {
+        self.level.read().unwrap().clone()
+    }
+
+    /// Changes the log level, for example when the configuration is reloaded.
+    pub fn set_level(&self, level: LogLevel) {
+        *self.level.write().unwrap() = level;
+    }
+
     /// Formats the current time into the format `YYYY-MM-DD HH:MM:SS`
     fn time_format() -> String {
         let time = DateTime::now();
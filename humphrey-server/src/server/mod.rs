@@ -3,10 +3,16 @@
 #![allow(clippy::module_inception)]
 
 pub mod cache;
+pub mod health;
 pub mod logger;
 pub mod proxy;
+pub mod proxy_cache;
 pub mod rand;
+pub mod reload;
 pub mod server;
+pub mod websocket_proxy;
 pub mod r#static;
+#[cfg(feature = "tls")]
+pub mod tls;
 
 pub use server::*;
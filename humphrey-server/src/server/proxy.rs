@@ -1,72 +1,359 @@
 //! Provides HTTP proxy functionality.
 
-use crate::config::LoadBalancerMode;
-use crate::rand::{Choose, Lcg};
+use crate::config::{ForwardingConfig, HealthConfig, LoadBalancerMode, RouteConfig};
+use crate::proxy_cache::{serve_cached, RouteKey};
+use crate::rand::Lcg;
 use crate::server::server::AppState;
 
 use humphrey::http::headers::HeaderType;
-use humphrey::http::proxy::proxy_request;
+use humphrey::http::proxy::{add_via, forward_request, strip_hop_by_hop_headers};
 use humphrey::http::{Request, Response, StatusCode};
 
 use std::net::ToSocketAddrs;
 use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How long to wait when connecting to a proxy target before considering it unreachable.
+pub const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of points on the hash ring for each unit of a target's weight.
+const RING_POINTS_PER_WEIGHT: usize = 100;
 
 /// Represents a load balancer.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LoadBalancer {
     /// The targets of the load balancer.
     pub targets: Vec<String>,
+    /// The weight of each target, in the same order as the targets.
+    pub weights: Vec<usize>,
     /// The algorithm used to choose a target.
     pub mode: LoadBalancerMode,
-    /// The current target.
+    /// The current position in the targets.
     pub index: usize,
     /// The random number generator used by the load balancer.
     pub lcg: Lcg,
+    /// The configuration for detecting unavailable targets.
+    pub health: HealthConfig,
+    /// The health of each target, in the same order as the targets.
+    pub target_health: Vec<TargetHealth>,
+    /// The number of requests currently being handled by each target, in the same order as the targets.
+    pub active_requests: Vec<usize>,
+    /// The points of each target on the hash ring used for consistent hashing, sorted by hash.
+    ring: Vec<(u64, usize)>,
+}
+
+/// Represents the health of a load balancer target.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TargetHealth {
+    /// The number of consecutive requests to the target which have failed.
+    pub failures: usize,
+    /// If the target has been taken out of rotation because of failed requests, the time at which it will be tried again.
+    pub ejected_until: Option<Instant>,
+    /// Whether the most recent active health check of the target failed.
+    pub check_failed: bool,
+}
+
+/// Represents a change in whether a load balancer target is available.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HealthChange {
+    /// The target has been taken out of rotation.
+    Down,
+    /// The target has been put back into rotation.
+    Up,
+}
+
+impl TargetHealth {
+    /// Checks whether the target can currently be selected.
+    pub fn is_available(&self, now: Instant) -> bool {
+        let ejected = match self.ejected_until {
+            Some(until) => now < until,
+            None => false,
+        };
+
+        !self.check_failed && !ejected
+    }
 }
 
 impl LoadBalancer {
-    /// Selects a target according to the load balancer mode.
-    pub fn select_target(&mut self) -> String {
-        match self.mode {
-            LoadBalancerMode::RoundRobin => {
-                let target_index = self.index;
-                self.index += 1;
-                if self.index == self.targets.len() {
-                    self.index = 0;
+    /// Creates a new load balancer with the given targets, all of which are initially available and have a weight of one.
+    pub fn new(targets: Vec<String>, mode: LoadBalancerMode, health: HealthConfig) -> Self {
+        let weights = vec![1; targets.len()];
+
+        Self {
+            target_health: vec![TargetHealth::default(); targets.len()],
+            active_requests: vec![0; targets.len()],
+            ring: build_ring(&targets, &weights),
+            targets,
+            weights,
+            mode,
+            index: 0,
+            lcg: Lcg::new(),
+            health,
+        }
+    }
+
+    /// Sets the weights of the targets, in the same order as the targets.
+    /// A target with a higher weight receives proportionally more requests.
+    pub fn with_weights(mut self, weights: Vec<usize>) -> Self {
+        assert_eq!(weights.len(), self.targets.len());
+
+        self.ring = build_ring(&self.targets, &weights);
+        self.weights = weights;
+        self
+    }
+
+    /// Selects an available target for the request according to the load balancer mode.
+    /// Returns `None` if every target is unavailable.
+    ///
+    /// Targets which were taken out of rotation because of failed requests become available again once their
+    ///   ejection time has passed, and are taken out again if the next request to them also fails.
+    ///
+    /// The selected target is counted as handling the request until its result is given to `report`.
+    pub fn select_target(&mut self, request: &Request) -> Option<String> {
+        let now = Instant::now();
+        let available: Vec<bool> = self
+            .target_health
+            .iter()
+            .map(|health| health.is_available(now))
+            .collect();
+
+        let index = match &self.mode {
+            LoadBalancerMode::RoundRobin => self.select_round_robin(&available),
+            LoadBalancerMode::Random => self.select_random(&available),
+            LoadBalancerMode::LeastConnections => self.select_least_connections(&available),
+            LoadBalancerMode::IpHash => {
+                self.select_hashed(&request.address.origin_addr.to_string(), &available)
+            }
+            LoadBalancerMode::CookieHash(name) => {
+                // Clients without the cookie, such as those which have not yet been given a session, are hashed by address
+                let key = request
+                    .get_cookie(name)
+                    .map(|cookie| cookie.value)
+                    .unwrap_or_else(|| request.address.origin_addr.to_string());
+
+                self.select_hashed(&key, &available)
+            }
+        }?;
+
+        self.active_requests[index] += 1;
+
+        Some(self.targets[index].clone())
+    }
+
+    /// Records whether a request to the given target succeeded, and that the target is no longer handling it.
+    ///
+    /// After `max_failures` consecutive failures the target is taken out of rotation for `eject_time`,
+    ///   and a successful request puts it back. Returns the change in the target's availability, if any.
+    pub fn report(&mut self, target: &str, success: bool) -> Option<HealthChange> {
+        let now = Instant::now();
+        let index = self.targets.iter().position(|t| t == target)?;
+        let health = &mut self.target_health[index];
+
+        self.active_requests[index] = self.active_requests[index].saturating_sub(1);
+
+        if success {
+            health.failures = 0;
+
+            match health.ejected_until.take() {
+                Some(_) if !health.check_failed => Some(HealthChange::Up),
+                _ => None,
+            }
+        } else {
+            health.failures += 1;
+
+            if self.health.max_failures == 0 || health.failures < self.health.max_failures {
+                return None;
+            }
+
+            let was_available = health.is_available(now);
+            health.ejected_until = Some(now + self.health.eject_time);
+
+            match was_available {
+                true => Some(HealthChange::Down),
+                false => None,
+            }
+        }
+    }
+
+    /// Records the result of an active health check of the given target.
+    ///
+    /// A target which fails a check is taken out of rotation until it passes one, and passing a check also puts back
+    ///   a target which was taken out because of failed requests. Returns the change in the target's availability, if any.
+    pub fn report_check(&mut self, target: &str, healthy: bool) -> Option<HealthChange> {
+        let now = Instant::now();
+        let index = self.targets.iter().position(|t| t == target)?;
+        let health = &mut self.target_health[index];
+        let was_available = health.is_available(now);
+
+        health.check_failed = !healthy;
+
+        if healthy {
+            health.failures = 0;
+            health.ejected_until = None;
+        }
+
+        match (was_available, health.is_available(now)) {
+            (true, false) => Some(HealthChange::Down),
+            (false, true) => Some(HealthChange::Up),
+            _ => None,
+        }
+    }
+
+    /// Selects targets in a repeating pattern, selecting each target as many times in a row as its weight.
+    fn select_round_robin(&mut self, available: &[bool]) -> Option<usize> {
+        let total_weight: usize = self.weights.iter().sum();
+
+        for _ in 0..total_weight {
+            let position = self.index % total_weight;
+            self.index = (position + 1) % total_weight;
+
+            let target_index = self.target_at(position);
+            if available[target_index] {
+                return Some(target_index);
+            }
+        }
+
+        None
+    }
+
+    /// Selects a random target, with the probability of each target proportional to its weight.
+    fn select_random(&mut self, available: &[bool]) -> Option<usize> {
+        let total_weight: usize = self
+            .weights
+            .iter()
+            .zip(available)
+            .filter(|(_, available)| **available)
+            .map(|(weight, _)| weight)
+            .sum();
+
+        if total_weight == 0 {
+            return None;
+        }
+
+        let mut position = self.lcg.next().unwrap() as usize % total_weight;
+
+        for (target_index, weight) in self.weights.iter().enumerate() {
+            if !available[target_index] {
+                continue;
+            } else if position < *weight {
+                return Some(target_index);
+            }
+
+            position -= weight;
+        }
+
+        None
+    }
+
+    /// Selects the target handling the fewest requests relative to its weight.
+    /// Ties are broken by starting the search at a different target each time.
+    fn select_least_connections(&mut self, available: &[bool]) -> Option<usize> {
+        let len = self.targets.len();
+        let mut best: Option<usize> = None;
+
+        for offset in 0..len {
+            let target_index = (self.index + offset) % len;
+
+            if !available[target_index] {
+                continue;
+            }
+
+            let less_loaded = match best {
+                Some(best) => {
+                    self.active_requests[target_index] * self.weights[best]
+                        < self.active_requests[best] * self.weights[target_index]
                 }
+                None => true,
+            };
+
+            if less_loaded {
+                best = Some(target_index);
+            }
+        }
+
+        self.index = (self.index + 1) % len.max(1);
+
+        best
+    }
+
+    /// Selects the target for the given key using consistent hashing, so that the same key is sent to the same
+    ///   target for as long as it is available, and only the keys of an unavailable target are moved to other targets.
+    fn select_hashed(&self, key: &str, available: &[bool]) -> Option<usize> {
+        let hash = stable_hash(key.as_bytes());
+        let start = self.ring.partition_point(|(point, _)| *point < hash);
+
+        self.ring
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(self.ring.len())
+            .map(|(_, target_index)| *target_index)
+            .find(|target_index| available[*target_index])
+    }
 
-                self.targets[target_index].clone()
+    /// Gets the target at the given position in the sequence where each target is repeated as many times as its weight.
+    fn target_at(&self, mut position: usize) -> usize {
+        for (target_index, weight) in self.weights.iter().enumerate() {
+            if position < *weight {
+                return target_index;
             }
-            LoadBalancerMode::Random => self.targets.choose(&mut self.lcg).unwrap().clone(),
+
+            position -= weight;
         }
+
+        self.targets.len() - 1
     }
 }
 
+/// Builds the hash ring for the given targets, with a number of points for each target proportional to its weight.
+fn build_ring(targets: &[String], weights: &[usize]) -> Vec<(u64, usize)> {
+    let mut ring: Vec<(u64, usize)> = targets
+        .iter()
+        .zip(weights)
+        .enumerate()
+        .flat_map(|(target_index, (target, weight))| {
+            (0..weight * RING_POINTS_PER_WEIGHT).map(move |point| {
+                let hash = stable_hash(format!("{}#{}", target, point).as_bytes());
+                (hash, target_index)
+            })
+        })
+        .collect();
+
+    ring.sort_unstable();
+    ring
+}
+
+/// Hashes the given bytes with the 64-bit FNV-1a algorithm, which is used since its output never changes.
+///
+/// The result is passed through the MurmurHash3 finalizer so that similar keys, such as consecutive addresses,
+///   are spread across the whole hash ring.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
 /// Handles proxy requests.
+///
+/// If the route has a cache configured, responses are served from it where possible.
 pub fn proxy_handler(
     request: Request,
     state: Arc<AppState>,
-    load_balancer: &EqMutex<LoadBalancer>,
-    matches: &str,
+    route: &RouteConfig,
+    route_key: RouteKey,
 ) -> Response {
-    let mut simplified_uri = request.uri.clone();
-
-    for ch in matches.chars() {
-        if ch != '*' {
-            simplified_uri.remove(0);
-        } else {
-            break;
-        }
-    }
-
-    if !simplified_uri.starts_with('/') {
-        simplified_uri.insert(0, '/');
-    }
+    let forwarding = route.forwarding.clone().unwrap_or_default();
+    let simplified_uri = rewrite_path(&request.uri, &route.matches, &forwarding);
 
     // Return error 403 if the address was blacklisted
     if state
-        .config
+        .config()
         .blacklist
         .list
         .contains(&request.address.origin_addr)
@@ -79,16 +366,21 @@ pub fn proxy_handler(
             .with_header(HeaderType::ContentType, "text/html")
             .with_bytes(b"<h1>403 Forbidden</h1>")
     } else {
-        // Gets a load balancer target using the thread-safe `Mutex`
-        let mut load_balancer_lock = load_balancer.lock().unwrap();
-        let target = load_balancer_lock.select_target();
-        drop(load_balancer_lock);
-
         let mut proxied_request = request.clone();
         proxied_request.uri = simplified_uri;
 
-        let target_sock = target.to_socket_addrs().unwrap().next().unwrap();
-        let response = proxy_request(&proxied_request, target_sock, Duration::from_secs(5));
+        // The target is selected when the request is made, since cached responses may be revalidated later
+        let load_balancer = route.load_balancer.clone().unwrap();
+        let fetch_state = state.clone();
+        let fetch = move |request: &Request| {
+            fetch_from_target(request, &fetch_state, &load_balancer, &forwarding)
+        };
+
+        let response = match route.cache {
+            Some(cache) => serve_cached(proxied_request, state.clone(), route_key, cache, fetch),
+            None => fetch(&proxied_request),
+        };
+
         let status: u16 = response.status_code.into();
         let status_string: &str = response.status_code.into();
 
@@ -101,6 +393,112 @@ pub fn proxy_handler(
     }
 }
 
+/// Proxies the request to a target selected by the load balancer, recording whether the target responded.
+///
+/// Returns error 503 if every target is unavailable, or error 502 if the selected target could not be reached.
+fn fetch_from_target(
+    request: &Request,
+    state: &AppState,
+    load_balancer: &EqMutex<LoadBalancer>,
+    forwarding: &ForwardingConfig,
+) -> Response {
+    // Gets a load balancer target using the thread-safe `Mutex`
+    let target = match load_balancer.lock().unwrap().select_target(request) {
+        Some(target) => target,
+        None => {
+            state.logger.warn(&format!(
+                "{}: No proxy targets are available for {}",
+                request.address, request.uri
+            ));
+
+            return Response::empty(StatusCode::ServiceUnavailable)
+                .with_header(HeaderType::ContentType, "text/html")
+                .with_bytes(b"<h1>503 Service Unavailable</h1>");
+        }
+    };
+
+    let forwarded_request = forward_request(request, &forwarding.headers, &target);
+
+    let response = target
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .and_then(|target_sock| {
+            state
+                .connection_pool
+                .send(target_sock, forwarded_request)
+                .ok()
+        })
+        .map(|mut response| {
+            strip_hop_by_hop_headers(&mut response.headers);
+
+            if let Some(pseudonym) = &forwarding.headers.via {
+                add_via(&mut response.headers, &response.version, pseudonym);
+            }
+
+            response
+        });
+
+    let change = load_balancer
+        .lock()
+        .unwrap()
+        .report(&target, response.is_some());
+    log_health_change(state, &target, change);
+
+    response.unwrap_or_else(|| {
+        state.logger.warn(&format!(
+            "{}: Proxy target {} could not be reached",
+            request.address, target
+        ));
+
+        Response::empty(StatusCode::BadGateway)
+            .with_header(HeaderType::ContentType, "text/html")
+            .with_bytes(b"<h1>502 Bad Gateway</h1>")
+    })
+}
+
+/// Rewrites the path of a request to a proxied route to the path which is requested from the target.
+///
+/// The part of the path matched by the route before the wildcard is removed if `strip_prefix` is enabled,
+///   then `add_prefix` is added to the start.
+pub fn rewrite_path(uri: &str, matches: &str, forwarding: &ForwardingConfig) -> String {
+    let mut simplified_uri = uri.to_string();
+
+    if forwarding.strip_prefix {
+        for ch in matches.chars() {
+            if ch != '*' && !simplified_uri.is_empty() {
+                simplified_uri.remove(0);
+            } else {
+                break;
+            }
+        }
+    }
+
+    if !simplified_uri.starts_with('/') {
+        simplified_uri.insert(0, '/');
+    }
+
+    match &forwarding.add_prefix {
+        Some(prefix) => format!("{}{}", prefix.trim_end_matches('/'), simplified_uri),
+        None => simplified_uri,
+    }
+}
+
+/// Logs a change in whether a proxy target is available.
+pub fn log_health_change(state: &AppState, target: &str, change: Option<HealthChange>) {
+    match change {
+        Some(HealthChange::Down) => state.logger.warn(&format!(
+            "Proxy target {} is unavailable and has been taken out of rotation",
+            target
+        )),
+        Some(HealthChange::Up) => state.logger.info(&format!(
+            "Proxy target {} is available and has been put back into rotation",
+            target
+        )),
+        None => (),
+    }
+}
+
 /// A `Mutex` which implements `PartialEq` for testing.
 #[derive(Debug)]
 pub struct EqMutex<T> {
@@ -0,0 +1,621 @@
+//! Provides caching of responses from proxied routes, following the semantics of [RFC 9111](https://datatracker.ietf.org/doc/html/rfc9111).
+
+use crate::config::RouteCacheConfig;
+use crate::server::server::AppState;
+
+use humphrey::http::conditional::{apply_conditional, Validators};
+use humphrey::http::date::DateTime;
+use humphrey::http::headers::{HeaderType, Headers};
+use humphrey::http::method::Method;
+use humphrey::http::{Request, Response, StatusCode};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::SystemTime;
+
+/// The status codes of responses which can be cached, as defined in [RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-15.1).
+const CACHEABLE_STATUS_CODES: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// The status codes of responses which indicate that the backend failed, allowing a stale response to be served instead.
+const ERROR_STATUS_CODES: [u16; 4] = [500, 502, 503, 504];
+
+/// Identifies a proxied route by the index of its host and its index within that host.
+pub type RouteKey = (usize, usize);
+
+/// Represents the cache of responses from proxied routes.
+#[derive(Default)]
+pub struct ProxyCache {
+    routes: HashMap<RouteKey, RouteCache>,
+}
+
+/// Represents the cached responses of a single route, keyed by the effective target URI of the request.
+///
+/// Since a route can serve several hosts, the URI includes the request's host as well as its path and query. Each URI has a short list of variants, one for each combination of the request headers named by the
+///   `Vary` header. When the route is over its size limit, the least recently used responses are removed first.
+#[derive(Default)]
+struct RouteCache {
+    size: usize,
+    clock: AtomicU64,
+    responses: HashMap<String, Vec<CacheEntry>>,
+}
+
+/// Represents a cached response along with when it was last used.
+struct CacheEntry {
+    response: CachedResponse,
+    last_used: AtomicU64,
+}
+
+/// Represents a cached response from a proxied route.
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    /// The effective target URI of the request which the response was for.
+    pub uri: String,
+    /// The request headers named by the `Vary` header of the response, along with their values in the request.
+    pub vary: Vec<(HeaderType, Option<String>)>,
+    /// The status code of the response.
+    pub status_code: StatusCode,
+    /// The headers of the response.
+    pub headers: Headers,
+    /// The body of the response.
+    pub body: Vec<u8>,
+    /// The time at which the response was received, as a UNIX timestamp.
+    pub response_time: u64,
+    /// The age of the response when it was received, in seconds.
+    pub initial_age: u64,
+    /// How long the response is fresh for, in seconds.
+    pub freshness_lifetime: u64,
+    /// How long after becoming stale the response can be served while it is revalidated, in seconds.
+    pub stale_while_revalidate: u64,
+    /// How long after becoming stale the response can be served if the backend fails, in seconds.
+    pub stale_if_error: u64,
+    /// Whether the response must not be served once stale.
+    pub must_revalidate: bool,
+    /// Whether the response is being revalidated in the background.
+    pub revalidating: bool,
+}
+
+/// The parsed directives of a `Cache-Control` header.
+struct CacheControl(Vec<(String, Option<String>)>);
+
+impl ProxyCache {
+    /// Finds the cached response to the request, if there is one, and marks it as recently used.
+    pub fn get(&self, route: RouteKey, request: &Request) -> Option<&CachedResponse> {
+        let route_cache = self.routes.get(&route)?;
+        let entry = route_cache
+            .responses
+            .get(&request_uri(request))?
+            .iter()
+            .find(|entry| entry.response.matches(request))?;
+
+        entry.last_used.store(route_cache.tick(), Ordering::Relaxed);
+
+        Some(&entry.response)
+    }
+
+    /// Stores a response in the cache, replacing any previous response to the same request.
+    /// The least recently used responses of the route are removed until it is within its size limit.
+    ///
+    /// Does nothing if the response is larger than the size limit.
+    pub fn set(&mut self, route: RouteKey, size_limit: usize, response: CachedResponse) {
+        if response.body.len() > size_limit {
+            return;
+        }
+
+        let route_cache = self.routes.entry(route).or_default();
+
+        if let Some(variants) = route_cache.responses.get_mut(&response.uri) {
+            if let Some(index) = variants
+                .iter()
+                .position(|entry| entry.response.vary == response.vary)
+            {
+                route_cache.size -= variants.remove(index).response.body.len();
+            }
+        }
+
+        while route_cache.size + response.body.len() > size_limit {
+            route_cache.evict();
+        }
+
+        let entry = CacheEntry {
+            last_used: AtomicU64::new(route_cache.tick()),
+            response,
+        };
+
+        route_cache.size += entry.response.body.len();
+        route_cache
+            .responses
+            .entry(entry.response.uri.clone())
+            .or_default()
+            .push(entry);
+    }
+
+    /// Removes all cached responses to the given effective target URI of a route.
+    pub fn invalidate(&mut self, route: RouteKey, uri: &str) {
+        if let Some(route_cache) = self.routes.get_mut(&route) {
+            if let Some(variants) = route_cache.responses.remove(uri) {
+                route_cache.size -= variants
+                    .iter()
+                    .map(|entry| entry.response.body.len())
+                    .sum::<usize>();
+            }
+        }
+    }
+
+    /// Updates the cache with a response from the backend to the request, returning the response to send to the client.
+    ///
+    /// A `304 Not Modified` response refreshes the cached response, which is then returned in its place. Other
+    ///   responses replace the cached response if they can be stored, and otherwise remove it, unless the backend failed.
+    pub fn update(
+        &mut self,
+        route: RouteKey,
+        request: &Request,
+        response: Response,
+        config: &RouteCacheConfig,
+    ) -> Response {
+        let now = now();
+        let uri = request_uri(request);
+
+        if let Some(existing) = self.get_mut(route, &uri, request) {
+            existing.revalidating = false;
+
+            if response.status_code == StatusCode::NotModified {
+                existing.refresh(&response, now, config);
+                return existing.to_response(now);
+            }
+        }
+
+        let status: u16 = response.status_code.into();
+
+        match CachedResponse::new(request, &response, now, config) {
+            Some(cached) => self.set(route, config.size_limit, cached),
+            None if !ERROR_STATUS_CODES.contains(&status) => self.invalidate(route, &uri),
+            None => (),
+        }
+
+        response
+    }
+
+    /// Marks the cached response to the request as being revalidated, returning `false` if it already was.
+    fn start_revalidation(&mut self, route: RouteKey, request: &Request) -> bool {
+        match self.get_mut(route, &request_uri(request), request) {
+            Some(cached) if !cached.revalidating => {
+                cached.revalidating = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Finds the cached response to the request with the given effective target URI, if there is one.
+    fn get_mut(
+        &mut self,
+        route: RouteKey,
+        uri: &str,
+        request: &Request,
+    ) -> Option<&mut CachedResponse> {
+        self.routes
+            .get_mut(&route)?
+            .responses
+            .get_mut(uri)?
+            .iter_mut()
+            .map(|entry| &mut entry.response)
+            .find(|cached| cached.matches(request))
+    }
+}
+
+impl RouteCache {
+    /// Advances the route's clock, returning the time to record as when a response was last used.
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Removes the least recently used response.
+    fn evict(&mut self) {
+        let oldest = self
+            .responses
+            .iter()
+            .flat_map(|(uri, variants)| {
+                variants.iter().enumerate().map(move |(index, entry)| {
+                    (entry.last_used.load(Ordering::Relaxed), uri, index)
+                })
+            })
+            .min()
+            .map(|(_, uri, index)| (uri.clone(), index));
+
+        if let Some((uri, index)) = oldest {
+            let variants = self.responses.get_mut(&uri).unwrap();
+            self.size -= variants.remove(index).response.body.len();
+
+            if variants.is_empty() {
+                self.responses.remove(&uri);
+            }
+        }
+    }
+}
+
+impl CachedResponse {
+    /// Creates a cached response from the backend's response to the request, or returns `None` if it cannot be stored.
+    ///
+    /// Only responses to `GET` requests with explicit freshness information from `Cache-Control` or `Expires` are stored.
+    pub fn new(
+        request: &Request,
+        response: &Response,
+        now: u64,
+        config: &RouteCacheConfig,
+    ) -> Option<Self> {
+        let status: u16 = response.status_code.into();
+        let request_directives = CacheControl::from(&request.headers);
+        let directives = CacheControl::from(&response.headers);
+
+        if request.method != Method::Get
+            || !CACHEABLE_STATUS_CODES.contains(&status)
+            || request_directives.has("no-store")
+            || directives.has("no-store")
+            || directives.has("no-cache")
+            || directives.has("private")
+            || response.headers.get(HeaderType::SetCookie).is_some()
+        {
+            return None;
+        }
+
+        // Responses to authenticated requests are only shared if the backend explicitly allows it
+        if request.headers.get(HeaderType::Authorization).is_some()
+            && !directives.has("public")
+            && !directives.has("s-maxage")
+            && !directives.has("must-revalidate")
+        {
+            return None;
+        }
+
+        let vary = response
+            .headers
+            .get_all(HeaderType::Vary)
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                if name == "*" {
+                    None
+                } else {
+                    let header = HeaderType::from(name);
+                    let value = header_value(&request.headers, &header);
+                    Some((header, value))
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut cached = Self {
+            uri: request_uri(request),
+            vary,
+            status_code: response.status_code,
+            headers: response.headers.clone(),
+            body: response.body.clone(),
+            response_time: now,
+            initial_age: 0,
+            freshness_lifetime: 0,
+            stale_while_revalidate: 0,
+            stale_if_error: 0,
+            must_revalidate: false,
+            revalidating: false,
+        };
+
+        cached.update_freshness(now, config)?;
+
+        Some(cached)
+    }
+
+    /// Gets the current age of the response in seconds.
+    pub fn age(&self, now: u64) -> u64 {
+        self.initial_age + now.saturating_sub(self.response_time)
+    }
+
+    /// Returns whether the response is fresh.
+    pub fn is_fresh(&self, now: u64) -> bool {
+        self.age(now) < self.freshness_lifetime
+    }
+
+    /// Returns whether the response can be served while it is revalidated in the background.
+    pub fn can_serve_while_revalidating(&self, now: u64) -> bool {
+        !self.must_revalidate
+            && self.age(now) < self.freshness_lifetime + self.stale_while_revalidate
+    }
+
+    /// Returns whether the response can be served because the backend failed.
+    pub fn can_serve_on_error(&self, now: u64) -> bool {
+        !self.must_revalidate && self.age(now) < self.freshness_lifetime + self.stale_if_error
+    }
+
+    /// Converts the cached response into a response to send to the client, with its current `Age`.
+    pub fn to_response(&self, now: u64) -> Response {
+        let mut response = Response::new(self.status_code, &self.body);
+        response.headers = self.headers.clone();
+        response.headers.remove(HeaderType::Age);
+        response
+            .headers
+            .add(HeaderType::Age, self.age(now).to_string());
+
+        response
+    }
+
+    /// Adds the validators of the cached response to the request, so the backend can respond with
+    ///   `304 Not Modified` if it is still current.
+    pub fn add_validators(&self, request: &mut Request) {
+        request.headers.remove(HeaderType::IfNoneMatch);
+        request.headers.remove(HeaderType::IfModifiedSince);
+
+        if let Some(etag) = self.headers.get(HeaderType::ETag) {
+            request.headers.add(HeaderType::IfNoneMatch, etag);
+        }
+
+        if let Some(last_modified) = self.headers.get(HeaderType::LastModified) {
+            request
+                .headers
+                .add(HeaderType::IfModifiedSince, last_modified);
+        }
+    }
+
+    /// Returns whether this is the cached response to a request with the given headers.
+    /// The target URI of the request is not checked, since responses are already found by it.
+    fn matches(&self, request: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(header, value)| header_value(&request.headers, header) == *value)
+    }
+
+    /// Refreshes the cached response with the headers of a `304 Not Modified` response from the backend.
+    fn refresh(&mut self, response: &Response, now: u64, config: &RouteCacheConfig) {
+        for header in response.headers.iter() {
+            if header.name != HeaderType::ContentLength && header.name != HeaderType::Connection {
+                self.headers.remove(&header.name);
+            }
+        }
+
+        for header in response.headers.iter() {
+            if header.name != HeaderType::ContentLength && header.name != HeaderType::Connection {
+                self.headers.push(header);
+            }
+        }
+
+        self.response_time = now;
+
+        if self.update_freshness(now, config).is_none() {
+            // The backend no longer gives the response a lifetime, so it must be revalidated next time
+            self.freshness_lifetime = 0;
+        }
+    }
+
+    /// Calculates the age and freshness of the response from its headers, as described in
+    ///   [RFC 9111](https://datatracker.ietf.org/doc/html/rfc9111#section-4.2).
+    ///
+    /// Returns `None` if the response has no explicit freshness information.
+    fn update_freshness(&mut self, now: u64, config: &RouteCacheConfig) -> Option<()> {
+        let directives = CacheControl::from(&self.headers);
+
+        let date = self
+            .headers
+            .get(HeaderType::Date)
+            .and_then(|date| DateTime::try_from(date).ok())
+            .map(|date| date.get_timestamp().max(0) as u64);
+        let age = self
+            .headers
+            .get(HeaderType::Age)
+            .and_then(|age| age.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+
+        let apparent_age = date.map_or(0, |date| now.saturating_sub(date));
+
+        self.freshness_lifetime = if let Some(s_maxage) = directives.seconds("s-maxage") {
+            s_maxage
+        } else if let Some(max_age) = directives.seconds("max-age") {
+            max_age
+        } else {
+            // An invalid `Expires` header means that the response has already expired
+            let expires = self.headers.get(HeaderType::Expires)?;
+
+            DateTime::try_from(expires).map_or(0, |expires| {
+                (expires.get_timestamp().max(0) as u64).saturating_sub(date.unwrap_or(now))
+            })
+        };
+
+        self.initial_age = apparent_age.max(age);
+        self.stale_while_revalidate = directives
+            .seconds("stale-while-revalidate")
+            .unwrap_or(config.stale_while_revalidate);
+        self.stale_if_error = directives
+            .seconds("stale-if-error")
+            .unwrap_or(config.stale_if_error);
+        self.must_revalidate = directives.has("must-revalidate")
+            || directives.has("proxy-revalidate")
+            || directives.has("s-maxage");
+
+        Some(())
+    }
+}
+
+impl CacheControl {
+    /// Returns whether the directive is present.
+    fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|(directive, _)| directive == name)
+    }
+
+    /// Gets the value of a directive which specifies a number of seconds.
+    fn seconds(&self, name: &str) -> Option<u64> {
+        self.0
+            .iter()
+            .find(|(directive, _)| directive == name)
+            .and_then(|(_, value)| value.as_ref())
+            .and_then(|value| value.parse().ok())
+    }
+}
+
+impl From<&Headers> for CacheControl {
+    fn from(headers: &Headers) -> Self {
+        let directives = headers
+            .get_all(HeaderType::CacheControl)
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .filter_map(|directive| {
+                let mut parts = directive.splitn(2, '=');
+                let name = parts.next()?.trim().to_ascii_lowercase();
+                let value = parts
+                    .next()
+                    .map(|value| value.trim().trim_matches('"').to_string());
+
+                if name.is_empty() {
+                    None
+                } else {
+                    Some((name, value))
+                }
+            })
+            .collect();
+
+        Self(directives)
+    }
+}
+
+/// Serves a request to a proxied route from the route's cache where possible, using `fetch` to send requests to the backend.
+///
+/// Fresh responses are served from the cache. Stale responses are served while they are revalidated in the background
+///   within their `stale-while-revalidate` time, and are otherwise revalidated before being served. If the backend fails,
+///   stale responses are served within their `stale-if-error` time.
+pub fn serve_cached<F>(
+    request: Request,
+    state: Arc<AppState>,
+    route: RouteKey,
+    config: RouteCacheConfig,
+    fetch: F,
+) -> Response
+where
+    F: Fn(&Request) -> Response + Send + 'static,
+{
+    if request.method != Method::Get && request.method != Method::Head {
+        let response = fetch(&request);
+        let status: u16 = response.status_code.into();
+
+        // Unsafe methods which succeed may have changed the resource, so the cached response is invalidated
+        if (200..400).contains(&status) {
+            let mut cache = state.proxy_cache.write().unwrap();
+            cache.invalidate(route, &request_uri(&request));
+        }
+
+        return response;
+    }
+
+    let directives = CacheControl::from(&request.headers);
+    let now = now();
+
+    let cached = if directives.has("no-cache") || directives.seconds("max-age") == Some(0) {
+        None
+    } else {
+        let cache = state.proxy_cache.read().unwrap();
+        cache.get(route, &request).cloned()
+    };
+
+    let cached = match cached {
+        Some(cached) => cached,
+        None if request.method == Method::Get => {
+            let response = fetch(&request);
+            let mut cache = state.proxy_cache.write().unwrap();
+            return cache.update(route, &request, response, &config);
+        }
+        None => return fetch(&request),
+    };
+
+    if cached.is_fresh(now) {
+        return client_response(&request, cached.to_response(now));
+    }
+
+    let mut revalidation = request.clone();
+    revalidation.method = Method::Get;
+    cached.add_validators(&mut revalidation);
+
+    if cached.can_serve_while_revalidating(now) {
+        let revalidate = {
+            let mut cache = state.proxy_cache.write().unwrap();
+            cache.start_revalidation(route, &request)
+        };
+
+        if revalidate {
+            let state = state.clone();
+
+            spawn(move || {
+                let response = fetch(&revalidation);
+                let mut cache = state.proxy_cache.write().unwrap();
+                cache.update(route, &revalidation, response, &config);
+            });
+        }
+
+        return client_response(&request, cached.to_response(now));
+    }
+
+    let response = fetch(&revalidation);
+    let status: u16 = response.status_code.into();
+
+    if ERROR_STATUS_CODES.contains(&status) && cached.can_serve_on_error(now) {
+        return client_response(&request, cached.to_response(now));
+    }
+
+    let response = {
+        let mut cache = state.proxy_cache.write().unwrap();
+        cache.update(route, &revalidation, response, &config)
+    };
+
+    client_response(&request, response)
+}
+
+/// Applies the client's conditional request headers to a response which was not fetched with them.
+fn client_response(request: &Request, response: Response) -> Response {
+    let validators = Validators {
+        etag: response
+            .headers
+            .get(HeaderType::ETag)
+            .map(|etag| etag.to_string()),
+        last_modified: response
+            .headers
+            .get(HeaderType::LastModified)
+            .and_then(|date| DateTime::try_from(date).ok())
+            .map(|date| date.get_timestamp()),
+    };
+
+    apply_conditional(request, response, &validators)
+}
+
+/// Gets the effective target URI of the request, which identifies the resource it is for, as described in
+///   [RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-7.1).
+fn request_uri(request: &Request) -> String {
+    let scheme = if request.https { "https" } else { "http" };
+    let host = request
+        .headers
+        .get(HeaderType::Host)
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    if request.query.is_empty() {
+        format!("{}://{}{}", scheme, host, request.uri)
+    } else {
+        format!("{}://{}{}?{}", scheme, host, request.uri, request.query)
+    }
+}
+
+/// Gets the combined value of the headers with the given name.
+fn header_value(headers: &Headers, header: &HeaderType) -> Option<String> {
+    let values = headers.get_all(header);
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
+}
+
+/// Gets the current time as a UNIX timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
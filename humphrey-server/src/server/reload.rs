@@ -0,0 +1,228 @@
+//! Provides functionality for reloading the configuration while the server is running.
+//!
+//! The configuration is reloaded when the process receives `SIGHUP` (on Unix), or when the configuration file
+//!   changes if `server.watch` is enabled.
+
+use crate::cache::Cache;
+use crate::config::{Config, ConfigSource};
+use crate::health::start_health_checks;
+use crate::proxy_cache::ProxyCache;
+use crate::server::server::AppState;
+#[cfg(feature = "tls")]
+use crate::tls::{cert_resolver, reload_certs};
+
+use std::fs::metadata;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::{Duration, SystemTime};
+
+/// How often to check whether the configuration should be reloaded.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Set by the signal handler when a reload has been requested.
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Starts a thread which reloads the configuration when requested.
+/// Does nothing if the configuration was not loaded from a file.
+pub fn watch_config(state: Arc<AppState>) {
+    let source = state.config().source;
+    let path = match source.path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    install_signal_handler();
+
+    spawn(move || {
+        let mut modified = modified_time(&path);
+        #[cfg(feature = "tls")]
+        let mut certs_modified = certs_modified_times(&state);
+
+        loop {
+            sleep(POLL_INTERVAL);
+
+            #[cfg(feature = "tls")]
+            {
+                let current_certs_modified = certs_modified_times(&state);
+                if current_certs_modified != certs_modified && state.config().watch {
+                    state
+                        .logger
+                        .info("TLS certificate files changed, reloading certificates");
+                    reload_certs(&state);
+                }
+                certs_modified = current_certs_modified;
+            }
+
+            let current_modified = modified_time(&path);
+            let changed = current_modified != modified && state.config().watch;
+            modified = current_modified;
+
+            if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+                state
+                    .logger
+                    .info("Received SIGHUP, reloading configuration");
+            } else if changed {
+                state
+                    .logger
+                    .info("Configuration file changed, reloading configuration");
+            } else {
+                continue;
+            }
+
+            match reload(&state, &path, source) {
+                Ok(()) => {
+                    start_health_checks(&state);
+
+                    #[cfg(feature = "tls")]
+                    {
+                        certs_modified = certs_modified_times(&state);
+                    }
+                }
+                Err(e) => {
+                    state
+                        .logger
+                        .error(&format!("Could not reload configuration: {}", e));
+                    state.logger.error("Keeping the current configuration");
+                }
+            }
+        }
+    });
+}
+
+/// Reloads the configuration from the given path, replacing the current configuration if the new one is valid.
+/// If it is not, the current configuration is kept and the error is returned.
+///
+/// Settings which are only used when the server starts, such as the address and port, are kept from the current
+///   configuration and a warning is logged if they were changed.
+pub fn reload(state: &AppState, path: &str, source: ConfigSource) -> Result<(), String> {
+    let mut config = Config::load_from(path, source)?;
+    let current = state.config();
+
+    for setting in keep_startup_settings(&current, &mut config) {
+        state.logger.warn(&format!(
+            "Changes to {} will not take effect until the server is restarted",
+            setting
+        ));
+    }
+
+    // Certificates are loaded before anything is replaced so that invalid certificates keep the current configuration
+    #[cfg(feature = "tls")]
+    let resolver = match config.tls_config {
+        Some(_) => Some(cert_resolver(&config).map_err(|e| e.to_string())?),
+        None => None,
+    };
+
+    state.logger.set_level(config.logging.level.clone());
+
+    // Cached items are identified by the index of their host, which may have changed, so the caches are rebuilt
+    let mut cache = state.cache.write().unwrap();
+    let mut proxy_cache = state.proxy_cache.write().unwrap();
+    state
+        .logger
+        .info(&format!("Clearing cache ({})", cache.stats()));
+    *cache = Cache::from(&config);
+    *proxy_cache = ProxyCache::default();
+    *state.config.write().unwrap() = Arc::new(config);
+    drop(proxy_cache);
+    drop(cache);
+
+    // Idle connections to targets which are no longer used would otherwise stay open
+    state.connection_pool.clear();
+
+    #[cfg(feature = "tls")]
+    if let Some(resolver) = resolver {
+        state.cert_resolver.replace(resolver);
+    }
+
+    state
+        .logger
+        .info(&format!("Configuration reloaded from {}", path));
+
+    Ok(())
+}
+
+/// Replaces the settings of the new configuration which cannot change while the server is running with those of
+///   the current configuration, returning the names of those which were changed.
+fn keep_startup_settings(current: &Config, new: &mut Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+
+    macro_rules! keep {
+        ($name:expr, $($field:ident).+) => {
+            if new.$($field).+ != current.$($field).+ {
+                new.$($field).+ = current.$($field).+.clone();
+                changed.push($name);
+            }
+        };
+    }
+
+    keep!("server.address", address);
+    keep!("server.port", port);
+    keep!("server.listen", listeners);
+    keep!("server.threads", threads);
+    keep!("server.timeout", connection_timeout);
+    keep!("server.websocket", default_websocket_proxy);
+    keep!("server.compression", compression);
+    keep!("server.log.console", logging.console);
+    keep!("server.log.file", logging.file);
+
+    // Certificates can change, but TLS cannot be turned on or off and forcing HTTPS and client authentication
+    //   cannot change
+    #[cfg(feature = "tls")]
+    if new
+        .tls_config
+        .as_ref()
+        .map(|tls| (tls.force, &tls.client_auth))
+        != current
+            .tls_config
+            .as_ref()
+            .map(|tls| (tls.force, &tls.client_auth))
+    {
+        new.tls_config = current.tls_config.clone();
+        changed.push("server.tls");
+    }
+
+    #[cfg(feature = "plugins")]
+    keep!("server.plugins", plugins);
+
+    changed
+}
+
+/// Gets the times at which the certificate and key files currently in use were last modified.
+#[cfg(feature = "tls")]
+fn certs_modified_times(state: &AppState) -> Vec<Option<SystemTime>> {
+    state
+        .cert_resolver
+        .paths()
+        .iter()
+        .map(|path| modified_time(path))
+        .collect()
+}
+
+/// Gets the time at which the file at the given path was last modified, if available.
+fn modified_time(path: &str) -> Option<SystemTime> {
+    metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Requests a reload of the configuration when the process receives `SIGHUP`.
+#[cfg(unix)]
+fn install_signal_handler() {
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    /// The number of the `SIGHUP` signal, which is the same on all Unix platforms.
+    const SIGHUP: i32 = 1;
+
+    extern "C" fn handle_sighup(_: i32) {
+        RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    unsafe {
+        signal(SIGHUP, handle_sighup);
+    }
+}
+
+/// Signals are not supported on this platform, so the configuration can only be reloaded when the file changes.
+#[cfg(not(unix))]
+fn install_signal_handler() {}
@@ -1,27 +1,37 @@
 //! Provides the core server functionality and manages the underlying Humphrey app.
 
+use humphrey::http::headers::HeaderType;
+use humphrey::http::pool::ConnectionPool;
 use humphrey::http::{Request, Response, StatusCode};
-use humphrey::monitor::event::ToEventMask;
+use humphrey::krauss::wildcard_match;
+use humphrey::listener::Listener;
 use humphrey::monitor::MonitorConfig;
+use humphrey::route::find_route;
 use humphrey::stream::Stream;
-use humphrey::{App, SubApp};
+#[cfg(feature = "tls")]
+use humphrey::tls::CertResolver;
+use humphrey::App;
 
 #[cfg(feature = "plugins")]
 use crate::plugins::manager::PluginManager;
 #[cfg(feature = "plugins")]
 use crate::plugins::plugin::PluginLoadResult;
-#[cfg(feature = "plugins")]
+#[cfg(any(feature = "plugins", feature = "tls"))]
 use std::process::exit;
 use std::thread::spawn;
 
 use crate::cache::Cache;
-use crate::config::{BlacklistMode, Config, ConfigSource, HostConfig, RouteType};
-use crate::logger::{monitor_thread, Logger};
-use crate::proxy::proxy_handler;
-use crate::r#static::{directory_handler, file_handler, redirect_handler};
+use crate::config::{BlacklistMode, Config, ConfigSource, ListenConfig, ListenMode, RouteType};
+use crate::health::start_health_checks;
+use crate::logger::{monitor_thread, Logger, INTERNAL_MASK_DEBUG};
+use crate::proxy::{proxy_handler, PROXY_CONNECT_TIMEOUT};
+use crate::proxy_cache::ProxyCache;
+use crate::r#static::{directory_handler, file_handler, not_found, redirect_handler};
+use crate::reload::watch_config;
+#[cfg(feature = "tls")]
+use crate::tls::{cert_resolver, client_verifier};
+use crate::websocket_proxy::{default_websocket_proxy_handler, websocket_proxy_handler};
 
-use std::error::Error;
-use std::io::{Read, Write};
 use std::net::TcpStream;
 use std::sync::mpsc::channel;
 use std::sync::{Arc, RwLock};
@@ -30,11 +40,19 @@ use std::sync::{Arc, RwLock};
 /// Includes the target directory, cache state, and the logger.
 pub struct AppState {
     /// The app's configuration.
-    pub config: Config,
+    /// This is replaced when the configuration is reloaded, so use `AppState::config` to get a consistent snapshot of it.
+    pub config: RwLock<Arc<Config>>,
     /// The app's cache.
     pub cache: RwLock<Cache>,
+    /// The cache of responses from proxied routes.
+    pub proxy_cache: RwLock<ProxyCache>,
+    /// The pool of keep-alive connections to proxy targets.
+    pub connection_pool: ConnectionPool,
     /// The app's logger.
     pub logger: Logger,
+    /// Selects the TLS certificate for each connection, and can be updated while the server is running.
+    #[cfg(feature = "tls")]
+    pub cert_resolver: Arc<CertResolver>,
     /// The app's plugin manager.
     #[cfg(feature = "plugins")]
     pub plugin_manager: RwLock<PluginManager>,
@@ -45,60 +63,86 @@ impl From<Config> for AppState {
         let cache = RwLock::new(Cache::from(&config));
         let logger = Logger::from(&config);
         Self {
-            config,
+            config: RwLock::new(Arc::new(config)),
             cache,
+            proxy_cache: RwLock::new(ProxyCache::default()),
+            connection_pool: ConnectionPool::new().with_connect_timeout(PROXY_CONNECT_TIMEOUT),
             logger,
+            #[cfg(feature = "tls")]
+            cert_resolver: Arc::new(CertResolver::new()),
             #[cfg(feature = "plugins")]
             plugin_manager: RwLock::new(PluginManager::default()),
         }
     }
 }
 
+impl AppState {
+    /// Gets the current configuration.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+}
+
 /// Main function for the static server.
 pub fn main(config: Config) {
     let connection_timeout = config.connection_timeout;
     let source = config.source;
+    let compression = match config.compression.enabled {
+        true => Some(config.compression.min_size),
+        false => None,
+    };
 
     let (monitor_tx, monitor_rx) = channel();
-    let mask = config.logging.level.to_event_mask();
 
-    let mut app: App<AppState> = App::new_with_config(config.threads, AppState::from(config))
+    // Hosts and routes are matched by the handlers themselves so that they can change when the configuration is reloaded
+    let app: App<AppState> = App::new_with_config(config.threads, AppState::from(config))
         .with_connection_condition(verify_connection)
         .with_connection_timeout(connection_timeout)
-        .with_monitor(MonitorConfig::new(monitor_tx).with_subscription_to(mask));
+        .with_compression(compression)
+        .with_monitor(MonitorConfig::new(monitor_tx).with_subscription_to(INTERNAL_MASK_DEBUG))
+        .with_route("/*", request_handler)
+        .with_websocket_route("/*", websocket_handler);
 
     let state = app.get_state();
+    let config = state.config();
     let monitor_state = app.get_state();
     spawn(move || monitor_thread(monitor_rx, monitor_state));
 
-    let top_level_routes = init_app_routes(&state.config.default_host, 0);
-
-    app = app.with_default_subapp(top_level_routes);
-
-    for (host_index, host) in state.config.hosts.iter().enumerate() {
-        app = app.with_host(&host.matches, init_app_routes(host, host_index + 1));
-    }
-
     #[cfg(feature = "tls")]
-    if let Some(tls_config) = &state.config.tls_config {
-        app = app
-            .with_cert(&tls_config.cert_file, &tls_config.key_file)
-            .with_forced_https(tls_config.force);
+    let app = match &config.tls_config {
+        Some(tls_config) => {
+            match cert_resolver(&config) {
+                Ok(resolver) => state.cert_resolver.replace(resolver),
+                Err(e) => {
+                    state
+                        .logger
+                        .error(&format!("Could not load TLS certificates: {}", e));
+                    exit(1);
+                }
+            }
 
-        if state.config.port != 443 {
-            state.logger.warn(&format!(
-                "HTTPS is typically served on port 443, so your setting of {} may cause issues.",
-                state.config.port,
-            ));
+            let app = app.with_cert_resolver(state.cert_resolver.clone());
+
+            match &tls_config.client_auth {
+                Some(client_auth) => match client_verifier(client_auth) {
+                    Ok(verifier) => app.with_client_auth(verifier),
+                    Err(e) => {
+                        state
+                            .logger
+                            .error(&format!("Could not load TLS client CA certificates: {}", e));
+                        exit(1);
+                    }
+                },
+                None => app,
+            }
         }
-    }
+        None => app,
+    };
 
-    #[cfg(feature = "plugins")]
-    {
-        app = app.with_websocket_route("/*", catch_all_websocket_route);
-    }
+    let app = config.listeners.iter().fold(app, |app, listener| {
+        app.with_listener(listener_for(listener))
+    });
 
-    let addr = format!("{}:{}", state.config.address, state.config.port);
     let logger = &state.logger;
 
     match source {
@@ -114,53 +158,90 @@ pub fn main(config: Config) {
         }
     }
 
-    logger.debug(&format!("Configuration: {:?}", state.config));
+    logger.debug(&format!("Configuration: {:?}", config));
 
     logger.info("Starting server");
 
     #[cfg(feature = "plugins")]
-    if let Ok(plugins_count) = load_plugins(&state.config, state.clone()) {
+    if let Ok(plugins_count) = load_plugins(&config, state.clone()) {
         logger.info(&format!("Loaded {} plugins", plugins_count))
     } else {
         exit(1);
     };
 
-    logger.info(&format!("Running at {}", addr));
+    start_health_checks(&state);
+    watch_config(state.clone());
 
-    #[cfg(feature = "tls")]
-    if state.config.tls_config.is_some() {
-        app.run_tls(addr).unwrap();
-    } else {
-        app.run(addr).unwrap();
+    for listener in &config.listeners {
+        match listener.mode {
+            ListenMode::Http => logger.info(&format!("Running at {}", listener.address)),
+            #[cfg(feature = "tls")]
+            ListenMode::Https => {
+                logger.info(&format!("Running at {} with HTTPS", listener.address))
+            }
+            ListenMode::Redirect(port) => logger.info(&format!(
+                "Redirecting {} to HTTPS on port {}",
+                listener.address, port
+            )),
+        }
     }
 
-    #[cfg(not(feature = "tls"))]
-    app.run(addr).unwrap();
+    app.run_listeners().unwrap();
 }
 
-fn init_app_routes(host: &HostConfig, host_index: usize) -> SubApp<AppState> {
-    let mut subapp: SubApp<AppState> = SubApp::new();
-
-    for (route_index, route) in host.routes.iter().enumerate() {
-        subapp = subapp.with_route(&route.matches, move |request, state| {
-            request_handler(request, state, host_index, route_index)
-        });
+/// Creates the listener for a configured address.
+fn listener_for(config: &ListenConfig) -> Listener {
+    match config.mode {
+        ListenMode::Http => Listener::http(&config.address),
+        #[cfg(feature = "tls")]
+        ListenMode::Https => Listener::https(&config.address),
+        ListenMode::Redirect(port) => Listener::redirect(&config.address, port),
+    }
+}
 
-        if route.websocket_proxy.is_some() {
-            subapp = subapp.with_websocket_route(&route.matches, move |request, stream, state| {
-                websocket_handler(request, stream, state, host_index, route_index)
-            });
+/// Finds the host and route which should handle the request, setting the request's path parameters.
+/// Routes of the host matching the `Host` header are tried first, followed by those of the default host.
+///
+/// If `websocket` is true, only routes which proxy WebSocket connections are considered, either to a WebSocket
+///   target or through the route's load balancer.
+fn match_route(config: &Config, request: &mut Request, websocket: bool) -> Option<(usize, usize)> {
+    let host = request.headers.get(&HeaderType::Host).and_then(|host| {
+        config
+            .hosts
+            .iter()
+            .position(|h| wildcard_match(&h.matches, host))
+            .map(|index| index + 1)
+    });
+
+    for host in host.into_iter().chain(std::iter::once(0)) {
+        let routes = &config.get_host(host).routes;
+        let candidates: Vec<usize> = (0..routes.len())
+            .filter(|&index| {
+                !websocket
+                    || routes[index].websocket_proxy.is_some()
+                    || routes[index].load_balancer.is_some()
+            })
+            .collect();
+
+        if let Some((index, params)) = find_route(
+            candidates.iter().map(|&index| &routes[index].matches),
+            &request.uri,
+        ) {
+            request.params = params;
+            return Some((host, candidates[index]));
         }
     }
 
-    subapp
+    None
 }
 
 /// Verifies that the client is allowed to connect by checking with the blacklist config.
 fn verify_connection(stream: &mut TcpStream, state: Arc<AppState>) -> bool {
     if let Ok(address) = stream.peer_addr() {
-        if state.config.blacklist.mode == BlacklistMode::Block
-            && state.config.blacklist.list.contains(&address.ip())
+        let config = state.config();
+
+        if config.blacklist.mode == BlacklistMode::Block
+            && config.blacklist.list.contains(&address.ip())
         {
             state.logger.warn(&format!(
                 "{}: Blacklisted IP attempted to connect",
@@ -176,19 +257,36 @@ fn verify_connection(stream: &mut TcpStream, state: Arc<AppState>) -> bool {
     true
 }
 
+/// Dispatches the request to the handler of the matching route.
+fn request_handler(mut request: Request, state: Arc<AppState>) -> Response {
+    let config = state.config();
+
+    match match_route(&config, &mut request, false) {
+        Some((host, route)) => route_request_handler(request, state.clone(), &config, host, route),
+        None => {
+            state.logger.warn(&format!(
+                "{}: 404 Not Found {}",
+                request.address, request.uri
+            ));
+            not_found()
+        }
+    }
+}
+
 #[cfg(feature = "plugins")]
-fn request_handler(
+fn route_request_handler(
     mut request: Request,
     state: Arc<AppState>,
+    config: &Config,
     host: usize,
     route: usize,
 ) -> Response {
     let plugins = state.plugin_manager.read().unwrap();
 
-    let route_config = state.config.get_route(host, route);
+    let route_config = config.get_route(host, route);
     let mut response = plugins
         .on_request(&mut request, state.clone(), route_config) // If the plugin overrides the response, return it
-        .unwrap_or_else(|| inner_request_handler(request, state.clone(), host, route)); // If no plugin overrides the response, generate it in the normal way
+        .unwrap_or_else(|| inner_request_handler(request, state.clone(), config, host, route)); // If no plugin overrides the response, generate it in the normal way
 
     // Pass the response to plugins before it is sent to the client
     plugins.on_response(&mut response, state.clone(), route_config);
@@ -197,17 +295,24 @@ fn request_handler(
 }
 
 #[cfg(not(feature = "plugins"))]
-fn request_handler(request: Request, state: Arc<AppState>, host: usize, route: usize) -> Response {
-    inner_request_handler(request, state, host, route)
+fn route_request_handler(
+    request: Request,
+    state: Arc<AppState>,
+    config: &Config,
+    host: usize,
+    route: usize,
+) -> Response {
+    inner_request_handler(request, state, config, host, route)
 }
 
 fn inner_request_handler(
     request: Request,
     state: Arc<AppState>,
+    config: &Config,
     host: usize,
-    route: usize,
+    route_index: usize,
 ) -> Response {
-    let route = state.config.get_route(host, route);
+    let route = config.get_route(host, route_index);
 
     match route.route_type {
         RouteType::File => file_handler(request, state.clone(), route.path.as_ref().unwrap(), host),
@@ -218,12 +323,7 @@ fn inner_request_handler(
             &route.matches,
             host,
         ),
-        RouteType::Proxy => proxy_handler(
-            request,
-            state.clone(),
-            route.load_balancer.as_ref().unwrap(),
-            &route.matches,
-        ),
+        RouteType::Proxy => proxy_handler(request, state.clone(), route, (host, route_index)),
         RouteType::Redirect => {
             redirect_handler(request, state.clone(), route.path.as_ref().unwrap())
         }
@@ -234,33 +334,47 @@ fn inner_request_handler(
     }
 }
 
+/// Dispatches the WebSocket request to the handler of the matching route.
+fn websocket_handler(mut request: Request, stream: Stream, state: Arc<AppState>) {
+    let config = state.config();
+
+    match match_route(&config, &mut request, true) {
+        Some((host, route)) => {
+            route_websocket_handler(request, stream, state, &config, host, route)
+        }
+        None => catch_all_websocket_handler(request, stream, state),
+    }
+}
+
 #[cfg(not(feature = "plugins"))]
-fn websocket_handler(
+fn route_websocket_handler(
     request: Request,
     stream: Stream,
     state: Arc<AppState>,
+    config: &Config,
     host: usize,
     route: usize,
 ) {
-    inner_websocket_handler(request, stream, state, host, route)
+    inner_websocket_handler(request, stream, state, config, host, route)
 }
 
 #[cfg(feature = "plugins")]
-fn websocket_handler(
+fn route_websocket_handler(
     mut request: Request,
     stream: Stream,
     state: Arc<AppState>,
+    config: &Config,
     host: usize,
     route: usize,
 ) {
     let plugins = state.plugin_manager.read().unwrap();
 
-    let route_config = state.config.get_route(host, route);
+    let route_config = config.get_route(host, route);
 
     if let Some(stream) =
         plugins.on_websocket_request(&mut request, stream, state.clone(), Some(route_config))
     {
-        inner_websocket_handler(request, stream, state.clone(), host, route)
+        inner_websocket_handler(request, stream, state.clone(), config, host, route)
     }
 }
 
@@ -268,80 +382,36 @@ fn inner_websocket_handler(
     request: Request,
     stream: Stream,
     state: Arc<AppState>,
+    config: &Config,
     host: usize,
     route: usize,
 ) {
-    let route = state.config.get_route(host, route);
+    let route = config.get_route(host, route);
 
-    if let Some(target) = route.websocket_proxy.as_ref() {
-        proxy_websocket(request, stream, &target.clone(), state).ok();
-    }
+    websocket_proxy_handler(request, stream, state, route);
 }
 
 #[cfg(feature = "plugins")]
-fn catch_all_websocket_route(mut request: Request, stream: Stream, state: Arc<AppState>) {
+fn catch_all_websocket_handler(mut request: Request, stream: Stream, state: Arc<AppState>) {
     let plugins = state.plugin_manager.read().unwrap();
 
-    plugins.on_websocket_request(&mut request, stream, state.clone(), None);
+    if let Some(stream) = plugins.on_websocket_request(&mut request, stream, state.clone(), None) {
+        inner_catch_all_websocket_handler(request, stream, state.clone());
+    }
 }
 
-fn proxy_websocket(
-    request: Request,
-    mut source: Stream,
-    target: &str,
-    state: Arc<AppState>,
-) -> Result<(), Box<dyn Error>> {
-    let source_addr = request.address.origin_addr.to_string();
-    let bytes: Vec<u8> = request.into();
-
-    if let Ok(destination) = TcpStream::connect(target) {
-        // The target was successfully connected to
-
-        let mut destination = Stream::Tcp(destination);
-
-        destination.write_all(&bytes)?;
-
-        state.logger.info(&format!(
-            "{}: WebSocket connected, proxying data",
-            source_addr
-        ));
-
-        source.set_nonblocking()?;
-        destination.set_nonblocking()?;
-
-        let mut source_buffer: [u8; 1024] = [0; 1024];
-        let mut destination_buffer: [u8; 1024] = [0; 1024];
-
-        loop {
-            let source_read = match source.read(&mut source_buffer) {
-                Ok(read) => read,
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => 0,
-                _ => break,
-            };
+#[cfg(not(feature = "plugins"))]
+fn catch_all_websocket_handler(request: Request, stream: Stream, state: Arc<AppState>) {
+    inner_catch_all_websocket_handler(request, stream, state);
+}
 
-            let destination_read = match destination.read(&mut destination_buffer) {
-                Ok(read) => read,
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => 0,
-                _ => break,
-            };
+/// Proxies WebSocket requests which do not match any route to the default WebSocket target, if there is one.
+fn inner_catch_all_websocket_handler(request: Request, stream: Stream, state: Arc<AppState>) {
+    let config = state.config();
 
-            if source_read != 0 {
-                destination.write_all(&source_buffer[0..source_read])?;
-            }
-
-            if destination_read != 0 {
-                source.write_all(&destination_buffer[0..destination_read])?;
-            }
-
-            std::thread::park_timeout(std::time::Duration::from_millis(10));
-        }
-    } else {
-        state
-            .logger
-            .error(&format!("{}: Could not connect to WebSocket", source_addr));
+    if let Some(target) = &config.default_websocket_proxy {
+        default_websocket_proxy_handler(request, stream, state.clone(), target);
     }
-
-    Ok(())
 }
 
 #[cfg(feature = "plugins")]
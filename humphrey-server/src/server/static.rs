@@ -1,15 +1,19 @@
 //! Provides functionality for serving static content.
 
+use crate::server::cache::is_file_modified;
 use crate::server::server::AppState;
 
+use humphrey::http::compression::{add_vary, apply_encoding, is_compressible, Encoding};
+use humphrey::http::conditional::{apply_conditional, Validators};
 use humphrey::http::headers::HeaderType;
 use humphrey::http::mime::MimeType;
+use humphrey::http::range::{apply_file_range, apply_range};
 use humphrey::http::{Request, Response, StatusCode};
 use humphrey::route::{try_find_path, LocatedPath};
 
 use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 const INDEX_FILES: [&str; 2] = ["index.html", "index.htm"];
@@ -99,31 +103,106 @@ fn inner_file_handler(
     let mime_type = MimeType::from_extension(file_extension);
     let mut contents: Vec<u8> = Vec::new();
 
-    let mut file = File::open(path).unwrap();
-    file.read_to_end(&mut contents).unwrap();
+    let (mut file, metadata) = match File::open(&path).and_then(|file| {
+        let metadata = file.metadata()?;
+        Ok((file, metadata))
+    }) {
+        Ok(opened) => opened,
+        Err(e) => return file_error(&request, &state, e),
+    };
 
-    if state.config.cache.size_limit >= contents.len() {
+    let validators = Validators::from_metadata(&metadata);
+
+    // A requested range is streamed from the file, so large files are not read into memory or cached
+    if request.headers.get(&HeaderType::Range).is_some() {
+        let vary = is_eligible(&state, mime_type, metadata.len() as usize)
+            || (state.config().compression.enabled && precompressed_path(&path).is_file());
+
+        let response = match range_response(&request, &path, mime_type, &validators, vary) {
+            Ok(response) => response,
+            Err(e) => return file_error(&request, &state, e),
+        };
+
+        state.logger.info(&format!(
+            "{}: {} {}",
+            request.address,
+            status_string(response.status_code),
+            request.uri
+        ));
+
+        return response;
+    }
+
+    let precompressed = match state.config().compression.enabled {
+        true => read_precompressed(&path),
+        false => None,
+    };
+
+    if let Err(e) = file.read_to_end(&mut contents) {
+        return file_error(&request, &state, e);
+    }
+
+    let eligible = is_eligible(&state, mime_type, contents.len());
+    let vary = eligible || precompressed.is_some();
+
+    let variant = match negotiate_encoding(&request, &state) {
+        Some(Encoding::Gzip) if precompressed.is_some() => precompressed
+            .clone()
+            .map(|precompressed| (Encoding::Gzip, precompressed)),
+        Some(encoding) if eligible => {
+            compress(encoding, &contents).map(|compressed| (encoding, compressed))
+        }
+        _ => None,
+    };
+
+    if state.config().cache.size_limit >= contents.len() {
         let mut cache = state.cache.write().unwrap();
-        cache.set(&request.uri, host, contents.clone(), mime_type);
-        state.logger.debug(&format!("Cached route {}", request.uri));
-    } else if state.config.cache.size_limit > 0 {
+        cache.set(
+            &request.uri,
+            host,
+            path,
+            contents.clone(),
+            mime_type,
+            validators.clone(),
+        );
+
+        if let Some(precompressed) = precompressed {
+            cache.set_variant(&request.uri, host, Encoding::Gzip, precompressed);
+        }
+
+        if let Some((encoding, compressed)) = &variant {
+            cache.set_variant(&request.uri, host, *encoding, compressed.clone());
+        }
+
+        state.logger.debug(&format!(
+            "Cached route {} ({} items, {} bytes; {})",
+            request.uri,
+            cache.len(),
+            cache.size(),
+            cache.stats()
+        ));
+    } else if state.config().cache.size_limit > 0 {
         state
             .logger
             .warn(&format!("Couldn't cache, cache too small {}", request.uri));
     }
 
-    state
-        .logger
-        .info(&format!("{}: 200 OK {}", request.address, request.uri));
-    Response::empty(StatusCode::OK)
-        .with_header(HeaderType::ContentType, mime_type.to_string())
-        .with_bytes(contents)
+    let response = content_response(&request, mime_type, contents, &validators, variant, vary);
+
+    state.logger.info(&format!(
+        "{}: {} {}",
+        request.address,
+        status_string(response.status_code),
+        request.uri
+    ));
+
+    response
 }
 
 fn blacklist_check(request: &Request, state: Arc<AppState>) -> Option<Response> {
     // Return error 403 if the address was blacklisted
     if state
-        .config
+        .config()
         .blacklist
         .list
         .contains(&request.address.origin_addr)
@@ -143,23 +222,194 @@ fn blacklist_check(request: &Request, state: Arc<AppState>) -> Option<Response>
 }
 
 fn cache_check(request: &Request, state: Arc<AppState>, host: usize) -> Option<Response> {
-    if state.config.cache.size_limit > 0 {
-        let cache = state.cache.read().unwrap();
-        if let Some(cached) = cache.get(&request.uri, host) {
-            state.logger.info(&format!(
-                "{}: 200 OK (cached) {}",
-                request.address, request.uri
-            ));
-            return Some(
-                Response::empty(StatusCode::OK)
-                    .with_header(HeaderType::ContentType, cached.mime_type.to_string())
-                    .with_bytes(cached.data.clone()),
-            );
+    if state.config().cache.size_limit == 0 {
+        return None;
+    }
+
+    // Only a read lock is held while the item is copied, so cache hits do not block each other
+    let cache = state.cache.read().unwrap();
+    let cached = cache.peek(&request.uri, host)?;
+
+    let encoding = negotiate_encoding(request, &state);
+    let eligible = is_eligible(&state, cached.mime_type, cached.data.len());
+    let vary = eligible || !cached.variants.is_empty();
+
+    let cached_variant = encoding.and_then(|encoding| {
+        cached
+            .variant(encoding)
+            .map(|compressed| (encoding, compressed.clone()))
+    });
+
+    let expired = cache.is_expired(cached);
+    let path = cached.path.clone();
+    let mime_type = cached.mime_type;
+    let data = cached.data.clone();
+    let validators = cached.validators.clone();
+    drop(cache);
+
+    // The file is checked for changes without holding the lock, since this needs a system call
+    if expired || is_file_modified(&path, &validators) {
+        state.cache.write().unwrap().invalidate(&request.uri, host);
+        return None;
+    }
+
+    state.cache.write().unwrap().touch(&request.uri, host);
+
+    // Compress the content if it has not been compressed with this encoding before
+    let variant = match (cached_variant, encoding) {
+        (Some(variant), _) => Some(variant),
+        (None, Some(encoding)) if eligible => {
+            let compressed = compress(encoding, &data);
+
+            if let Some(compressed) = &compressed {
+                let mut cache = state.cache.write().unwrap();
+                cache.set_variant(&request.uri, host, encoding, compressed.clone());
+            }
+
+            compressed.map(|compressed| (encoding, compressed))
         }
-        drop(cache);
+        _ => None,
+    };
+
+    let response = content_response(request, mime_type, data, &validators, variant, vary);
+
+    state.logger.info(&format!(
+        "{}: {} (cached) {}",
+        request.address,
+        status_string(response.status_code),
+        request.uri
+    ));
+
+    Some(response)
+}
+
+/// Builds the response for static content, answering conditional and range requests.
+///
+/// If a compressed version of the content is given, it is served instead of the original content,
+///   unless a range of the content was requested. If the content varies by encoding, the `Vary`
+///   header is added.
+fn content_response(
+    request: &Request,
+    mime_type: MimeType,
+    data: Vec<u8>,
+    validators: &Validators,
+    variant: Option<(Encoding, Vec<u8>)>,
+    vary: bool,
+) -> Response {
+    let mut response = apply_conditional(
+        request,
+        Response::empty(StatusCode::OK)
+            .with_header(HeaderType::ContentType, mime_type.to_string())
+            .with_bytes(data),
+        validators,
+    );
+
+    if vary {
+        add_vary(&mut response.headers);
     }
 
-    None
+    if response.status_code != StatusCode::OK {
+        return response;
+    }
+
+    match variant {
+        Some((encoding, compressed)) if request.headers.get(&HeaderType::Range).is_none() => {
+            apply_encoding(response, encoding, compressed)
+        }
+        _ => apply_range(request, response),
+    }
+}
+
+/// Builds the response to a range request for a file, streaming the requested ranges from the file.
+///
+/// Compressed versions of the file are never used, since ranges refer to the original content.
+fn range_response(
+    request: &Request,
+    path: &Path,
+    mime_type: MimeType,
+    validators: &Validators,
+    vary: bool,
+) -> std::io::Result<Response> {
+    let mut response = apply_conditional(
+        request,
+        Response::empty(StatusCode::OK).with_header(HeaderType::ContentType, mime_type.to_string()),
+        validators,
+    );
+
+    if vary {
+        add_vary(&mut response.headers);
+    }
+
+    apply_file_range(request, response, path)
+}
+
+/// Chooses the encoding to compress content with, if compression is enabled and the client supports it.
+fn negotiate_encoding(request: &Request, state: &AppState) -> Option<Encoding> {
+    if !state.config().compression.enabled {
+        return None;
+    }
+
+    request
+        .headers
+        .get(&HeaderType::AcceptEncoding)
+        .and_then(Encoding::negotiate)
+}
+
+/// Checks whether content of the given type and length should be compressed.
+fn is_eligible(state: &AppState, mime_type: MimeType, length: usize) -> bool {
+    state.config().compression.enabled
+        && length >= state.config().compression.min_size
+        && is_compressible(&mime_type.to_string())
+}
+
+/// Compresses the content with the encoding, returning `None` if this does not make it smaller.
+fn compress(encoding: Encoding, data: &[u8]) -> Option<Vec<u8>> {
+    let compressed = encoding.encode(data);
+
+    match compressed.len() < data.len() {
+        true => Some(compressed),
+        false => None,
+    }
+}
+
+/// Reads the precompressed version of a file, which is stored alongside it with the `.gz` extension.
+fn read_precompressed(path: &Path) -> Option<Vec<u8>> {
+    let mut file = File::open(precompressed_path(path)).ok()?;
+    let mut contents: Vec<u8> = Vec::new();
+    file.read_to_end(&mut contents).ok()?;
+
+    Some(contents)
+}
+
+/// Gets the path of the precompressed version of a file.
+fn precompressed_path(path: &Path) -> PathBuf {
+    let mut precompressed_path = path.as_os_str().to_owned();
+    precompressed_path.push(".gz");
+
+    PathBuf::from(precompressed_path)
+}
+
+/// Logs an error opening or reading a file, returning a 404 response if it does not exist and a 500 response otherwise.
+fn file_error(request: &Request, state: &AppState, error: std::io::Error) -> Response {
+    if error.kind() == ErrorKind::NotFound {
+        state.logger.warn(&format!(
+            "{}: 404 Not Found {}",
+            request.address, request.uri
+        ));
+        not_found()
+    } else {
+        state.logger.error(&format!(
+            "{}: 500 Internal Server Error {} ({})",
+            request.address, request.uri, error
+        ));
+        internal_error()
+    }
+}
+
+/// Formats a status code for logging, for example "200 OK".
+fn status_string(status_code: StatusCode) -> String {
+    let status_str: &str = status_code.into();
+    format!("{} {}", u16::from(status_code), status_str)
 }
 
 /// Generates a 404 response.
@@ -168,3 +418,10 @@ pub fn not_found() -> Response {
         .with_header(HeaderType::ContentType, "text/html")
         .with_bytes(b"<h1>404 Not Found</h1>")
 }
+
+/// Generates a 500 response.
+pub fn internal_error() -> Response {
+    Response::empty(StatusCode::InternalError)
+        .with_header(HeaderType::ContentType, "text/html")
+        .with_bytes(b"<h1>500 Internal Server Error</h1>")
+}
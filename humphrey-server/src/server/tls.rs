@@ -0,0 +1,46 @@
+//! Provides selection of TLS certificates by host, reloading of certificates while the server is running, and
+//!   authentication of clients with client certificates.
+
+use crate::config::{ClientAuthConfig, Config};
+use crate::server::server::AppState;
+
+use humphrey::tls::{CertError, CertResolver, ClientVerifier};
+
+/// Creates a certificate resolver for the configuration.
+///
+/// Hosts with their own certificate use it when they are requested with SNI, and the certificate in the server's
+///   `tls` section is used for every other connection.
+pub fn cert_resolver(config: &Config) -> Result<CertResolver, CertError> {
+    let mut resolver = CertResolver::new();
+
+    if let Some(tls_config) = &config.tls_config {
+        resolver = resolver.with_default_cert(&tls_config.cert_file, &tls_config.key_file)?;
+    }
+
+    for host in &config.hosts {
+        if let Some(tls) = &host.tls {
+            resolver = resolver.with_host_cert(&host.matches, &tls.cert_file, &tls.key_file)?;
+        }
+    }
+
+    Ok(resolver)
+}
+
+/// Creates a verifier which authenticates clients using certificates signed by the configured CAs.
+pub fn client_verifier(config: &ClientAuthConfig) -> Result<ClientVerifier, CertError> {
+    ClientVerifier::from_ca_file(&config.ca_file, config.mode)
+}
+
+/// Reads the certificates currently in use from disk again, keeping the current certificates if any of them
+///   cannot be loaded.
+pub fn reload_certs(state: &AppState) {
+    match state.cert_resolver.reload() {
+        Ok(()) => state.logger.info("TLS certificates reloaded"),
+        Err(e) => {
+            state
+                .logger
+                .error(&format!("Could not reload TLS certificates: {}", e));
+            state.logger.error("Keeping the current certificates");
+        }
+    }
+}
@@ -0,0 +1,392 @@
+//! Provides WebSocket proxy functionality.
+
+use crate::config::{ForwardingConfig, RouteConfig};
+use crate::proxy::{log_health_change, rewrite_path, PROXY_CONNECT_TIMEOUT};
+use crate::server::server::AppState;
+
+use humphrey::http::headers::HeaderType;
+use humphrey::http::proxy::{add_via, forward_request, strip_hop_by_hop_headers};
+use humphrey::http::{Request, Response, StatusCode};
+use humphrey::stream::Stream;
+
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::park_timeout;
+use std::time::Duration;
+
+/// The maximum size of the head of the target's response to the upgrade request.
+const MAX_HEAD_SIZE: usize = 16384;
+
+/// The shortest time to wait for data when neither side of a connection has sent anything.
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The longest time to wait for data when neither side of a connection has sent anything.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The close frame sent to the client when the target disconnects without closing the WebSocket,
+///   with the status code 1001 "Going Away".
+const CLOSE_FRAME_TO_CLIENT: [u8; 4] = [0x88, 0x02, 0x03, 0xe9];
+
+/// The close frame sent to the target when the client disconnects without closing the WebSocket,
+///   with the status code 1001 "Going Away".
+///
+/// Frames sent to the target must be masked, so this uses a mask of zero which leaves the payload unchanged.
+const CLOSE_FRAME_TO_TARGET: [u8; 8] = [0x88, 0x82, 0x00, 0x00, 0x00, 0x00, 0x03, 0xe9];
+
+/// Handles WebSocket requests to a route.
+///
+/// If the route has a WebSocket target, the connection is proxied there with the path unchanged. Otherwise, the
+///   route's load balancer selects the target and the path is rewritten as for HTTP requests, with the target
+///   counting as in use until the WebSocket is closed.
+pub fn websocket_proxy_handler(
+    mut request: Request,
+    mut stream: Stream,
+    state: Arc<AppState>,
+    route: &RouteConfig,
+) {
+    let forwarding = route.forwarding.clone().unwrap_or_default();
+
+    if let Some(target) = &route.websocket_proxy {
+        proxy_websocket(request, stream, target, &forwarding, &state).ok();
+    } else if let Some(load_balancer) = &route.load_balancer {
+        let target = match load_balancer.lock().unwrap().select_target(&request) {
+            Some(target) => target,
+            None => {
+                state.logger.warn(&format!(
+                    "{}: No proxy targets are available for WebSocket {}",
+                    request.address, request.uri
+                ));
+
+                let response: Vec<u8> = Response::empty(StatusCode::ServiceUnavailable)
+                    .with_header(HeaderType::ContentType, "text/html")
+                    .with_bytes(b"<h1>503 Service Unavailable</h1>")
+                    .into();
+
+                stream.write_all(&response).ok();
+                return;
+            }
+        };
+
+        request.uri = rewrite_path(&request.uri, &route.matches, &forwarding);
+
+        let success = proxy_websocket(request, stream, &target, &forwarding, &state).is_ok();
+        let change = load_balancer.lock().unwrap().report(&target, success);
+        log_health_change(&state, &target, change);
+    }
+}
+
+/// Handles WebSocket requests which do not match any route by proxying them to the default WebSocket target.
+pub fn default_websocket_proxy_handler(
+    request: Request,
+    stream: Stream,
+    state: Arc<AppState>,
+    target: &str,
+) {
+    proxy_websocket(
+        request,
+        stream,
+        target,
+        &ForwardingConfig::default(),
+        &state,
+    )
+    .ok();
+}
+
+/// Performs the WebSocket handshake with the target and then relays data between the client and the target
+///   until either side closes the connection.
+///
+/// If the target rejects the upgrade, its response is passed on to the client. Returns an error if the target
+///   could not be reached or did not respond with a valid HTTP response, in which case the client receives
+///   error 502.
+pub fn proxy_websocket(
+    request: Request,
+    mut client: Stream,
+    target: &str,
+    forwarding: &ForwardingConfig,
+    state: &AppState,
+) -> Result<(), Error> {
+    let address = request.address.clone();
+    let uri = request.uri.clone();
+
+    let mut target_stream = match handshake(&request, target, forwarding) {
+        Ok((target_stream, response)) => {
+            let status: u16 = response.status_code.into();
+
+            if response.status_code != StatusCode::SwitchingProtocols {
+                state.logger.warn(&format!(
+                    "{}: WebSocket target {} rejected the upgrade of {} with status {}",
+                    address, target, uri, status
+                ));
+
+                let response = response_to_client(response, forwarding);
+                client.write_all(&response).ok();
+                return Ok(());
+            }
+
+            if client.write_all(&response.body).is_err() {
+                return Ok(());
+            }
+
+            target_stream
+        }
+        Err(e) => {
+            state.logger.warn(&format!(
+                "{}: WebSocket target {} could not be reached",
+                address, target
+            ));
+
+            let response: Vec<u8> = Response::empty(StatusCode::BadGateway)
+                .with_header(HeaderType::ContentType, "text/html")
+                .with_bytes(b"<h1>502 Bad Gateway</h1>")
+                .into();
+
+            client.write_all(&response).ok();
+            return Err(e);
+        }
+    };
+
+    state.logger.info(&format!(
+        "{}: WebSocket {} connected to {}, proxying data",
+        address, uri, target
+    ));
+
+    pump(&mut client, &mut target_stream).ok();
+
+    client.shutdown().ok();
+    target_stream.shutdown().ok();
+
+    state.logger.info(&format!(
+        "{}: WebSocket {} to {} closed",
+        address, uri, target
+    ));
+
+    Ok(())
+}
+
+/// Sends the upgrade request to the target and reads the head of its response.
+///
+/// If the target accepted the upgrade, the raw bytes of the response head are returned as the body so they can be
+///   passed on to the client unchanged. Otherwise, the whole response is read.
+fn handshake(
+    request: &Request,
+    target: &str,
+    forwarding: &ForwardingConfig,
+) -> Result<(Stream, Response), Error> {
+    let mut forwarded_request = forward_request(request, &forwarding.headers, target);
+
+    // The upgrade headers are hop-by-hop, so they must be added back for the target to see the upgrade request
+    let upgrade = request
+        .headers
+        .get(HeaderType::Upgrade)
+        .unwrap_or("websocket")
+        .to_string();
+
+    forwarded_request
+        .headers
+        .add(HeaderType::Connection, "Upgrade");
+    forwarded_request.headers.add(HeaderType::Upgrade, upgrade);
+
+    let target_sock = target
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "target has no address"))?;
+
+    let mut target_stream = TcpStream::connect_timeout(&target_sock, PROXY_CONNECT_TIMEOUT)?;
+    target_stream.set_read_timeout(Some(PROXY_CONNECT_TIMEOUT))?;
+
+    let request_bytes: Vec<u8> = forwarded_request.into();
+    target_stream.write_all(&request_bytes)?;
+
+    let head = read_head(&mut target_stream)?;
+    let mut response = Response::head_from_stream(&mut head.as_slice())
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid response"))?;
+
+    if response.status_code == StatusCode::SwitchingProtocols {
+        target_stream.set_read_timeout(None)?;
+        response.body = head;
+    } else {
+        response = Response::from_stream(&mut head.as_slice().chain(&mut target_stream))
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid response"))?;
+    }
+
+    Ok((Stream::Tcp(target_stream), response))
+}
+
+/// Reads the head of a response one byte at a time, so that no WebSocket data sent straight after it is consumed.
+fn read_head(stream: &mut TcpStream) -> Result<Vec<u8>, Error> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "response head too long"));
+        }
+
+        stream.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+
+    Ok(head)
+}
+
+/// Prepares a response rejecting the upgrade to be passed on to the client.
+fn response_to_client(mut response: Response, forwarding: &ForwardingConfig) -> Vec<u8> {
+    strip_hop_by_hop_headers(&mut response.headers);
+
+    if let Some(pseudonym) = &forwarding.headers.via {
+        add_via(&mut response.headers, &response.version, pseudonym);
+    }
+
+    response.into()
+}
+
+/// Relays data in both directions until the connection is closed.
+///
+/// The connection is closed once both sides have sent a close frame, or as soon as either side disconnects. If a
+///   side disconnects without sending a close frame, one is sent to the other side on its behalf.
+fn pump(client: &mut Stream, target: &mut Stream) -> Result<(), Error> {
+    client.set_nonblocking()?;
+    target.set_nonblocking()?;
+
+    let mut buf = [0; 8192];
+    let mut from_client = FrameTracker::default();
+    let mut from_target = FrameTracker::default();
+    let mut poll_interval = MIN_POLL_INTERVAL;
+
+    loop {
+        let client_read = relay(client, target, &mut buf, &mut from_client)?;
+        if client_read.is_none() {
+            if !from_client.closed {
+                write_all_nonblocking(target, &CLOSE_FRAME_TO_TARGET).ok();
+            }
+
+            return Ok(());
+        }
+
+        let target_read = relay(target, client, &mut buf, &mut from_target)?;
+        if target_read.is_none() {
+            if !from_target.closed {
+                write_all_nonblocking(client, &CLOSE_FRAME_TO_CLIENT).ok();
+            }
+
+            return Ok(());
+        }
+
+        // The closing handshake is complete, so neither side will send anything more
+        if from_client.closed && from_target.closed {
+            return Ok(());
+        }
+
+        if client_read == Some(0) && target_read == Some(0) {
+            park_timeout(poll_interval);
+            poll_interval = (poll_interval * 2).min(MAX_POLL_INTERVAL);
+        } else {
+            poll_interval = MIN_POLL_INTERVAL;
+        }
+    }
+}
+
+/// Relays any data available from the source to the destination without blocking.
+///
+/// Returns the number of bytes relayed, or `None` if the source has disconnected.
+fn relay(
+    source: &mut Stream,
+    destination: &mut Stream,
+    buf: &mut [u8],
+    tracker: &mut FrameTracker,
+) -> Result<Option<usize>, Error> {
+    match source.read(buf) {
+        Ok(0) => Ok(None),
+        Ok(n) => {
+            tracker.feed(&buf[..n]);
+            write_all_nonblocking(destination, &buf[..n])?;
+            Ok(Some(n))
+        }
+        Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {
+            Ok(Some(0))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Writes all of the data to a nonblocking stream, waiting whenever the stream is not ready.
+fn write_all_nonblocking(stream: &mut Stream, mut data: &[u8]) -> Result<(), Error> {
+    while !data.is_empty() {
+        match stream.write(data) {
+            Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {
+                park_timeout(MIN_POLL_INTERVAL)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    stream.flush()
+}
+
+/// Follows the frame boundaries of one direction of a WebSocket connection to detect when a close frame is sent.
+#[derive(Default)]
+pub struct FrameTracker {
+    /// The bytes of the header of the current frame which have been seen so far.
+    header: Vec<u8>,
+    /// The number of bytes of the current frame's payload which have not yet been seen.
+    remaining: u64,
+    /// Whether a close frame has been seen.
+    pub closed: bool,
+}
+
+impl FrameTracker {
+    /// Follows the frames in the next chunk of data.
+    pub fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let skipped = self.remaining.min(data.len() as u64);
+                self.remaining -= skipped;
+                data = &data[skipped as usize..];
+                continue;
+            }
+
+            self.header.push(data[0]);
+            data = &data[1..];
+
+            if let Some((opcode, length)) = parse_frame_header(&self.header) {
+                if opcode == 0x8 {
+                    self.closed = true;
+                }
+
+                self.remaining = length;
+                self.header.clear();
+            }
+        }
+    }
+}
+
+/// Parses the opcode and payload length from a frame header, returning `None` if the header is incomplete.
+fn parse_frame_header(header: &[u8]) -> Option<(u8, u64)> {
+    if header.len() < 2 {
+        return None;
+    }
+
+    let opcode = header[0] & 0x0f;
+    let mask_length = if header[1] & 0x80 != 0 { 4 } else { 0 };
+    let extended_length = match header[1] & 0x7f {
+        126 => 2,
+        127 => 8,
+        _ => 0,
+    };
+
+    if header.len() < 2 + extended_length + mask_length {
+        return None;
+    }
+
+    let length = match extended_length {
+        0 => (header[1] & 0x7f) as u64,
+        _ => header[2..2 + extended_length]
+            .iter()
+            .fold(0, |length, &byte| (length << 8) | byte as u64),
+    };
+
+    Some((opcode, length))
+}
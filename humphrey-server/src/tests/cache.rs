@@ -0,0 +1,139 @@
+use humphrey_server::cache::{Cache, CacheStats};
+use humphrey_server::config::{CacheConfig, Config};
+
+use humphrey::http::conditional::Validators;
+use humphrey::http::mime::MimeType;
+
+use std::env::temp_dir;
+use std::fs::{metadata, remove_file, write};
+use std::path::PathBuf;
+
+fn cache(size_limit: usize) -> Cache {
+    Cache::from(&Config {
+        cache: CacheConfig {
+            size_limit,
+            time_limit: 60,
+        },
+        ..Default::default()
+    })
+}
+
+/// Writes the contents to a temporary file and caches them at the given route.
+fn set(cache: &mut Cache, route: &str, contents: &[u8]) -> PathBuf {
+    let path = temp_dir().join(format!(
+        "humphrey-cache-{}-{}",
+        route.trim_start_matches('/'),
+        std::process::id()
+    ));
+    write(&path, contents).unwrap();
+
+    let validators = Validators::from_metadata(&metadata(&path).unwrap());
+    cache.set(
+        route,
+        0,
+        path.clone(),
+        contents.to_vec(),
+        MimeType::TextPlain,
+        validators,
+    );
+
+    path
+}
+
+#[test]
+fn test_lru_eviction() {
+    let mut cache = cache(10);
+
+    set(&mut cache, "/a", b"aaaa");
+    let b = set(&mut cache, "/b", b"bbbb");
+
+    // Using `/a` makes `/b` the least recently used item, so it is evicted first
+    assert!(cache.get("/a", 0).is_some());
+    let c = set(&mut cache, "/c", b"cccc");
+
+    assert!(cache.get("/b", 0).is_none());
+    assert_eq!(cache.get("/a", 0).unwrap().data, b"aaaa");
+    assert_eq!(cache.get("/c", 0).unwrap().data, b"cccc");
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.size(), 8);
+
+    // Replacing an item updates the size rather than evicting another item
+    let a = set(&mut cache, "/a", b"aaaaaa");
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.size(), 10);
+
+    // Items larger than the cache are not stored
+    let d = set(&mut cache, "/d", b"ddddddddddd");
+    assert!(cache.get("/d", 0).is_none());
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 3,
+            misses: 2,
+            evictions: 1,
+            invalidations: 0,
+        }
+    );
+
+    for path in [a, b, c, d] {
+        remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_modified_file_invalidated() {
+    let mut cache = cache(100);
+
+    let path = set(&mut cache, "/modified", b"original");
+    assert_eq!(cache.get("/modified", 0).unwrap().data, b"original");
+
+    write(&path, b"edited contents").unwrap();
+    assert!(cache.get("/modified", 0).is_none());
+    assert!(cache.is_empty());
+
+    remove_file(&path).unwrap();
+
+    let path = set(&mut cache, "/modified", b"original");
+    remove_file(&path).unwrap();
+    assert!(cache.get("/modified", 0).is_none());
+
+    assert_eq!(cache.stats().invalidations, 2);
+    assert_eq!(cache.size(), 0);
+}
+
+#[test]
+fn test_peek_and_touch() {
+    let mut cache = cache(10);
+
+    let a = set(&mut cache, "/a", b"aaaa");
+    let b = set(&mut cache, "/b", b"bbbb");
+
+    // Peeking does not mark `/a` as used, so it is still evicted first
+    assert_eq!(cache.peek("/a", 0).unwrap().data, b"aaaa");
+    let c = set(&mut cache, "/c", b"cccc");
+    assert!(cache.peek("/a", 0).is_none());
+
+    // Touching `/b` does, so `/c` is evicted instead
+    cache.touch("/b", 0);
+    let d = set(&mut cache, "/d", b"dddd");
+    assert!(cache.peek("/b", 0).is_some());
+    assert!(cache.peek("/c", 0).is_none());
+
+    cache.invalidate("/b", 0);
+    assert!(cache.peek("/b", 0).is_none());
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 1,
+            misses: 4,
+            evictions: 2,
+            invalidations: 1,
+        }
+    );
+
+    for path in [a, b, c, d] {
+        remove_file(path).unwrap();
+    }
+}
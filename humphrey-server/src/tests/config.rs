@@ -1,8 +1,9 @@
 #![allow(unused_imports)]
 use super::tree::CONF;
 use humphrey_server::config::config::{
-    BlacklistConfig, BlacklistMode, CacheConfig, Config, ConfigSource, HostConfig,
-    LoadBalancerMode, LoggingConfig, RouteConfig, RouteType,
+    BlacklistConfig, BlacklistMode, CacheConfig, CompressionConfig, Config, ConfigSource,
+    ForwardingConfig, HealthConfig, HostConfig, ListenConfig, ListenMode, LoadBalancerMode,
+    LoggingConfig, RouteCacheConfig, RouteConfig, RouteType,
 };
 use humphrey_server::config::tree::{parse_conf, ConfigNode};
 use humphrey_server::logger::LogLevel;
@@ -10,9 +11,9 @@ use humphrey_server::logger::LogLevel;
 #[cfg(feature = "plugins")]
 use humphrey_server::config::config::PluginConfig;
 use humphrey_server::proxy::{EqMutex, LoadBalancer};
-use humphrey_server::rand::Lcg;
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[test]
@@ -33,12 +34,18 @@ fn test_parse_config() {
         source: ConfigSource::Default,
         address: "0.0.0.0".into(),
         port: 80,
+        listeners: vec![ListenConfig {
+            address: "0.0.0.0:80".into(),
+            mode: ListenMode::Http,
+        }],
         threads: 32,
         #[cfg(feature = "tls")]
         tls_config: None,
         default_websocket_proxy: Some("localhost:1234".into()),
         default_host: HostConfig {
             matches: "*".into(),
+            #[cfg(feature = "tls")]
+            tls: None,
             routes: vec![
                 RouteConfig {
                     route_type: RouteType::Directory,
@@ -46,18 +53,21 @@ fn test_parse_config() {
                     path: Some("/var/www".into()),
                     load_balancer: None,
                     websocket_proxy: None,
+                    cache: None,
+                    forwarding: None,
                 },
                 RouteConfig {
                     route_type: RouteType::Proxy,
                     matches: "/*".into(),
                     path: None,
-                    load_balancer: Some(EqMutex::new(LoadBalancer {
-                        targets: vec!["127.0.0.1:8000".into(), "127.0.0.1:8080".into()],
-                        mode: LoadBalancerMode::RoundRobin,
-                        index: 0,
-                        lcg: Lcg::new(),
-                    })),
+                    load_balancer: Some(Arc::new(EqMutex::new(LoadBalancer::new(
+                        vec!["127.0.0.1:8000".into(), "127.0.0.1:8080".into()],
+                        LoadBalancerMode::RoundRobin,
+                        HealthConfig::default(),
+                    )))),
                     websocket_proxy: None,
+                    cache: None,
+                    forwarding: Some(ForwardingConfig::default()),
                 },
             ],
         },
@@ -77,11 +87,16 @@ fn test_parse_config() {
             size_limit: 134217728,
             time_limit: 60,
         },
+        compression: CompressionConfig {
+            enabled: true,
+            min_size: 2048,
+        },
         blacklist: BlacklistConfig {
             list: Vec::new(),
             mode: BlacklistMode::Block,
         },
         connection_timeout: Some(Duration::from_secs(5)),
+        watch: false,
     };
 
     assert_eq!(conf, expected_conf);
@@ -96,39 +111,55 @@ fn test_host_config() {
         source: ConfigSource::Default,
         address: "0.0.0.0".into(),
         port: 80,
+        listeners: vec![ListenConfig {
+            address: "0.0.0.0:80".into(),
+            mode: ListenMode::Http,
+        }],
         threads: 32,
         #[cfg(feature = "tls")]
         tls_config: None,
         default_websocket_proxy: None,
         default_host: HostConfig {
             matches: "*".into(),
+            #[cfg(feature = "tls")]
+            tls: None,
             routes: vec![RouteConfig {
                 route_type: RouteType::Directory,
                 matches: "/*".into(),
                 path: Some("/var/www".into()),
                 load_balancer: None,
                 websocket_proxy: None,
+                cache: None,
+                forwarding: None,
             }],
         },
         hosts: vec![
             HostConfig {
                 matches: "localhost".into(),
+                #[cfg(feature = "tls")]
+                tls: None,
                 routes: vec![RouteConfig {
                     route_type: RouteType::Redirect,
                     matches: "/".into(),
                     path: Some("/app/dev".into()),
                     load_balancer: None,
                     websocket_proxy: None,
+                    cache: None,
+                    forwarding: None,
                 }],
             },
             HostConfig {
                 matches: "*.example.com".into(),
+                #[cfg(feature = "tls")]
+                tls: None,
                 routes: vec![RouteConfig {
                     route_type: RouteType::Redirect,
                     matches: "/".into(),
                     path: Some("/app/prod".into()),
                     load_balancer: None,
                     websocket_proxy: None,
+                    cache: None,
+                    forwarding: None,
                 }],
             },
         ],
@@ -143,11 +174,16 @@ fn test_host_config() {
             size_limit: 0,
             time_limit: 0,
         },
+        compression: CompressionConfig {
+            enabled: false,
+            min_size: 1024,
+        },
         blacklist: BlacklistConfig {
             list: Vec::new(),
             mode: BlacklistMode::Block,
         },
         connection_timeout: None,
+        watch: false,
     };
 
     assert_eq!(conf, expected_conf);
@@ -162,12 +198,18 @@ fn comma_separated_routes() {
         source: ConfigSource::Default,
         address: "0.0.0.0".into(),
         port: 80,
+        listeners: vec![ListenConfig {
+            address: "0.0.0.0:80".into(),
+            mode: ListenMode::Http,
+        }],
         threads: 32,
         #[cfg(feature = "tls")]
         tls_config: None,
         default_websocket_proxy: None,
         default_host: HostConfig {
             matches: "*".into(),
+            #[cfg(feature = "tls")]
+            tls: None,
             routes: vec![
                 RouteConfig {
                     route_type: RouteType::Directory,
@@ -175,6 +217,8 @@ fn comma_separated_routes() {
                     path: Some("/var/www".into()),
                     load_balancer: None,
                     websocket_proxy: None,
+                    cache: None,
+                    forwarding: None,
                 },
                 RouteConfig {
                     route_type: RouteType::Directory,
@@ -182,6 +226,8 @@ fn comma_separated_routes() {
                     path: Some("/var/www".into()),
                     load_balancer: None,
                     websocket_proxy: None,
+                    cache: None,
+                    forwarding: None,
                 },
             ],
         },
@@ -197,12 +243,125 @@ fn comma_separated_routes() {
             size_limit: 0,
             time_limit: 0,
         },
+        compression: CompressionConfig {
+            enabled: false,
+            min_size: 1024,
+        },
         blacklist: BlacklistConfig {
             list: Vec::new(),
             mode: BlacklistMode::Block,
         },
         connection_timeout: None,
+        watch: false,
     };
 
     assert_eq!(conf, expected_conf);
 }
+
+#[test]
+fn test_route_cache_config() {
+    let string = r#"
+server {
+    route /api/* {
+        proxy "127.0.0.1:8000"
+
+        cache {
+            size                   16M
+            stale_while_revalidate 10
+        }
+    }
+}
+"#;
+
+    let conf = Config::from_tree(parse_conf(string, "cache.conf").unwrap()).unwrap();
+
+    assert_eq!(
+        conf.default_host.routes[0].cache,
+        Some(RouteCacheConfig {
+            size_limit: 16 * 1024 * 1024,
+            stale_while_revalidate: 10,
+            stale_if_error: 0,
+        })
+    );
+
+    let string = r#"
+server {
+    route /* {
+        directory "/var/www"
+
+        cache {
+            size 16M
+        }
+    }
+}
+"#;
+
+    assert_eq!(
+        Config::from_tree(parse_conf(string, "cache.conf").unwrap()),
+        Err("Response caching is only supported on proxy routes")
+    );
+}
+
+#[test]
+fn test_load_balancer_config() {
+    let string = r#"
+server {
+    route /a/* {
+        proxy              "127.0.0.1:8000=3,127.0.0.1:8080"
+        load_balancer_mode "least-connections"
+    }
+
+    route /b/* {
+        proxy              "127.0.0.1:8000"
+        load_balancer_mode "ip-hash"
+    }
+
+    route /c/* {
+        proxy              "127.0.0.1:8000"
+        load_balancer_mode "cookie-hash:session"
+    }
+}
+"#;
+
+    let conf = Config::from_tree(parse_conf(string, "lb.conf").unwrap()).unwrap();
+    let load_balancers: Vec<LoadBalancer> = conf
+        .default_host
+        .routes
+        .iter()
+        .map(|route| {
+            route
+                .load_balancer
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .clone()
+        })
+        .collect();
+
+    assert_eq!(
+        load_balancers[0].targets,
+        vec!["127.0.0.1:8000", "127.0.0.1:8080"]
+    );
+    assert_eq!(load_balancers[0].weights, vec![3, 1]);
+    assert_eq!(load_balancers[0].mode, LoadBalancerMode::LeastConnections);
+    assert_eq!(load_balancers[1].mode, LoadBalancerMode::IpHash);
+    assert_eq!(
+        load_balancers[2].mode,
+        LoadBalancerMode::CookieHash("session".into())
+    );
+
+    for (targets, mode) in [
+        ("127.0.0.1:8000=0", "round-robin"),
+        ("127.0.0.1:8000=heavy", "round-robin"),
+        ("127.0.0.1:8000", "cookie-hash:"),
+        ("127.0.0.1:8000", "fastest"),
+    ] {
+        let string = format!(
+            "server {{\n    route /* {{\n        proxy \"{}\"\n        load_balancer_mode \"{}\"\n    }}\n}}\n",
+            targets, mode
+        );
+
+        assert!(Config::from_tree(parse_conf(&string, "lb.conf").unwrap()).is_err());
+    }
+}
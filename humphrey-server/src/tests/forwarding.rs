@@ -0,0 +1,93 @@
+use humphrey_server::config::tree::parse_conf;
+use humphrey_server::config::{Config, ForwardingConfig};
+use humphrey_server::proxy::rewrite_path;
+
+use humphrey::http::proxy::{HostRewrite, ProxyHeaders};
+
+#[test]
+fn test_rewrite_path() {
+    let default = ForwardingConfig::default();
+
+    assert_eq!(rewrite_path("/api/users", "/api/*", &default), "/users");
+    assert_eq!(rewrite_path("/api", "/api*", &default), "/");
+
+    let keep = ForwardingConfig {
+        strip_prefix: false,
+        ..ForwardingConfig::default()
+    };
+
+    assert_eq!(rewrite_path("/api/users", "/api/*", &keep), "/api/users");
+
+    let replace = ForwardingConfig {
+        add_prefix: Some("/v2/".into()),
+        ..ForwardingConfig::default()
+    };
+
+    assert_eq!(rewrite_path("/api/users", "/api/*", &replace), "/v2/users");
+}
+
+#[test]
+fn test_forwarding_config() {
+    let string = r#"
+server {
+    route /api/* {
+        proxy        "127.0.0.1:8000"
+        strip_prefix false
+        add_prefix   "/v2"
+        host         "target"
+
+        forwarding {
+            x_forwarded false
+            forwarded   true
+            via         false
+        }
+    }
+
+    route /other/* {
+        proxy "127.0.0.1:8000"
+        host  "internal.example.com"
+    }
+}
+"#;
+
+    let conf = Config::from_tree(parse_conf(string, "forwarding.conf").unwrap()).unwrap();
+
+    assert_eq!(
+        conf.default_host.routes[0].forwarding,
+        Some(ForwardingConfig {
+            strip_prefix: false,
+            add_prefix: Some("/v2".into()),
+            headers: ProxyHeaders {
+                x_forwarded: false,
+                forwarded: true,
+                via: None,
+                host: HostRewrite::Target,
+            },
+        })
+    );
+
+    assert_eq!(
+        conf.default_host.routes[1].forwarding,
+        Some(ForwardingConfig {
+            headers: ProxyHeaders {
+                host: HostRewrite::Custom("internal.example.com".into()),
+                ..ProxyHeaders::default()
+            },
+            ..ForwardingConfig::default()
+        })
+    );
+
+    let string = r#"
+server {
+    route /api/* {
+        proxy      "127.0.0.1:8000"
+        add_prefix "v2"
+    }
+}
+"#;
+
+    assert_eq!(
+        Config::from_tree(parse_conf(string, "forwarding.conf").unwrap()),
+        Err("Added path prefix must start with `/`")
+    );
+}
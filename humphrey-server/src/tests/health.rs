@@ -0,0 +1,191 @@
+use humphrey_server::config::tree::parse_conf;
+use humphrey_server::config::{Config, HealthCheckConfig, HealthConfig, LoadBalancerMode};
+use humphrey_server::health::check_target;
+use humphrey_server::proxy::{HealthChange, LoadBalancer};
+
+use super::mock_request;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+fn load_balancer(mode: LoadBalancerMode, eject_time: Duration) -> LoadBalancer {
+    LoadBalancer::new(
+        vec!["a:80".into(), "b:80".into()],
+        mode,
+        HealthConfig {
+            max_failures: 2,
+            eject_time,
+            check: None,
+        },
+    )
+}
+
+#[test]
+fn test_passive_ejection() {
+    let mut lb = load_balancer(LoadBalancerMode::RoundRobin, Duration::from_secs(60));
+
+    assert_eq!(lb.report("a:80", false), None);
+    assert_eq!(lb.report("a:80", false), Some(HealthChange::Down));
+
+    for _ in 0..4 {
+        assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+    }
+
+    assert_eq!(lb.report("b:80", false), None);
+    assert_eq!(lb.report("b:80", true), None);
+    assert_eq!(lb.report("b:80", false), None);
+    assert_eq!(lb.report("b:80", false), Some(HealthChange::Down));
+
+    assert_eq!(lb.select_target(&mock_request()), None);
+
+    let mut lb = load_balancer(LoadBalancerMode::Random, Duration::from_secs(60));
+    lb.report("a:80", false);
+    lb.report("a:80", false);
+
+    for _ in 0..8 {
+        assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+    }
+}
+
+#[test]
+fn test_readmission() {
+    let mut lb = load_balancer(LoadBalancerMode::RoundRobin, Duration::from_millis(50));
+
+    lb.report("a:80", false);
+    lb.report("a:80", false);
+    assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+    assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+
+    sleep(Duration::from_millis(100));
+
+    // Once the ejection time has passed, the target is tried again and taken out immediately if it still fails
+    assert_eq!(lb.select_target(&mock_request()), Some("a:80".into()));
+    assert_eq!(lb.report("a:80", false), Some(HealthChange::Down));
+    assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+
+    sleep(Duration::from_millis(100));
+
+    assert_eq!(lb.select_target(&mock_request()), Some("a:80".into()));
+    assert_eq!(lb.report("a:80", true), Some(HealthChange::Up));
+    assert_eq!(lb.report("a:80", false), None);
+}
+
+#[test]
+fn test_active_check_results() {
+    let mut lb = load_balancer(LoadBalancerMode::RoundRobin, Duration::from_secs(60));
+
+    assert_eq!(lb.report_check("a:80", false), Some(HealthChange::Down));
+    assert_eq!(lb.report_check("a:80", false), None);
+    assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+    assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+
+    // A successful request does not override a failed check
+    assert_eq!(lb.report("a:80", true), None);
+    assert_eq!(lb.select_target(&mock_request()), Some("b:80".into()));
+
+    assert_eq!(lb.report_check("a:80", true), Some(HealthChange::Up));
+
+    // A passing check puts back a target which was taken out because of failed requests
+    lb.report("b:80", false);
+    assert_eq!(lb.report("b:80", false), Some(HealthChange::Down));
+    assert_eq!(lb.report_check("b:80", true), Some(HealthChange::Up));
+}
+
+#[test]
+fn test_check_target() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    spawn(move || {
+        for (i, stream) in listener.incoming().take(2).enumerate() {
+            let mut stream = stream.unwrap();
+            let mut buf = [0; 1024];
+            let len = stream.read(&mut buf).unwrap();
+            assert!(buf[..len].starts_with(b"GET /health HTTP/1.1\r\n"));
+
+            let status = if i == 0 {
+                "200 OK"
+            } else {
+                "500 Internal Server Error"
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+        }
+    });
+
+    let check = HealthCheckConfig {
+        path: "/health".into(),
+        interval: Duration::from_secs(1),
+        status: 200,
+        timeout: Duration::from_secs(1),
+    };
+
+    assert!(check_target(&addr, &check));
+    assert!(!check_target(&addr, &check));
+}
+
+#[test]
+fn test_health_config() {
+    let string = r#"
+server {
+    route /api/* {
+        proxy "127.0.0.1:8000"
+
+        health {
+            max_failures 5
+            eject_time   30
+            path         "/health"
+            interval     5
+            status       204
+        }
+    }
+}
+"#;
+
+    let conf = Config::from_tree(parse_conf(string, "health.conf").unwrap()).unwrap();
+    let health = conf.default_host.routes[0]
+        .load_balancer
+        .as_ref()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .health
+        .clone();
+
+    assert_eq!(
+        health,
+        HealthConfig {
+            max_failures: 5,
+            eject_time: Duration::from_secs(30),
+            check: Some(HealthCheckConfig {
+                path: "/health".into(),
+                interval: Duration::from_secs(5),
+                status: 204,
+                timeout: Duration::from_secs(2),
+            }),
+        }
+    );
+
+    let string = r#"
+server {
+    route /api/* {
+        proxy "127.0.0.1:8000"
+
+        health {
+            path "health"
+        }
+    }
+}
+"#;
+
+    assert_eq!(
+        Config::from_tree(parse_conf(string, "health.conf").unwrap()),
+        Err("Health check path must start with `/`")
+    );
+}
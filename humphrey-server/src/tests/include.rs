@@ -1,14 +1,15 @@
 use humphrey_server::config::tree::parse_conf;
 use humphrey_server::config::{
-    BlacklistConfig, BlacklistMode, CacheConfig, Config, ConfigSource, HostConfig,
-    LoadBalancerMode, LoggingConfig, RouteConfig, RouteType,
+    BlacklistConfig, BlacklistMode, CacheConfig, CompressionConfig, Config, ConfigSource,
+    ForwardingConfig, HealthConfig, HostConfig, ListenConfig, ListenMode, LoadBalancerMode,
+    LoggingConfig, RouteConfig, RouteType,
 };
 use humphrey_server::logger::LogLevel;
 use humphrey_server::proxy::{EqMutex, LoadBalancer};
-use humphrey_server::rand::Lcg;
 
 use std::env::set_current_dir;
 use std::path::Path;
+use std::sync::Arc;
 
 #[test]
 fn include_route() {
@@ -23,18 +24,26 @@ fn include_route() {
         source: ConfigSource::Default,
         address: "0.0.0.0".into(),
         port: 80,
+        listeners: vec![ListenConfig {
+            address: "0.0.0.0:80".into(),
+            mode: ListenMode::Http,
+        }],
         threads: 32,
         #[cfg(feature = "tls")]
         tls_config: None,
         default_websocket_proxy: None,
         default_host: HostConfig {
             matches: "*".into(),
+            #[cfg(feature = "tls")]
+            tls: None,
             routes: vec![RouteConfig {
                 route_type: RouteType::Directory,
                 matches: "/*".into(),
                 path: Some("/var/www".into()),
                 load_balancer: None,
                 websocket_proxy: None,
+                cache: None,
+                forwarding: None,
             }],
         },
         hosts: Vec::new(),
@@ -49,11 +58,16 @@ fn include_route() {
             size_limit: 0,
             time_limit: 0,
         },
+        compression: CompressionConfig {
+            enabled: false,
+            min_size: 1024,
+        },
         blacklist: BlacklistConfig {
             list: Vec::new(),
             mode: BlacklistMode::Block,
         },
         connection_timeout: None,
+        watch: false,
     });
 
     assert_eq!(config, expected_conf);
@@ -72,23 +86,30 @@ fn nested_include() {
         source: ConfigSource::Default,
         address: "0.0.0.0".into(),
         port: 80,
+        listeners: vec![ListenConfig {
+            address: "0.0.0.0:80".into(),
+            mode: ListenMode::Http,
+        }],
         threads: 32,
         #[cfg(feature = "tls")]
         tls_config: None,
         default_websocket_proxy: None,
         default_host: HostConfig {
             matches: "*".into(),
+            #[cfg(feature = "tls")]
+            tls: None,
             routes: vec![RouteConfig {
                 route_type: RouteType::Proxy,
                 matches: "/test".into(),
                 path: None,
-                load_balancer: Some(EqMutex::new(LoadBalancer {
-                    targets: vec!["127.0.0.1".into()],
-                    mode: LoadBalancerMode::Random,
-                    index: 0,
-                    lcg: Lcg::new(),
-                })),
+                load_balancer: Some(Arc::new(EqMutex::new(LoadBalancer::new(
+                    vec!["127.0.0.1".into()],
+                    LoadBalancerMode::Random,
+                    HealthConfig::default(),
+                )))),
                 websocket_proxy: None,
+                cache: None,
+                forwarding: Some(ForwardingConfig::default()),
             }],
         },
         hosts: Vec::new(),
@@ -103,11 +124,16 @@ fn nested_include() {
             size_limit: 0,
             time_limit: 0,
         },
+        compression: CompressionConfig {
+            enabled: false,
+            min_size: 1024,
+        },
         blacklist: BlacklistConfig {
             list: Vec::new(),
             mode: BlacklistMode::Block,
         },
         connection_timeout: None,
+        watch: false,
     });
 
     assert_eq!(config, expected_conf);
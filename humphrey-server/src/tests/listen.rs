@@ -0,0 +1,108 @@
+use humphrey_server::config::tree::parse_conf;
+use humphrey_server::config::{Config, ListenConfig, ListenMode};
+
+fn listeners(options: &str) -> Result<Vec<ListenConfig>, &'static str> {
+    let string = format!("server {{\n{}}}\n", options);
+
+    Config::from_tree(parse_conf(&string, "listen.conf").unwrap()).map(|config| config.listeners)
+}
+
+#[cfg(feature = "tls")]
+fn tls_listeners(options: &str) -> Result<Vec<ListenConfig>, &'static str> {
+    listeners(&format!(
+        "{}    tls {{\n        cert_file \"cert.pem\"\n        key_file \"key.pem\"\n    }}\n",
+        options
+    ))
+}
+
+fn listener(address: &str, mode: ListenMode) -> ListenConfig {
+    ListenConfig {
+        address: address.into(),
+        mode,
+    }
+}
+
+#[test]
+fn test_default_listeners() {
+    assert_eq!(
+        listeners("    address \"127.0.0.1\"\n    port 8080\n"),
+        Ok(vec![listener("127.0.0.1:8080", ListenMode::Http)])
+    );
+}
+
+#[test]
+fn test_listeners() {
+    assert_eq!(
+        listeners("    listen \"127.0.0.1:8080\"\n    listen \"[::1]:8081 http\"\n"),
+        Ok(vec![
+            listener("127.0.0.1:8080", ListenMode::Http),
+            listener("[::1]:8081", ListenMode::Http),
+        ])
+    );
+}
+
+#[test]
+fn test_invalid_listeners() {
+    assert_eq!(
+        listeners("    listen \"127.0.0.1\"\n"),
+        Err("Invalid listen address")
+    );
+    assert_eq!(
+        listeners("    listen \"127.0.0.1:8080 http extra\"\n"),
+        Err("Invalid listen address")
+    );
+    assert_eq!(
+        listeners("    listen \"127.0.0.1:8080 ftp\"\n"),
+        Err("Invalid listener mode")
+    );
+    assert_eq!(
+        listeners("    listen \"0.0.0.0:443 https\"\n"),
+        Err("HTTPS listeners require the `tls` section")
+    );
+    assert_eq!(
+        listeners("    listen \"0.0.0.0:80 redirect\"\n"),
+        Err("Redirecting listeners require an HTTPS listener")
+    );
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn test_tls_default_listeners() {
+    assert_eq!(
+        tls_listeners("    port 8443\n"),
+        Ok(vec![listener("0.0.0.0:8443", ListenMode::Https)])
+    );
+
+    // Forcing HTTPS no longer requires port 443
+    assert_eq!(
+        listeners(
+            "    port 8443\n    tls {\n        cert_file \"cert.pem\"\n        key_file \"key.pem\"\n        force true\n    }\n"
+        ),
+        Ok(vec![
+            listener("0.0.0.0:8443", ListenMode::Https),
+            listener("0.0.0.0:80", ListenMode::Redirect(8443)),
+        ])
+    );
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn test_tls_listeners() {
+    assert_eq!(
+        tls_listeners(
+            "    listen \"0.0.0.0:80 redirect\"\n    listen \"0.0.0.0:8443 https\"\n    listen \"127.0.0.1:8080\"\n"
+        ),
+        Ok(vec![
+            listener("0.0.0.0:80", ListenMode::Redirect(8443)),
+            listener("0.0.0.0:8443", ListenMode::Https),
+            listener("127.0.0.1:8080", ListenMode::Http),
+        ])
+    );
+
+    assert_eq!(
+        listeners(
+            "    listen \"0.0.0.0:443 https\"\n    tls {\n        cert_file \"cert.pem\"\n        key_file \"key.pem\"\n        force true\n    }\n"
+        ),
+        Err("Use a `redirect` listener instead of forcing HTTPS when listeners are configured")
+    );
+}
@@ -0,0 +1,154 @@
+use humphrey_server::config::{HealthConfig, LoadBalancerMode};
+use humphrey_server::proxy::LoadBalancer;
+
+use humphrey::http::address::Address;
+use humphrey::http::headers::HeaderType;
+use humphrey::http::Request;
+
+use super::mock_request;
+
+fn request(address: &str, cookie: Option<&str>) -> Request {
+    let mut request = Request {
+        address: Address::new(address).unwrap(),
+        ..mock_request()
+    };
+
+    if let Some(cookie) = cookie {
+        request.headers.add(HeaderType::Cookie, cookie);
+    }
+
+    request
+}
+
+fn load_balancer(mode: LoadBalancerMode, weights: Vec<usize>) -> LoadBalancer {
+    let targets = (0..weights.len())
+        .map(|i| format!("127.0.0.1:{}", 8000 + i))
+        .collect();
+
+    LoadBalancer::new(targets, mode, HealthConfig::default()).with_weights(weights)
+}
+
+#[test]
+fn test_weighted_round_robin() {
+    let mut lb = load_balancer(LoadBalancerMode::RoundRobin, vec![3, 1]);
+    let request = request("1.2.3.4:5678", None);
+
+    let selected: Vec<String> = (0..8)
+        .map(|_| lb.select_target(&request).unwrap())
+        .collect();
+
+    assert_eq!(
+        selected,
+        vec![
+            "127.0.0.1:8000",
+            "127.0.0.1:8000",
+            "127.0.0.1:8000",
+            "127.0.0.1:8001",
+            "127.0.0.1:8000",
+            "127.0.0.1:8000",
+            "127.0.0.1:8000",
+            "127.0.0.1:8001"
+        ]
+    );
+}
+
+#[test]
+fn test_weighted_random() {
+    let mut lb = load_balancer(LoadBalancerMode::Random, vec![9, 1]);
+    let request = request("1.2.3.4:5678", None);
+
+    let first = (0..1000)
+        .filter(|_| lb.select_target(&request).unwrap() == "127.0.0.1:8000")
+        .count();
+
+    assert!(first > 800 && first < 980);
+}
+
+#[test]
+fn test_least_connections() {
+    let mut lb = load_balancer(LoadBalancerMode::LeastConnections, vec![1, 1, 2]);
+    let request = request("1.2.3.4:5678", None);
+
+    let mut selected: Vec<String> = (0..4)
+        .map(|_| lb.select_target(&request).unwrap())
+        .collect();
+    selected.sort();
+
+    // The third target has twice the weight, so it is given twice as many requests
+    assert_eq!(
+        selected,
+        vec![
+            "127.0.0.1:8000",
+            "127.0.0.1:8001",
+            "127.0.0.1:8002",
+            "127.0.0.1:8002"
+        ]
+    );
+    assert_eq!(lb.active_requests, vec![1, 1, 2]);
+
+    lb.report("127.0.0.1:8001", true);
+    assert_eq!(lb.active_requests, vec![1, 0, 2]);
+    assert_eq!(lb.select_target(&request).unwrap(), "127.0.0.1:8001");
+}
+
+#[test]
+fn test_ip_hash() {
+    let mut lb = load_balancer(LoadBalancerMode::IpHash, vec![1, 1, 1]);
+
+    let clients: Vec<Request> = (0..50)
+        .map(|i| request(&format!("10.0.0.{}:1234", i), None))
+        .collect();
+
+    let selected: Vec<String> = clients
+        .iter()
+        .map(|client| lb.select_target(client).unwrap())
+        .collect();
+
+    // Every target is used, and clients are sent to the same target every time
+    for target in &lb.targets {
+        assert!(selected.contains(target));
+    }
+
+    for (client, target) in clients.iter().zip(&selected) {
+        assert_eq!(&lb.select_target(client).unwrap(), target);
+    }
+
+    // Only the clients of an unavailable target are moved
+    lb.report_check("127.0.0.1:8001", false);
+
+    for (client, target) in clients.iter().zip(&selected) {
+        let new_target = lb.select_target(client).unwrap();
+
+        if target == "127.0.0.1:8001" {
+            assert_ne!(&new_target, target);
+        } else {
+            assert_eq!(&new_target, target);
+        }
+    }
+}
+
+#[test]
+fn test_cookie_hash() {
+    let mut lb = load_balancer(LoadBalancerMode::CookieHash("session".into()), vec![1, 1]);
+
+    let sessions: Vec<String> = (0..20)
+        .map(|i| {
+            lb.select_target(&request(
+                &format!("10.0.0.{}:1234", i),
+                Some("theme=dark; session=abc123"),
+            ))
+            .unwrap()
+        })
+        .collect();
+
+    // Requests with the same cookie are sent to the same target regardless of their address
+    assert!(sessions.iter().all(|target| *target == sessions[0]));
+
+    // Requests without the cookie are hashed by address
+    let mut ip_lb = load_balancer(LoadBalancerMode::IpHash, vec![1, 1]);
+
+    for i in 0..20 {
+        let request = request(&format!("10.0.0.{}:1234", i), Some("theme=dark"));
+        assert_eq!(lb.select_target(&request), ip_lb.select_target(&request));
+    }
+}
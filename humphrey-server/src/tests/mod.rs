@@ -1,4 +1,39 @@
+pub mod cache;
 pub mod config;
+pub mod forwarding;
+pub mod health;
 pub mod include;
 pub mod invalid_config;
+pub mod listen;
+pub mod load_balancer;
+pub mod proxy_cache;
+pub mod reload;
+pub mod r#static;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub mod tree;
+pub mod websocket_proxy;
+
+use humphrey::http::address::Address;
+use humphrey::http::headers::Headers;
+use humphrey::http::method::Method;
+use humphrey::http::Request;
+
+use std::collections::HashMap;
+
+/// Creates a `GET /` request from `1.2.3.4:5678` with no headers, for tests to change as they need.
+pub fn mock_request() -> Request {
+    Request {
+        method: Method::Get,
+        uri: "/".into(),
+        query: "".into(),
+        params: HashMap::new(),
+        version: "HTTP/1.1".into(),
+        headers: Headers::new(),
+        trailers: Headers::new(),
+        content: None,
+        address: Address::new("1.2.3.4:5678").unwrap(),
+        peer_identity: None,
+        https: false,
+    }
+}
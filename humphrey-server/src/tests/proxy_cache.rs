@@ -0,0 +1,348 @@
+use humphrey_server::config::{Config, RouteCacheConfig};
+use humphrey_server::proxy_cache::{serve_cached, CachedResponse};
+use humphrey_server::AppState;
+
+use humphrey::http::date::DateTime;
+use humphrey::http::headers::HeaderType;
+use humphrey::http::method::Method;
+use humphrey::http::{Request, Response, StatusCode};
+
+use super::mock_request;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+const CONFIG: RouteCacheConfig = RouteCacheConfig {
+    size_limit: 1024,
+    stale_while_revalidate: 0,
+    stale_if_error: 0,
+};
+
+fn request(method: Method, uri: &str) -> Request {
+    Request {
+        method,
+        uri: uri.into(),
+        ..mock_request()
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// Serves the request through the cache, with a backend which responds using `respond` and counts its requests.
+fn serve<F>(
+    state: &Arc<AppState>,
+    request: Request,
+    config: RouteCacheConfig,
+    count: &Arc<AtomicUsize>,
+    respond: F,
+) -> Response
+where
+    F: Fn(&Request, usize) -> Response + Send + 'static,
+{
+    let count = count.clone();
+
+    serve_cached(request, state.clone(), (0, 0), config, move |request| {
+        respond(request, count.fetch_add(1, Ordering::SeqCst) + 1)
+    })
+}
+
+fn ok(cache_control: &str, body: &str) -> Response {
+    Response::empty(StatusCode::OK)
+        .with_header(HeaderType::CacheControl, cache_control)
+        .with_bytes(body)
+}
+
+#[test]
+fn test_fresh_response_cached() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..3 {
+        let response = serve(&state, request(Method::Get, "/"), CONFIG, &count, |_, n| {
+            ok("max-age=60", &format!("response {}", n))
+        });
+
+        assert_eq!(response.body, b"response 1");
+    }
+
+    let response = serve(
+        &state,
+        request(Method::Head, "/"),
+        CONFIG,
+        &count,
+        |_, _| Response::empty(StatusCode::InternalError),
+    );
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.headers.get(HeaderType::Age), Some("0"));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_uncacheable_responses() {
+    let state = Arc::new(AppState::from(Config::default()));
+
+    for cache_control in ["no-store", "private, max-age=60", "no-cache", ""] {
+        let count = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..2 {
+            serve(
+                &state,
+                request(Method::Get, "/"),
+                CONFIG,
+                &count,
+                move |_, _| ok(cache_control, "response"),
+            );
+        }
+
+        assert_eq!(count.load(Ordering::SeqCst), 2, "{}", cache_control);
+    }
+
+    // Requests can bypass the cache
+    let count = Arc::new(AtomicUsize::new(0));
+    for _ in 0..2 {
+        let mut request = request(Method::Get, "/bypass");
+        request.headers.add(HeaderType::CacheControl, "no-cache");
+        serve(&state, request, CONFIG, &count, |_, _| {
+            ok("max-age=60", "x")
+        });
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_vary() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+
+    for language in ["en", "fr", "en", "fr"] {
+        let mut request = request(Method::Get, "/");
+        request.headers.add(HeaderType::AcceptLanguage, language);
+
+        let response = serve(&state, request, CONFIG, &count, |request, _| {
+            ok(
+                "max-age=60",
+                request.headers.get(HeaderType::AcceptLanguage).unwrap(),
+            )
+            .with_header(HeaderType::Vary, "Accept-Language")
+        });
+
+        assert_eq!(response.body, language.as_bytes());
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_hosts_cached_separately() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+
+    // A wildcard host block such as `*.example.com` serves both hosts through the same route
+    for host in [
+        "a.example.com",
+        "b.example.com",
+        "A.example.com",
+        "b.example.com",
+    ] {
+        let mut request = request(Method::Get, "/");
+        request.headers.add(HeaderType::Host, host);
+
+        let response = serve(&state, request, CONFIG, &count, |request, _| {
+            ok(
+                "max-age=60",
+                &request
+                    .headers
+                    .get(HeaderType::Host)
+                    .unwrap()
+                    .to_ascii_lowercase(),
+            )
+        });
+
+        assert_eq!(response.body, host.to_ascii_lowercase().as_bytes());
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_least_recently_used_evicted() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+    let config = RouteCacheConfig {
+        size_limit: 20,
+        ..CONFIG
+    };
+
+    // Each response is 8 bytes, so only two fit in the cache, and `/b` is used least recently when `/c` is added
+    for uri in ["/a", "/b", "/a", "/c", "/a", "/c", "/b"] {
+        let response = serve(
+            &state,
+            request(Method::Get, uri),
+            config,
+            &count,
+            |request, _| ok("max-age=60", &format!("body {}", request.uri)),
+        );
+
+        assert_eq!(response.body, format!("body {}", uri).as_bytes());
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn test_revalidation() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let backend = |request: &Request, n: usize| {
+        if n == 1 {
+            ok("max-age=0", "response").with_header(HeaderType::ETag, "\"v1\"")
+        } else {
+            assert_eq!(request.headers.get(HeaderType::IfNoneMatch), Some("\"v1\""));
+            Response::empty(StatusCode::NotModified)
+                .with_header(HeaderType::CacheControl, "max-age=60")
+        }
+    };
+
+    serve(&state, request(Method::Get, "/"), CONFIG, &count, backend);
+    let response = serve(&state, request(Method::Get, "/"), CONFIG, &count, backend);
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"response");
+
+    // The 304 response made the cached response fresh again
+    let mut conditional = request(Method::Get, "/");
+    conditional.headers.add(HeaderType::IfNoneMatch, "\"v1\"");
+    let response = serve(&state, conditional, CONFIG, &count, backend);
+
+    assert_eq!(response.status_code, StatusCode::NotModified);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_stale_if_error() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let backend = |_: &Request, n: usize| match n {
+        1 => ok("max-age=0, stale-if-error=60", "response"),
+        _ => Response::empty(StatusCode::BadGateway),
+    };
+
+    serve(&state, request(Method::Get, "/"), CONFIG, &count, backend);
+    let response = serve(&state, request(Method::Get, "/"), CONFIG, &count, backend);
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"response");
+
+    // Without `stale-if-error`, the error is passed on to the client
+    let backend = |_: &Request, n: usize| match n {
+        3 => ok("max-age=0, must-revalidate", "response"),
+        _ => Response::empty(StatusCode::BadGateway),
+    };
+
+    serve(
+        &state,
+        request(Method::Get, "/other"),
+        CONFIG,
+        &count,
+        backend,
+    );
+    let response = serve(
+        &state,
+        request(Method::Get, "/other"),
+        CONFIG,
+        &count,
+        backend,
+    );
+
+    assert_eq!(response.status_code, StatusCode::BadGateway);
+}
+
+#[test]
+fn test_stale_while_revalidate() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+    let config = RouteCacheConfig {
+        stale_while_revalidate: 60,
+        ..CONFIG
+    };
+
+    let backend = |_: &Request, n: usize| {
+        if n > 1 {
+            sleep(Duration::from_millis(200));
+        }
+
+        ok("max-age=0", &format!("response {}", n))
+    };
+
+    serve(&state, request(Method::Get, "/"), config, &count, backend);
+
+    // Both requests are answered immediately with the stale response, and only one revalidates it
+    for _ in 0..2 {
+        let response = serve(&state, request(Method::Get, "/"), config, &count, backend);
+        assert_eq!(response.body, b"response 1");
+    }
+
+    sleep(Duration::from_millis(500));
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    let response = serve(&state, request(Method::Get, "/"), config, &count, backend);
+    assert_eq!(response.body, b"response 2");
+}
+
+#[test]
+fn test_unsafe_method_invalidates() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let backend = |_: &Request, n: usize| ok("max-age=60", &format!("response {}", n));
+
+    serve(&state, request(Method::Get, "/"), CONFIG, &count, backend);
+    serve(&state, request(Method::Post, "/"), CONFIG, &count, backend);
+    let response = serve(&state, request(Method::Get, "/"), CONFIG, &count, backend);
+
+    assert_eq!(response.body, b"response 3");
+}
+
+#[test]
+fn test_freshness() {
+    let now = now();
+
+    let response = Response::empty(StatusCode::OK)
+        .with_header(HeaderType::Date, DateTime::from(now - 10).to_string())
+        .with_header(HeaderType::Expires, DateTime::from(now + 90).to_string())
+        .with_header(HeaderType::Age, "30");
+
+    let cached =
+        CachedResponse::new(&request(Method::Get, "/"), &response, now as u64, &CONFIG).unwrap();
+
+    assert_eq!(cached.freshness_lifetime, 100);
+    assert_eq!(cached.initial_age, 30);
+    assert!(cached.is_fresh(now as u64 + 69));
+    assert!(!cached.is_fresh(now as u64 + 70));
+
+    // `s-maxage` takes precedence over `max-age` and `Expires`
+    let response = response.with_header(HeaderType::CacheControl, "max-age=5, s-maxage=500");
+    let cached =
+        CachedResponse::new(&request(Method::Get, "/"), &response, now as u64, &CONFIG).unwrap();
+
+    assert_eq!(cached.freshness_lifetime, 500);
+    assert!(cached.must_revalidate);
+
+    // Responses without explicit freshness information are not stored
+    let response = Response::empty(StatusCode::OK);
+    assert!(
+        CachedResponse::new(&request(Method::Get, "/"), &response, now as u64, &CONFIG).is_none()
+    );
+}
@@ -0,0 +1,117 @@
+use humphrey_server::config::{Config, ConfigSource};
+use humphrey_server::logger::LogLevel;
+use humphrey_server::reload::reload;
+use humphrey_server::AppState;
+
+use std::env::temp_dir;
+use std::fs::{remove_file, write};
+use std::path::PathBuf;
+
+const INITIAL: &str = r#"
+server {
+    port 8080
+
+    log {
+        level   "error"
+        console false
+    }
+
+    route /* {
+        directory "/var/www"
+    }
+}
+"#;
+
+const UPDATED: &str = r#"
+server {
+    port 8081
+
+    log {
+        level   "debug"
+        console false
+    }
+
+    cache {
+        size 1024
+    }
+
+    host "example.com" {
+        route /* {
+            redirect "https://example.com/"
+        }
+    }
+
+    route /static/* {
+        directory "/var/static"
+    }
+}
+"#;
+
+fn config_path(name: &str) -> PathBuf {
+    temp_dir().join(format!(
+        "humphrey-reload-{}-{}.conf",
+        name,
+        std::process::id()
+    ))
+}
+
+#[test]
+fn test_reload() {
+    let path = config_path("valid");
+    write(&path, INITIAL).unwrap();
+
+    let path_str = path.to_str().unwrap();
+    let state = AppState::from(Config::load_from(path_str, ConfigSource::Argument).unwrap());
+
+    write(&path, UPDATED).unwrap();
+    assert_eq!(reload(&state, path_str, ConfigSource::Argument), Ok(()));
+
+    let config = state.config();
+    remove_file(&path).unwrap();
+
+    // Routes, hosts, cache and log level settings are reloaded
+    assert_eq!(config.default_host.routes[0].matches, "/static/*");
+    assert_eq!(config.hosts.len(), 1);
+    assert_eq!(config.hosts[0].matches, "example.com");
+    assert_eq!(config.cache.size_limit, 1024);
+    assert_eq!(state.cache.read().unwrap().cache_limit, 1024);
+    assert_eq!(state.logger.level(), LogLevel::Debug);
+
+    // The port can only be changed by restarting the server
+    assert_eq!(config.port, 8080);
+    assert_eq!(config.source, ConfigSource::Argument);
+}
+
+#[test]
+fn test_reload_invalid() {
+    let path = config_path("invalid");
+    write(&path, INITIAL).unwrap();
+
+    let path_str = path.to_str().unwrap();
+    let state = AppState::from(Config::load_from(path_str, ConfigSource::Argument).unwrap());
+
+    write(&path, "server {\n    threads 0\n}\n").unwrap();
+    let invalid = reload(&state, path_str, ConfigSource::Argument);
+
+    write(&path, "server {\n    route /* {\n").unwrap();
+    let unparseable = reload(&state, path_str, ConfigSource::Argument);
+
+    remove_file(&path).unwrap();
+    let missing = reload(&state, path_str, ConfigSource::Argument);
+
+    assert_eq!(
+        invalid,
+        Err("You cannot specify less than 1 thread".to_string())
+    );
+    assert!(unparseable.is_err());
+    assert!(missing.is_err());
+
+    // The original configuration is kept
+    let config = state.config();
+    assert_eq!(config.default_host.routes[0].matches, "/*");
+    assert_eq!(
+        config.default_host.routes[0].path.as_deref(),
+        Some("/var/www")
+    );
+    assert_eq!(state.logger.level(), LogLevel::Error);
+}
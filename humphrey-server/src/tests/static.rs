@@ -0,0 +1,47 @@
+use humphrey_server::config::Config;
+use humphrey_server::r#static::file_handler;
+use humphrey_server::AppState;
+
+use humphrey::http::headers::HeaderType;
+use humphrey::http::StatusCode;
+
+use super::mock_request;
+
+use std::env::temp_dir;
+use std::fs::{remove_file, write};
+use std::io::Read;
+use std::sync::Arc;
+
+#[test]
+fn test_range_streamed_from_file() {
+    let state = Arc::new(AppState::from(Config::default()));
+    let path = temp_dir().join(format!("humphrey-static-{}.txt", std::process::id()));
+    write(&path, b"0123456789").unwrap();
+
+    let mut request = mock_request();
+    request.headers.add(HeaderType::Range, "bytes=-3");
+
+    let response = file_handler(request, state.clone(), path.to_str().unwrap(), 0);
+
+    assert_eq!(response.status_code, StatusCode::PartialContent);
+    assert_eq!(
+        response.headers.get(HeaderType::ContentRange),
+        Some("bytes 7-9/10")
+    );
+    assert_eq!(response.content_length(), Some(3));
+
+    let mut body = Vec::new();
+    response
+        .stream
+        .unwrap()
+        .source
+        .read_to_end(&mut body)
+        .unwrap();
+
+    assert_eq!(body, b"789");
+
+    remove_file(&path).unwrap();
+
+    let response = file_handler(mock_request(), state, path.to_str().unwrap(), 0);
+    assert_eq!(response.status_code, StatusCode::NotFound);
+}
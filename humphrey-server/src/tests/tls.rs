@@ -0,0 +1,193 @@
+use humphrey_server::config::config::{ClientAuthConfig, HostTlsConfig};
+use humphrey_server::config::tree::parse_conf;
+use humphrey_server::config::{Config, ConfigSource};
+use humphrey_server::reload::reload;
+use humphrey_server::tls::cert_resolver;
+use humphrey_server::AppState;
+
+use humphrey::tls::{load_certified_key, ClientAuth};
+
+use std::env::temp_dir;
+use std::fs::{remove_file, write};
+
+const CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../humphrey/src/tests/certs/");
+
+fn tls_config(host_cert: &str) -> String {
+    format!(
+        r#"
+server {{
+    port 443
+
+    log {{
+        console false
+    }}
+
+    tls {{
+        cert_file "{0}localhost.pem"
+        key_file  "{0}localhost.key"
+    }}
+
+    host "one.example.com" {{
+        tls {{
+            cert_file "{0}{1}.pem"
+            key_file  "{0}{1}.key"
+        }}
+
+        route /* {{
+            directory "/var/www/one"
+        }}
+    }}
+
+    host "other.example.com" {{
+        route /* {{
+            directory "/var/www/other"
+        }}
+    }}
+}}
+"#,
+        CERTS, host_cert
+    )
+}
+
+#[test]
+fn test_host_tls_config() {
+    let conf =
+        Config::from_tree(parse_conf(&tls_config("one.example.com"), "tls.conf").unwrap()).unwrap();
+
+    assert_eq!(conf.default_host.tls, None);
+    assert_eq!(
+        conf.hosts[0].tls,
+        Some(HostTlsConfig {
+            cert_file: format!("{}one.example.com.pem", CERTS),
+            key_file: format!("{}one.example.com.key", CERTS),
+        })
+    );
+    assert_eq!(conf.hosts[1].tls, None);
+
+    let string = r#"
+server {
+    host "example.com" {
+        tls {
+            cert_file "example.pem"
+            key_file  "example.key"
+        }
+    }
+}
+"#;
+
+    assert_eq!(
+        Config::from_tree(parse_conf(string, "tls.conf").unwrap()),
+        Err("Host certificates require a default certificate in the `tls` section")
+    );
+
+    let string = r#"
+server {
+    tls {
+        cert_file "cert.pem"
+        key_file  "key.pem"
+    }
+
+    host "example.com" {
+        tls {
+            cert_file "example.pem"
+        }
+    }
+}
+"#;
+
+    assert_eq!(
+        Config::from_tree(parse_conf(string, "tls.conf").unwrap()),
+        Err("Missing key file for TLS")
+    );
+}
+
+#[test]
+fn test_client_auth_config() {
+    let client_auth = |options: &str| {
+        let string = format!(
+            "server {{\n    tls {{\n        cert_file \"cert.pem\"\n        key_file \"key.pem\"\n{}    }}\n}}\n",
+            options
+        );
+
+        Config::from_tree(parse_conf(&string, "tls.conf").unwrap())
+            .map(|config| config.tls_config.unwrap().client_auth)
+    };
+
+    assert_eq!(client_auth(""), Ok(None));
+    assert_eq!(
+        client_auth("        client_ca \"ca.pem\"\n"),
+        Ok(Some(ClientAuthConfig {
+            ca_file: "ca.pem".into(),
+            mode: ClientAuth::Required,
+        }))
+    );
+    assert_eq!(
+        client_auth("        client_ca \"ca.pem\"\n        client_auth \"optional\"\n"),
+        Ok(Some(ClientAuthConfig {
+            ca_file: "ca.pem".into(),
+            mode: ClientAuth::Optional,
+        }))
+    );
+    assert_eq!(
+        client_auth("        client_ca \"ca.pem\"\n        client_auth \"sometimes\"\n"),
+        Err("Invalid TLS client authentication mode")
+    );
+    assert_eq!(
+        client_auth("        client_auth \"required\"\n"),
+        Err("Missing client CA file for TLS")
+    );
+}
+
+#[test]
+fn test_cert_reload() {
+    let cert = |name: &str| {
+        load_certified_key(
+            format!("{}{}.pem", CERTS, name),
+            format!("{}{}.key", CERTS, name),
+        )
+        .unwrap()
+        .cert
+    };
+
+    let path = temp_dir().join(format!("humphrey-tls-{}.conf", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    write(&path, tls_config("one.example.com")).unwrap();
+
+    let config = Config::load_from(path_str, ConfigSource::Argument).unwrap();
+    let state = AppState::from(config);
+    state
+        .cert_resolver
+        .replace(cert_resolver(&state.config()).unwrap());
+
+    let resolver = &state.cert_resolver;
+    assert_eq!(
+        resolver.cert_for(Some("one.example.com")).unwrap().cert,
+        cert("one.example.com")
+    );
+    assert_eq!(
+        resolver.cert_for(Some("other.example.com")).unwrap().cert,
+        cert("localhost")
+    );
+
+    // Changing a host's certificate takes effect when the configuration is reloaded
+    write(&path, tls_config("two.example.com")).unwrap();
+    assert_eq!(reload(&state, path_str, ConfigSource::Argument), Ok(()));
+    assert_eq!(
+        resolver.cert_for(Some("one.example.com")).unwrap().cert,
+        cert("two.example.com")
+    );
+
+    // Certificates which cannot be loaded keep the current configuration
+    write(&path, tls_config("missing")).unwrap();
+    assert!(reload(&state, path_str, ConfigSource::Argument).is_err());
+    assert_eq!(
+        resolver.cert_for(Some("one.example.com")).unwrap().cert,
+        cert("two.example.com")
+    );
+    assert_eq!(
+        state.config().hosts[0].tls.as_ref().unwrap().cert_file,
+        format!("{}two.example.com.pem", CERTS)
+    );
+
+    remove_file(&path).unwrap();
+}
@@ -35,6 +35,10 @@ fn test_build_tree() {
             ConfigNode::Number("size".into(), "134217728".into()),
             ConfigNode::Number("time".into(), "60".into())
         ]),
+        ConfigNode::Section("compression".into(), vec![
+            ConfigNode::Boolean("enabled".into(), "true".into()),
+            ConfigNode::Number("min_size".into(), "2048".into())
+        ]),
         ConfigNode::Route("/static/*".into(), vec![
             ConfigNode::String("directory".into(), "/var/www".into()),
         ]),
@@ -66,6 +70,8 @@ fn test_flatten_config() {
     expected_hashmap.insert("server.log.file".into(), ConfigNode::String("file".into(), "humphrey.log".into()));
     expected_hashmap.insert("server.cache.size".into(), ConfigNode::Number("size".into(), "134217728".into()));
     expected_hashmap.insert("server.cache.time".into(), ConfigNode::Number("time".into(), "60".into()));
+    expected_hashmap.insert("server.compression.enabled".into(), ConfigNode::Boolean("enabled".into(), "true".into()));
+    expected_hashmap.insert("server.compression.min_size".into(), ConfigNode::Number("min_size".into(), "2048".into()));
 
     let mut actual_hashmap: HashMap<String, ConfigNode> = HashMap::new();
     parsed_conf.flatten(&mut actual_hashmap, &Vec::new());
@@ -0,0 +1,256 @@
+use humphrey_server::config::tree::parse_conf;
+use humphrey_server::config::{Config, ForwardingConfig};
+use humphrey_server::websocket_proxy::{proxy_websocket, websocket_proxy_handler, FrameTracker};
+use humphrey_server::AppState;
+
+use humphrey::http::Request;
+use humphrey::stream::Stream;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+
+const UPGRADE_REQUEST: &[u8] = b"GET /ws/chat HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+
+const UPGRADE_RESPONSE: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+
+const CLOSE_FRAME: [u8; 4] = [0x88, 0x02, 0x03, 0xe8];
+
+/// Starts a target which reads the upgrade request, responds with the given bytes, then passes the request line
+///   and connection to the callback.
+fn start_target(
+    response: &'static [u8],
+    callback: impl FnOnce(String, TcpStream) + Send + 'static,
+) -> (String, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let handle = spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+
+        let head = String::from_utf8(head).unwrap();
+        let lowercase_head = head.to_ascii_lowercase();
+        assert!(lowercase_head.contains("connection: upgrade\r\n"));
+        assert!(lowercase_head.contains("upgrade: websocket\r\n"));
+        assert!(lowercase_head.contains("sec-websocket-key: dghlihnhbxbszsbub25jzq==\r\n"));
+
+        stream.write_all(response).unwrap();
+        callback(head.lines().next().unwrap().to_string(), stream);
+    });
+
+    (addr, handle)
+}
+
+/// Connects a client to the proxy, returning the client's connection and the request and stream which the proxy
+///   receives for it.
+fn connect_client() -> (TcpStream, Request, Stream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    client.write_all(UPGRADE_REQUEST).unwrap();
+
+    let (mut stream, addr): (TcpStream, SocketAddr) = listener.accept().unwrap();
+    let request = Request::from_stream(&mut stream, addr).unwrap();
+
+    (client, request, Stream::Tcp(stream))
+}
+
+fn read_bytes(stream: &mut TcpStream, length: usize) -> Vec<u8> {
+    let mut buf = vec![0; length];
+    stream.read_exact(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn test_websocket_proxy_relays_data() {
+    let (target, handle) = start_target(UPGRADE_RESPONSE, |request_line, mut stream| {
+        assert_eq!(request_line, "GET /chat HTTP/1.1");
+
+        // A frame sent straight after the handshake must not be lost
+        stream.write_all(&[0x81, 0x02, b'h', b'i']).unwrap();
+
+        // Echo one masked text frame back unmasked, then disconnect without closing the WebSocket
+        let mut frame = [0; 9];
+        stream.read_exact(&mut frame).unwrap();
+        assert_eq!(frame[..2], [0x81, 0x83]);
+
+        let payload: Vec<u8> = frame[6..]
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ frame[2 + i % 4])
+            .collect();
+
+        stream.write_all(&[0x81, 0x03]).unwrap();
+        stream.write_all(&payload).unwrap();
+    });
+
+    let string = format!(
+        "server {{\n    route /ws/* {{\n        proxy \"{}\"\n        load_balancer_mode \"least-connections\"\n    }}\n}}\n",
+        target
+    );
+    let config = Config::from_tree(parse_conf(&string, "ws.conf").unwrap()).unwrap();
+    let load_balancer = config.default_host.routes[0].load_balancer.clone().unwrap();
+    let state = Arc::new(AppState::from(config));
+
+    let (mut client, request, stream) = connect_client();
+    let proxy = spawn(move || {
+        let config = state.config();
+        websocket_proxy_handler(
+            request,
+            stream,
+            state.clone(),
+            &config.default_host.routes[0],
+        )
+    });
+
+    assert_eq!(
+        read_bytes(&mut client, UPGRADE_RESPONSE.len()),
+        UPGRADE_RESPONSE
+    );
+    assert_eq!(read_bytes(&mut client, 4), [0x81, 0x02, b'h', b'i']);
+
+    client
+        .write_all(&[
+            0x81,
+            0x83,
+            0x01,
+            0x02,
+            0x03,
+            0x04,
+            b'a' ^ 1,
+            b'b' ^ 2,
+            b'c' ^ 3,
+        ])
+        .unwrap();
+    assert_eq!(read_bytes(&mut client, 5), [0x81, 0x03, b'a', b'b', b'c']);
+
+    // The target disconnected, so the client is sent a close frame with the status code 1001
+    assert_eq!(read_bytes(&mut client, 4), [0x88, 0x02, 0x03, 0xe9]);
+    assert_eq!(client.read(&mut [0]).unwrap(), 0);
+
+    handle.join().unwrap();
+    proxy.join().unwrap();
+
+    assert_eq!(load_balancer.lock().unwrap().active_requests, vec![0]);
+}
+
+#[test]
+fn test_websocket_proxy_close_handshake() {
+    let (target, handle) = start_target(UPGRADE_RESPONSE, |request_line, mut stream| {
+        assert_eq!(request_line, "GET /ws/chat HTTP/1.1");
+
+        let mut frame = [0; 8];
+        stream.read_exact(&mut frame).unwrap();
+        assert_eq!(frame[..2], [0x88, 0x82]);
+
+        // Reply to the close frame but keep the connection open, which the proxy should close
+        stream.write_all(&CLOSE_FRAME).unwrap();
+        assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+    });
+
+    let state = AppState::from(Config::default());
+    let (mut client, request, stream) = connect_client();
+    let proxy = spawn(move || {
+        proxy_websocket(
+            request,
+            stream,
+            &target,
+            &ForwardingConfig::default(),
+            &state,
+        )
+    });
+
+    assert_eq!(
+        read_bytes(&mut client, UPGRADE_RESPONSE.len()),
+        UPGRADE_RESPONSE
+    );
+
+    client
+        .write_all(&[0x88, 0x82, 0x00, 0x00, 0x00, 0x00, 0x03, 0xe8])
+        .unwrap();
+    assert_eq!(read_bytes(&mut client, 4), CLOSE_FRAME);
+    assert_eq!(client.read(&mut [0]).unwrap(), 0);
+
+    handle.join().unwrap();
+    assert!(proxy.join().unwrap().is_ok());
+}
+
+#[test]
+fn test_websocket_proxy_rejected() {
+    let (target, handle) = start_target(
+        b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\nConnection: close\r\n\r\nNot Found",
+        |_, _| {},
+    );
+
+    let state = AppState::from(Config::default());
+    let (mut client, request, stream) = connect_client();
+
+    let result = proxy_websocket(
+        request,
+        stream,
+        &target,
+        &ForwardingConfig::default(),
+        &state,
+    );
+    assert!(result.is_ok());
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.contains("Via: 1.1 humphrey\r\n"));
+    assert!(!response.contains("Connection: close"));
+    assert!(response.contains("\r\n\r\nNot Found"));
+
+    handle.join().unwrap();
+
+    // The target cannot be reached, so the client receives error 502
+    let (mut client, request, stream) = connect_client();
+    let result = proxy_websocket(
+        request,
+        stream,
+        &target,
+        &ForwardingConfig::default(),
+        &state,
+    );
+    assert!(result.is_err());
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+}
+
+#[test]
+fn test_frame_tracker() {
+    let mut tracker = FrameTracker::default();
+
+    // A masked frame with a 16-bit length split across several chunks
+    let mut frame = vec![0x82, 0xfe, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00];
+    frame.extend([0x88; 256]);
+
+    for chunk in frame.chunks(3) {
+        tracker.feed(chunk);
+    }
+
+    assert!(!tracker.closed);
+
+    tracker.feed(&[0x88]);
+    assert!(!tracker.closed);
+
+    tracker.feed(&[0x00]);
+    assert!(tracker.closed);
+}
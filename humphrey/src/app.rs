@@ -2,41 +2,61 @@
 
 #![allow(clippy::new_without_default)]
 
-use crate::http::cors::Cors;
+use crate::http::compression::apply_compression;
+use crate::http::cors::{join_methods, Cors};
 use crate::http::date::DateTime;
 use crate::http::headers::HeaderType;
 use crate::http::method::Method;
-use crate::http::request::{Request, RequestError};
+use crate::http::request::{read_first_byte, BodyLength, Request, RequestBody, RequestError};
 use crate::http::response::Response;
 use crate::http::status::StatusCode;
 use crate::krauss::wildcard_match;
+use crate::listener::{https_redirect, ConnectionMode, Listener, ListenerMode};
 use crate::monitor::event::{Event, EventType};
 use crate::monitor::MonitorConfig;
-use crate::route::{Route, RouteHandler, SubApp};
+use crate::route::{find_route, Handler, RouteHandler, SubApp};
 use crate::stream::Stream;
 use crate::thread::pool::ThreadPool;
 
-use std::io::Write;
+use std::io::{BufRead, BufReader};
 use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
+use std::thread::spawn;
 use std::time::Duration;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 
-#[cfg(windows)]
-mod os_dep {
-    pub use std::os::windows::io::AsRawSocket;
-    pub use winapi::um::winsock2;
-}
-#[cfg(not(windows))]
-mod os_dep {
-    pub use libc;
-    pub use std::os::unix::io::AsRawFd;
-}
-use os_dep::*;
-
+#[cfg(feature = "tls")]
+use crate::http::response::StreamedBody;
+#[cfg(feature = "tls")]
+use crate::http2::connection::Connection;
+#[cfg(feature = "tls")]
+use crate::http2::frame::ErrorCode;
+#[cfg(feature = "tls")]
+use crate::http2::{
+    prepare_response, served_event, CHUNK_SIZE, POLL_INTERVAL, RESPONSE_POLL_INTERVAL,
+};
+#[cfg(feature = "tls")]
+use crate::thread::pool::{Task, ThreadPoolHandle};
+#[cfg(feature = "tls")]
+use crate::tls::{server_config, ClientVerifier};
+#[cfg(feature = "tls")]
+use rustls::server::ResolvesServerCert;
 #[cfg(feature = "tls")]
 use rustls::ServerConfig;
+#[cfg(feature = "tls")]
+use std::collections::VecDeque;
+#[cfg(feature = "tls")]
+use std::io::{ErrorKind, Read, Write};
+#[cfg(feature = "tls")]
+use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+#[cfg(feature = "tls")]
+use std::sync::Mutex;
+#[cfg(feature = "tls")]
+use std::time::Instant;
 
 /// Represents the Humphrey app.
 ///
@@ -56,11 +76,20 @@ where
     connection_handler: ConnectionHandler<State>,
     connection_condition: ConnectionCondition<State>,
     connection_timeout: Option<Duration>,
+    max_body_size: Option<usize>,
+    compression: Option<usize>,
+    middleware: Vec<Arc<dyn Middleware<State>>>,
     shutdown: Option<Arc<AtomicBool>>,
+    drain_timeout: Option<Duration>,
+    listeners: Vec<Listener>,
     #[cfg(feature = "tls")]
     tls_config: Option<Arc<ServerConfig>>,
     #[cfg(feature = "tls")]
+    client_verifier: Option<ClientVerifier>,
+    #[cfg(feature = "tls")]
     force_https: bool,
+    #[cfg(feature = "tls")]
+    http2: bool,
 }
 
 /// Represents a function able to handle a connection.
@@ -72,9 +101,24 @@ pub type ConnectionHandler<State> = fn(
     Arc<ErrorHandler>,
     Arc<State>,
     MonitorConfig,
-    Option<Duration>,
+    &ConnectionConfig,
 );
 
+/// Represents the settings of the app which apply to each connection, which are given to the connection handler.
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct ConnectionConfig {
+    /// The time after which idle connections are closed, if any.
+    pub timeout: Option<Duration>,
+    /// The largest request body which will be read, if any.
+    pub max_body_size: Option<usize>,
+    /// The smallest response body which is compressed, if compression is enabled.
+    pub compression: Option<usize>,
+    /// Set once the app starts shutting down, after which connections should be closed once their current
+    ///   requests have been answered.
+    pub draining: Arc<AtomicBool>,
+}
+
 /// Represents a function able to calculate whether a connection will be accepted.
 pub type ConnectionCondition<State> = fn(&mut TcpStream, Arc<State>) -> bool;
 
@@ -128,11 +172,20 @@ where
             connection_handler: client_handler,
             connection_condition: |_, _| true,
             connection_timeout: None,
+            max_body_size: None,
+            compression: None,
+            middleware: Vec::new(),
             shutdown: None,
+            drain_timeout: None,
+            listeners: Vec::new(),
             #[cfg(feature = "tls")]
             tls_config: None,
             #[cfg(feature = "tls")]
+            client_verifier: None,
+            #[cfg(feature = "tls")]
             force_https: false,
+            #[cfg(feature = "tls")]
+            http2: true,
         }
     }
 
@@ -148,212 +201,214 @@ where
             connection_handler: client_handler,
             connection_condition: |_, _| true,
             connection_timeout: None,
+            max_body_size: None,
+            compression: None,
+            middleware: Vec::new(),
             shutdown: None,
+            drain_timeout: None,
+            listeners: Vec::new(),
             #[cfg(feature = "tls")]
             tls_config: None,
             #[cfg(feature = "tls")]
+            client_verifier: None,
+            #[cfg(feature = "tls")]
             force_https: false,
+            #[cfg(feature = "tls")]
+            http2: true,
         }
     }
 
     /// Runs the Humphrey app on the given socket address.
     /// This function will only return if a fatal error is thrown such as the port being in use.
-    pub fn run<A>(mut self, addr: A) -> Result<(), HumphreyError>
+    pub fn run<A>(self, addr: A) -> Result<(), HumphreyError>
     where
         A: ToSocketAddrs,
     {
         let socket = TcpListener::bind(addr)?;
 
-        if self.shutdown.is_some() {
-            socket.set_nonblocking(true).expect("Cannot set non-blocking");
-        }
-
-        let subapps = Arc::new(self.subapps);
-        let default_subapp = Arc::new(self.default_subapp);
-        let error_handler = Arc::new(self.error_handler);
-
-        self.thread_pool.register_monitor(self.monitor.clone());
-        self.thread_pool.start();
-
-        for stream in socket.incoming() {
-            match stream {
-                Ok(mut stream) => {
-                    let cloned_state = self.state.clone();
-
-                    // Check that the client is allowed to connect
-                    if (self.connection_condition)(&mut stream, cloned_state) {
-                        let cloned_state = self.state.clone();
-                        let cloned_monitor = self.monitor.clone();
-                        let cloned_subapps = subapps.clone();
-                        let cloned_default_subapp = default_subapp.clone();
-                        let cloned_error_handler = error_handler.clone();
-                        let cloned_handler = self.connection_handler;
-                        let cloned_timeout = self.connection_timeout;
-
-                        cloned_monitor.send(
-                            Event::new(EventType::ConnectionSuccess)
-                                .with_peer_result(stream.peer_addr()),
-                        );
-
-                        // Spawn a new thread to handle the connection
-                        self.thread_pool.execute(move || {
-                            cloned_monitor.send(
-                                Event::new(EventType::ThreadPoolProcessStarted)
-                                    .with_peer_result(stream.peer_addr()),
-                            );
-
-                            (cloned_handler)(
-                                Stream::Tcp(stream),
-                                cloned_subapps,
-                                cloned_default_subapp,
-                                cloned_error_handler,
-                                cloned_state,
-                                cloned_monitor,
-                                cloned_timeout,
-                            )
-                        });
-                    } else {
-                        self.monitor.send(
-                            Event::new(EventType::ConnectionDenied)
-                                .with_peer_result(stream.peer_addr()),
-                        );
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    if let Some(ref shutdown) = self.shutdown {
-                        if shutdown.load(Ordering::Relaxed) { break }
-					}
-					std::thread::sleep(std::time::Duration::from_millis(10));
-                },
-                Err(e) => self
-                    .monitor
-                    .send(Event::new(EventType::ConnectionError).with_info(e.to_string())),
-            }
-        }
-		unsafe {
-            #[cfg(windows)]
-            winsock2::closesocket(socket.as_raw_socket() as usize);
-            #[cfg(not(windows))]
-            libc::close(socket.as_raw_fd());
-        }
-		self.thread_pool.stop();
-        drop(self.thread_pool);
-        Ok(())
+        self.serve(vec![(socket, ConnectionMode::Http)])
     }
 
     /// Securely runs the Humphrey app on the given socket address.
     /// This function will only return if a fatal error is thrown such as the port being in use or the TLS certificate being invalid.
     #[cfg(feature = "tls")]
-    pub fn run_tls<A>(mut self, addr: A) -> Result<(), HumphreyError>
+    pub fn run_tls<A>(self, addr: A) -> Result<(), HumphreyError>
     where
         A: ToSocketAddrs,
     {
-        use rustls::ServerConnection;
-
         let socket = TcpListener::bind(addr)?;
+        let https_port = socket.local_addr()?.port();
+        let mut listeners = vec![(
+            socket,
+            ConnectionMode::Https(self.tls_server_config(None, None)),
+        )];
+
+        if self.force_https {
+            listeners.push(self.bind(&Listener::redirect("0.0.0.0:80", https_port))?);
+        }
+
+        self.serve(listeners)
+    }
 
-        if self.shutdown.is_some() {
-            socket.set_nonblocking(true).expect("Cannot set non-blocking");
+    /// Runs the Humphrey app on every listener added with `with_listener`, sharing the routes between them.
+    /// This function will only return if a fatal error is thrown such as a port being in use or a TLS certificate
+    ///   being invalid.
+    pub fn run_listeners(self) -> Result<(), HumphreyError> {
+        if self.listeners.is_empty() {
+            return Err("No listeners were added to the app".into());
         }
 
+        let listeners = self
+            .listeners
+            .iter()
+            .map(|listener| self.bind(listener))
+            .collect::<Result<Vec<_>, HumphreyError>>()?;
+
+        self.serve(listeners)
+    }
+
+    /// Binds the socket of a listener, returning it along with how its connections are handled.
+    fn bind(&self, listener: &Listener) -> Result<(TcpListener, ConnectionMode), HumphreyError> {
+        let socket = TcpListener::bind(&listener.addr)?;
+        Ok((socket, self.connection_mode(&listener.mode)))
+    }
+
+    /// Accepts connections on the given listeners until the app shuts down, handling them according to the
+    ///   listener they were accepted on.
+    fn serve(mut self, listeners: Vec<(TcpListener, ConnectionMode)>) -> Result<(), HumphreyError> {
+        self.apply_middleware();
+
         let subapps = Arc::new(self.subapps);
         let default_subapp = Arc::new(self.default_subapp);
         let error_handler = Arc::new(self.error_handler);
+        let draining = Arc::new(AtomicBool::new(false));
+        let connection_config = ConnectionConfig {
+            timeout: self.connection_timeout,
+            max_body_size: self.max_body_size,
+            compression: self.compression,
+            draining: draining.clone(),
+        };
 
         self.thread_pool.register_monitor(self.monitor.clone());
         self.thread_pool.start();
 
-        if self.force_https {
-            let cloned_monitor = self.monitor.clone();
+        // Each listener accepts connections on its own thread, passing them to this thread to be checked and handled
+        let (tx, rx) = channel();
+        let mut accept_threads = Vec::with_capacity(listeners.len());
 
-            if self.thread_pool.thread_count() < 2 {
-                println!("Error: A minimum of two threads are required to force HTTPS since one is required for redirects.");
-                std::process::exit(1);
-            }
-
-            self.thread_pool
-                .execute(|| force_https_thread(cloned_monitor).unwrap_or(()));
-        }
-
-        for sock in socket.incoming() {
-            match sock {
-                Ok(mut sock) => {
-                    let cloned_state = self.state.clone();
-
-                    // Check that the client is allowed to connect
-                    if (self.connection_condition)(&mut sock, cloned_state) {
-                        let cloned_state = self.state.clone();
-                        let cloned_subapps = subapps.clone();
-                        let cloned_default_subapp = default_subapp.clone();
-                        let cloned_error_handler = error_handler.clone();
-                        let cloned_handler = self.connection_handler;
-                        let cloned_timeout = self.connection_timeout;
-                        let cloned_monitor = self.monitor.clone();
-                        let cloned_config = self
-                            .tls_config
-                            .as_ref()
-                            .expect("TLS certificate not supplied")
-                            .clone();
-
-                        cloned_monitor.send(
-                            Event::new(EventType::ConnectionSuccess)
-                                .with_peer_result(sock.peer_addr()),
-                        );
-
-                        // Spawn a new thread to handle the connection
-                        self.thread_pool.execute(move || {
-                            cloned_monitor.send(
-                                Event::new(EventType::ThreadPoolProcessStarted)
-                                    .with_peer_result(sock.peer_addr()),
-                            );
+        for (socket, mode) in listeners {
+            let tx = tx.clone();
+            let shutdown = self.shutdown.clone();
+            let monitor = self.monitor.clone();
 
-                            let server = ServerConnection::new(cloned_config).unwrap();
-                            let tls_stream = rustls::StreamOwned::new(server, sock);
-                            let stream = Stream::Tls(tls_stream);
+            accept_threads.push(spawn(move || {
+                accept_connections(socket, mode, tx, shutdown, monitor)
+            }));
+        }
 
-                            (cloned_handler)(
+        drop(tx);
+
+        // This finishes once every listener has stopped accepting connections
+        for (mut stream, mode) in rx {
+            let cloned_state = self.state.clone();
+
+            // Check that the client is allowed to connect
+            if (self.connection_condition)(&mut stream, cloned_state) {
+                let cloned_state = self.state.clone();
+                let cloned_monitor = self.monitor.clone();
+                let cloned_subapps = subapps.clone();
+                let cloned_default_subapp = default_subapp.clone();
+                let cloned_error_handler = error_handler.clone();
+                let cloned_handler = self.connection_handler;
+                let cloned_config = connection_config.clone();
+
+                cloned_monitor.send(
+                    Event::new(EventType::ConnectionSuccess).with_peer_result(stream.peer_addr()),
+                );
+
+                // Spawn a new thread to handle the connection
+                self.thread_pool.execute(move || {
+                    cloned_monitor.send(
+                        Event::new(EventType::ThreadPoolProcessStarted)
+                            .with_peer_result(stream.peer_addr()),
+                    );
+
+                    let stream = match mode {
+                        ConnectionMode::Http => Stream::Tcp(stream),
+                        #[cfg(feature = "tls")]
+                        ConnectionMode::Https(config) => {
+                            let server = rustls::ServerConnection::new(config).unwrap();
+                            Stream::Tls(rustls::StreamOwned::new(server, stream))
+                        }
+                        ConnectionMode::Redirect(https_port) => {
+                            redirect_to_https(
                                 stream,
-                                cloned_subapps,
-                                cloned_default_subapp,
-                                cloned_error_handler,
-                                cloned_state,
+                                https_port,
+                                cloned_config.timeout,
                                 cloned_monitor,
-                                cloned_timeout,
-                            )
-                        });
-                    } else {
-                        self.monitor.send(
-                            Event::new(EventType::ConnectionDenied)
-                                .with_peer_result(sock.peer_addr()),
-                        );
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    if let Some(ref shutdown) = self.shutdown {
-                        if shutdown.load(Ordering::Relaxed) { break }
-                    }
-                },
-                Err(e) => self
-                    .monitor
-                    .send(Event::new(EventType::ConnectionError).with_info(e.to_string())),
+                            );
+                            return;
+                        }
+                    };
+
+                    (cloned_handler)(
+                        stream,
+                        cloned_subapps,
+                        cloned_default_subapp,
+                        cloned_error_handler,
+                        cloned_state,
+                        cloned_monitor,
+                        &cloned_config,
+                    )
+                });
+            } else {
+                self.monitor.send(
+                    Event::new(EventType::ConnectionDenied).with_peer_result(stream.peer_addr()),
+                );
             }
         }
-		unsafe {
-            #[cfg(windows)]
-            winsock2::closesocket(socket.as_raw_socket() as usize);
-            #[cfg(not(windows))]
-            libc::close(socket.as_raw_fd());
+
+        // Stop accepting connections, then let the current requests finish and close idle connections
+        for thread in accept_threads {
+            thread.join().ok();
         }
-		self.thread_pool.stop();
+
+        draining.store(true, Ordering::Relaxed);
+
+        self.thread_pool.set_drain_timeout(self.drain_timeout);
+        self.thread_pool.stop();
+
         Ok(())
     }
 
     /// Adds a flag for shutdown. When it becomes true, the loop in run or run_tls stops.
+    ///
+    /// The app then stops accepting connections and closes idle keep-alive connections.
+    ///   Requests which are already being handled are allowed to finish for up to the drain timeout,
+    ///   set with `with_drain_timeout`.
     pub fn with_shutdown(mut self, shutdown: Arc<AtomicBool>) -> Self {
         self.shutdown = Some(shutdown);
         self
     }
 
+    /// Sets the maximum amount of time to wait for requests which are being handled to finish when the app shuts down.
+    ///   Defaults to `None`, in which case `run` returns without waiting.
+    ///
+    /// Connections are closed once their current request has been answered.
+    pub fn with_drain_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Adds a listener on which the app accepts connections when it is run with `run_listeners`.
+    ///
+    /// Listeners can serve the app over HTTP or HTTPS, or redirect to HTTPS, and every listener shares the app's
+    ///   routes.
+    pub fn with_listener(mut self, listener: Listener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
     /// Sets the default state for the server.
     /// Should only be used in cases where the `Default` trait cannot be implemented for `State`.
     /// For example, if the default state is dynamically generated as it is in the CLI.
@@ -413,6 +468,77 @@ where
         self
     }
 
+    /// Adds a route and associated streaming handler to the server.
+    /// Routes can include wildcards, for example `/upload/*`.
+    /// The request body is not read into memory, but is instead passed to the handler to be read
+    ///   from the connection as it arrives. The maximum body size does not apply to it.
+    pub fn with_streaming_route<T>(mut self, route: &str, handler: T) -> Self
+    where
+        T: StreamingRequestHandler<State> + 'static,
+    {
+        self.default_subapp = self.default_subapp.with_streaming_route(route, handler);
+        self
+    }
+
+    /// Adds a route and associated handler to the server which only matches requests with the given method.
+    /// Routes can include wildcards, for example `/blog/*`.
+    ///
+    /// If requests are made to the route with a method which has no handler, they are rejected with
+    ///   `405 Method Not Allowed`. `HEAD` requests are handled by the `GET` handler if there is no `HEAD` handler,
+    ///   and `OPTIONS` requests are answered automatically if there is no `OPTIONS` handler.
+    pub fn with_method_route<T>(mut self, method: Method, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.default_subapp = self.default_subapp.with_method_route(method, route, handler);
+        self
+    }
+
+    /// Adds a route and associated handler to the server which only matches `GET` and `HEAD` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_get<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Get, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `POST` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_post<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Post, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `PUT` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_put<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Put, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `PATCH` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_patch<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Patch, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `DELETE` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_delete<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Delete, route, handler)
+    }
+
     /// Adds a WebSocket route and associated handler to the server.
     /// Routes can include wildcards, for example `/ws/*`.
     /// The handler is passed the stream, state, and the request which triggered its calling.
@@ -456,6 +582,48 @@ where
         self
     }
 
+    /// Sets the maximum size of a request body in bytes, or `None` for no limit. Defaults to `None`.
+    ///
+    /// Requests with larger bodies are rejected with `413 Payload Too Large` before the body is read.
+    /// This does not apply to routes with streaming handlers, which read the body themselves.
+    /// Bodies received over HTTP/2 are always read into memory, so the limit applies to every route.
+    pub fn with_max_body_size(mut self, max_body_size: Option<usize>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Sets the minimum size in bytes of response bodies to compress, or `None` to disable compression. Defaults to `None`.
+    ///
+    /// Responses are compressed with gzip or deflate, depending on the client's `Accept-Encoding` header,
+    ///   if their MIME type is compressible. See `humphrey::http::compression` for more details.
+    pub fn with_compression(mut self, min_size: Option<usize>) -> Self {
+        self.compression = min_size;
+        self
+    }
+
+    /// Adds middleware to the app, which runs for every request to every host.
+    ///
+    /// App middleware runs before the middleware of sub-apps and routes, in the order it was added.
+    ///   See the `Middleware` trait for more details.
+    pub fn with_middleware<T>(mut self, middleware: T) -> Self
+    where
+        T: Middleware<State> + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Adds middleware to the specified route, which runs after the app's middleware.
+    ///
+    /// The route must already have been added, since the middleware is attached to its handler.
+    pub fn with_route_middleware<T>(mut self, route: &str, middleware: T) -> Self
+    where
+        T: Middleware<State> + 'static,
+    {
+        self.default_subapp = self.default_subapp.with_route_middleware(route, middleware);
+        self
+    }
+
     /// Sets the CORS configuration for the app.
     ///
     /// This overrides the CORS configuration for existing and future individual routes.
@@ -476,8 +644,8 @@ where
 
     /// Sets whether HTTPS should be forced on all connections. Defaults to false.
     ///
-    /// If this is set to true, a background thread will be spawned when `run_tls` is called to send
-    ///   redirect responses to all insecure requests on port 80.
+    /// If this is set to true, `run_tls` also listens on port 80 and sends redirect responses to all insecure
+    ///   requests. To redirect from a different port, use `run_listeners` with a `Listener::redirect` listener.
     #[cfg(feature = "tls")]
     pub fn with_forced_https(mut self, forced: bool) -> Self {
         self.force_https = forced;
@@ -523,6 +691,43 @@ where
         self
     }
 
+    /// Sets the TLS configuration for the server to select the certificate for each connection using the given
+    ///   resolver, for example a `CertResolver` which selects the certificate by the requested host name.
+    ///
+    /// This, or `with_cert`, **must** be called before `run_tls` is called.
+    #[cfg(feature = "tls")]
+    pub fn with_cert_resolver(mut self, resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        let config = Arc::new(
+            ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_cert_resolver(resolver),
+        );
+
+        self.tls_config = Some(config);
+
+        self
+    }
+
+    /// Sets the verifier used to authenticate clients with TLS client certificates signed by a trusted CA.
+    ///
+    /// The verified identity of the client is available to handlers as `request.peer_identity`.
+    #[cfg(feature = "tls")]
+    pub fn with_client_auth(mut self, verifier: ClientVerifier) -> Self {
+        self.client_verifier = Some(verifier);
+        self
+    }
+
+    /// Sets whether clients connecting over TLS may use HTTP/2, which they negotiate with ALPN. Defaults to true.
+    ///
+    /// Clients which do not support HTTP/2 use HTTP/1.1 either way. WebSocket routes are only available over
+    ///   HTTP/1.1, so clients which support HTTP/2 connect to them using a separate HTTP/1.1 connection.
+    #[cfg(feature = "tls")]
+    pub fn with_http2(mut self, http2: bool) -> Self {
+        self.http2 = http2;
+        self
+    }
+
     /// Adds a global WebSocket handler to the server.
     ///
     /// ## Deprecated
@@ -539,11 +744,69 @@ where
 
     /// Overrides the default connection handler, allowing for manual control over the TCP requests and responses.
     /// Not recommended as it basically disables most of the server's features.
+    ///
+    /// Since the handler is given the raw connection, this also disables HTTP/2.
     pub fn with_custom_connection_handler(mut self, handler: ConnectionHandler<State>) -> Self {
         self.connection_handler = handler;
+
+        #[cfg(feature = "tls")]
+        {
+            self.http2 = false;
+        }
+
         self
     }
 
+    /// Adds the app's middleware to the start of every sub-app's middleware, so that it runs first.
+    fn apply_middleware(&mut self) {
+        for subapp in self
+            .subapps
+            .iter_mut()
+            .chain(std::iter::once(&mut self.default_subapp))
+        {
+            subapp.middleware.splice(0..0, self.middleware.iter().cloned());
+        }
+    }
+
+    /// Gets how connections to a listener are handled, using the app's TLS configuration unless the listener has
+    ///   its own.
+    fn connection_mode(&self, mode: &ListenerMode) -> ConnectionMode {
+        match mode {
+            ListenerMode::Http => ConnectionMode::Http,
+            #[cfg(feature = "tls")]
+            ListenerMode::Https {
+                cert_resolver,
+                client_verifier,
+            } => ConnectionMode::Https(
+                self.tls_server_config(cert_resolver.clone(), client_verifier.as_ref()),
+            ),
+            ListenerMode::Redirect(https_port) => ConnectionMode::Redirect(*https_port),
+        }
+    }
+
+    /// Gets the TLS configuration for the server, using the app's certificate and client authentication unless
+    ///   others are given.
+    #[cfg(feature = "tls")]
+    fn tls_server_config(
+        &self,
+        cert_resolver: Option<Arc<dyn ResolvesServerCert>>,
+        client_verifier: Option<&ClientVerifier>,
+    ) -> Arc<ServerConfig> {
+        let cert_resolver = cert_resolver.unwrap_or_else(|| {
+            self.tls_config
+                .as_ref()
+                .expect("TLS certificate not supplied")
+                .cert_resolver
+                .clone()
+        });
+
+        server_config(
+            cert_resolver,
+            client_verifier.or(self.client_verifier.as_ref()),
+            self.http2,
+        )
+    }
+
     /// Gets a reference to the app's state.
     /// This should only be used in the main thread, as the state is passed to request handlers otherwise.
     pub fn get_state(&self) -> Arc<State> {
@@ -554,7 +817,6 @@ where
 /// Handles a connection with a client.
 /// The connection will be opened upon the first request and closed as soon as a request is
 ///   received without the `Connection: Keep-Alive` header.
-#[allow(clippy::too_many_arguments)]
 fn client_handler<State>(
     mut stream: Stream,
     subapps: Arc<Vec<SubApp<State>>>,
@@ -562,8 +824,13 @@ fn client_handler<State>(
     error_handler: Arc<ErrorHandler>,
     state: Arc<State>,
     monitor: MonitorConfig,
-    timeout: Option<Duration>,
-) {
+    config: &ConnectionConfig,
+) where
+    State: Send + Sync + 'static,
+{
+    let timeout = config.timeout;
+    let draining = &config.draining;
+
     let addr = if let Ok(addr) = stream.peer_addr() {
         addr
     } else {
@@ -573,11 +840,41 @@ fn client_handler<State>(
     };
 
     loop {
-        // Parses the request from the stream
-        let request = match timeout {
-            Some(timeout) => Request::from_stream_with_timeout(&mut stream, addr, timeout),
-            None => Request::from_stream(&mut stream, addr),
-        };
+        // Parses the head of the request from the stream
+        let mut reader = BufReader::new(&mut stream);
+        let first_byte = read_first_byte(&mut reader, timeout, draining);
+
+        // Clients which negotiated HTTP/2 send its preface instead of a request
+        #[cfg(feature = "tls")]
+        if let Ok(first_byte) = first_byte {
+            if reader.get_ref().is_http2() {
+                let mut received = vec![first_byte];
+                received.extend_from_slice(reader.buffer());
+                drop(reader);
+
+                http2_handler(
+                    stream,
+                    addr,
+                    &received,
+                    &subapps,
+                    &default_subapp,
+                    *error_handler,
+                    &state,
+                    &monitor,
+                    config,
+                );
+
+                break;
+            }
+        }
+
+        let mut request = first_byte
+            .and_then(|first_byte| Request::head_from_reader(&mut reader, addr, first_byte));
+
+        if let Ok(request) = &mut request {
+            request.peer_identity = reader.get_ref().peer_identity();
+            request.https = reader.get_ref().is_tls();
+        }
 
         let cloned_state = state.clone();
 
@@ -586,6 +883,7 @@ fn client_handler<State>(
             if req.headers.get(&HeaderType::Upgrade) == Some("websocket") {
                 monitor.send(Event::new(EventType::WebsocketConnectionRequested).with_peer(addr));
 
+                drop(reader);
                 call_websocket_handler(req, &subapps, &default_subapp, cloned_state, stream);
 
                 monitor.send(Event::new(EventType::WebsocketConnectionClosed).with_peer(addr));
@@ -593,8 +891,13 @@ fn client_handler<State>(
             }
         }
 
+        let (subapp, handler, allowed_methods) = match &mut request {
+            Ok(request) => get_handler(request, &subapps, &default_subapp),
+            Err(_) => (default_subapp.as_ref(), None, Vec::new()),
+        };
+
         // Get the keep alive information from the request before it is consumed by the handler
-        let keep_alive = if let Ok(request) = &request {
+        let mut keep_alive = if let Ok(request) = &request {
             if let Some(connection) = request.headers.get(&HeaderType::Connection) {
                 connection.to_ascii_lowercase() == "keep-alive"
             } else {
@@ -605,50 +908,41 @@ fn client_handler<State>(
         };
 
         // Generate the response based on the handlers
-        let response = match &request {
-            Ok(request) if request.method == Method::Options => {
-                let handler = get_handler(request, &subapps, &default_subapp);
-
-                match handler {
-                    Some(handler) => {
-                        let mut response = Response::empty(StatusCode::NoContent)
-                            .with_header(HeaderType::Date, DateTime::now().to_string())
-                            .with_header(HeaderType::Server, "Humphrey")
-                            .with_header(
-                                HeaderType::Connection,
-                                match keep_alive {
-                                    true => "Keep-Alive",
-                                    false => "Close",
-                                },
-                            );
-
-                        handler.cors.set_headers(&mut response.headers);
-
-                        response
-                    }
-                    None => error_handler(StatusCode::NotFound),
+        let handled = request.and_then(|request| {
+            route_response(
+                request,
+                &mut reader,
+                subapp,
+                handler,
+                &allowed_methods,
+                *error_handler,
+                &state,
+                config.max_body_size,
+                config.compression,
+            )
+        });
+
+        let (request, response) = match handled {
+            Ok((request, mut response, body_read)) => {
+                // If the body was not read, it is still on the connection, so it cannot be reused
+                if !body_read {
+                    keep_alive = false;
                 }
-            }
-            Ok(request) => {
-                let handler = get_handler(request, &subapps, &default_subapp);
 
-                let mut response = match handler {
-                    Some(handler) => {
-                        let mut response: Response =
-                            handler.handler.serve(request.clone(), state.clone());
-
-                        handler.cors.set_headers(&mut response.headers);
-
-                        response
-                    }
-                    None => error_handler(StatusCode::NotFound),
-                };
+                // Connections are closed after their current request once the app starts shutting down
+                if draining.load(Ordering::Relaxed) {
+                    keep_alive = false;
+                }
 
                 // Automatically generate required headers
                 match response.headers.get_mut(HeaderType::Connection) {
                     Some(_) => (),
                     None => {
-                        if let Some(connection) = &request.headers.get(&HeaderType::Connection) {
+                        if !keep_alive {
+                            response.headers.add(HeaderType::Connection, "Close");
+                        } else if let Some(connection) =
+                            &request.headers.get(&HeaderType::Connection)
+                        {
                             response.headers.add(HeaderType::Connection, connection);
                         } else {
                             response.headers.add(HeaderType::Connection, "Close");
@@ -674,33 +968,66 @@ fn client_handler<State>(
 
                 match response.headers.get_mut(HeaderType::ContentLength) {
                     Some(_) => (),
-                    None => {
-                        response
-                            .headers
-                            .add(HeaderType::ContentLength, response.body.len().to_string());
-                    }
+                    // `304 Not Modified` responses never have a body, so the length of the unsent content is unknown
+                    None if response.status_code == StatusCode::NotModified => (),
+                    // `204 No Content` responses must not have a `Content-Length` header
+                    None if response.status_code == StatusCode::NoContent => (),
+                    None => match response.content_length() {
+                        Some(length) => {
+                            response
+                                .headers
+                                .add(HeaderType::ContentLength, length.to_string());
+                        }
+                        None if request.version == "HTTP/1.0" => {
+                            // Chunked encoding is not supported, so the end of the body is marked by closing the connection
+                            keep_alive = false;
+                            response.headers.remove(HeaderType::Connection);
+                            response.headers.add(HeaderType::Connection, "Close");
+                        }
+                        None => {
+                            if response.headers.get(HeaderType::TransferEncoding).is_none() {
+                                response
+                                    .headers
+                                    .add(HeaderType::TransferEncoding, "chunked");
+                            }
+                        }
+                    },
+                }
+
+                // Responses to `HEAD` requests have the same headers as those to `GET` requests, but no body
+                if request.method == Method::Head {
+                    response.body.clear();
+                    response.stream = None;
                 }
 
                 // Set HTTP version
                 response.version = request.version.clone();
 
-                response
+                (Ok(request), response)
+            }
+            Err(e) => {
+                let response = match e {
+                    RequestError::Request => error_handler(StatusCode::BadRequest),
+                    RequestError::Timeout => error_handler(StatusCode::RequestTimeout),
+                    RequestError::PayloadTooLarge => {
+                        error_handler(StatusCode::RequestEntityTooLarge)
+                            .with_header(HeaderType::Connection, "Close")
+                    }
+                    RequestError::Disconnected => return,
+                    RequestError::Stream => {
+                        return monitor.send(Event::new(EventType::RequestServedError))
+                    }
+                };
+
+                (Err(e), response)
             }
-            Err(e) => match e {
-                RequestError::Request => error_handler(StatusCode::BadRequest),
-                RequestError::Timeout => error_handler(StatusCode::RequestTimeout),
-                RequestError::Disconnected => return,
-                RequestError::Stream => {
-                    return monitor.send(Event::new(EventType::RequestServedError))
-                }
-            },
         };
 
         // Write the response to the stream
+        drop(reader);
         let status = response.status_code;
-        let response_bytes: Vec<u8> = response.into();
 
-        if let Err(e) = stream.write_all(&response_bytes) {
+        if let Err(e) = response.write_to(&mut stream) {
             monitor.send(
                 Event::new(EventType::RequestServedError)
                     .with_peer(addr)
@@ -740,8 +1067,8 @@ fn client_handler<State>(
             }
         }
 
-        // If the request specified to keep the connection open, respect this
-        if !keep_alive {
+        // If the request specified to keep the connection open, respect this unless the app is shutting down
+        if !keep_alive || draining.load(Ordering::Relaxed) {
             break;
         }
 
@@ -751,43 +1078,472 @@ fn client_handler<State>(
     monitor.send(Event::new(EventType::ConnectionClosed).with_peer(addr));
 }
 
-/// Gets the correct handler for the given request.
+/// Gets the most specific handler for the given request, along with the sub-app it belongs to and the methods
+///   allowed at its route, and sets the request's parameters from its route.
+///
+/// If no route matches, the sub-app for the request's host is returned, or the default sub-app if there is none.
+///   If a route matches but cannot handle the request's method, no handler is returned, but the allowed methods are.
 pub(crate) fn get_handler<'a, State>(
-    request: &'a Request,
+    request: &mut Request,
     subapps: &'a [SubApp<State>],
     default_subapp: &'a SubApp<State>,
-) -> Option<&'a RouteHandler<State>> {
+) -> (
+    &'a SubApp<State>,
+    Option<&'a RouteHandler<State>>,
+    Vec<Method>,
+) {
+    let mut host_subapp = None;
+
     // Iterate over the sub-apps and find the one which matches the host
     if let Some(host) = request.headers.get(&HeaderType::Host) {
         if let Some(subapp) = subapps
             .iter()
             .find(|subapp| wildcard_match(&subapp.host, host))
         {
-            // If the sub-app has a handler for this route, call it
-            if let Some(handler) = subapp
-                .routes // Get the routes of the sub-app
-                .iter() // Iterate over the routes
-                .find(|route| route.route.route_matches(&request.uri))
-            // Find the route that matches
-            {
-                return Some(handler);
+            // If the sub-app has a route for this path, use it
+            if let Some(route) = subapp.find_handler(&request.uri, &request.method) {
+                request.params = route.params;
+                return (subapp, route.handler, route.allowed_methods);
             }
+
+            host_subapp = Some(subapp);
         }
     }
 
     // If no sub-app was found, try to use the handler on the default sub-app
-    if let Some(handler) = default_subapp
-        .routes
+    if let Some(route) = default_subapp.find_handler(&request.uri, &request.method) {
+        request.params = route.params;
+        return (default_subapp, route.handler, route.allowed_methods);
+    }
+
+    (host_subapp.unwrap_or(default_subapp), None, Vec::new())
+}
+
+/// Generates the automatic response to a CORS preflight or other `OPTIONS` request for a route with no handler
+///   specifically for them.
+pub(crate) fn preflight_response<State>(
+    handler: &RouteHandler<State>,
+    allowed_methods: &[Method],
+) -> Response {
+    let mut response = Response::empty(StatusCode::NoContent)
+        .with_header(HeaderType::Date, DateTime::now().to_string())
+        .with_header(HeaderType::Server, "Humphrey")
+        .with_header(HeaderType::Allow, join_methods(allowed_methods));
+
+    handler
+        .cors
+        .set_preflight_headers(&mut response.headers, allowed_methods);
+
+    response
+}
+
+/// Generates the response to a request using the given handler, running the middleware around it and
+///   compressing the response if enabled.
+///
+/// The middleware runs before the body is read, so it can reject requests without reading their bodies. The body
+///   is then read from the reader, unless the handler streams it. Returns the request as modified by the middleware,
+///   the response, and whether the whole body was read, or an error if the body could not be read.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn route_response<State>(
+    mut request: Request,
+    reader: &mut dyn BufRead,
+    subapp: &SubApp<State>,
+    handler: Option<&RouteHandler<State>>,
+    allowed_methods: &[Method],
+    error_handler: ErrorHandler,
+    state: &Arc<State>,
+    max_body_size: Option<usize>,
+    compression: Option<usize>,
+) -> Result<(Request, Response, bool), RequestError> {
+    // The body length is taken before the middleware runs, since it may change the headers
+    let body_length = request.body_length().ok();
+    let mut body_read = true;
+
+    // `OPTIONS` requests are answered automatically unless the route has a handler specifically for them
+    let preflight = handler.is_some_and(|handler| {
+        request.method == Method::Options && handler.method != Some(Method::Options)
+    });
+
+    let streaming = !preflight
+        && matches!(
+            handler,
+            Some(RouteHandler {
+                handler: Handler::Streaming(_),
+                ..
+            })
+        );
+
+    let middleware: Vec<&Arc<dyn Middleware<State>>> = subapp
+        .middleware
         .iter()
-        .find(|route| route.route.route_matches(&request.uri))
-    {
-        return Some(handler);
+        .chain(handler.iter().flat_map(|handler| handler.middleware.iter()))
+        .collect();
+
+    // Run the middleware in order until one of them responds directly, if any
+    let mut early_response = None;
+    let mut middleware_run = 0;
+
+    for middleware in &middleware {
+        early_response = middleware.before(&mut request, state.clone());
+
+        if early_response.is_some() {
+            break;
+        }
+
+        middleware_run += 1;
     }
 
-    None
+    // Read the body of the request unless the middleware responded directly or the handler will stream it
+    if early_response.is_none() && !streaming {
+        match request.read_body(reader, max_body_size) {
+            Ok(()) => (),
+            Err(RequestError::PayloadTooLarge) => {
+                early_response = Some(
+                    error_handler(StatusCode::RequestEntityTooLarge)
+                        .with_header(HeaderType::Connection, "Close"),
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut response = match (early_response, handler) {
+        (Some(response), _) => {
+            // If the middleware responded directly, the body has not been read
+            if request.content.is_none() && body_length != Some(BodyLength::Fixed(0)) {
+                body_read = false;
+            }
+
+            response
+        }
+        (None, Some(handler)) if preflight => preflight_response(handler, allowed_methods),
+        (None, Some(handler)) => match &handler.handler {
+            Handler::Buffered(handler) => handler.serve(request.clone(), state.clone()),
+            Handler::Streaming(handler) => match body_length {
+                Some(length) => {
+                    let mut body = RequestBody::new(reader, length);
+                    let response = handler.serve(request.clone(), &mut body, state.clone());
+
+                    body_read = body.is_finished();
+
+                    response
+                }
+                None => {
+                    body_read = false;
+                    error_handler(StatusCode::BadRequest)
+                }
+            },
+        },
+        (None, None) if allowed_methods.is_empty() => error_handler(StatusCode::NotFound),
+        (None, None) => error_handler(StatusCode::MethodNotAllowed)
+            .with_header(HeaderType::Allow, join_methods(allowed_methods)),
+    };
+
+    match handler {
+        Some(handler) if !preflight => handler.cors.set_headers(&mut response.headers),
+        _ => (),
+    }
+
+    // Run the middleware which ran before the handler again in reverse order
+    for middleware in middleware[..middleware_run].iter().rev() {
+        middleware.after(&request, &mut response, state.clone());
+    }
+
+    if let Some(min_size) = compression {
+        response = apply_compression(&request, response, min_size);
+    }
+
+    Ok((request, response, body_read))
 }
 
-/// Calls the correct WebSocket handler for the given request.
+/// Handles a connection with a client which negotiated HTTP/2, starting with the data already received.
+///
+/// Each request is handled by a thread in the pool, and the responses are sent as quickly as the client's flow
+///   control allows. Requests which no thread has started handling within the poll interval are handled by the
+///   connection's own thread instead, so that the connection still makes progress when every thread is busy.
+#[cfg(feature = "tls")]
+#[allow(clippy::too_many_arguments)]
+fn http2_handler<State>(
+    mut stream: Stream,
+    addr: SocketAddr,
+    received: &[u8],
+    subapps: &Arc<Vec<SubApp<State>>>,
+    default_subapp: &Arc<SubApp<State>>,
+    error_handler: ErrorHandler,
+    state: &Arc<State>,
+    monitor: &MonitorConfig,
+    config: &ConnectionConfig,
+) where
+    State: Send + Sync + 'static,
+{
+    let compression = config.compression;
+    let peer_identity = stream.peer_identity();
+    let pool = ThreadPoolHandle::current();
+    let mut connection = Connection::new(addr, config.max_body_size);
+    let jobs: Arc<Mutex<VecDeque<(Instant, Task)>>> = Arc::new(Mutex::new(VecDeque::new()));
+    let (response_tx, response_rx) = channel();
+    let mut bodies: Vec<(u32, StreamedBody)> = Vec::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut last_received = Instant::now();
+
+    // After an error, the connection is finished once the `GOAWAY` frame has been sent
+    let mut requests = connection.receive(received).unwrap_or_default();
+
+    loop {
+        // Give every new request to the pool
+        for (stream_id, request) in requests.drain(..) {
+            let request = request.map(|mut request| {
+                request.peer_identity = peer_identity.clone();
+                request
+            });
+
+            let subapps = subapps.clone();
+            let default_subapp = default_subapp.clone();
+            let state = state.clone();
+            let response_tx = response_tx.clone();
+
+            let job: Task = Box::new(move || {
+                let response = catch_unwind(AssertUnwindSafe(|| {
+                    http2_response(
+                        request,
+                        &subapps,
+                        &default_subapp,
+                        error_handler,
+                        &state,
+                        compression,
+                    )
+                }));
+
+                // The stream is reset if the handler panics, and the panic continues so the thread is recovered
+                match response {
+                    Ok(response) => response_tx.send((stream_id, Some(response))).ok(),
+                    Err(panic) => {
+                        response_tx.send((stream_id, None)).ok();
+                        resume_unwind(panic)
+                    }
+                };
+            });
+
+            jobs.lock().unwrap().push_back((Instant::now(), job));
+
+            if let Some(pool) = &pool {
+                let jobs = jobs.clone();
+
+                pool.execute(move || {
+                    let job = jobs.lock().unwrap().pop_front();
+
+                    if let Some((_, job)) = job {
+                        job();
+                    }
+                });
+            }
+        }
+
+        // Handle a request here if the pool has not got to it
+        let stalled = {
+            let mut jobs = jobs.lock().unwrap();
+
+            match jobs.front() {
+                Some((queued, _)) if pool.is_none() || queued.elapsed() >= POLL_INTERVAL => {
+                    jobs.pop_front()
+                }
+                _ => None,
+            }
+        };
+
+        if let Some((_, job)) = stalled {
+            job();
+        }
+
+        // Send the responses which are ready
+        while let Ok((stream_id, response)) = response_rx.try_recv() {
+            match response {
+                Some((response, uri)) => {
+                    monitor.send(served_event(addr, response.status_code, uri.as_deref()));
+
+                    if let Some(body) = connection.send_response(stream_id, response) {
+                        bodies.push((stream_id, body));
+                    }
+                }
+                None => connection.fail_response(stream_id),
+            }
+        }
+
+        // Send the next part of every streamed body which the client is ready for
+        bodies.retain_mut(|(stream_id, body)| {
+            send_body_chunk(&mut connection, *stream_id, body, &mut buf)
+        });
+
+        // Connections are closed once their current requests have been answered if the app is shutting down
+        //   or they have been idle for too long
+        let idle_timeout = match config.timeout {
+            Some(timeout) if connection.is_idle() && connection.unanswered() == 0 => {
+                Some(timeout.saturating_sub(last_received.elapsed()))
+            }
+            _ => None,
+        };
+
+        if config.draining.load(Ordering::Relaxed) || idle_timeout.is_some_and(|t| t.is_zero()) {
+            connection.shutdown();
+        }
+
+        let output = connection.take_output();
+
+        if !output.is_empty() && stream.write_all(&output).is_err() {
+            break;
+        }
+
+        if connection.is_finished() {
+            break;
+        }
+
+        // Only wait for more data once no body can be sent, checking often for responses while requests are
+        //   being handled
+        let waiting = bodies.iter().all(|(stream_id, _)| {
+            connection
+                .pending_data(*stream_id)
+                .is_some_and(|pending| pending >= CHUNK_SIZE)
+        });
+
+        if !waiting {
+            continue;
+        }
+
+        let wait = match connection.unanswered() {
+            0 => idle_timeout.map_or(POLL_INTERVAL, |t| t.min(POLL_INTERVAL)),
+            _ => RESPONSE_POLL_INTERVAL,
+        };
+
+        if stream.set_timeout(Some(wait)).is_err() {
+            break;
+        }
+
+        let read = stream.read(&mut buf);
+
+        if stream.set_timeout(None).is_err() {
+            break;
+        }
+
+        match read {
+            Ok(0) => break,
+            Ok(length) => {
+                last_received = Instant::now();
+
+                // After an error, the connection is finished once the `GOAWAY` frame has been sent
+                if let Ok(received_requests) = connection.receive(&buf[..length]) {
+                    requests = received_requests;
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => (),
+            Err(_) => break,
+        }
+    }
+
+    // Requests which no thread has started handling are abandoned
+    jobs.lock().unwrap().clear();
+}
+
+/// Generates the response to a request received over HTTP/2, returning it along with the URI of the request if
+///   it could be handled.
+///
+/// The body has already been received, so it is read from memory.
+#[cfg(feature = "tls")]
+fn http2_response<State>(
+    request: Result<Request, RequestError>,
+    subapps: &[SubApp<State>],
+    default_subapp: &SubApp<State>,
+    error_handler: ErrorHandler,
+    state: &Arc<State>,
+    compression: Option<usize>,
+) -> (Response, Option<String>) {
+    let mut request = match request {
+        Ok(request) => request,
+        Err(e) => {
+            let status = match e {
+                RequestError::PayloadTooLarge => StatusCode::RequestEntityTooLarge,
+                _ => StatusCode::BadRequest,
+            };
+
+            let mut response = error_handler(status);
+            prepare_response(&mut response, false);
+
+            return (response, None);
+        }
+    };
+
+    let (subapp, handler, allowed_methods) = get_handler(&mut request, subapps, default_subapp);
+    let body = request.content.take().unwrap_or_default();
+
+    let (request, mut response) = match route_response(
+        request,
+        &mut body.as_slice(),
+        subapp,
+        handler,
+        &allowed_methods,
+        error_handler,
+        state,
+        None,
+        compression,
+    ) {
+        Ok((request, response, _)) => (request, response),
+        Err(_) => {
+            let mut response = error_handler(StatusCode::BadRequest);
+            prepare_response(&mut response, false);
+
+            return (response, None);
+        }
+    };
+
+    prepare_response(&mut response, request.method == Method::Head);
+
+    (response, Some(request.uri))
+}
+
+/// Sends the next part of a streamed response body over HTTP/2 if the client is ready for it.
+/// Returns false once the whole body has been sent or the stream has been closed.
+#[cfg(feature = "tls")]
+fn send_body_chunk(
+    connection: &mut Connection,
+    stream_id: u32,
+    body: &mut StreamedBody,
+    buf: &mut [u8],
+) -> bool {
+    match connection.pending_data(stream_id) {
+        Some(pending) if pending >= CHUNK_SIZE => return true,
+        Some(_) => (),
+        None => return false,
+    }
+
+    let to_read = match body.length {
+        Some(remaining) => remaining.min(CHUNK_SIZE as u64) as usize,
+        None => CHUNK_SIZE,
+    };
+
+    if to_read == 0 {
+        connection.send_data(stream_id, &[], true);
+        return false;
+    }
+
+    match body.source.read(&mut buf[..to_read]) {
+        // A body which ends before its length cannot be completed
+        Ok(0) if body.length.is_some() => connection.reset(stream_id, ErrorCode::InternalError),
+        Ok(0) => connection.send_data(stream_id, &[], true),
+        Ok(read) => {
+            body.length = body.length.map(|remaining| remaining - read as u64);
+
+            let end = body.length == Some(0);
+            connection.send_data(stream_id, &buf[..read], end);
+
+            return !end;
+        }
+        Err(e) if e.kind() == ErrorKind::Interrupted => return true,
+        Err(_) => connection.reset(stream_id, ErrorCode::InternalError),
+    }
+
+    false
+}
+
+/// Calls the most specific WebSocket handler for the given request.
 fn call_websocket_handler<State>(
     request: &Request,
     subapps: &[SubApp<State>],
@@ -795,6 +1551,8 @@ fn call_websocket_handler<State>(
     state: Arc<State>,
     stream: Stream,
 ) {
+    let mut request = request.clone();
+
     // Iterate over the sub-apps and find the one which matches the host
     if let Some(host) = request.headers.get(&HeaderType::Host) {
         if let Some(subapp) = subapps
@@ -802,56 +1560,88 @@ fn call_websocket_handler<State>(
             .find(|subapp| wildcard_match(&subapp.host, host))
         {
             // If the sub-app has a handler for this route, call it
-            if let Some(handler) = subapp
-                .websocket_routes // Get the WebSocket routes of the sub-app
-                .iter() // Iterate over the routes
-                .find(|route| route.route.route_matches(&request.uri))
-            {
-                handler.handler.serve(request.clone(), stream, state);
+            if let Some((index, params)) = find_route(
+                subapp.websocket_routes.iter().map(|route| &route.route),
+                &request.uri,
+            ) {
+                request.params = params;
+                subapp.websocket_routes[index]
+                    .handler
+                    .serve(request, stream, state);
                 return;
             }
         }
     }
 
     // If no sub-app was found, try to use the handler on the default sub-app
-    if let Some(handler) = default_subapp
-        .websocket_routes
-        .iter()
-        .find(|route| route.route.route_matches(&request.uri))
-    {
-        handler.handler.serve(request.clone(), stream, state)
+    if let Some((index, params)) = find_route(
+        default_subapp.websocket_routes.iter().map(|route| &route.route),
+        &request.uri,
+    ) {
+        request.params = params;
+        default_subapp.websocket_routes[index]
+            .handler
+            .serve(request, stream, state)
     }
 }
 
-#[cfg(feature = "tls")]
-fn force_https_thread(monitor: MonitorConfig) -> Result<(), Box<dyn std::error::Error>> {
-    let socket = TcpListener::bind("0.0.0.0:80")?;
-
-    for mut stream in socket.incoming().flatten() {
-        let addr = stream.peer_addr()?;
-        let request = Request::from_stream(&mut stream, addr)?;
-
-        let response = if let Some(host) = request.headers.get(&HeaderType::Host) {
-            Response::empty(StatusCode::MovedPermanently)
-                .with_header(
-                    HeaderType::Location,
-                    format!("https://{}{}", host, request.uri),
-                )
-                .with_header(HeaderType::Connection, "Close")
-        } else {
-            Response::empty(StatusCode::OK)
-                .with_bytes(b"<h1>Please access over HTTPS</h1>")
-                .with_header(HeaderType::ContentLength, "33")
-                .with_header(HeaderType::Connection, "Close")
-        };
+/// Accepts connections on the listener, passing them to the app until it shuts down.
+fn accept_connections(
+    socket: TcpListener,
+    mode: ConnectionMode,
+    tx: Sender<(TcpStream, ConnectionMode)>,
+    shutdown: Option<Arc<AtomicBool>>,
+    monitor: MonitorConfig,
+) {
+    if shutdown.is_some() {
+        socket.set_nonblocking(true).expect("Cannot set non-blocking");
+    }
 
-        let response_bytes: Vec<u8> = response.into();
-        stream.write_all(&response_bytes)?;
+    for stream in socket.incoming() {
+        match stream {
+            Ok(stream) => {
+                if tx.send((stream, mode.clone())).is_err() {
+                    break;
+                }
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if let Some(ref shutdown) = shutdown {
+                    if shutdown.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
 
-        monitor.send(Event::new(EventType::HTTPSRedirect).with_peer(addr));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => monitor.send(Event::new(EventType::ConnectionError).with_info(e.to_string())),
+        }
     }
+}
+
+/// Responds to a request on an insecure listener with a redirect to the same URL over HTTPS.
+fn redirect_to_https(
+    mut stream: TcpStream,
+    https_port: u16,
+    timeout: Option<Duration>,
+    monitor: MonitorConfig,
+) {
+    let addr = if let Ok(addr) = stream.peer_addr() {
+        addr
+    } else {
+        monitor.send(EventType::StreamDisconnectedWhileWaiting);
+
+        return;
+    };
+
+    stream.set_read_timeout(timeout).ok();
 
-    Ok(())
+    if let Ok(request) = Request::from_stream(&mut stream, addr) {
+        let response = https_redirect(&request, https_port);
+
+        if response.write_to(&mut stream).is_ok() {
+            monitor.send(Event::new(EventType::HTTPSRedirect).with_peer(addr));
+        }
+    }
 }
 
 /// The default error handler for every Humphrey app.
@@ -4,11 +4,14 @@ use crate::http::address::Address;
 use crate::http::cookie::Cookie;
 use crate::http::headers::{Header, HeaderLike, HeaderType, Headers};
 use crate::http::method::Method;
+use crate::http::pool::ConnectionPool;
 use crate::http::{Request, Response, StatusCode};
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::io::Write;
 use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
 
 #[cfg(feature = "tls")]
 use rustls::{Certificate, ClientConfig, ClientConnection, RootCertStore, StreamOwned};
@@ -16,8 +19,6 @@ use rustls::{Certificate, ClientConfig, ClientConnection, RootCertStore, StreamO
 use rustls_native_certs::load_native_certs;
 #[cfg(feature = "tls")]
 use std::convert::TryInto;
-#[cfg(feature = "tls")]
-use std::sync::Arc;
 
 /// Represents an HTTP client.
 ///
@@ -27,6 +28,7 @@ use std::sync::Arc;
 pub struct Client {
     #[cfg(feature = "tls")]
     tls_config: Option<Arc<ClientConfig>>,
+    pool: Option<Arc<ConnectionPool>>,
 }
 
 impl Client {
@@ -35,6 +37,15 @@ impl Client {
         Self::default()
     }
 
+    /// Sends requests using connections from the given pool, keeping them open to be reused by later requests.
+    /// The pool can be shared with other clients.
+    ///
+    /// Connections are only pooled for requests without TLS.
+    pub fn with_connection_pool(mut self, pool: Arc<ConnectionPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
     /// Creates a GET request to the given URL.
     pub fn get(&mut self, url: impl AsRef<str>) -> Result<ClientRequest, Box<dyn Error>> {
         let url = Self::parse_url(url).ok_or("Invalid URL")?;
@@ -42,10 +53,14 @@ impl Client {
             method: Method::Get,
             uri: url.path,
             headers: url.host_headers,
+            trailers: Headers::new(),
             query: url.query,
+            params: HashMap::new(),
             version: "HTTP/1.1".to_string(),
             content: None,
             address: Address::new(url.host).unwrap(),
+            peer_identity: None,
+            https: false,
         };
 
         Ok(ClientRequest {
@@ -71,10 +86,14 @@ impl Client {
             method: Method::Post,
             uri: url.path,
             headers: url.host_headers,
+            trailers: Headers::new(),
             query: url.query,
+            params: HashMap::new(),
             version: "HTTP/1.1".to_string(),
             content: Some(data),
             address: Address::new(url.host).unwrap(),
+            peer_identity: None,
+            https: false,
         };
 
         request.headers.push(content_length);
@@ -102,10 +121,14 @@ impl Client {
             method: Method::Put,
             uri: url.path,
             headers: url.host_headers,
+            trailers: Headers::new(),
             query: url.query,
+            params: HashMap::new(),
             version: "HTTP/1.1".to_string(),
             content: Some(data),
             address: Address::new(url.host).unwrap(),
+            peer_identity: None,
+            https: false,
         };
 
         request.headers.push(content_length);
@@ -127,10 +150,14 @@ impl Client {
             method: Method::Delete,
             uri: url.path,
             headers: url.host_headers,
+            trailers: Headers::new(),
             query: url.query,
+            params: HashMap::new(),
             version: "HTTP/1.1".to_string(),
             content: None,
             address: Address::new(url.host).unwrap(),
+            peer_identity: None,
+            https: false,
         };
 
         Ok(ClientRequest {
@@ -149,6 +176,12 @@ impl Client {
         address: impl ToSocketAddrs,
         request: Request,
     ) -> Result<Response, Box<dyn Error>> {
+        if let Some(pool) = &self.pool {
+            let address = address.to_socket_addrs()?.next().ok_or("Invalid address")?;
+
+            return Ok(pool.send(address, request)?);
+        }
+
         let mut stream = TcpStream::connect(address)?;
         let request_bytes: Vec<u8> = request.into();
         stream.write_all(&request_bytes)?;
@@ -323,10 +356,14 @@ impl<'a> ClientRequest<'a> {
                             method: self.request.method,
                             uri: new_url.path,
                             headers: new_url.host_headers,
+                            trailers: Headers::new(),
                             query: new_url.query,
+                            params: HashMap::new(),
                             version: "HTTP/1.1".to_string(),
                             content: self.request.content,
                             address: Address::new(new_url.host).unwrap(),
+                            peer_identity: None,
+                            https: false,
                         };
 
                         self.protocol = new_url.protocol;
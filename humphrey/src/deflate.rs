@@ -0,0 +1,298 @@
+//! Provides a dependency-free implementation of the DEFLATE compression algorithm, as defined in
+//!   [RFC 1951](https://datatracker.ietf.org/doc/html/rfc1951), along with the zlib and gzip formats which wrap it.
+//!
+//! The encoder finds repeated strings with a hash chain and encodes them with the fixed Huffman codes,
+//!   which favours speed and simplicity over achieving the smallest possible output.
+
+/// The size of the sliding window in which repeated strings are searched for.
+const WINDOW_SIZE: usize = 32768;
+/// The number of bits used for the hash of the next three bytes.
+const HASH_BITS: usize = 15;
+/// The maximum number of previous positions to check when searching for a match.
+const MAX_CHAIN_LENGTH: usize = 64;
+/// The minimum length of a match.
+const MIN_MATCH: usize = 3;
+/// The maximum length of a match.
+const MAX_MATCH: usize = 258;
+
+/// The smallest length which can be encoded with each length code, starting with code 257.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+/// The number of extra bits following each length code, starting with code 257.
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// The smallest distance which can be encoded with each distance code.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+/// The number of extra bits following each distance code.
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The lookup table for the CRC-32 checksum used by gzip.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// Compresses the data into a raw DEFLATE stream.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::with_capacity(data.len() / 2);
+
+    // A single final block compressed with the fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut head: Vec<usize> = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev: Vec<usize> = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut i = 0;
+
+    while i < data.len() {
+        let (length, distance) = longest_match(data, i, &head, &prev);
+
+        if length >= MIN_MATCH {
+            writer.write_length(length);
+            writer.write_distance(distance);
+
+            for position in i..i + length {
+                insert_hash(data, position, &mut head, &mut prev);
+            }
+
+            i += length;
+        } else {
+            writer.write_literal(data[i] as u16);
+            insert_hash(data, i, &mut head, &mut prev);
+
+            i += 1;
+        }
+    }
+
+    // End of block
+    writer.write_literal(256);
+
+    writer.finish()
+}
+
+/// Compresses the data into the zlib format, as defined in [RFC 1950](https://datatracker.ietf.org/doc/html/rfc1950).
+///
+/// This is the format used by the `deflate` HTTP content coding.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, using the fastest compression level, with no preset dictionary
+    let mut output = vec![0x78, 0x01];
+    output.extend(deflate(data));
+    output.extend(adler32(data).to_be_bytes());
+
+    output
+}
+
+/// Compresses the data into the gzip format, as defined in [RFC 1952](https://datatracker.ietf.org/doc/html/rfc1952).
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // Magic number, deflate method, no flags, no modification time, no extra flags, unknown OS
+    let mut output = vec![0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff];
+    output.extend(deflate(data));
+    output.extend(crc32(data).to_le_bytes());
+    output.extend((data.len() as u32).to_le_bytes());
+
+    output
+}
+
+/// Calculates the CRC-32 checksum of the data.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, byte| {
+        CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Calculates the Adler-32 checksum of the data.
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    // 5552 is the largest number of bytes which can be summed before `b` could overflow
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+/// Writes bits to a buffer, least significant bit first.
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            output: Vec::with_capacity(capacity),
+            buffer: 0,
+            bits: 0,
+        }
+    }
+
+    /// Writes the given number of bits of the value, least significant bit first.
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.bits;
+        self.bits += count;
+
+        while self.bits >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is packed starting with its most significant bit.
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write_bits(reversed, length);
+    }
+
+    /// Writes a symbol from the literal/length alphabet using the fixed Huffman codes.
+    fn write_literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    /// Writes the length of a match as a length code followed by its extra bits.
+    fn write_length(&mut self, length: usize) {
+        let index = LENGTH_BASE
+            .iter()
+            .rposition(|base| *base as usize <= length)
+            .unwrap();
+
+        self.write_literal(257 + index as u16);
+        self.write_bits(
+            (length - LENGTH_BASE[index] as usize) as u32,
+            LENGTH_EXTRA_BITS[index] as u32,
+        );
+    }
+
+    /// Writes the distance of a match as a distance code followed by its extra bits.
+    fn write_distance(&mut self, distance: usize) {
+        let index = DISTANCE_BASE
+            .iter()
+            .rposition(|base| *base as usize <= distance)
+            .unwrap();
+
+        self.write_code(index as u32, 5);
+        self.write_bits(
+            (distance - DISTANCE_BASE[index] as usize) as u32,
+            DISTANCE_EXTRA_BITS[index] as u32,
+        );
+    }
+
+    /// Flushes any remaining bits, padding the final byte with zeroes.
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.output.push(self.buffer as u8);
+        }
+
+        self.output
+    }
+}
+
+/// Hashes the three bytes at the given position.
+fn hash(data: &[u8], position: usize) -> usize {
+    let value = (data[position] as usize) << 16
+        | (data[position + 1] as usize) << 8
+        | data[position + 2] as usize;
+
+    (value.wrapping_mul(2654435761) >> 8) & ((1 << HASH_BITS) - 1)
+}
+
+/// Records the position in the hash chain of the three bytes starting there.
+fn insert_hash(data: &[u8], position: usize, head: &mut [usize], prev: &mut [usize]) {
+    if position + MIN_MATCH <= data.len() {
+        let hash = hash(data, position);
+        prev[position % WINDOW_SIZE] = head[hash];
+        head[hash] = position;
+    }
+}
+
+/// Finds the longest earlier occurrence within the window of the bytes starting at the given position.
+///
+/// Returns the length and distance of the match, or a length of zero if there is none.
+fn longest_match(data: &[u8], position: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if position + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max_length = MAX_MATCH.min(data.len() - position);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(data, position)];
+
+    for _ in 0..MAX_CHAIN_LENGTH {
+        if candidate == usize::MAX || position - candidate > WINDOW_SIZE {
+            break;
+        }
+
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[position..position + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if length > best.0 {
+            best = (length, position - candidate);
+
+            if length == max_length {
+                break;
+            }
+        }
+
+        let next = prev[candidate % WINDOW_SIZE];
+
+        // Entries in the chain which have been overwritten by newer positions are no longer valid
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+
+        candidate = next;
+    }
+
+    best
+}
+
+/// Generates the lookup table for the CRC-32 checksum.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
@@ -1,5 +1,7 @@
 //! Defines traits for handler functions.
 
+use crate::http::request::RequestBody;
+use crate::http::response::IntoResponse;
 use crate::http::{Request, Response};
 use crate::stream::Stream;
 
@@ -20,7 +22,7 @@ where
 }
 
 /// Represents a function able to handle a request.
-/// It is passed the request as well as the app's state, and must return a response.
+/// It is passed the request as well as the app's state, and must return a response or another type which implements `IntoResponse`.
 ///
 /// ## Example
 /// The most basic request handler would be as follows:
@@ -33,17 +35,44 @@ pub trait RequestHandler<State>: Send + Sync {
     #[allow(missing_docs)]
     fn serve(&self, request: Request, state: Arc<State>) -> Response;
 }
-impl<F, State> RequestHandler<State> for F
+impl<F, R, State> RequestHandler<State> for F
 where
-    F: Fn(Request, Arc<State>) -> Response + Send + Sync,
+    F: Fn(Request, Arc<State>) -> R + Send + Sync,
+    R: IntoResponse,
 {
     fn serve(&self, request: Request, state: Arc<State>) -> Response {
-        self(request, state)
+        self(request, state).into_response()
+    }
+}
+
+/// Represents a function able to handle a request whose body is read from the connection as it arrives.
+/// It is passed the request, its body and the app's state, and must return a response or another type which implements `IntoResponse`.
+/// The `content` field of the request will always be `None`.
+///
+/// ## Example
+/// A streaming request handler which counts the bytes uploaded would be as follows:
+/// ```
+/// fn handler(_: Request, body: &mut RequestBody, _: Arc<()>) -> Response {
+///     let length = std::io::copy(body, &mut std::io::sink()).unwrap();
+///     Response::new(StatusCode::OK, format!("Received {} bytes", length))
+/// }
+/// ```
+pub trait StreamingRequestHandler<State>: Send + Sync {
+    #[allow(missing_docs)]
+    fn serve(&self, request: Request, body: &mut RequestBody, state: Arc<State>) -> Response;
+}
+impl<F, R, State> StreamingRequestHandler<State> for F
+where
+    F: Fn(Request, &mut RequestBody, Arc<State>) -> R + Send + Sync,
+    R: IntoResponse,
+{
+    fn serve(&self, request: Request, body: &mut RequestBody, state: Arc<State>) -> Response {
+        self(request, body, state).into_response()
     }
 }
 
 /// Represents a function able to handle a request.
-/// It is passed only the request, and must return a response.
+/// It is passed only the request, and must return a response or another type which implements `IntoResponse`.
 /// If you want access to the app's state, consider using the `RequestHandler` trait instead.
 ///
 /// ## Example
@@ -57,12 +86,13 @@ pub trait StatelessRequestHandler<State>: Send + Sync {
     #[allow(missing_docs)]
     fn serve(&self, request: Request) -> Response;
 }
-impl<F, State> StatelessRequestHandler<State> for F
+impl<F, R, State> StatelessRequestHandler<State> for F
 where
-    F: Fn(Request) -> Response + Send + Sync,
+    F: Fn(Request) -> R + Send + Sync,
+    R: IntoResponse,
 {
     fn serve(&self, request: Request) -> Response {
-        self(request)
+        self(request).into_response()
     }
 }
 
@@ -88,3 +118,39 @@ where
         self(request, state, route)
     }
 }
+
+/// Represents middleware, which runs around the handlers of an app, sub-app or route.
+///
+/// `before` is called before the request is handled, and can modify the request or respond to it directly,
+///   in which case the handler and any inner middleware are skipped. It runs before the body of the request is
+///   read, so `content` is always `None`, and requests which are rejected never have their bodies read.
+///   `after` is called before the response is sent, and can modify it.
+///
+/// App middleware runs first, followed by sub-app middleware and then route middleware, with `after` being
+///   called in the reverse order. If middleware responds directly, `after` is still called on the middleware
+///   which ran before it.
+///
+/// ## Example
+/// Middleware which rejects requests without an `Authorization` header would be as follows:
+/// ```
+/// struct RequireAuthorization;
+///
+/// impl Middleware<()> for RequireAuthorization {
+///     fn before(&self, request: &mut Request, _: Arc<()>) -> Option<Response> {
+///         match request.headers.get(HeaderType::Authorization) {
+///             Some(_) => None,
+///             None => Some(Response::empty(StatusCode::Unauthorized)),
+///         }
+///     }
+/// }
+/// ```
+pub trait Middleware<State>: Send + Sync {
+    /// Called before the request is handled.
+    /// Returning a response skips the handler and sends the response instead.
+    fn before(&self, _request: &mut Request, _state: Arc<State>) -> Option<Response> {
+        None
+    }
+
+    /// Called after the request has been handled, before the response is sent.
+    fn after(&self, _request: &Request, _response: &mut Response, _state: Arc<State>) {}
+}
@@ -1,14 +1,16 @@
 //! Provides a number of useful handlers for Humphrey apps.
 
 use crate::app::error_handler;
+use crate::http::conditional::{apply_conditional, Validators};
 use crate::http::headers::HeaderType;
 use crate::http::mime::MimeType;
+use crate::http::range::{apply_file_range, apply_range};
 use crate::http::{Request, Response, StatusCode};
 use crate::route::{try_find_path, LocatedPath};
 
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 const INDEX_FILES: [&str; 2] = ["index.html", "index.htm"];
@@ -17,22 +19,8 @@ const INDEX_FILES: [&str; 2] = ["index.html", "index.htm"];
 pub fn serve_file<T>(file_path: &'static str) -> impl Fn(Request, Arc<T>) -> Response {
     let path_buf = PathBuf::from(file_path);
 
-    move |_, _| {
-        if let Ok(mut file) = File::open(&path_buf) {
-            let mut buf = Vec::new();
-            if file.read_to_end(&mut buf).is_ok() {
-                return if let Some(extension) = path_buf.extension() {
-                    Response::new(StatusCode::OK, buf).with_header(
-                        HeaderType::ContentType,
-                        MimeType::from_extension(extension.to_str().unwrap()).to_string(),
-                    )
-                } else {
-                    Response::new(StatusCode::OK, buf)
-                };
-            }
-        }
-
-        error_handler(StatusCode::NotFound)
+    move |request: Request, _| {
+        file_response(&request, &path_buf).unwrap_or_else(|| error_handler(StatusCode::NotFound))
     }
 }
 
@@ -52,21 +40,7 @@ pub fn serve_as_file_path<T>(directory_path: &'static str) -> impl Fn(Request, A
 
         let path_buf = PathBuf::from(path);
 
-        if let Ok(mut file) = File::open(&path_buf) {
-            let mut buf = Vec::new();
-            if file.read_to_end(&mut buf).is_ok() {
-                return if let Some(extension) = path_buf.extension() {
-                    Response::new(StatusCode::OK, buf).with_header(
-                        HeaderType::ContentType,
-                        MimeType::from_extension(extension.to_str().unwrap()).to_string(),
-                    )
-                } else {
-                    Response::new(StatusCode::OK, buf)
-                };
-            }
-        }
-
-        error_handler(StatusCode::NotFound)
+        file_response(&request, &path_buf).unwrap_or_else(|| error_handler(StatusCode::NotFound))
     }
 }
 
@@ -89,24 +63,8 @@ pub fn serve_dir<T>(directory_path: &'static str) -> impl Fn(Request, Arc<T>, &s
             match located {
                 LocatedPath::Directory => Response::empty(StatusCode::MovedPermanently)
                     .with_header(HeaderType::Location, format!("{}/", &request.uri)),
-                LocatedPath::File(path) => {
-                    if let Ok(mut file) = File::open(&path) {
-                        let mut buf = Vec::new();
-                        if file.read_to_end(&mut buf).is_ok() {
-                            return if let Some(extension) = path.extension() {
-                                Response::new(StatusCode::OK, buf).with_header(
-                                    HeaderType::ContentType,
-                                    MimeType::from_extension(extension.to_str().unwrap())
-                                        .to_string(),
-                                )
-                            } else {
-                                Response::new(StatusCode::OK, buf)
-                            };
-                        }
-                    }
-
-                    error_handler(StatusCode::InternalError)
-                }
+                LocatedPath::File(path) => file_response(&request, &path)
+                    .unwrap_or_else(|| error_handler(StatusCode::InternalError)),
             }
         } else {
             error_handler(StatusCode::NotFound)
@@ -118,3 +76,35 @@ pub fn serve_dir<T>(directory_path: &'static str) -> impl Fn(Request, Arc<T>, &s
 pub fn redirect<T>(location: &'static str) -> impl Fn(Request, Arc<T>) -> Response {
     move |_, _| Response::redirect(location)
 }
+
+/// Reads the file at the given path into a response, respecting any conditional headers and range requested.
+/// Returns `None` if the file could not be read.
+///
+/// When a range is requested, only that range is streamed from the file rather than reading all of it.
+fn file_response(request: &Request, path: &Path) -> Option<Response> {
+    let mut file = File::open(path).ok()?;
+    let validators = Validators::from_metadata(&file.metadata().ok()?);
+
+    let response = match path.extension() {
+        Some(extension) => Response::empty(StatusCode::OK).with_header(
+            HeaderType::ContentType,
+            MimeType::from_extension(extension.to_str().unwrap()).to_string(),
+        ),
+        None => Response::empty(StatusCode::OK),
+    };
+
+    let response = apply_conditional(request, response, &validators);
+
+    if response.status_code != StatusCode::OK {
+        return Some(response);
+    }
+
+    if request.headers.get(&HeaderType::Range).is_some() {
+        return apply_file_range(request, response, path).ok();
+    }
+
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).ok()?;
+
+    Some(apply_range(request, response.with_bytes(buf)))
+}
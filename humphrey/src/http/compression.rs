@@ -0,0 +1,182 @@
+//! Provides functionality for compressing responses with the content codings negotiated by the `Accept-Encoding` header.
+
+use crate::deflate::{gzip, zlib};
+use crate::http::headers::{HeaderType, Headers};
+use crate::http::status::StatusCode;
+use crate::http::{Request, Response};
+
+/// The default minimum size of a response body, in bytes, for it to be compressed.
+/// Smaller bodies gain little from compression, so are not worth the extra processing.
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// Represents a content coding which responses can be compressed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// The `gzip` content coding.
+    Gzip,
+    /// The `deflate` content coding, which is DEFLATE data in the zlib format.
+    Deflate,
+}
+
+impl Encoding {
+    /// Chooses the encoding most preferred by the client from the value of an `Accept-Encoding` header.
+    ///
+    /// Quality values are respected, with gzip preferred when the client has no preference.
+    ///   Returns `None` if the client does not accept any supported encoding.
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let mut gzip_quality: Option<f32> = None;
+        let mut deflate_quality: Option<f32> = None;
+        let mut wildcard_quality: Option<f32> = None;
+
+        for coding in accept_encoding.to_ascii_lowercase().split(',') {
+            let mut parts = coding.split(';').map(|part| part.trim());
+            let name = parts.next().unwrap_or("");
+
+            // Codings with invalid quality values are ignored
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |quality| quality.trim().parse::<f32>().ok());
+
+            let quality = match quality {
+                Some(quality) => quality,
+                None => continue,
+            };
+
+            match name {
+                "gzip" | "x-gzip" => gzip_quality = Some(quality),
+                "deflate" => deflate_quality = Some(quality),
+                "*" => wildcard_quality = Some(quality),
+                _ => (),
+            }
+        }
+
+        let gzip_quality = gzip_quality.or(wildcard_quality).unwrap_or(0.0);
+        let deflate_quality = deflate_quality.or(wildcard_quality).unwrap_or(0.0);
+
+        if gzip_quality > 0.0 && gzip_quality >= deflate_quality {
+            Some(Self::Gzip)
+        } else if deflate_quality > 0.0 {
+            Some(Self::Deflate)
+        } else {
+            None
+        }
+    }
+
+    /// Compresses the data with the encoding.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Gzip => gzip(data),
+            Self::Deflate => zlib(data),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    /// Formats the name of the encoding as used in the `Content-Encoding` header.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gzip => write!(f, "gzip"),
+            Self::Deflate => write!(f, "deflate"),
+        }
+    }
+}
+
+/// Checks whether content of the given MIME type, as specified in the `Content-Type` header, benefits from compression.
+///
+/// Text-based types are compressible, while most media types are already compressed.
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    mime_type.starts_with("text/")
+        || mime_type.ends_with("+json")
+        || mime_type.ends_with("+xml")
+        || matches!(
+            mime_type.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/vnd.microsoft.icon"
+                | "font/ttf"
+                | "font/otf"
+        )
+}
+
+/// Compresses the response with the encoding most preferred by the client, if it is eligible for compression.
+///
+/// Only `200 OK` responses with in-memory bodies of a compressible MIME type and at least `min_size` bytes long
+///   are compressed. These are given the `Vary: Accept-Encoding` header, since their content depends on it.
+pub fn apply_compression(request: &Request, mut response: Response, min_size: usize) -> Response {
+    if response.status_code != StatusCode::OK
+        || response.stream.is_some()
+        || response.body.len() < min_size
+        || response.headers.get(&HeaderType::ContentEncoding).is_some()
+        || !response
+            .headers
+            .get(&HeaderType::ContentType)
+            .is_some_and(is_compressible)
+    {
+        return response;
+    }
+
+    add_vary(&mut response.headers);
+
+    let encoding = match request
+        .headers
+        .get(&HeaderType::AcceptEncoding)
+        .and_then(Encoding::negotiate)
+    {
+        Some(encoding) => encoding,
+        None => return response,
+    };
+
+    let body = encoding.encode(&response.body);
+
+    if body.len() >= response.body.len() {
+        return response;
+    }
+
+    apply_encoding(response, encoding, body)
+}
+
+/// Replaces the body of the response with the given body, which has already been compressed with the encoding.
+///
+/// This sets the `Content-Encoding` and `Vary` headers, and weakens the entity tag of the response since the
+///   compressed content is not byte-for-byte identical to the original.
+pub fn apply_encoding(mut response: Response, encoding: Encoding, body: Vec<u8>) -> Response {
+    response.body = body;
+    response.headers.remove(&HeaderType::ContentLength);
+    response
+        .headers
+        .add(HeaderType::ContentEncoding, encoding.to_string());
+    add_vary(&mut response.headers);
+
+    if let Some(etag) = response.headers.get_mut(&HeaderType::ETag) {
+        if !etag.starts_with("W/") {
+            etag.insert_str(0, "W/");
+        }
+    }
+
+    response
+}
+
+/// Adds `Accept-Encoding` to the `Vary` header, creating it if necessary.
+pub fn add_vary(headers: &mut Headers) {
+    match headers.get_mut(&HeaderType::Vary) {
+        Some(vary) => {
+            let already_varies = vary.split(',').any(|field| {
+                field.trim() == "*" || field.trim().eq_ignore_ascii_case("accept-encoding")
+            });
+
+            if !already_varies {
+                vary.push_str(", Accept-Encoding");
+            }
+        }
+        None => headers.add(HeaderType::Vary, "Accept-Encoding"),
+    }
+}
@@ -0,0 +1,134 @@
+//! Provides functionality for handling conditional requests, as defined in [RFC 7232](https://datatracker.ietf.org/doc/html/rfc7232).
+
+use crate::http::date::DateTime;
+use crate::http::headers::HeaderType;
+use crate::http::method::Method;
+use crate::http::status::StatusCode;
+use crate::http::{Request, Response};
+
+use std::convert::TryFrom;
+use std::fs::Metadata;
+use std::time::SystemTime;
+
+/// The headers which are copied from the full response into a `304 Not Modified` response.
+const NOT_MODIFIED_HEADERS: [HeaderType; 6] = [
+    HeaderType::CacheControl,
+    HeaderType::ContentLocation,
+    HeaderType::ETag,
+    HeaderType::Expires,
+    HeaderType::LastModified,
+    HeaderType::Vary,
+];
+
+/// Represents the validators of a version of a resource, used to determine whether the client's copy is current.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// The entity tag of the resource, including its quotes, for example `"5f3a-1b"`.
+    pub etag: Option<String>,
+    /// The UNIX timestamp at which the resource was last modified.
+    pub last_modified: Option<i64>,
+}
+
+impl Validators {
+    /// Generates validators for a file from its metadata.
+    ///
+    /// The entity tag is made from the modification time and size of the file, so it changes whenever
+    ///   the file is modified. If the modification time is unavailable on the platform, no validators are generated.
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok());
+
+        match modified {
+            Some(modified) => Self {
+                etag: Some(format!(
+                    "\"{:x}-{:x}\"",
+                    modified.as_nanos(),
+                    metadata.len()
+                )),
+                last_modified: Some(modified.as_secs() as i64),
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+/// Adds the validators to a response containing the whole content, and applies the `If-None-Match` and
+///   `If-Modified-Since` headers of the request to it.
+///
+/// Returns a `304 Not Modified` response if the client's copy of the content is current. Otherwise, the
+///   response is returned with `ETag` and `Last-Modified` headers added.
+///
+/// Only `200 OK` responses to `GET` and `HEAD` requests are affected.
+pub fn apply_conditional(
+    request: &Request,
+    mut response: Response,
+    validators: &Validators,
+) -> Response {
+    if response.status_code != StatusCode::OK {
+        return response;
+    }
+
+    if let Some(etag) = &validators.etag {
+        if response.headers.get(&HeaderType::ETag).is_none() {
+            response.headers.add(HeaderType::ETag, etag);
+        }
+    }
+
+    if let Some(last_modified) = validators.last_modified {
+        if response.headers.get(&HeaderType::LastModified).is_none() {
+            response.headers.add(
+                HeaderType::LastModified,
+                DateTime::from(last_modified).to_string(),
+            );
+        }
+    }
+
+    if request.method != Method::Get && request.method != Method::Head {
+        return response;
+    }
+
+    // `If-Modified-Since` is only considered if `If-None-Match` is absent, since entity tags are more accurate
+    let not_modified = if let Some(if_none_match) = request.headers.get(&HeaderType::IfNoneMatch) {
+        validators
+            .etag
+            .as_ref()
+            .is_some_and(|etag| etag_list_matches(if_none_match, etag))
+    } else if let Some(if_modified_since) = request.headers.get(&HeaderType::IfModifiedSince) {
+        match (
+            validators.last_modified,
+            DateTime::try_from(if_modified_since),
+        ) {
+            (Some(last_modified), Ok(date)) => last_modified <= date.get_timestamp(),
+            _ => false,
+        }
+    } else {
+        false
+    };
+
+    if !not_modified {
+        return response;
+    }
+
+    let mut not_modified_response = Response::empty(StatusCode::NotModified);
+
+    for header in response.headers.iter() {
+        if NOT_MODIFIED_HEADERS.contains(&header.name) {
+            not_modified_response.headers.push(header);
+        }
+    }
+
+    not_modified_response
+}
+
+/// Checks whether the entity tag matches any in the list, which is the value of an `If-None-Match` header.
+///
+/// Uses the weak comparison function, so `W/"a"` matches `"a"`, and `*` matches any entity tag.
+fn etag_list_matches(list: &str, etag: &str) -> bool {
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+
+    list.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
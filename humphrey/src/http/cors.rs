@@ -106,14 +106,7 @@ impl Cors {
         if headers.get(HeaderType::AccessControlAllowMethods).is_none() {
             match self.allowed_methods {
                 Wildcardable::Value(ref methods) if !methods.is_empty() => {
-                    headers.add(
-                        HeaderType::AccessControlAllowMethods,
-                        &methods
-                            .iter()
-                            .map(|m| m.to_string())
-                            .collect::<Vec<_>>()
-                            .join(", "),
-                    );
+                    headers.add(HeaderType::AccessControlAllowMethods, join_methods(methods));
                 }
                 _ => (),
             }
@@ -134,6 +127,33 @@ impl Cors {
             }
         }
     }
+
+    /// Sets the appropriate headers for a response to a preflight request to a route which allows the given methods.
+    ///
+    /// If every method is allowed, the methods of the route are listed explicitly, since browsers only allow
+    ///   simple methods when the `Access-Control-Allow-Methods` header is missing.
+    pub(crate) fn set_preflight_headers(&self, headers: &mut Headers, allowed_methods: &[Method]) {
+        self.set_headers(headers);
+
+        if matches!(self.allowed_methods, Wildcardable::Wildcard)
+            && headers.get(HeaderType::AccessControlAllowMethods).is_none()
+            && !allowed_methods.is_empty()
+        {
+            headers.add(
+                HeaderType::AccessControlAllowMethods,
+                join_methods(allowed_methods),
+            );
+        }
+    }
+}
+
+/// Joins the methods into a comma-separated list, as used in the `Allow` and `Access-Control-Allow-Methods` headers.
+pub(crate) fn join_methods(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl<T> Default for Wildcardable<T>
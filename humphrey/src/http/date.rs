@@ -1,5 +1,6 @@
 //! Provides functionality for handling HTTP date timestamps.
 
+use std::convert::TryFrom;
 use std::time::SystemTime;
 
 const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
@@ -37,6 +38,10 @@ pub struct DateTime {
     pub second: u8,
 }
 
+/// Represents an error parsing an HTTP date.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DateTimeError;
+
 impl DateTime {
     /// Creates a new `DateTime` from the current time.
     pub fn now() -> Self {
@@ -129,6 +134,99 @@ impl From<i64> for DateTime {
     }
 }
 
+impl TryFrom<&str> for DateTime {
+    type Error = DateTimeError;
+
+    /// Parses an HTTP date in any of the three formats defined in [RFC 7231](https://datatracker.ietf.org/doc/html/rfc7231#section-7.1.1.1).
+    ///
+    /// ## Example
+    /// ```
+    /// let date = DateTime::try_from("Sun, 06 Nov 1994 08:49:37 GMT")?;
+    /// assert_eq!(date.get_timestamp(), 784111777);
+    /// ```
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let parts: Vec<&str> = value.split_whitespace().collect();
+
+        let (day, month, year, time) = match parts.as_slice() {
+            // IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT"
+            [_, day, month, year, time, "GMT"] => (
+                *day,
+                *month,
+                year.parse().map_err(|_| DateTimeError)?,
+                *time,
+            ),
+            // RFC 850, e.g. "Sunday, 06-Nov-94 08:49:37 GMT"
+            [_, date, time, "GMT"] => {
+                let mut date_parts = date.split('-');
+                let day = date_parts.next().ok_or(DateTimeError)?;
+                let month = date_parts.next().ok_or(DateTimeError)?;
+                let year: i64 = date_parts
+                    .next()
+                    .and_then(|year| year.parse().ok())
+                    .ok_or(DateTimeError)?;
+
+                // Two-digit years are assumed to be in the most recent century that makes sense
+                let year = if year < 70 { year + 2000 } else { year + 1900 };
+
+                (day, month, year, *time)
+            }
+            // ANSI C's `asctime()` format, e.g. "Sun Nov  6 08:49:37 1994"
+            [_, month, day, time, year] => (
+                *day,
+                *month,
+                year.parse().map_err(|_| DateTimeError)?,
+                *time,
+            ),
+            _ => return Err(DateTimeError),
+        };
+
+        let day: i64 = day.parse().map_err(|_| DateTimeError)?;
+        let month = MONTHS
+            .iter()
+            .position(|m| *m == month)
+            .ok_or(DateTimeError)? as i64;
+
+        let time_parts: Vec<i64> = time
+            .split(':')
+            .map(|part| part.parse().map_err(|_| DateTimeError))
+            .collect::<Result<_, _>>()?;
+
+        let (hour, minute, second) = match time_parts.as_slice() {
+            [hour, minute, second]
+                if (0..24).contains(hour)
+                    && (0..60).contains(minute)
+                    && (0..61).contains(second) =>
+            {
+                (*hour, *minute, *second)
+            }
+            _ => return Err(DateTimeError),
+        };
+
+        // Limiting the year also stops the timestamp from overflowing
+        if !(1..=31).contains(&day) || !(0..=9999).contains(&year) {
+            return Err(DateTimeError);
+        }
+
+        // Counts days from the start of March so leap days fall at the end of the year.
+        // Algorithm from [here](http://howardhinnant.github.io/date_algorithms.html#days_from_civil).
+        let shifted_year = if month < 2 { year - 1 } else { year };
+        let era = shifted_year.div_euclid(400);
+        let year_of_era = shifted_year.rem_euclid(400);
+        let day_of_year = (153 * ((month + 10) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * DAYS_400_YEARS + day_of_era - 719468;
+
+        let date = Self::from(days * DAY + hour * HOUR + minute * MINUTE + second);
+
+        // Dates such as the 31st of February would otherwise silently roll over into the next month
+        if date.day as i64 != day {
+            return Err(DateTimeError);
+        }
+
+        Ok(date)
+    }
+}
+
 impl ToString for DateTime {
     /// Returns a string formatted as an HTTP date representing the `DateTime`.
     ///
@@ -0,0 +1,606 @@
+//! Provides parsing of query strings and form bodies, both `application/x-www-form-urlencoded`
+//!   and `multipart/form-data` as in [RFC 7578](https://datatracker.ietf.org/doc/html/rfc7578).
+
+use crate::http::headers::HeaderType;
+use crate::http::request::{Request, RequestBody};
+use crate::percent::PercentDecode;
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(not(feature = "tokio"))]
+use std::io::Read;
+
+#[cfg(feature = "tokio")]
+use tokio::io::AsyncReadExt;
+
+/// The maximum size of the headers of a single part of a multipart body.
+const MAX_PART_HEADERS_SIZE: usize = 16384;
+
+/// The maximum size of a single text field of a multipart body.
+const MAX_FIELD_SIZE: usize = 1024 * 1024;
+
+/// The maximum number of parts in a multipart body.
+const MAX_PARTS: usize = 1024;
+
+/// The size of the buffer used when reading a streamed multipart body.
+const READ_BUFFER_SIZE: usize = 16384;
+
+/// Used to give every file written to disk during multipart parsing a unique name.
+static UPLOAD_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Represents a list of name-value pairs decoded from a query string or form body.
+///
+/// The same name may appear more than once, in which case `get` returns the first value
+///   and `get_all` returns every value in the order in which they appeared.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormData {
+    pairs: Vec<(String, String)>,
+}
+
+/// Represents a parsed `multipart/form-data` body.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Multipart {
+    /// The text fields of the form, i.e. the parts without a filename.
+    pub fields: FormData,
+    /// The file parts of the form, i.e. the parts with a filename.
+    pub files: Vec<FilePart>,
+}
+
+/// Represents a file uploaded as part of a `multipart/form-data` body.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilePart {
+    /// The name of the form field.
+    pub name: String,
+    /// The filename supplied by the client.
+    ///
+    /// This is not sanitised in any way, so should not be used as a path without checking it first.
+    pub filename: String,
+    /// The content type of the file, if supplied.
+    pub content_type: Option<String>,
+    /// The content of the file.
+    pub content: FileContent,
+}
+
+/// Represents the content of an uploaded file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileContent {
+    /// The file was read into memory.
+    Memory(Vec<u8>),
+    /// The file was streamed to disk at the given path.
+    ///
+    /// The file is not deleted automatically, so the handler should move or remove it once done.
+    Disk(PathBuf),
+}
+
+/// An error which occurred while parsing a form.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FormError {
+    /// The request did not have the content type required by the parser.
+    UnsupportedContentType,
+    /// The form could not be parsed due to invalid data.
+    Invalid,
+    /// The form could not be parsed due to an issue with the stream or with writing files to disk.
+    Stream,
+}
+
+impl std::fmt::Display for FormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "FormError")
+    }
+}
+
+impl Error for FormError {}
+
+impl FormData {
+    /// Creates a new, empty set of form data.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses an `application/x-www-form-urlencoded` string, such as a query string.
+    ///
+    /// Both `+` and `%20` are decoded as spaces. Pairs which cannot be percent-decoded are kept as they are,
+    ///   and pairs without an `=` are given an empty value.
+    pub fn parse(data: impl AsRef<str>) -> Self {
+        let pairs = data
+            .as_ref()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_component(name), decode_component(value))
+            })
+            .collect();
+
+        Self { pairs }
+    }
+
+    /// Gets the first value with the given name.
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name.as_ref())
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Gets every value with the given name, in the order in which they appeared.
+    pub fn get_all(&self, name: impl AsRef<str>) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(n, _)| n == name.as_ref())
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Returns true if there is at least one value with the given name.
+    pub fn contains(&self, name: impl AsRef<str>) -> bool {
+        self.pairs.iter().any(|(n, _)| n == name.as_ref())
+    }
+
+    /// Adds a value with the given name.
+    pub fn add(&mut self, name: impl AsRef<str>, value: impl AsRef<str>) {
+        self.pairs
+            .push((name.as_ref().to_string(), value.as_ref().to_string()));
+    }
+
+    /// Returns an iterator over the name-value pairs in the order in which they appeared.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns the number of name-value pairs.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns true if there are no name-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+impl Multipart {
+    /// Parses a `multipart/form-data` body which has already been read into memory, using the given boundary.
+    ///
+    /// All files are kept in memory.
+    pub fn parse(body: &[u8], boundary: impl AsRef<str>) -> Result<Self, FormError> {
+        let mut parser = MultipartParser::new(boundary.as_ref(), None);
+        parser.feed(body)?;
+        parser.finish()
+    }
+
+    /// Parses the `multipart/form-data` body of a request which uses a streaming handler,
+    ///   writing each file to a new file in the given directory as it arrives.
+    ///
+    /// This allows large uploads to be accepted without reading them into memory.
+    /// Text fields are still kept in memory, so each is limited to 1MiB, and the body is limited to 1024 parts.
+    /// If the body cannot be parsed, any files which were already written are deleted.
+    #[cfg(not(feature = "tokio"))]
+    pub fn from_body(
+        request: &Request,
+        body: &mut RequestBody,
+        directory: impl AsRef<Path>,
+    ) -> Result<Self, FormError> {
+        let boundary = multipart_boundary(request)?;
+        let mut parser = MultipartParser::new(&boundary, Some(directory.as_ref()));
+        let mut buf = [0; READ_BUFFER_SIZE];
+
+        loop {
+            match body.read(&mut buf).map_err(|_| FormError::Stream)? {
+                0 => break,
+                n => parser.feed(&buf[..n])?,
+            }
+        }
+
+        parser.finish()
+    }
+
+    /// Parses the `multipart/form-data` body of a request which uses a streaming handler,
+    ///   writing each file to a new file in the given directory as it arrives.
+    ///
+    /// This allows large uploads to be accepted without reading them into memory.
+    /// Text fields are still kept in memory, so each is limited to 1MiB, and the body is limited to 1024 parts.
+    /// If the body cannot be parsed, any files which were already written are deleted.
+    #[cfg(feature = "tokio")]
+    pub async fn from_body(
+        request: &Request,
+        body: &mut RequestBody,
+        directory: impl AsRef<Path>,
+    ) -> Result<Self, FormError> {
+        let boundary = multipart_boundary(request)?;
+        let mut parser = MultipartParser::new(&boundary, Some(directory.as_ref()));
+        let mut buf = [0; READ_BUFFER_SIZE];
+
+        loop {
+            match body.read(&mut buf).await.map_err(|_| FormError::Stream)? {
+                0 => break,
+                n => parser.feed(&buf[..n])?,
+            }
+        }
+
+        parser.finish()
+    }
+}
+
+/// Gets the boundary of a `multipart/form-data` request from its `Content-Type` header.
+pub(crate) fn multipart_boundary(request: &Request) -> Result<String, FormError> {
+    let content_type = request
+        .headers
+        .get(HeaderType::ContentType)
+        .ok_or(FormError::UnsupportedContentType)?;
+
+    if !media_type(content_type).eq_ignore_ascii_case("multipart/form-data") {
+        return Err(FormError::UnsupportedContentType);
+    }
+
+    header_parameters(content_type)
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value)
+        .filter(|boundary| !boundary.is_empty() && boundary.len() <= 70)
+        .ok_or(FormError::Invalid)
+}
+
+/// Gets the media type of a header value such as `Content-Type`, without its parameters.
+pub(crate) fn media_type(value: &str) -> &str {
+    value.split(';').next().unwrap_or("").trim()
+}
+
+/// Parses the parameters of a header value such as `Content-Type` or `Content-Disposition`,
+///   for example `name` and `filename` in `form-data; name="file"; filename="a.txt"`.
+///
+/// Quoted values may contain semicolons and backslash-escaped characters.
+fn header_parameters(value: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut chars = value.chars().skip_while(|c| *c != ';').peekable();
+
+    while chars.next().is_some() {
+        let name: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        let mut parameter_value = String::new();
+
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        if chars.peek() == Some(&'"') {
+            chars.next();
+
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => parameter_value.extend(chars.next()),
+                    c => parameter_value.push(c),
+                }
+            }
+
+            while chars.peek().is_some_and(|c| *c != ';') {
+                chars.next();
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ';') {
+                parameter_value.push(c);
+            }
+        }
+
+        let name = name.trim();
+
+        if !name.is_empty() {
+            parameters.push((name.to_string(), parameter_value.trim().to_string()));
+        }
+    }
+
+    parameters
+}
+
+/// Decodes one component of an `application/x-www-form-urlencoded` string.
+fn decode_component(component: &str) -> String {
+    let component = component.replace('+', " ");
+
+    component
+        .percent_decode()
+        .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+        .unwrap_or(component)
+}
+
+/// The state of a `MultipartParser`.
+enum ParserState {
+    /// Skipping data before the first boundary.
+    Preamble,
+    /// Immediately after a boundary, waiting to find out whether it is the final one.
+    Boundary,
+    /// Reading the headers of a part.
+    Headers,
+    /// Reading the body of a part.
+    Body(Part),
+    /// The final boundary has been read.
+    Done,
+}
+
+/// A part of a multipart body which is currently being read.
+struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    content: PartContent,
+}
+
+/// The destination of the content of a part.
+enum PartContent {
+    Memory(Vec<u8>),
+    Disk(File, PathBuf),
+}
+
+/// An incremental `multipart/form-data` parser, to which the body is fed as it arrives.
+///
+/// Only the data which could still be the start of a boundary is buffered, so file parts
+///   can be streamed to disk without reading the whole body into memory. Every file written to disk is deleted
+///   when the parser is dropped unless the body was parsed successfully.
+struct MultipartParser<'a> {
+    delimiter: Vec<u8>,
+    directory: Option<&'a Path>,
+    buffer: Vec<u8>,
+    state: ParserState,
+    result: Multipart,
+    created: Vec<PathBuf>,
+}
+
+impl<'a> MultipartParser<'a> {
+    /// Creates a new parser for the given boundary.
+    /// If a directory is given, file parts are written to it instead of being kept in memory.
+    fn new(boundary: &str, directory: Option<&'a Path>) -> Self {
+        Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            directory,
+            // The first boundary is not preceded by a line break, so one is added to treat it like the others.
+            buffer: b"\r\n".to_vec(),
+            state: ParserState::Preamble,
+            result: Multipart::default(),
+            created: Vec::new(),
+        }
+    }
+
+    /// Feeds the next piece of the body to the parser.
+    fn feed(&mut self, data: &[u8]) -> Result<(), FormError> {
+        self.buffer.extend_from_slice(data);
+
+        loop {
+            let state = std::mem::replace(&mut self.state, ParserState::Done);
+
+            let (state, progressed) = match state {
+                ParserState::Preamble => match find(&self.buffer, &self.delimiter) {
+                    Some(index) => {
+                        self.buffer.drain(..index + self.delimiter.len());
+                        (ParserState::Boundary, true)
+                    }
+                    None => {
+                        let keep = self.delimiter.len().min(self.buffer.len());
+                        self.buffer.drain(..self.buffer.len() - keep);
+                        (ParserState::Preamble, false)
+                    }
+                },
+                ParserState::Boundary => {
+                    if self.buffer.starts_with(b"--") {
+                        self.buffer.clear();
+                        (ParserState::Done, false)
+                    } else if let Some(index) = find(&self.buffer, b"\r\n") {
+                        // Any transport padding after the boundary is ignored.
+                        self.buffer.drain(..index + 2);
+                        (ParserState::Headers, true)
+                    } else if self.buffer.len() > MAX_PART_HEADERS_SIZE {
+                        return Err(FormError::Invalid);
+                    } else {
+                        (ParserState::Boundary, false)
+                    }
+                }
+                ParserState::Headers => {
+                    let end = if self.buffer.starts_with(b"\r\n") {
+                        Some(0)
+                    } else {
+                        find(&self.buffer, b"\r\n\r\n").map(|index| index + 2)
+                    };
+
+                    match end {
+                        Some(end) => {
+                            let headers: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                            (ParserState::Body(self.start_part(&headers)?), true)
+                        }
+                        None if self.buffer.len() > MAX_PART_HEADERS_SIZE => {
+                            return Err(FormError::Invalid)
+                        }
+                        None => (ParserState::Headers, false),
+                    }
+                }
+                ParserState::Body(mut part) => match find(&self.buffer, &self.delimiter) {
+                    Some(index) => {
+                        part.write(&self.buffer[..index])?;
+                        self.buffer.drain(..index + self.delimiter.len());
+                        self.finish_part(part)?;
+                        (ParserState::Boundary, true)
+                    }
+                    None => {
+                        let safe = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+                        part.write(&self.buffer[..safe])?;
+                        self.buffer.drain(..safe);
+                        (ParserState::Body(part), false)
+                    }
+                },
+                ParserState::Done => {
+                    self.buffer.clear();
+                    (ParserState::Done, false)
+                }
+            };
+
+            self.state = state;
+
+            if !progressed {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the parsed body, or an error if the final boundary was never reached.
+    fn finish(mut self) -> Result<Multipart, FormError> {
+        match self.state {
+            ParserState::Done => {
+                // The files now belong to the result, so they must not be deleted
+                self.created.clear();
+                Ok(std::mem::take(&mut self.result))
+            }
+            _ => Err(FormError::Invalid),
+        }
+    }
+
+    /// Parses the headers of a part and prepares somewhere to put its content.
+    fn start_part(&mut self, headers: &[u8]) -> Result<Part, FormError> {
+        if self.result.fields.len() + self.result.files.len() >= MAX_PARTS {
+            return Err(FormError::Invalid);
+        }
+
+        let headers = std::str::from_utf8(headers).map_err(|_| FormError::Invalid)?;
+        let mut disposition = None;
+        let mut content_type = None;
+
+        for line in headers.split("\r\n").filter(|line| !line.is_empty()) {
+            let (name, value) = line.split_once(':').ok_or(FormError::Invalid)?;
+
+            match HeaderType::from(name.trim()) {
+                HeaderType::ContentDisposition => disposition = Some(value.trim()),
+                HeaderType::ContentType => content_type = Some(value.trim().to_string()),
+                _ => (),
+            }
+        }
+
+        let disposition = disposition.ok_or(FormError::Invalid)?;
+
+        if !media_type(disposition).eq_ignore_ascii_case("form-data") {
+            return Err(FormError::Invalid);
+        }
+
+        let parameters = header_parameters(disposition);
+        let parameter = |name: &str| {
+            parameters
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+
+        let name = parameter("name").ok_or(FormError::Invalid)?;
+        let filename = parameter("filename");
+
+        let content = match (&filename, self.directory) {
+            (Some(_), Some(directory)) => {
+                let (file, path) = create_upload(directory).map_err(|_| FormError::Stream)?;
+                self.created.push(path.clone());
+                PartContent::Disk(file, path)
+            }
+            _ => PartContent::Memory(Vec::new()),
+        };
+
+        Ok(Part {
+            name,
+            filename,
+            content_type,
+            content,
+        })
+    }
+
+    /// Adds a part whose content has been fully read to the result.
+    fn finish_part(&mut self, part: Part) -> Result<(), FormError> {
+        let content = match part.content {
+            PartContent::Memory(data) => FileContent::Memory(data),
+            PartContent::Disk(mut file, path) => {
+                file.flush().map_err(|_| FormError::Stream)?;
+                FileContent::Disk(path)
+            }
+        };
+
+        match (part.filename, content) {
+            (Some(filename), content) => self.result.files.push(FilePart {
+                name: part.name,
+                filename,
+                content_type: part.content_type,
+                content,
+            }),
+            (None, FileContent::Memory(data)) => self
+                .result
+                .fields
+                .add(part.name, String::from_utf8_lossy(&data)),
+            (None, FileContent::Disk(_)) => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MultipartParser<'_> {
+    fn drop(&mut self) {
+        // Close any file which is still open before deleting it
+        self.state = ParserState::Done;
+
+        for path in &self.created {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+impl Part {
+    /// Writes some of the content of the part to its destination.
+    ///
+    /// Text fields are kept in memory, so they are limited to `MAX_FIELD_SIZE`.
+    fn write(&mut self, data: &[u8]) -> Result<(), FormError> {
+        match &mut self.content {
+            PartContent::Memory(buf)
+                if self.filename.is_none() && buf.len() + data.len() > MAX_FIELD_SIZE =>
+            {
+                return Err(FormError::Invalid)
+            }
+            PartContent::Memory(buf) => buf.extend_from_slice(data),
+            PartContent::Disk(file, _) => file.write_all(data).map_err(|_| FormError::Stream)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates a new file for an upload in the given directory, returning it along with its path.
+///
+/// The file must not already exist, so that a file or symbolic link planted at a predicted path in a shared
+///   directory is never written to. Another name is tried if it does.
+fn create_upload(directory: &Path) -> std::io::Result<(File, PathBuf)> {
+    loop {
+        let path = directory.join(upload_filename());
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Generates a unique name for a file uploaded to disk.
+///
+/// The filename supplied by the client is deliberately not used, since it cannot be trusted.
+fn upload_filename() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let count = UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("humphrey-upload-{}-{}-{}", std::process::id(), nanos, count)
+}
+
+/// Finds the first occurrence of the needle in the haystack.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,328 @@
+//! Provides functionality for reading the identity of a client from its TLS certificate.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// The DER tag of a sequence.
+const SEQUENCE: u8 = 0x30;
+
+/// The DER tag of an object identifier.
+const OBJECT_IDENTIFIER: u8 = 0x06;
+
+/// The DER tag of the explicitly-tagged extensions of a certificate.
+const EXTENSIONS: u8 = 0xa3;
+
+/// The DER encoding of the subject alternative name extension's object identifier, 2.5.29.17.
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// The DER encoding of the common name attribute's object identifier, 2.5.4.3.
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// The short names of common attributes of distinguished names, along with the DER encoding of their object
+///   identifiers.
+const ATTRIBUTE_NAMES: [(&[u8], &str); 9] = [
+    (COMMON_NAME, "CN"),
+    (&[0x55, 0x04, 0x05], "serialNumber"),
+    (&[0x55, 0x04, 0x06], "C"),
+    (&[0x55, 0x04, 0x07], "L"),
+    (&[0x55, 0x04, 0x08], "ST"),
+    (&[0x55, 0x04, 0x09], "STREET"),
+    (&[0x55, 0x04, 0x0a], "O"),
+    (&[0x55, 0x04, 0x0b], "OU"),
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01],
+        "emailAddress",
+    ),
+];
+
+/// Represents the verified identity of a client which authenticated with a TLS client certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// The distinguished name of the certificate's subject, for example `CN=client,O=Example`.
+    pub subject: String,
+    /// The common name of the certificate's subject, if it has one.
+    pub common_name: Option<String>,
+    /// The distinguished name of the certificate's issuer.
+    pub issuer: String,
+    /// The subject alternative names of the certificate.
+    pub alt_names: Vec<AltName>,
+    /// The DER-encoded certificate.
+    pub certificate: Vec<u8>,
+}
+
+/// Represents a subject alternative name of a certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AltName {
+    /// A DNS name, for example `service.example.com`.
+    Dns(String),
+    /// An email address.
+    Email(String),
+    /// A URI, for example a SPIFFE ID like `spiffe://example.com/service`.
+    Uri(String),
+    /// An IP address.
+    Ip(IpAddr),
+}
+
+impl PeerIdentity {
+    /// Reads the identity from a DER-encoded X.509 certificate, returning `None` if the certificate is invalid.
+    ///
+    /// The certificate is not verified, since this is done by the TLS implementation when the client connects.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (certificate, _) = read_expected(der, SEQUENCE)?;
+        let (tbs_certificate, _) = read_expected(certificate, SEQUENCE)?;
+
+        // The version is optional and explicitly tagged
+        let (tag, _, mut rest) = read_tlv(tbs_certificate)?;
+        if tag == 0xa0 {
+            rest = read_tlv(rest)?.2;
+        }
+
+        let (_signature, rest) = read_expected(rest, SEQUENCE)?;
+        let (issuer, rest) = read_expected(rest, SEQUENCE)?;
+        let (_validity, rest) = read_expected(rest, SEQUENCE)?;
+        let (subject, mut rest) = read_expected(rest, SEQUENCE)?;
+        let (_public_key, _) = read_expected(rest, SEQUENCE)?;
+
+        let mut alt_names = Vec::new();
+
+        while !rest.is_empty() {
+            let (tag, value, next) = read_tlv(rest)?;
+
+            if tag == EXTENSIONS {
+                alt_names = parse_alt_names(read_expected(value, SEQUENCE)?.0)?;
+            }
+
+            rest = next;
+        }
+
+        let subject_attributes = parse_name(subject)?;
+        let common_name = subject_attributes
+            .iter()
+            .find(|(oid, _)| *oid == COMMON_NAME)
+            .map(|(_, value)| value.clone());
+
+        Some(Self {
+            subject: format_name(&subject_attributes),
+            common_name,
+            issuer: format_name(&parse_name(issuer)?),
+            alt_names,
+            certificate: der.to_vec(),
+        })
+    }
+
+    /// Gets the DNS names of the certificate.
+    pub fn dns_names(&self) -> impl Iterator<Item = &str> {
+        self.alt_names.iter().filter_map(|name| match name {
+            AltName::Dns(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Gets the URIs of the certificate.
+    pub fn uris(&self) -> impl Iterator<Item = &str> {
+        self.alt_names.iter().filter_map(|name| match name {
+            AltName::Uri(uri) => Some(uri.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// Reads the tag, value and remaining data of the DER-encoded value at the start of the data.
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first_length = *data.get(1)? as usize;
+
+    let (length, header_length) = if first_length < 0x80 {
+        (first_length, 2)
+    } else {
+        let length_bytes = first_length & 0x7f;
+        if length_bytes == 0 || length_bytes > 4 {
+            return None;
+        }
+
+        let length = data
+            .get(2..2 + length_bytes)?
+            .iter()
+            .fold(0, |length, &byte| (length << 8) | byte as usize);
+
+        (length, 2 + length_bytes)
+    };
+
+    let value = data.get(header_length..header_length.checked_add(length)?)?;
+
+    Some((tag, value, &data[header_length + length..]))
+}
+
+/// Reads the value and remaining data of the DER-encoded value at the start of the data, checking its tag.
+fn read_expected(data: &[u8], expected_tag: u8) -> Option<(&[u8], &[u8])> {
+    match read_tlv(data)? {
+        (tag, value, rest) if tag == expected_tag => Some((value, rest)),
+        _ => None,
+    }
+}
+
+/// Parses the attributes of a distinguished name, in the order they appear in the certificate.
+fn parse_name(mut name: &[u8]) -> Option<Vec<(&[u8], String)>> {
+    let mut attributes = Vec::new();
+
+    while !name.is_empty() {
+        let (mut set, rest) = read_expected(name, 0x31)?;
+
+        while !set.is_empty() {
+            let (attribute, next) = read_expected(set, SEQUENCE)?;
+            let (oid, value) = read_expected(attribute, OBJECT_IDENTIFIER)?;
+
+            attributes.push((oid, decode_value(value)?));
+            set = next;
+        }
+
+        name = rest;
+    }
+
+    Some(attributes)
+}
+
+/// Formats the attributes of a distinguished name as a string as specified by RFC 4514, which lists them in the
+///   reverse of the order they appear in the certificate.
+fn format_name(attributes: &[(&[u8], String)]) -> String {
+    attributes
+        .iter()
+        .rev()
+        .map(|(oid, value)| {
+            let name = ATTRIBUTE_NAMES
+                .iter()
+                .find(|(attribute_oid, _)| attribute_oid == oid)
+                .map(|(_, name)| name.to_string())
+                .unwrap_or_else(|| format_oid(oid));
+
+            format!("{}={}", name, escape_value(value))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses the subject alternative names from the extensions of a certificate.
+fn parse_alt_names(mut extensions: &[u8]) -> Option<Vec<AltName>> {
+    while !extensions.is_empty() {
+        let (extension, rest) = read_expected(extensions, SEQUENCE)?;
+        let (oid, mut value) = read_expected(extension, OBJECT_IDENTIFIER)?;
+
+        if oid == SUBJECT_ALT_NAME {
+            // Skips the critical flag if present
+            if value.first() == Some(&0x01) {
+                value = read_tlv(value)?.2;
+            }
+
+            let (octets, _) = read_expected(value, 0x04)?;
+            let (mut names, _) = read_expected(octets, SEQUENCE)?;
+            let mut alt_names = Vec::new();
+
+            while !names.is_empty() {
+                let (tag, name, rest) = read_tlv(names)?;
+
+                match tag {
+                    0x81 => alt_names.push(AltName::Email(String::from_utf8_lossy(name).into())),
+                    0x82 => alt_names.push(AltName::Dns(String::from_utf8_lossy(name).into())),
+                    0x86 => alt_names.push(AltName::Uri(String::from_utf8_lossy(name).into())),
+                    0x87 => {
+                        if let Ok(octets) = <[u8; 4]>::try_from(name) {
+                            alt_names.push(AltName::Ip(IpAddr::V4(Ipv4Addr::from(octets))));
+                        } else if let Ok(octets) = <[u8; 16]>::try_from(name) {
+                            alt_names.push(AltName::Ip(IpAddr::V6(Ipv6Addr::from(octets))));
+                        }
+                    }
+                    _ => (),
+                }
+
+                names = rest;
+            }
+
+            return Some(alt_names);
+        }
+
+        extensions = rest;
+    }
+
+    Some(Vec::new())
+}
+
+/// Decodes the DER-encoded value of an attribute if it is a string, or formats its encoding as hexadecimal as
+///   specified by RFC 4514 if it is not.
+fn decode_value(encoded: &[u8]) -> Option<String> {
+    let (tag, value, _) = read_tlv(encoded)?;
+
+    let decoded = match tag {
+        // UTF8String, PrintableString and IA5String
+        0x0c | 0x13 | 0x16 => String::from_utf8_lossy(value).to_string(),
+        // TeletexString, which is treated as Latin-1
+        0x14 => value.iter().map(|&byte| byte as char).collect(),
+        // BMPString
+        0x1e => String::from_utf16_lossy(
+            &value
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        _ => {
+            let length = encoded.len() - read_tlv(encoded)?.2.len();
+            let mut hex = String::from("#");
+
+            for byte in &encoded[..length] {
+                let _ = write!(hex, "{:02x}", byte);
+            }
+
+            hex
+        }
+    };
+
+    Some(decoded)
+}
+
+/// Formats a DER-encoded object identifier in dotted decimal notation.
+fn format_oid(oid: &[u8]) -> String {
+    let mut components: Vec<u64> = Vec::new();
+    let mut current: u64 = 0;
+
+    for &byte in oid {
+        current = (current << 7) | (byte & 0x7f) as u64;
+
+        if byte & 0x80 == 0 {
+            if components.is_empty() {
+                let first = (current / 40).min(2);
+                components.push(first);
+                components.push(current - first * 40);
+            } else {
+                components.push(current);
+            }
+
+            current = 0;
+        }
+    }
+
+    components
+        .iter()
+        .map(|component| component.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Escapes the special characters of an attribute value as specified by RFC 4514.
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+
+    for (i, ch) in value.chars().enumerate() {
+        let special = matches!(ch, ',' | '+' | '"' | '\\' | '<' | '>' | ';')
+            || (i == 0 && (ch == '#' || ch == ' '))
+            || (i == last && ch == ' ');
+
+        if special {
+            escaped.push('\\');
+        }
+
+        escaped.push(ch);
+    }
+
+    escaped
+}
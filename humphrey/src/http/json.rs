@@ -0,0 +1,95 @@
+//! Provides integration with [Humphrey JSON](https://humphrey.whenderson.dev/json/index.html)
+//!   for parsing request bodies and generating responses.
+//!
+//! Only available with the `json` feature.
+
+use crate::http::form::media_type;
+use crate::http::headers::HeaderType;
+use crate::http::response::IntoResponse;
+use crate::http::{Request, Response, StatusCode};
+
+use humphrey_json::prelude::*;
+
+use std::error::Error;
+
+/// An error which occurred while parsing the JSON body of a request.
+///
+/// It can be converted into the appropriate error response, so can be returned from a handler with `?`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum JsonError {
+    /// The request did not have a JSON content type. Converted into a 415 Unsupported Media Type response.
+    UnsupportedMediaType,
+    /// The body was not valid JSON or did not match the expected type. Converted into a 400 Bad Request response.
+    Invalid,
+}
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "JsonError")
+    }
+}
+
+impl Error for JsonError {}
+
+impl IntoResponse for JsonError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnsupportedMediaType => Response::new(
+                StatusCode::UnsupportedMediaType,
+                "Expected a JSON request body",
+            ),
+            Self::Invalid => Response::new(StatusCode::BadRequest, "Invalid JSON request body"),
+        }
+    }
+}
+
+impl<T, E> IntoResponse for Result<T, E>
+where
+    T: IntoJson,
+    E: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => Response::json(&value),
+            Err(error) => error.into_response(),
+        }
+    }
+}
+
+impl Request {
+    /// Parses the JSON body of the request into the given type.
+    ///
+    /// The content type must be `application/json` or end with `+json`, otherwise
+    ///   `JsonError::UnsupportedMediaType` is returned.
+    pub fn json<T>(&self) -> Result<T, JsonError>
+    where
+        T: FromJson,
+    {
+        let content_type = self
+            .headers
+            .get(HeaderType::ContentType)
+            .map(media_type)
+            .ok_or(JsonError::UnsupportedMediaType)?
+            .to_ascii_lowercase();
+
+        if content_type != "application/json" && !content_type.ends_with("+json") {
+            return Err(JsonError::UnsupportedMediaType);
+        }
+
+        let content = self.content.as_deref().unwrap_or_default();
+        let content = std::str::from_utf8(content).map_err(|_| JsonError::Invalid)?;
+
+        humphrey_json::from_str(content).map_err(|_| JsonError::Invalid)
+    }
+}
+
+impl Response {
+    /// Creates a new 200 OK response with the given value serialized as JSON.
+    pub fn json<T>(value: &T) -> Self
+    where
+        T: IntoJson,
+    {
+        Response::new(StatusCode::OK, humphrey_json::to_string(value))
+            .with_header(HeaderType::ContentType, "application/json")
+    }
+}
@@ -9,10 +9,14 @@ use std::fmt::Display;
 pub enum Method {
     /// The `GET` method.
     Get,
+    /// The `HEAD` method.
+    Head,
     /// The `POST` method.
     Post,
     /// The `PUT` method.
     Put,
+    /// The `PATCH` method.
+    Patch,
     /// The `DELETE` method.
     Delete,
     /// The `OPTIONS` method.
@@ -30,13 +34,21 @@ impl Method {
     pub fn from_name(name: &str) -> Result<Self, RequestError> {
         match name {
             "GET" => Ok(Self::Get),
+            "HEAD" => Ok(Self::Head),
             "POST" => Ok(Self::Post),
             "PUT" => Ok(Self::Put),
+            "PATCH" => Ok(Self::Patch),
             "DELETE" => Ok(Self::Delete),
             "OPTIONS" => Ok(Self::Options),
             _ => Err(RequestError::Request),
         }
     }
+
+    /// Returns `true` if sending the request more than once has the same effect as sending it once,
+    ///   so it can safely be retried if the connection fails.
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(self, Method::Post | Method::Patch)
+    }
 }
 
 impl Display for Method {
@@ -46,8 +58,10 @@ impl Display for Method {
             "{}",
             match self {
                 Method::Get => "GET",
+                Method::Head => "HEAD",
                 Method::Post => "POST",
                 Method::Put => "PUT",
+                Method::Patch => "PATCH",
                 Method::Delete => "DELETE",
                 Method::Options => "OPTIONS",
             }
@@ -1,13 +1,21 @@
 //! Contains the Humphrey HTTP implementation.
 
 pub mod address;
+pub mod compression;
+pub mod conditional;
 pub mod cookie;
 pub mod cors;
 pub mod date;
+pub mod form;
 pub mod headers;
+pub mod identity;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod method;
 pub mod mime;
+pub mod pool;
 pub mod proxy;
+pub mod range;
 pub mod request;
 pub mod response;
 pub mod status;
@@ -0,0 +1,224 @@
+//! Provides a pool of keep-alive connections for sending requests to other servers.
+
+use crate::http::headers::HeaderType;
+use crate::http::method::Method;
+use crate::http::response::ResponseError;
+use crate::http::{Request, Response, StatusCode};
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The default maximum number of idle connections kept open to each target.
+pub const DEFAULT_MAX_IDLE: usize = 16;
+
+/// The default time after which an idle connection is closed.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Represents a pool of keep-alive connections to other servers, shared between threads.
+///
+/// Connections are kept open after a request so that the next request to the same target can skip connecting.
+///   Idle connections are checked before being used, and idempotent requests are retried on a new connection if
+///   an idle connection was closed by the target while it was being used.
+///
+/// ## Example
+/// ```
+/// let pool = ConnectionPool::new()
+///     .with_max_idle(4)
+///     .with_idle_timeout(Duration::from_secs(10));
+///
+/// let response = pool.send("127.0.0.1:8000".parse().unwrap(), request)?;
+/// ```
+pub struct ConnectionPool {
+    idle: Mutex<HashMap<SocketAddr, Vec<IdleConnection>>>,
+    max_idle: usize,
+    idle_timeout: Duration,
+    connect_timeout: Option<Duration>,
+}
+
+/// An open connection which is not currently being used.
+struct IdleConnection {
+    stream: TcpStream,
+    since: Instant,
+}
+
+impl ConnectionPool {
+    /// Creates a new, empty connection pool with the default limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of idle connections kept open to each target.
+    /// If this is zero, connections are never reused.
+    pub fn with_max_idle(mut self, max_idle: usize) -> Self {
+        self.max_idle = max_idle;
+        self
+    }
+
+    /// Sets the time after which an idle connection is closed.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Sets the time after which connecting to a target fails.
+    /// By default, the operating system's timeout is used.
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Sends the request to the target, reusing an idle connection if possible, and returns its response.
+    ///
+    /// The `Connection` header of the request is replaced so that the target keeps the connection open.
+    pub fn send(
+        &self,
+        target: SocketAddr,
+        mut request: Request,
+    ) -> Result<Response, ResponseError> {
+        request.headers.remove(HeaderType::Connection);
+        request.headers.add(HeaderType::Connection, "keep-alive");
+
+        let method = request.method.clone();
+        let request_bytes: Vec<u8> = request.into();
+
+        while let Some(mut stream) = self.take_idle(target) {
+            // If the request could not be written, the target cannot have acted on it
+            if stream.write_all(&request_bytes).is_err() {
+                continue;
+            }
+
+            match read_response(&mut stream, &method) {
+                Ok(response) => {
+                    self.release(target, stream, &method, &response);
+                    return Ok(response);
+                }
+                Err(_) if method.is_idempotent() => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut stream = match self.connect_timeout {
+            Some(timeout) => TcpStream::connect_timeout(&target, timeout),
+            None => TcpStream::connect(target),
+        }
+        .map_err(|_| ResponseError::Stream)?;
+
+        stream
+            .write_all(&request_bytes)
+            .map_err(|_| ResponseError::Stream)?;
+
+        let response = read_response(&mut stream, &method)?;
+        self.release(target, stream, &method, &response);
+
+        Ok(response)
+    }
+
+    /// Gets the number of idle connections to the target.
+    pub fn idle_connections(&self, target: SocketAddr) -> usize {
+        self.idle
+            .lock()
+            .unwrap()
+            .get(&target)
+            .map_or(0, |connections| connections.len())
+    }
+
+    /// Closes every idle connection.
+    pub fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    /// Takes the most recently used idle connection to the target which is still open, closing any which have
+    ///   been idle for too long or were closed by the target.
+    fn take_idle(&self, target: SocketAddr) -> Option<TcpStream> {
+        let mut idle = self.idle.lock().unwrap();
+        let connections = idle.get_mut(&target)?;
+
+        while let Some(connection) = connections.pop() {
+            if connection.since.elapsed() < self.idle_timeout && is_open(&connection.stream) {
+                return Some(connection.stream);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the connection to the pool if the response allows it to be reused and the pool is not full.
+    fn release(&self, target: SocketAddr, stream: TcpStream, method: &Method, response: &Response) {
+        if !is_reusable(method, response) {
+            return;
+        }
+
+        let mut idle = self.idle.lock().unwrap();
+        let connections = idle.entry(target).or_default();
+
+        connections.retain(|connection| connection.since.elapsed() < self.idle_timeout);
+
+        if connections.len() < self.max_idle {
+            connections.push(IdleConnection {
+                stream,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+impl Default for ConnectionPool {
+    fn default() -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+            max_idle: DEFAULT_MAX_IDLE,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            connect_timeout: None,
+        }
+    }
+}
+
+/// Reads the response to a request with the given method from the stream.
+fn read_response(stream: &mut TcpStream, method: &Method) -> Result<Response, ResponseError> {
+    // Responses to `HEAD` requests have a `Content-Length` header but no body
+    match method {
+        Method::Head => Response::head_from_stream(stream),
+        _ => Response::from_stream(stream),
+    }
+}
+
+/// Checks whether the target has not closed the idle connection, without blocking.
+fn is_open(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    // An idle connection should have nothing to read, so either data or the end of the stream means it is unusable
+    let open = matches!(stream.peek(&mut [0]), Err(e) if e.kind() == ErrorKind::WouldBlock);
+
+    stream.set_nonblocking(false).is_ok() && open
+}
+
+/// Checks whether the connection can be used for another request after the response has been read.
+///
+/// This requires the target to have agreed to keep the connection open, and the end of the response to have been
+///   known without the connection being closed.
+fn is_reusable(method: &Method, response: &Response) -> bool {
+    let connection = response
+        .headers
+        .get(HeaderType::Connection)
+        .map(|connection| connection.to_ascii_lowercase());
+
+    let keep_alive = match response.version.as_str() {
+        "HTTP/1.1" => match connection {
+            Some(connection) => !connection.contains("close"),
+            None => true,
+        },
+        _ => connection.is_some_and(|connection| connection.contains("keep-alive")),
+    };
+
+    let delimited = *method == Method::Head
+        || response.headers.get(HeaderType::ContentLength).is_some()
+        || response.status_code == StatusCode::NoContent
+        || response.status_code == StatusCode::NotModified;
+
+    keep_alive && delimited
+}
@@ -1,23 +1,77 @@
 //! Provides functionality for HTTP proxying.
 
+use crate::http::headers::{HeaderLike, HeaderType, Headers};
+use crate::http::method::Method;
+use crate::http::pool::ConnectionPool;
 use crate::http::response::ResponseError;
 use crate::http::{Request, Response, StatusCode};
 
 use std::io::Write;
-use std::net::{SocketAddr, TcpStream};
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::time::Duration;
 
+/// The headers which only apply to a single connection, so are removed from requests and responses by proxies.
+pub const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "Proxy-Connection",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// Represents which headers are added to requests forwarded by a proxy, and how their `Host` header is set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyHeaders {
+    /// Whether to add the `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` headers.
+    pub x_forwarded: bool,
+    /// Whether to add the standard `Forwarded` header defined in [RFC 7239](https://datatracker.ietf.org/doc/html/rfc7239).
+    pub forwarded: bool,
+    /// The name by which the proxy identifies itself in the `Via` header, or `None` to not add it.
+    pub via: Option<String>,
+    /// How to set the `Host` header.
+    pub host: HostRewrite,
+}
+
+/// Represents how a proxy sets the `Host` header of the requests it forwards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostRewrite {
+    /// Keeps the `Host` header sent by the client.
+    Preserve,
+    /// Sets the `Host` header to the address of the target.
+    Target,
+    /// Sets the `Host` header to the given value.
+    Custom(String),
+}
+
+impl Default for ProxyHeaders {
+    fn default() -> Self {
+        Self {
+            x_forwarded: true,
+            forwarded: false,
+            via: Some("humphrey".into()),
+            host: HostRewrite::Preserve,
+        }
+    }
+}
+
 /// Proxies a request to the given target, timing out and returning an error 502 after `timeout`.
 /// Always returns a response.
 pub fn proxy_request(request: &Request, target: SocketAddr, timeout: Duration) -> Response {
-    match proxy_request_internal(request, target, timeout) {
+    match try_proxy_request(request, target, timeout) {
         Ok(response) => response,
         Err(_) => Response::empty(StatusCode::BadGateway)
             .with_bytes(b"<html><body><h1>502 Bad Gateway</h1></body></html>"),
     }
 }
 
-fn proxy_request_internal(
+/// Proxies a request to the given target, timing out after `timeout`.
+/// The request is prepared with `forward_request` using the default forwarding headers.
+/// Returns an error if the target could not be reached or its response could not be parsed.
+pub fn try_proxy_request(
     request: &Request,
     target: SocketAddr,
     timeout: Duration,
@@ -25,14 +79,164 @@ fn proxy_request_internal(
     let mut stream =
         TcpStream::connect_timeout(&target, timeout).map_err(|_| ResponseError::Stream)?;
 
-    let mut cloned_request = request.clone();
-    cloned_request
-        .headers
-        .add("X-Forwarded-For", request.address.origin_addr.to_string());
-    let request_bytes: Vec<u8> = cloned_request.into();
+    let mut forwarded = forward_request(request, &ProxyHeaders::default(), &target.to_string());
+    forwarded.headers.add(HeaderType::Connection, "close");
+
+    let request_bytes: Vec<u8> = forwarded.into();
     stream
         .write_all(&request_bytes)
         .map_err(|_| ResponseError::Stream)?;
 
-    Response::from_stream(&mut stream)
+    // Responses to `HEAD` requests have a `Content-Length` header but no body
+    let mut response = match request.method {
+        Method::Head => Response::head_from_stream(&mut stream),
+        _ => Response::from_stream(&mut stream),
+    }?;
+
+    strip_hop_by_hop_headers(&mut response.headers);
+
+    Ok(response)
+}
+
+/// Proxies a request to the given target using a connection from the pool, opening a new one if none are idle.
+/// The request is prepared with `forward_request` using the default forwarding headers.
+/// Returns an error if the target could not be reached or its response could not be parsed.
+pub fn try_proxy_request_with_pool(
+    request: &Request,
+    target: SocketAddr,
+    pool: &ConnectionPool,
+) -> Result<Response, ResponseError> {
+    let forwarded = forward_request(request, &ProxyHeaders::default(), &target.to_string());
+    let mut response = pool.send(target, forwarded)?;
+    strip_hop_by_hop_headers(&mut response.headers);
+
+    Ok(response)
+}
+
+/// Prepares a request to be forwarded to the given target by a proxy.
+///
+/// Removes the hop-by-hop headers of the request, adds the configured forwarding headers and `Via` header,
+///   and sets the `Host` header. The protocol in the forwarding headers is taken from `request.https`.
+pub fn forward_request(request: &Request, headers: &ProxyHeaders, target: &str) -> Request {
+    let mut forwarded = request.clone();
+    strip_hop_by_hop_headers(&mut forwarded.headers);
+
+    // Requests received over HTTP/2 are forwarded over HTTP/1.1
+    if forwarded.version == "HTTP/2.0" {
+        forwarded.version = "HTTP/1.1".to_string();
+    }
+
+    let client = client_addr(request);
+    let proto = if request.https { "https" } else { "http" };
+    let host = request
+        .headers
+        .get(HeaderType::Host)
+        .map(|host| host.to_string());
+
+    if headers.x_forwarded {
+        append_header(
+            &mut forwarded.headers,
+            "X-Forwarded-For",
+            &client.to_string(),
+        );
+        forwarded.headers.remove("X-Forwarded-Proto");
+        forwarded.headers.add("X-Forwarded-Proto", proto);
+
+        if let Some(host) = &host {
+            forwarded.headers.remove("X-Forwarded-Host");
+            forwarded.headers.add("X-Forwarded-Host", host);
+        }
+    }
+
+    if headers.forwarded {
+        // IPv6 addresses must be quoted and enclosed in brackets
+        let mut element = match client {
+            IpAddr::V4(addr) => format!("for={}", addr),
+            IpAddr::V6(addr) => format!("for=\"[{}]\"", addr),
+        };
+
+        if let Some(host) = &host {
+            element.push_str(&format!(";host=\"{}\"", host));
+        }
+
+        element.push_str(&format!(";proto={}", proto));
+
+        append_header(&mut forwarded.headers, HeaderType::Forwarded, &element);
+    }
+
+    if let Some(pseudonym) = &headers.via {
+        add_via(&mut forwarded.headers, &request.version, pseudonym);
+    }
+
+    match &headers.host {
+        HostRewrite::Preserve => (),
+        HostRewrite::Target => {
+            forwarded.headers.remove(HeaderType::Host);
+            forwarded.headers.add(HeaderType::Host, target);
+        }
+        HostRewrite::Custom(host) => {
+            forwarded.headers.remove(HeaderType::Host);
+            forwarded.headers.add(HeaderType::Host, host);
+        }
+    }
+
+    forwarded
+}
+
+/// Removes the hop-by-hop headers from the headers of a request or response, including any headers listed
+///   in its `Connection` header.
+///
+/// `Host` and `Content-Length` are never removed, even if they are listed in the `Connection` header,
+///   since the message cannot be forwarded correctly without them.
+pub fn strip_hop_by_hop_headers(headers: &mut Headers) {
+    let listed: Vec<HeaderType> = headers
+        .get_all(HeaderType::Connection)
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(HeaderType::from)
+        .filter(|name| *name != HeaderType::Host && *name != HeaderType::ContentLength)
+        .collect();
+
+    for name in listed {
+        headers.remove(name);
+    }
+
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+/// Adds the proxy to the `Via` header of a request or response with the given HTTP version.
+pub fn add_via(headers: &mut Headers, version: &str, pseudonym: &str) {
+    let protocol = version.strip_prefix("HTTP/").unwrap_or(version);
+    append_header(
+        headers,
+        HeaderType::Via,
+        &format!("{} {}", protocol, pseudonym),
+    );
+}
+
+/// Gets the address of the client which connected to the proxy, which is the last proxy the request passed
+///   through if there were any.
+fn client_addr(request: &Request) -> IpAddr {
+    *request
+        .address
+        .proxies
+        .last()
+        .unwrap_or(&request.address.origin_addr)
+}
+
+/// Appends a value to the comma-separated list in the given header, adding the header if it is not present.
+fn append_header(headers: &mut Headers, name: impl HeaderLike, value: &str) {
+    let name = name.to_header();
+
+    match headers.get_mut(&name) {
+        Some(existing) => {
+            existing.push_str(", ");
+            existing.push_str(value);
+        }
+        None => headers.add(name, value),
+    }
 }
@@ -0,0 +1,365 @@
+//! Provides functionality for handling range requests, as defined in [RFC 7233](https://datatracker.ietf.org/doc/html/rfc7233).
+
+use crate::http::headers::HeaderType;
+use crate::http::response::BodySource;
+use crate::http::status::StatusCode;
+use crate::http::{Request, Response};
+
+use std::fs::File;
+use std::io::{self, Cursor, Seek, SeekFrom};
+use std::path::Path;
+use std::time::SystemTime;
+
+#[cfg(not(feature = "tokio"))]
+use std::io::{empty, Read};
+
+#[cfg(feature = "tokio")]
+use tokio::io::{empty, AsyncReadExt};
+
+/// The maximum number of ranges which will be served in one response.
+/// Requests for more ranges than this are served the whole content instead.
+const MAX_RANGES: usize = 32;
+
+/// Represents a satisfiable range of bytes within content of a known length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    /// The index of the first byte of the range.
+    pub start: u64,
+    /// The index of the last byte of the range, inclusive.
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// Returns the number of bytes in the range.
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// Parses the value of a `Range` header for content of the given length.
+///
+/// Returns `None` if the header is invalid or uses a unit other than bytes, in which case it should be ignored.
+/// Otherwise, returns the satisfiable ranges in the order they were requested, which will be empty if
+///   none of them can be satisfied.
+pub fn parse_range_header(value: &str, length: u64) -> Option<Vec<ByteRange>> {
+    let (unit, specs) = value.split_once('=')?;
+
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    let mut specified = false;
+
+    for spec in specs.split(',').map(|spec| spec.trim()) {
+        if spec.is_empty() {
+            continue;
+        }
+
+        specified = true;
+
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        if start.is_empty() {
+            // A suffix range, specifying the number of bytes at the end of the content
+            let suffix_length: u64 = end.parse().ok()?;
+
+            if suffix_length > 0 && length > 0 {
+                ranges.push(ByteRange {
+                    start: length.saturating_sub(suffix_length),
+                    end: length - 1,
+                });
+            }
+        } else {
+            let start: u64 = start.parse().ok()?;
+            let end: Option<u64> = match end.is_empty() {
+                true => None,
+                false => Some(end.parse().ok()?),
+            };
+
+            if end.is_some_and(|end| end < start) {
+                return None;
+            }
+
+            if start < length {
+                ranges.push(ByteRange {
+                    start,
+                    end: end.map_or(length - 1, |end| end.min(length - 1)),
+                });
+            }
+        }
+    }
+
+    match specified {
+        true => Some(ranges),
+        false => None,
+    }
+}
+
+/// Applies the `Range` and `If-Range` headers of the request to a response containing the whole content.
+///
+/// Returns a `206 Partial Content` response containing the requested range, or a `multipart/byteranges`
+///   body if multiple ranges were requested. Overlapping and adjacent ranges are merged so that no part of the
+///   content is sent more than once. If none of the ranges can be satisfied, returns a
+///   `416 Range Not Satisfiable` response. Otherwise, the response is returned unchanged.
+///
+/// Only `200 OK` responses with in-memory bodies are affected, and these are given the
+///   `Accept-Ranges: bytes` header to advertise support for range requests.
+pub fn apply_range(request: &Request, mut response: Response) -> Response {
+    if response.status_code != StatusCode::OK || response.stream.is_some() {
+        return response;
+    }
+
+    let length = response.body.len() as u64;
+
+    let ranges = match requested_ranges(request, &mut response, length) {
+        Some(ranges) => ranges,
+        None => return response,
+    };
+
+    match ranges.as_slice() {
+        [] => not_satisfiable(length),
+        [range] => {
+            response.body = response.body[range.start as usize..=range.end as usize].to_vec();
+            partial_response(response, range, length)
+        }
+        ranges => {
+            let boundary = generate_boundary();
+            let content_type = response
+                .headers
+                .get(&HeaderType::ContentType)
+                .map(|content_type| content_type.to_string());
+
+            let mut body: Vec<u8> = Vec::new();
+
+            for range in ranges {
+                body.extend(part_head(&boundary, &content_type, range, length).as_bytes());
+                body.extend(&response.body[range.start as usize..=range.end as usize]);
+                body.extend(b"\r\n");
+            }
+
+            body.extend(format!("--{}--\r\n", boundary).as_bytes());
+
+            response.body = body;
+            multipart_response(response, &boundary)
+        }
+    }
+}
+
+/// Applies the `Range` and `If-Range` headers of the request to a response for the file at the given path,
+///   streaming only the requested ranges from the file instead of reading all of it into memory.
+///
+/// The response should have its headers set but no body. Ranges are handled in the same way as by `apply_range`,
+///   with their lengths taken from the file's metadata, and if no range applies, the whole file is streamed.
+///
+/// Only `200 OK` responses are affected. Returns an error if the file cannot be opened.
+pub fn apply_file_range(
+    request: &Request,
+    mut response: Response,
+    path: &Path,
+) -> io::Result<Response> {
+    if response.status_code != StatusCode::OK {
+        return Ok(response);
+    }
+
+    let length = std::fs::metadata(path)?.len();
+
+    let ranges = match requested_ranges(request, &mut response, length) {
+        Some(ranges) => ranges,
+        None => {
+            let source = file_section(path, 0, length)?;
+            return Ok(response.with_stream(source, Some(length)));
+        }
+    };
+
+    match ranges.as_slice() {
+        [] => Ok(not_satisfiable(length)),
+        [range] => {
+            let source = file_section(path, range.start, range.length())?;
+            let response = response.with_stream(source, Some(range.length()));
+
+            Ok(partial_response(response, range, length))
+        }
+        ranges => {
+            let boundary = generate_boundary();
+            let content_type = response
+                .headers
+                .get(&HeaderType::ContentType)
+                .map(|content_type| content_type.to_string());
+
+            // Each part has its own handle to the file, so nothing is read until the part is sent
+            let mut source: BodySource = Box::new(empty());
+            let mut body_length: u64 = 0;
+
+            for range in ranges {
+                let head = part_head(&boundary, &content_type, range, length).into_bytes();
+                body_length += head.len() as u64 + range.length() + 2;
+
+                source = Box::new(
+                    source
+                        .chain(Cursor::new(head))
+                        .chain(file_section(path, range.start, range.length())?)
+                        .chain(Cursor::new(b"\r\n")),
+                );
+            }
+
+            let tail = format!("--{}--\r\n", boundary).into_bytes();
+            body_length += tail.len() as u64;
+            source = Box::new(source.chain(Cursor::new(tail)));
+
+            let response = response.with_stream(source, Some(body_length));
+
+            Ok(multipart_response(response, &boundary))
+        }
+    }
+}
+
+/// Finds the coalesced ranges of content of the given length which the request asks for, adding the
+///   `Accept-Ranges: bytes` header to the response.
+///
+/// Returns `None` if the whole content should be sent instead, for example because the client's partial copy
+///   is outdated or too many ranges were requested. Otherwise, the `Content-Length` header of the response is
+///   removed since it no longer describes the body.
+fn requested_ranges(
+    request: &Request,
+    response: &mut Response,
+    length: u64,
+) -> Option<Vec<ByteRange>> {
+    if response.headers.get(&HeaderType::AcceptRanges).is_none() {
+        response.headers.add(HeaderType::AcceptRanges, "bytes");
+    }
+
+    let range = request.headers.get(&HeaderType::Range)?;
+
+    // If the validator does not match, the client's partial copy is outdated so the whole content is sent
+    if let Some(if_range) = request.headers.get(&HeaderType::IfRange) {
+        if !if_range_matches(if_range, response) {
+            return None;
+        }
+    }
+
+    let ranges = match parse_range_header(range, length) {
+        Some(ranges) if ranges.len() <= MAX_RANGES => coalesce_ranges(ranges),
+        _ => return None,
+    };
+
+    response.headers.remove(&HeaderType::ContentLength);
+
+    Some(ranges)
+}
+
+/// Creates the `416 Range Not Satisfiable` response for content of the given length.
+fn not_satisfiable(length: u64) -> Response {
+    Response::empty(StatusCode::RequestedRangeNotSatisfiable)
+        .with_header(HeaderType::AcceptRanges, "bytes")
+        .with_header(HeaderType::ContentRange, format!("bytes */{}", length))
+}
+
+/// Turns a response whose body has been set to a single range of the content into a `206 Partial Content` response.
+fn partial_response(mut response: Response, range: &ByteRange, length: u64) -> Response {
+    response.status_code = StatusCode::PartialContent;
+    response.headers.add(
+        HeaderType::ContentRange,
+        format!("bytes {}-{}/{}", range.start, range.end, length),
+    );
+
+    response
+}
+
+/// Turns a response whose body has been set to several ranges of the content into a `206 Partial Content`
+///   response with a `multipart/byteranges` body.
+fn multipart_response(mut response: Response, boundary: &str) -> Response {
+    response.status_code = StatusCode::PartialContent;
+    response.headers.remove(&HeaderType::ContentType);
+    response.headers.add(
+        HeaderType::ContentType,
+        format!("multipart/byteranges; boundary={}", boundary),
+    );
+
+    response
+}
+
+/// Formats the boundary and headers which come before a range in a `multipart/byteranges` body.
+fn part_head(
+    boundary: &str,
+    content_type: &Option<String>,
+    range: &ByteRange,
+    length: u64,
+) -> String {
+    let mut head = format!("--{}\r\n", boundary);
+
+    if let Some(content_type) = content_type {
+        head.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+
+    head.push_str(&format!(
+        "Content-Range: bytes {}-{}/{}\r\n\r\n",
+        range.start, range.end, length
+    ));
+
+    head
+}
+
+/// Opens the file at the given path as a source of `length` bytes starting at `start`.
+#[cfg(not(feature = "tokio"))]
+fn file_section(path: &Path, start: u64, length: u64) -> io::Result<BodySource> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+
+    Ok(Box::new(file.take(length)))
+}
+
+/// Opens the file at the given path as a source of `length` bytes starting at `start`.
+#[cfg(feature = "tokio")]
+fn file_section(path: &Path, start: u64, length: u64) -> io::Result<BodySource> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+
+    Ok(Box::new(tokio::fs::File::from_std(file).take(length)))
+}
+
+/// Merges overlapping and adjacent ranges, returning them in order of their first byte.
+///
+/// This is allowed by [RFC 7233 Section 4.1](https://datatracker.ietf.org/doc/html/rfc7233#section-4.1), and
+///   stops clients from requesting the same content many times in one response.
+fn coalesce_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut coalesced: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => coalesced.push(range),
+        }
+    }
+
+    coalesced
+}
+
+/// Checks whether the validator in an `If-Range` header matches the response.
+///
+/// Entity tags must match strongly, so weak entity tags never match. Dates must match the
+///   `Last-Modified` header exactly.
+fn if_range_matches(if_range: &str, response: &Response) -> bool {
+    if if_range.starts_with('"') {
+        response.headers.get(&HeaderType::ETag) == Some(if_range)
+    } else if if_range.starts_with("W/") {
+        false
+    } else {
+        response.headers.get(&HeaderType::LastModified) == Some(if_range)
+    }
+}
+
+/// Generates a boundary string for a `multipart/byteranges` body.
+fn generate_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+
+    format!("humphrey-{:x}", nanos)
+}
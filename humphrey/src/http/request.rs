@@ -2,9 +2,12 @@
 
 use crate::http::address::Address;
 use crate::http::cookie::Cookie;
+use crate::http::form::{media_type, multipart_boundary, FormData, FormError, Multipart};
 use crate::http::headers::{HeaderType, Headers};
+use crate::http::identity::PeerIdentity;
 use crate::http::method::Method;
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::net::SocketAddr;
 
@@ -13,10 +16,38 @@ use crate::stream::Stream;
 #[cfg(not(feature = "tokio"))]
 use std::io::{BufRead, BufReader, ErrorKind, Read};
 #[cfg(not(feature = "tokio"))]
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(feature = "tokio"))]
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "tokio")]
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll};
+#[cfg(feature = "tokio")]
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream,
+    ReadBuf,
+};
+
+/// How often a connection waiting for a request checks whether the app is shutting down.
+#[cfg(not(feature = "tokio"))]
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest chunk size line accepted, including any extensions.
+const MAX_CHUNK_SIZE_LINE: u64 = 4096;
+
+/// The most bytes accepted for the trailers of a chunked body, including line endings.
+const MAX_TRAILERS_SIZE: u64 = 8192;
+
+/// The most trailer fields accepted after a chunked body.
+const MAX_TRAILERS: usize = 100;
+
+/// The size of the buffer between the connection and a streamed request body.
+#[cfg(feature = "tokio")]
+const BODY_BUFFER_SIZE: usize = 16384;
 
 /// Represents a request to the server.
 /// Contains parsed information about the request's data.
@@ -28,14 +59,23 @@ pub struct Request {
     pub uri: String,
     /// The query string of the request.
     pub query: String,
+    /// The parameters extracted from the path by the route which matched the request, for example `id` for `/users/:id`.
+    pub params: HashMap<String, String>,
     /// The HTTP version of the request.
     pub version: String,
     /// A list of headers included in the request.
     pub headers: Headers,
+    /// The trailers sent after a chunked request body, kept apart from the headers since they
+    ///   arrive after the headers have already been acted on.
+    pub trailers: Headers,
     /// The request body, if supplied.
     pub content: Option<Vec<u8>>,
     /// The address from which the request came
     pub address: Address,
+    /// The verified identity of the client, if it authenticated with a TLS client certificate.
+    pub peer_identity: Option<PeerIdentity>,
+    /// Whether the request was received over an HTTPS connection.
+    pub https: bool,
 }
 
 /// An error which occurred during the parsing of a request.
@@ -49,6 +89,8 @@ pub enum RequestError {
     Disconnected,
     /// The request timed out.
     Timeout,
+    /// The request body was larger than the maximum size allowed by the app.
+    PayloadTooLarge,
 }
 
 trait OptionToRequestResult<T> {
@@ -76,27 +118,29 @@ impl Request {
     where
         T: Read,
     {
+        let mut reader = BufReader::new(stream);
         let mut first_buf: [u8; 1] = [0; 1];
-        stream
+        reader
             .read_exact(&mut first_buf)
             .map_err(|_| RequestError::Disconnected)?;
 
-        Self::from_stream_inner(stream, address, first_buf[0])
+        let mut request = Self::head_from_reader(&mut reader, address, first_buf[0])?;
+        request.read_body(&mut reader, None)?;
+
+        Ok(request)
     }
 
     /// Attempts to read and parse one HTTP request from the given reader.
     #[cfg(feature = "tokio")]
     pub async fn from_stream<T>(stream: &mut T, address: SocketAddr) -> Result<Self, RequestError>
     where
-        T: AsyncReadExt + Unpin,
+        T: AsyncRead + Unpin,
     {
-        let mut first_buf: [u8; 1] = [0; 1];
-        stream
-            .read_exact(&mut first_buf)
-            .await
-            .map_err(|_| RequestError::Disconnected)?;
+        let mut reader = BufReader::new(stream);
+        let mut request = Self::head_from_reader(&mut reader, address).await?;
+        request.read_body(&mut reader, None).await?;
 
-        Self::from_stream_inner(stream, address, first_buf[0]).await
+        Ok(request)
     }
 
     /// Attempts to read and parse one HTTP request from the given stream, timing out after the timeout.
@@ -106,22 +150,13 @@ impl Request {
         address: SocketAddr,
         timeout: Duration,
     ) -> Result<Self, RequestError> {
-        stream
-            .set_timeout(Some(timeout))
-            .map_err(|_| RequestError::Stream)?;
-
-        let mut first_buf: [u8; 1] = [0; 1];
-        stream
-            .read_exact(&mut first_buf)
-            .map_err(|e| match e.kind() {
-                ErrorKind::TimedOut => RequestError::Timeout,
-                ErrorKind::WouldBlock => RequestError::Timeout,
-                _ => RequestError::Disconnected,
-            })?;
+        let mut reader = BufReader::new(stream);
+        let first_byte = read_first_byte(&mut reader, Some(timeout), &AtomicBool::new(false))?;
 
-        stream.set_timeout(None).map_err(|_| RequestError::Stream)?;
+        let mut request = Self::head_from_reader(&mut reader, address, first_byte)?;
+        request.read_body(&mut reader, None)?;
 
-        Self::from_stream_inner(stream, address, first_buf[0])
+        Ok(request)
     }
 
     /// Get the cookies from the request.
@@ -147,17 +182,100 @@ impl Request {
             .find(|cookie| cookie.name == name.as_ref())
     }
 
-    /// Attempts to read and parse one HTTP request from the given reader.
+    /// Gets the value of a parameter extracted from the path by the route which matched the request.
+    pub fn param(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.params.get(name.as_ref()).map(|value| value.as_str())
+    }
+
+    /// Parses the query string of the request.
+    pub fn query_params(&self) -> FormData {
+        FormData::parse(&self.query)
+    }
+
+    /// Parses the `application/x-www-form-urlencoded` body of the request.
+    ///
+    /// Returns an error if the request has a different content type or the body is not valid UTF-8.
+    /// A request without a body is treated as an empty form.
+    pub fn form(&self) -> Result<FormData, FormError> {
+        let content_type = self
+            .headers
+            .get(HeaderType::ContentType)
+            .ok_or(FormError::UnsupportedContentType)?;
+
+        if !media_type(content_type).eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return Err(FormError::UnsupportedContentType);
+        }
+
+        let content = self.content.as_deref().unwrap_or_default();
+        let content = std::str::from_utf8(content).map_err(|_| FormError::Invalid)?;
+
+        Ok(FormData::parse(content))
+    }
+
+    /// Parses the `multipart/form-data` body of the request, keeping all files in memory.
+    ///
+    /// For large uploads, use a streaming handler with `Multipart::from_body` instead.
+    pub fn multipart(&self) -> Result<Multipart, FormError> {
+        let boundary = multipart_boundary(self)?;
+        let content = self.content.as_deref().unwrap_or_default();
+
+        Multipart::parse(content, boundary)
+    }
+
+    /// Gets the length of the request body as specified by its headers.
+    ///
+    /// If the body uses chunked transfer encoding, this takes precedence over any `Content-Length` header.
+    /// Any other transfer encoding is not supported and will result in an error.
+    pub(crate) fn body_length(&self) -> Result<BodyLength, RequestError> {
+        if let Some(transfer_encoding) = self.headers.get(&HeaderType::TransferEncoding) {
+            let final_encoding = transfer_encoding.rsplit(',').next().unwrap_or("").trim();
+
+            return match final_encoding.eq_ignore_ascii_case("chunked") {
+                true => Ok(BodyLength::Chunked),
+                false => Err(RequestError::Request),
+            };
+        }
+
+        match self.headers.get(&HeaderType::ContentLength) {
+            Some(content_length) => content_length
+                .parse()
+                .map(BodyLength::Fixed)
+                .map_err(|_| RequestError::Request),
+            None => Ok(BodyLength::Fixed(0)),
+        }
+    }
+
+    /// Replaces the framing headers of a request whose chunked body has been decoded,
+    ///   so that the request can be forwarded with its content as-is.
+    fn set_decoded_length(&mut self, length: usize) {
+        let remaining_encodings = self
+            .headers
+            .get(&HeaderType::TransferEncoding)
+            .and_then(|encodings| encodings.rsplit_once(','))
+            .map(|(encodings, _)| encodings.trim().to_string());
+
+        self.headers.remove(&HeaderType::TransferEncoding);
+        self.headers.remove(&HeaderType::ContentLength);
+
+        if let Some(encodings) = remaining_encodings {
+            self.headers.add(HeaderType::TransferEncoding, encodings);
+        }
+
+        self.headers
+            .add(HeaderType::ContentLength, length.to_string());
+    }
+
+    /// Attempts to read and parse the start line and headers of one HTTP request from the given reader.
+    /// The body is left unread on the reader.
     #[cfg(not(feature = "tokio"))]
-    fn from_stream_inner<T>(
-        stream: &mut T,
+    pub(crate) fn head_from_reader<T>(
+        reader: &mut T,
         address: SocketAddr,
         first_byte: u8,
     ) -> Result<Self, RequestError>
     where
-        T: Read,
+        T: BufRead,
     {
-        let mut reader = BufReader::new(stream);
         let mut start_line_buf: Vec<u8> = Vec::with_capacity(256);
         reader
             .read_until(0xA, &mut start_line_buf)
@@ -165,26 +283,7 @@ impl Request {
 
         start_line_buf.insert(0, first_byte);
 
-        let start_line_string =
-            std::str::from_utf8(&start_line_buf).map_err(|_| RequestError::Request)?;
-        let mut start_line = start_line_string.split(' ');
-
-        let method = Method::from_name(start_line.next().to_error(RequestError::Request)?)?;
-        let mut uri_iter = start_line
-            .next()
-            .to_error(RequestError::Request)?
-            .splitn(2, '?');
-        let version = start_line
-            .next()
-            .to_error(RequestError::Request)?
-            .strip_suffix("\r\n")
-            .unwrap_or("")
-            .to_string();
-
-        safe_assert(!version.is_empty())?;
-
-        let uri = uri_iter.next().unwrap().to_string();
-        let query = uri_iter.next().unwrap_or("").to_string();
+        let (method, uri, query, version) = parse_start_line(&start_line_buf)?;
 
         let mut headers = Headers::new();
 
@@ -193,96 +292,55 @@ impl Request {
             reader
                 .read_until(0xA, &mut line_buf)
                 .map_err(|_| RequestError::Stream)?;
-            let line = std::str::from_utf8(&line_buf).map_err(|_| RequestError::Request)?;
 
-            if line == "\r\n" {
+            if !parse_header_line(&line_buf, &mut headers)? {
                 break;
-            } else {
-                safe_assert(line.len() >= 2)?;
-                let line_without_crlf = &line[0..line.len() - 2];
-                let mut line_parts = line_without_crlf.splitn(2, ':');
-                headers.add(
-                    HeaderType::from(line_parts.next().to_error(RequestError::Request)?),
-                    line_parts
-                        .next()
-                        .to_error(RequestError::Request)?
-                        .trim_start(),
-                );
             }
         }
 
         let address =
             Address::from_headers(&headers, address).map_err(|_| RequestError::Request)?;
 
-        if let Some(content_length) = headers.get(&HeaderType::ContentLength) {
-            let content_length: usize =
-                content_length.parse().map_err(|_| RequestError::Request)?;
-            let mut content_buf: Vec<u8> = vec![0u8; content_length];
-            reader
-                .read_exact(&mut content_buf)
-                .map_err(|_| RequestError::Stream)?;
-
-            Ok(Self {
-                method,
-                uri,
-                query,
-                version,
-                headers,
-                content: Some(content_buf),
-                address,
-            })
-        } else {
-            Ok(Self {
-                method,
-                uri,
-                query,
-                version,
-                headers,
-                content: None,
-                address,
-            })
-        }
+        Ok(Self {
+            method,
+            uri,
+            query,
+            params: HashMap::new(),
+            version,
+            headers,
+            trailers: Headers::new(),
+            content: None,
+            address,
+            peer_identity: None,
+            https: false,
+        })
     }
 
-    /// Attempts to read and parse one HTTP request from the given reader.
+    /// Attempts to read and parse the start line and headers of one HTTP request from the given reader.
+    /// The body is left unread on the reader.
     #[cfg(feature = "tokio")]
-    async fn from_stream_inner<T>(
-        stream: &mut T,
+    pub(crate) async fn head_from_reader<T>(
+        reader: &mut T,
         address: SocketAddr,
-        first_byte: u8,
     ) -> Result<Self, RequestError>
     where
-        T: AsyncReadExt + Unpin,
+        T: AsyncBufRead + Unpin,
     {
-        let mut reader = BufReader::new(stream);
+        let mut first_buf: [u8; 1] = [0; 1];
+        reader
+            .read_exact(&mut first_buf)
+            .await
+            .map_err(|_| RequestError::Disconnected)?;
+
         let mut start_line_buf: Vec<u8> = Vec::with_capacity(256);
         reader
             .read_until(0xA, &mut start_line_buf)
             .await
             .map_err(|_| RequestError::Stream)?;
 
-        start_line_buf.insert(0, first_byte);
-
-        let start_line_string =
-            std::str::from_utf8(&start_line_buf).map_err(|_| RequestError::Request)?;
-        let mut start_line = start_line_string.split(' ');
+        start_line_buf.insert(0, first_buf[0]);
 
-        let method = Method::from_name(start_line.next().to_error(RequestError::Request)?)?;
-        let mut uri_iter = start_line
-            .next()
-            .to_error(RequestError::Request)?
-            .splitn(2, '?');
-        let version = start_line
-            .next()
-            .to_error(RequestError::Request)?
-            .strip_suffix("\r\n")
-            .unwrap_or("")
-            .to_string();
-
-        safe_assert(!version.is_empty())?;
-
-        let uri = uri_iter.next().unwrap().to_string();
-        let query = uri_iter.next().unwrap_or("").to_string();
+        let (method, uri, query, version) = parse_start_line(&start_line_buf)?;
 
         let mut headers = Headers::new();
 
@@ -292,57 +350,656 @@ impl Request {
                 .read_until(0xA, &mut line_buf)
                 .await
                 .map_err(|_| RequestError::Stream)?;
-            let line = std::str::from_utf8(&line_buf).map_err(|_| RequestError::Request)?;
 
-            if line == "\r\n" {
+            if !parse_header_line(&line_buf, &mut headers)? {
                 break;
-            } else {
-                safe_assert(line.len() >= 2)?;
-                let line_without_crlf = &line[0..line.len() - 2];
-                let mut line_parts = line_without_crlf.splitn(2, ':');
-                headers.add(
-                    HeaderType::from(line_parts.next().to_error(RequestError::Request)?),
-                    line_parts
-                        .next()
-                        .to_error(RequestError::Request)?
-                        .trim_start(),
-                );
             }
         }
 
         let address =
             Address::from_headers(&headers, address).map_err(|_| RequestError::Request)?;
 
-        if let Some(content_length) = headers.get(&HeaderType::ContentLength) {
-            let content_length: usize =
-                content_length.parse().map_err(|_| RequestError::Request)?;
-            let mut content_buf: Vec<u8> = vec![0u8; content_length];
-            reader
-                .read_exact(&mut content_buf)
-                .await
-                .map_err(|_| RequestError::Stream)?;
+        Ok(Self {
+            method,
+            uri,
+            query,
+            params: HashMap::new(),
+            version,
+            headers,
+            trailers: Headers::new(),
+            content: None,
+            address,
+            peer_identity: None,
+            https: false,
+        })
+    }
 
-            Ok(Self {
-                method,
-                uri,
-                query,
-                version,
-                headers,
-                content: Some(content_buf),
-                address,
-            })
+    /// Reads the body of the request from the given reader into `content`.
+    ///
+    /// If the body is larger than the maximum size, `RequestError::PayloadTooLarge` is returned
+    ///   without reading the rest of it. Chunked bodies are decoded, and any trailers are stored
+    ///   in `trailers`.
+    #[cfg(not(feature = "tokio"))]
+    pub(crate) fn read_body<T>(
+        &mut self,
+        reader: &mut T,
+        max_size: Option<usize>,
+    ) -> Result<(), RequestError>
+    where
+        T: BufRead + ?Sized,
+    {
+        if self.headers.get(&HeaderType::ContentLength).is_none()
+            && self.headers.get(&HeaderType::TransferEncoding).is_none()
+        {
+            return Ok(());
+        }
+
+        let too_large = |length: u64| max_size.is_some_and(|max_size| length > max_size as u64);
+
+        match self.body_length()? {
+            BodyLength::Fixed(content_length) => {
+                if too_large(content_length) {
+                    return Err(RequestError::PayloadTooLarge);
+                }
+
+                let mut content_buf: Vec<u8> = Vec::new();
+                (&mut *reader)
+                    .take(content_length)
+                    .read_to_end(&mut content_buf)
+                    .map_err(|_| RequestError::Stream)?;
+
+                safe_read(content_buf.len() as u64 == content_length)?;
+
+                self.content = Some(content_buf);
+            }
+            BodyLength::Chunked => {
+                let mut content_buf: Vec<u8> = Vec::new();
+
+                loop {
+                    let chunk_size = read_chunk_size(reader)?;
+
+                    if chunk_size == 0 {
+                        break;
+                    }
+
+                    // Chunk sizes large enough to overflow are certainly too large
+                    let chunk_too_large = match (content_buf.len() as u64).checked_add(chunk_size) {
+                        Some(length) => too_large(length),
+                        None => true,
+                    };
+
+                    if chunk_too_large {
+                        return Err(RequestError::PayloadTooLarge);
+                    }
+
+                    let read = (&mut *reader)
+                        .take(chunk_size)
+                        .read_to_end(&mut content_buf)
+                        .map_err(|_| RequestError::Stream)?;
+
+                    safe_read(read as u64 == chunk_size)?;
+                    read_crlf(reader)?;
+                }
+
+                read_trailers(reader, &mut self.trailers)?;
+
+                self.set_decoded_length(content_buf.len());
+                self.content = Some(content_buf);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the body of the request from the given reader into `content`.
+    ///
+    /// If the body is larger than the maximum size, `RequestError::PayloadTooLarge` is returned
+    ///   without reading the rest of it. Chunked bodies are decoded, and any trailers are stored
+    ///   in `trailers`.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn read_body<T>(
+        &mut self,
+        reader: &mut T,
+        max_size: Option<usize>,
+    ) -> Result<(), RequestError>
+    where
+        T: AsyncBufRead + Unpin,
+    {
+        if self.headers.get(&HeaderType::ContentLength).is_none()
+            && self.headers.get(&HeaderType::TransferEncoding).is_none()
+        {
+            return Ok(());
+        }
+
+        let too_large = |length: u64| max_size.is_some_and(|max_size| length > max_size as u64);
+
+        match self.body_length()? {
+            BodyLength::Fixed(content_length) => {
+                if too_large(content_length) {
+                    return Err(RequestError::PayloadTooLarge);
+                }
+
+                let mut content_buf: Vec<u8> = Vec::new();
+                (&mut *reader)
+                    .take(content_length)
+                    .read_to_end(&mut content_buf)
+                    .await
+                    .map_err(|_| RequestError::Stream)?;
+
+                safe_read(content_buf.len() as u64 == content_length)?;
+
+                self.content = Some(content_buf);
+            }
+            BodyLength::Chunked => {
+                let mut content_buf: Vec<u8> = Vec::new();
+
+                loop {
+                    let chunk_size = read_chunk_size(reader).await?;
+
+                    if chunk_size == 0 {
+                        break;
+                    }
+
+                    // Chunk sizes large enough to overflow are certainly too large
+                    let chunk_too_large = match (content_buf.len() as u64).checked_add(chunk_size) {
+                        Some(length) => too_large(length),
+                        None => true,
+                    };
+
+                    if chunk_too_large {
+                        return Err(RequestError::PayloadTooLarge);
+                    }
+
+                    let read = (&mut *reader)
+                        .take(chunk_size)
+                        .read_to_end(&mut content_buf)
+                        .await
+                        .map_err(|_| RequestError::Stream)?;
+
+                    safe_read(read as u64 == chunk_size)?;
+                    read_crlf(reader).await?;
+                }
+
+                read_trailers(reader, &mut self.trailers).await?;
+
+                self.set_decoded_length(content_buf.len());
+                self.content = Some(content_buf);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The length of a request body, as specified by the request's headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BodyLength {
+    /// The body is of the given length in bytes.
+    Fixed(u64),
+    /// The body uses chunked transfer encoding, so its length is not known in advance.
+    Chunked,
+}
+
+/// Represents the body of a request which is read from the connection as it is consumed,
+///   instead of being read into memory before the handler is called.
+///
+/// It implements `Read`, and reaches the end of the stream at the end of the body.
+/// Chunked bodies are decoded as they are read.
+#[cfg(not(feature = "tokio"))]
+pub struct RequestBody<'a> {
+    reader: &'a mut dyn BufRead,
+    chunked: bool,
+    in_chunk: bool,
+    remaining: u64,
+    finished: bool,
+    trailers: Headers,
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<'a> RequestBody<'a> {
+    /// Creates a new request body of the given length, reading from the given reader.
+    pub(crate) fn new(reader: &'a mut dyn BufRead, length: BodyLength) -> Self {
+        let (chunked, remaining) = match length {
+            BodyLength::Fixed(length) => (false, length),
+            BodyLength::Chunked => (true, 0),
+        };
+
+        Self {
+            reader,
+            chunked,
+            in_chunk: false,
+            remaining,
+            finished: !chunked && remaining == 0,
+            trailers: Headers::new(),
+        }
+    }
+
+    /// Gets the trailers sent after a chunked body.
+    /// These are only available once the whole body has been read.
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    /// Returns true if the whole body has been read from the connection.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Reads the size of the next chunk, or the trailers if the last chunk has been reached.
+    fn next_chunk(&mut self) -> Result<(), RequestError> {
+        if self.in_chunk {
+            read_crlf(self.reader)?;
+        }
+
+        let chunk_size = read_chunk_size(self.reader)?;
+
+        if chunk_size == 0 {
+            read_trailers(self.reader, &mut self.trailers)?;
+            self.finished = true;
         } else {
-            Ok(Self {
-                method,
-                uri,
-                query,
-                version,
-                headers,
-                content: None,
-                address,
-            })
+            self.in_chunk = true;
+            self.remaining = chunk_size;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Read for RequestBody<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.finished || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            self.next_chunk()
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+
+            if self.finished {
+                return Ok(0);
+            }
+        }
+
+        let max = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.reader.read(&mut buf[..max])?;
+
+        if n == 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "connection closed before the end of the request body",
+            ));
+        }
+
+        self.remaining -= n as u64;
+
+        if !self.chunked && self.remaining == 0 {
+            self.finished = true;
+        }
+
+        Ok(n)
+    }
+}
+
+/// Represents the body of a request which is read from the connection as it is consumed,
+///   instead of being read into memory before the handler is called.
+///
+/// It implements `AsyncRead`, and reaches the end of the stream at the end of the body.
+/// Chunked bodies are decoded as they are read.
+#[cfg(feature = "tokio")]
+pub struct RequestBody {
+    reader: DuplexStream,
+    trailers: Arc<Mutex<Headers>>,
+}
+
+/// The connection handler's end of a `RequestBody`, through which it passes the body as it arrives.
+#[cfg(feature = "tokio")]
+pub(crate) struct BodyWriter {
+    writer: DuplexStream,
+    trailers: Arc<Mutex<Headers>>,
+}
+
+#[cfg(feature = "tokio")]
+impl RequestBody {
+    /// Creates a new request body along with the writer through which the connection handler
+    ///   passes it the body as it arrives.
+    pub(crate) fn new() -> (Self, BodyWriter) {
+        let (reader, writer) = tokio::io::duplex(BODY_BUFFER_SIZE);
+        let trailers = Arc::new(Mutex::new(Headers::new()));
+
+        (
+            Self {
+                reader,
+                trailers: trailers.clone(),
+            },
+            BodyWriter { writer, trailers },
+        )
+    }
+
+    /// Gets the trailers sent after a chunked body.
+    /// These are only available once the whole body has been read.
+    pub fn trailers(&self) -> Headers {
+        self.trailers.lock().unwrap().clone()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for RequestBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+/// Copies a request body of the given length from the reader to the writer of a `RequestBody`,
+///   decoding it if it is chunked.
+/// Returns true if the whole body was read from the reader, which will not be the case if the
+///   handler dropped the body before reading it.
+#[cfg(feature = "tokio")]
+pub(crate) async fn pipe_body<T>(reader: &mut T, mut writer: BodyWriter, length: BodyLength) -> bool
+where
+    T: AsyncBufRead + Unpin,
+{
+    match length {
+        BodyLength::Fixed(length) => pipe_exact(reader, &mut writer.writer, length).await,
+        BodyLength::Chunked => {
+            loop {
+                let chunk_size = match read_chunk_size(reader).await {
+                    Ok(chunk_size) => chunk_size,
+                    Err(_) => return false,
+                };
+
+                if chunk_size == 0 {
+                    break;
+                }
+
+                if !pipe_exact(reader, &mut writer.writer, chunk_size).await
+                    || read_crlf(reader).await.is_err()
+                {
+                    return false;
+                }
+            }
+
+            let mut trailers = Headers::new();
+
+            if read_trailers(reader, &mut trailers).await.is_err() {
+                return false;
+            }
+
+            // The trailers must be available before the handler reaches the end of the body
+            *writer.trailers.lock().unwrap() = trailers;
+
+            true
+        }
+    }
+}
+
+/// Copies exactly `length` bytes from the reader to the writer.
+/// Returns false if the reader ended early or the writer was closed.
+#[cfg(feature = "tokio")]
+async fn pipe_exact<T>(reader: &mut T, writer: &mut DuplexStream, length: u64) -> bool
+where
+    T: AsyncRead + Unpin,
+{
+    let mut remaining = length;
+    let mut buf = [0u8; BODY_BUFFER_SIZE];
+
+    while remaining > 0 {
+        let max = buf.len().min(remaining.min(usize::MAX as u64) as usize);
+
+        match reader.read(&mut buf[..max]).await {
+            Ok(0) | Err(_) => return false,
+            Ok(n) => {
+                remaining -= n as u64;
+
+                if writer.write_all(&buf[..n]).await.is_err() {
+                    return false;
+                }
+            }
+        }
+    }
+
+    true
+}
+
+/// Reads the size line of a chunk using the chunked transfer encoding, ignoring any extensions.
+#[cfg(not(feature = "tokio"))]
+fn read_chunk_size<T>(reader: &mut T) -> Result<u64, RequestError>
+where
+    T: BufRead + ?Sized,
+{
+    // Lines which are too long are cut off, so they are rejected for not ending with CRLF
+    let mut line_buf: Vec<u8> = Vec::with_capacity(16);
+    reader
+        .take(MAX_CHUNK_SIZE_LINE)
+        .read_until(0xA, &mut line_buf)
+        .map_err(|_| RequestError::Stream)?;
+
+    parse_chunk_size(&line_buf)
+}
+
+/// Reads the size line of a chunk using the chunked transfer encoding, ignoring any extensions.
+#[cfg(feature = "tokio")]
+async fn read_chunk_size<T>(reader: &mut T) -> Result<u64, RequestError>
+where
+    T: AsyncBufRead + Unpin + ?Sized,
+{
+    // Lines which are too long are cut off, so they are rejected for not ending with CRLF
+    let mut line_buf: Vec<u8> = Vec::with_capacity(16);
+    reader
+        .take(MAX_CHUNK_SIZE_LINE)
+        .read_until(0xA, &mut line_buf)
+        .await
+        .map_err(|_| RequestError::Stream)?;
+
+    parse_chunk_size(&line_buf)
+}
+
+/// Reads the CRLF which follows the data of a chunk.
+#[cfg(not(feature = "tokio"))]
+fn read_crlf<T>(reader: &mut T) -> Result<(), RequestError>
+where
+    T: BufRead + ?Sized,
+{
+    let mut crlf = [0u8; 2];
+    reader
+        .read_exact(&mut crlf)
+        .map_err(|_| RequestError::Stream)?;
+
+    safe_assert(&crlf == b"\r\n")
+}
+
+/// Reads the CRLF which follows the data of a chunk.
+#[cfg(feature = "tokio")]
+async fn read_crlf<T>(reader: &mut T) -> Result<(), RequestError>
+where
+    T: AsyncBufRead + Unpin + ?Sized,
+{
+    let mut crlf = [0u8; 2];
+    reader
+        .read_exact(&mut crlf)
+        .await
+        .map_err(|_| RequestError::Stream)?;
+
+    safe_assert(&crlf == b"\r\n")
+}
+
+/// Reads the trailers which follow the last chunk of a chunked body, adding them to the given headers.
+///
+/// Trailers larger than `MAX_TRAILERS_SIZE` in total, or more than `MAX_TRAILERS` of them, are
+///   rejected with `RequestError::PayloadTooLarge`.
+#[cfg(not(feature = "tokio"))]
+fn read_trailers<T>(reader: &mut T, headers: &mut Headers) -> Result<(), RequestError>
+where
+    T: BufRead + ?Sized,
+{
+    let mut remaining = MAX_TRAILERS_SIZE;
+
+    // One more line than the number of trailers is allowed for the empty line which ends them
+    for _ in 0..=MAX_TRAILERS {
+        let mut line_buf: Vec<u8> = Vec::with_capacity(256);
+        let read = (&mut *reader)
+            .take(remaining)
+            .read_until(0xA, &mut line_buf)
+            .map_err(|_| RequestError::Stream)?;
+
+        remaining -= read as u64;
+
+        if remaining == 0 && line_buf.last() != Some(&0xA) {
+            return Err(RequestError::PayloadTooLarge);
+        }
+
+        if !parse_header_line(&line_buf, headers)? {
+            return Ok(());
+        }
+    }
+
+    Err(RequestError::PayloadTooLarge)
+}
+
+/// Reads the trailers which follow the last chunk of a chunked body, adding them to the given headers.
+///
+/// Trailers larger than `MAX_TRAILERS_SIZE` in total, or more than `MAX_TRAILERS` of them, are
+///   rejected with `RequestError::PayloadTooLarge`.
+#[cfg(feature = "tokio")]
+async fn read_trailers<T>(reader: &mut T, headers: &mut Headers) -> Result<(), RequestError>
+where
+    T: AsyncBufRead + Unpin + ?Sized,
+{
+    let mut remaining = MAX_TRAILERS_SIZE;
+
+    // One more line than the number of trailers is allowed for the empty line which ends them
+    for _ in 0..=MAX_TRAILERS {
+        let mut line_buf: Vec<u8> = Vec::with_capacity(256);
+        let read = (&mut *reader)
+            .take(remaining)
+            .read_until(0xA, &mut line_buf)
+            .await
+            .map_err(|_| RequestError::Stream)?;
+
+        remaining -= read as u64;
+
+        if remaining == 0 && line_buf.last() != Some(&0xA) {
+            return Err(RequestError::PayloadTooLarge);
+        }
+
+        if !parse_header_line(&line_buf, headers)? {
+            return Ok(());
         }
     }
+
+    Err(RequestError::PayloadTooLarge)
+}
+
+/// Reads the first byte of a request from the given reader, timing out after the timeout if specified.
+///
+/// If the app starts shutting down while waiting, the connection is treated as disconnected.
+#[cfg(not(feature = "tokio"))]
+pub(crate) fn read_first_byte(
+    reader: &mut BufReader<&mut Stream>,
+    timeout: Option<Duration>,
+    draining: &AtomicBool,
+) -> Result<u8, RequestError> {
+    let mut first_buf: [u8; 1] = [0; 1];
+    let start = Instant::now();
+
+    loop {
+        // Wait in short intervals so idle connections can be closed promptly when the app shuts down
+        let wait = match timeout {
+            Some(timeout) => timeout
+                .saturating_sub(start.elapsed())
+                .min(DRAIN_POLL_INTERVAL),
+            None => DRAIN_POLL_INTERVAL,
+        };
+
+        if wait.is_zero() {
+            return Err(RequestError::Timeout);
+        }
+
+        reader
+            .get_mut()
+            .set_timeout(Some(wait))
+            .map_err(|_| RequestError::Stream)?;
+
+        match reader.read_exact(&mut first_buf) {
+            Ok(()) => break,
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                if draining.load(Ordering::Relaxed) {
+                    return Err(RequestError::Disconnected);
+                }
+            }
+            Err(_) => return Err(RequestError::Disconnected),
+        }
+    }
+
+    reader
+        .get_mut()
+        .set_timeout(None)
+        .map_err(|_| RequestError::Stream)?;
+
+    Ok(first_buf[0])
+}
+
+/// Parses the start line of a request into its method, URI, query string and version.
+fn parse_start_line(line: &[u8]) -> Result<(Method, String, String, String), RequestError> {
+    let start_line_string = std::str::from_utf8(line).map_err(|_| RequestError::Request)?;
+    let mut start_line = start_line_string.split(' ');
+
+    let method = Method::from_name(start_line.next().to_error(RequestError::Request)?)?;
+    let mut uri_iter = start_line
+        .next()
+        .to_error(RequestError::Request)?
+        .splitn(2, '?');
+    let version = start_line
+        .next()
+        .to_error(RequestError::Request)?
+        .strip_suffix("\r\n")
+        .unwrap_or("")
+        .to_string();
+
+    safe_assert(!version.is_empty())?;
+
+    let uri = uri_iter.next().unwrap().to_string();
+    let query = uri_iter.next().unwrap_or("").to_string();
+
+    Ok((method, uri, query, version))
+}
+
+/// Parses the size line of a chunk, which is hexadecimal and may be followed by extensions.
+fn parse_chunk_size(line: &[u8]) -> Result<u64, RequestError> {
+    let line = std::str::from_utf8(line).map_err(|_| RequestError::Request)?;
+    let line = line.strip_suffix("\r\n").to_error(RequestError::Request)?;
+    let size = line.split(';').next().unwrap_or("").trim();
+
+    u64::from_str_radix(size, 16).map_err(|_| RequestError::Request)
+}
+
+/// Parses a header line and adds it to the headers.
+/// Returns false if the line is the empty line marking the end of the headers.
+fn parse_header_line(line: &[u8], headers: &mut Headers) -> Result<bool, RequestError> {
+    let line = std::str::from_utf8(line).map_err(|_| RequestError::Request)?;
+
+    if line == "\r\n" {
+        return Ok(false);
+    }
+
+    safe_assert(line.len() >= 2)?;
+    let line_without_crlf = &line[0..line.len() - 2];
+    let mut line_parts = line_without_crlf.splitn(2, ':');
+    headers.add(
+        HeaderType::from(line_parts.next().to_error(RequestError::Request)?),
+        line_parts
+            .next()
+            .to_error(RequestError::Request)?
+            .trim_start(),
+    );
+
+    Ok(true)
 }
 
 /// Asserts that the condition is true, returning a `Result`.
@@ -353,6 +1010,14 @@ fn safe_assert(condition: bool) -> Result<(), RequestError> {
     }
 }
 
+/// Asserts that the expected amount of data was read from the stream, returning a `Result`.
+fn safe_read(condition: bool) -> Result<(), RequestError> {
+    match condition {
+        true => Ok(()),
+        false => Err(RequestError::Stream),
+    }
+}
+
 impl From<Request> for Vec<u8> {
     fn from(req: Request) -> Self {
         let start_line = if req.query.is_empty() {
@@ -6,8 +6,26 @@ use crate::http::status::StatusCode;
 
 use std::convert::TryFrom;
 use std::error::Error;
+use std::fmt::Debug;
 use std::io::{BufRead, BufReader, Read};
 
+#[cfg(not(feature = "tokio"))]
+use std::io::{ErrorKind, Write};
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The size of the buffer used when copying a streamed body to the client.
+const STREAM_BUFFER_SIZE: usize = 16384;
+
+/// Represents a source of bytes for a streamed response body.
+#[cfg(not(feature = "tokio"))]
+pub type BodySource = Box<dyn Read + Send>;
+
+/// Represents a source of bytes for a streamed response body.
+#[cfg(feature = "tokio")]
+pub type BodySource = Box<dyn AsyncRead + Send + Unpin>;
+
 /// Represents a response from the server.
 /// Implements `Into<Vec<u8>>` so can be serialised into bytes to transmit.
 ///
@@ -32,6 +50,19 @@ pub struct Response {
     pub headers: Headers,
     /// The body of the response.
     pub body: Vec<u8>,
+    /// A streamed body, which if present is sent instead of `body`.
+    pub stream: Option<StreamedBody>,
+}
+
+/// Represents a response body which is written to the client as it is read from its source,
+///   rather than being held in memory.
+///
+/// If the length is not known in advance, the body is sent using chunked transfer encoding.
+pub struct StreamedBody {
+    /// The source of the body.
+    pub source: BodySource,
+    /// The length of the body in bytes, if known.
+    pub length: Option<u64>,
 }
 
 /// An error which occurred during the parsing of a response.
@@ -51,6 +82,27 @@ impl std::fmt::Display for ResponseError {
 
 impl Error for ResponseError {}
 
+/// Represents a type which can be converted into a response, and can therefore be returned from a handler.
+///
+/// With the `json` feature, this is also implemented for `Result<T, E>` where `T` can be converted into JSON
+///   and `E` can be converted into a response.
+pub trait IntoResponse {
+    /// Converts itself into a response.
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for StatusCode {
+    fn into_response(self) -> Response {
+        Response::empty(self)
+    }
+}
+
 impl Response {
     /// Creates a new response object with the given status code, bytes and request.
     /// Functionally equivalent to the following (but with some allocation optimisations not shown):
@@ -71,6 +123,7 @@ impl Response {
             status_code,
             headers: Headers::new(),
             body: bytes.as_ref().to_vec(),
+            stream: None,
         }
     }
 
@@ -82,6 +135,7 @@ impl Response {
             status_code,
             headers: Headers::new(),
             body: Vec::new(),
+            stream: None,
         }
     }
 
@@ -129,6 +183,60 @@ impl Response {
         self
     }
 
+    /// Sets the body of the response to be streamed from the given source.
+    /// Returns itself for use in a builder pattern.
+    ///
+    /// If `length` is `None`, the body will be sent with chunked transfer encoding.
+    /// Any bytes previously added to the body are discarded.
+    ///
+    /// ## Example
+    /// ```
+    /// let file = File::open("video.mp4")?;
+    /// let length = file.metadata()?.len();
+    ///
+    /// Response::empty(StatusCode::OK)
+    ///     .with_header(HeaderType::ContentType, "video/mp4")
+    ///     .with_stream(file, Some(length))
+    /// ```
+    #[cfg(not(feature = "tokio"))]
+    pub fn with_stream<T>(mut self, source: T, length: Option<u64>) -> Self
+    where
+        T: Read + Send + 'static,
+    {
+        self.body.clear();
+        self.stream = Some(StreamedBody {
+            source: Box::new(source),
+            length,
+        });
+        self
+    }
+
+    /// Sets the body of the response to be streamed from the given source.
+    /// Returns itself for use in a builder pattern.
+    ///
+    /// If `length` is `None`, the body will be sent with chunked transfer encoding.
+    /// Any bytes previously added to the body are discarded.
+    #[cfg(feature = "tokio")]
+    pub fn with_stream<T>(mut self, source: T, length: Option<u64>) -> Self
+    where
+        T: AsyncRead + Send + Unpin + 'static,
+    {
+        self.body.clear();
+        self.stream = Some(StreamedBody {
+            source: Box::new(source),
+            length,
+        });
+        self
+    }
+
+    /// Returns the length of the body in bytes, or `None` if it is streamed with an unknown length.
+    pub fn content_length(&self) -> Option<u64> {
+        match &self.stream {
+            Some(stream) => stream.length,
+            None => Some(self.body.len() as u64),
+        }
+    }
+
     /// Returns a reference to the response's headers.
     pub fn get_headers(&self) -> &Headers {
         &self.headers
@@ -147,39 +255,7 @@ impl Response {
         T: Read,
     {
         let mut reader = BufReader::new(stream);
-        let mut start_line_buf: Vec<u8> = Vec::new();
-        reader
-            .read_until(0xA, &mut start_line_buf)
-            .map_err(|_| ResponseError::Stream)?;
-
-        let start_line_string =
-            String::from_utf8(start_line_buf).map_err(|_| ResponseError::Response)?;
-        let start_line: Vec<&str> = start_line_string.splitn(3, ' ').collect();
-
-        safe_assert(start_line.len() == 3)?;
-
-        let version = start_line[0].to_string();
-        let status_code: u16 = start_line[1].parse().map_err(|_| ResponseError::Response)?;
-        let status = StatusCode::try_from(status_code).map_err(|_| ResponseError::Response)?;
-
-        let mut headers = Headers::new();
-
-        loop {
-            let mut line_buf: Vec<u8> = Vec::new();
-            reader
-                .read_until(0xA, &mut line_buf)
-                .map_err(|_| ResponseError::Stream)?;
-            let line = String::from_utf8(line_buf).map_err(|_| ResponseError::Response)?;
-
-            if line == "\r\n" {
-                break;
-            } else {
-                safe_assert(line.len() >= 2)?;
-                let line_without_crlf = &line[0..line.len() - 2];
-                let line_parts: Vec<&str> = line_without_crlf.splitn(2, ':').collect();
-                headers.add(HeaderType::from(line_parts[0]), line_parts[1].trim_start());
-            }
-        }
+        let (version, status, mut headers) = read_head(&mut reader)?;
 
         if headers
             .get(&HeaderType::TransferEncoding)
@@ -200,6 +276,7 @@ impl Response {
                 status_code: status,
                 headers,
                 body,
+                stream: None,
             })
         } else if let Some(content_length) = headers.get(&HeaderType::ContentLength) {
             let content_length: usize = content_length
@@ -215,6 +292,7 @@ impl Response {
                 status_code: status,
                 headers,
                 body: content_buf,
+                stream: None,
             })
         } else {
             Ok(Self {
@@ -222,25 +300,185 @@ impl Response {
                 status_code: status,
                 headers,
                 body: Vec::new(),
+                stream: None,
             })
         }
     }
+
+    /// Attempts to read and parse the status line and headers of one HTTP response from the given stream,
+    ///   without reading a body.
+    ///
+    /// This should be used for responses to `HEAD` requests, which have the headers of the corresponding `GET`
+    ///   response, including `Content-Length`, but never have a body.
+    pub fn head_from_stream<T>(stream: &mut T) -> Result<Self, ResponseError>
+    where
+        T: Read,
+    {
+        let mut reader = BufReader::new(stream);
+        let (version, status, headers) = read_head(&mut reader)?;
+
+        Ok(Self {
+            version,
+            status_code: status,
+            headers,
+            body: Vec::new(),
+            stream: None,
+        })
+    }
 }
 
-impl From<Response> for Vec<u8> {
-    fn from(val: Response) -> Self {
+impl Response {
+    /// Writes the response to the given writer, streaming the body from its source if applicable.
+    ///
+    /// If the `Transfer-Encoding` header is set to `chunked`, a streamed body is written using
+    ///   chunked transfer encoding.
+    #[cfg(not(feature = "tokio"))]
+    pub fn write_to<W>(mut self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => {
+                let bytes: Vec<u8> = self.into();
+                writer.write_all(&bytes)?;
+                return writer.flush();
+            }
+        };
+
+        let chunked = self.is_chunked();
+        writer.write_all(&self.head_bytes())?;
+
+        let mut source = stream.source;
+        let mut buf = vec![0u8; STREAM_BUFFER_SIZE];
+        let mut written: u64 = 0;
+
+        loop {
+            let to_read = match stream.length {
+                Some(length) => (length - written).min(STREAM_BUFFER_SIZE as u64) as usize,
+                None => STREAM_BUFFER_SIZE,
+            };
+
+            if to_read == 0 {
+                break;
+            }
+
+            let read = match source.read(&mut buf[..to_read]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            if chunked {
+                writer.write_all(format!("{:X}\r\n", read).as_bytes())?;
+                writer.write_all(&buf[..read])?;
+                writer.write_all(b"\r\n")?;
+            } else {
+                writer.write_all(&buf[..read])?;
+            }
+
+            written += read as u64;
+        }
+
+        if chunked {
+            writer.write_all(b"0\r\n\r\n")?;
+        }
+
+        if stream.length.is_some_and(|length| written < length) {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+
+        writer.flush()
+    }
+
+    /// Writes the response to the given writer, streaming the body from its source if applicable.
+    ///
+    /// If the `Transfer-Encoding` header is set to `chunked`, a streamed body is written using
+    ///   chunked transfer encoding.
+    #[cfg(feature = "tokio")]
+    pub async fn write_to<W>(mut self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => {
+                let bytes: Vec<u8> = self.into();
+                writer.write_all(&bytes).await?;
+                return writer.flush().await;
+            }
+        };
+
+        let chunked = self.is_chunked();
+        writer.write_all(&self.head_bytes()).await?;
+
+        let mut source = stream.source;
+        let mut buf = vec![0u8; STREAM_BUFFER_SIZE];
+        let mut written: u64 = 0;
+
+        loop {
+            let to_read = match stream.length {
+                Some(length) => (length - written).min(STREAM_BUFFER_SIZE as u64) as usize,
+                None => STREAM_BUFFER_SIZE,
+            };
+
+            if to_read == 0 {
+                break;
+            }
+
+            let read = match source.read(&mut buf[..to_read]).await? {
+                0 => break,
+                read => read,
+            };
+
+            if chunked {
+                writer
+                    .write_all(format!("{:X}\r\n", read).as_bytes())
+                    .await?;
+                writer.write_all(&buf[..read]).await?;
+                writer.write_all(b"\r\n").await?;
+            } else {
+                writer.write_all(&buf[..read]).await?;
+            }
+
+            written += read as u64;
+        }
+
+        if chunked {
+            writer.write_all(b"0\r\n\r\n").await?;
+        }
+
+        if stream.length.is_some_and(|length| written < length) {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+
+        writer.flush().await
+    }
+
+    /// Returns `true` if the response is set to use chunked transfer encoding.
+    fn is_chunked(&self) -> bool {
+        self.headers
+            .get(&HeaderType::TransferEncoding)
+            .map(|te| te.eq_ignore_ascii_case("chunked"))
+            .unwrap_or(false)
+    }
+
+    /// Serialises the status line and headers of the response, including the blank line which
+    ///   separates them from the body.
+    fn head_bytes(&self) -> Vec<u8> {
         let status_line = format!(
             "{} {} {}",
-            val.version,
-            Into::<u16>::into(val.status_code),
-            Into::<&str>::into(val.status_code)
+            self.version,
+            Into::<u16>::into(self.status_code),
+            Into::<&str>::into(self.status_code)
         );
 
         let mut bytes: Vec<u8> =
-            Vec::with_capacity(status_line.len() + val.body.len() + val.headers.len() * 32);
+            Vec::with_capacity(status_line.len() + self.body.len() + self.headers.len() * 32);
         bytes.extend(status_line.as_bytes());
 
-        for header in val.get_headers().iter() {
+        for header in self.get_headers().iter() {
             bytes.extend(b"\r\n");
             bytes.extend(header.name.to_string().as_bytes());
             bytes.extend(b": ");
@@ -249,6 +487,18 @@ impl From<Response> for Vec<u8> {
 
         bytes.extend(b"\r\n\r\n");
 
+        bytes
+    }
+}
+
+/// Serialises the response into bytes.
+///
+/// A streamed body is not included, so responses with streamed bodies should be sent with
+///   `Response::write_to` instead.
+impl From<Response> for Vec<u8> {
+    fn from(val: Response) -> Self {
+        let mut bytes = val.head_bytes();
+
         if !val.body.is_empty() {
             bytes.extend(val.body);
             bytes.extend(b"\r\n");
@@ -258,6 +508,56 @@ impl From<Response> for Vec<u8> {
     }
 }
 
+impl Debug for StreamedBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamedBody")
+            .field("length", &self.length)
+            .finish()
+    }
+}
+
+/// Reads and parses the status line and headers of a response, leaving the body unread.
+fn read_head<T>(reader: &mut BufReader<T>) -> Result<(String, StatusCode, Headers), ResponseError>
+where
+    T: Read,
+{
+    let mut start_line_buf: Vec<u8> = Vec::new();
+    reader
+        .read_until(0xA, &mut start_line_buf)
+        .map_err(|_| ResponseError::Stream)?;
+
+    let start_line_string =
+        String::from_utf8(start_line_buf).map_err(|_| ResponseError::Response)?;
+    let start_line: Vec<&str> = start_line_string.splitn(3, ' ').collect();
+
+    safe_assert(start_line.len() == 3)?;
+
+    let version = start_line[0].to_string();
+    let status_code: u16 = start_line[1].parse().map_err(|_| ResponseError::Response)?;
+    let status = StatusCode::try_from(status_code).map_err(|_| ResponseError::Response)?;
+
+    let mut headers = Headers::new();
+
+    loop {
+        let mut line_buf: Vec<u8> = Vec::new();
+        reader
+            .read_until(0xA, &mut line_buf)
+            .map_err(|_| ResponseError::Stream)?;
+        let line = String::from_utf8(line_buf).map_err(|_| ResponseError::Response)?;
+
+        if line == "\r\n" {
+            break;
+        } else {
+            safe_assert(line.len() >= 2)?;
+            let line_without_crlf = &line[0..line.len() - 2];
+            let line_parts: Vec<&str> = line_without_crlf.splitn(2, ':').collect();
+            headers.add(HeaderType::from(line_parts[0]), line_parts[1].trim_start());
+        }
+    }
+
+    Ok((version, status, headers))
+}
+
 /// Parses a chunk using the chunked transfer encoding.
 fn parse_chunk<T>(stream: &mut BufReader<T>) -> Option<Vec<u8>>
 where
@@ -0,0 +1,977 @@
+//! Provides the state of an HTTP/2 connection, which turns the frames received from the client into requests, and
+//!   responses into the frames sent back to it.
+//!
+//! The connection does no I/O itself, so the threaded and tokio apps share it and only differ in how they read
+//!   from and write to the socket.
+
+use crate::http::address::Address;
+use crate::http::headers::{HeaderType, Headers};
+use crate::http::method::Method;
+use crate::http::request::{Request, RequestError};
+use crate::http::response::{Response, StreamedBody};
+use crate::http2::frame::{
+    ErrorCode, Frame, FrameError, FrameHeader, Setting, DEFAULT_MAX_FRAME_SIZE,
+    FRAME_HEADER_LENGTH, MAX_ALLOWED_FRAME_SIZE,
+};
+use crate::http2::hpack::{self, Decoder, HeaderField, HpackError, DEFAULT_TABLE_SIZE};
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
+
+/// The preface which every client sends at the start of an HTTP/2 connection.
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The number of streams the client may open at once, which also limits the number of requests being handled
+///   at once on a connection.
+const MAX_CONCURRENT_STREAMS: usize = 100;
+
+/// The largest header block the client may send, which is advertised as the maximum header list size and also
+///   limits the size of the decoded header list.
+const MAX_HEADER_BLOCK_SIZE: usize = 65536;
+
+/// The initial flow control window of the connection and every stream, in both directions.
+const DEFAULT_WINDOW_SIZE: i64 = 65535;
+
+/// The largest flow control window allowed.
+const MAX_WINDOW_SIZE: i64 = 0x7fffffff;
+
+/// The amount of request body data which may be buffered on a connection before the client must wait for
+///   responses to be sent, unless the maximum body size is larger.
+const DEFAULT_BUFFER_SIZE: usize = 16 * 1024 * 1024;
+
+/// Headers which are specific to HTTP/1 connections, so they are not allowed in HTTP/2.
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Represents the state of an HTTP/2 connection with a client.
+pub struct Connection {
+    address: SocketAddr,
+    max_body_size: Option<usize>,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    preface_received: bool,
+    settings_received: bool,
+    decoder: Decoder,
+    streams: BTreeMap<u32, Stream>,
+    last_stream_id: u32,
+    continuation: Option<PartialHeaders>,
+    peer_max_frame_size: u32,
+    peer_initial_window: i64,
+    send_window: i64,
+    receive_window: i64,
+    buffer_size: i64,
+    buffered: i64,
+    unanswered: usize,
+    goaway_sent: bool,
+    goaway_received: bool,
+    error: bool,
+}
+
+/// Represents the state of a stream, which carries one request and its response.
+struct Stream {
+    request: Option<Request>,
+    body: Vec<u8>,
+    content_length: Option<usize>,
+    remote_closed: bool,
+    local_closed: bool,
+    send_window: i64,
+    receive_window: i64,
+    buffered: i64,
+    data: VecDeque<u8>,
+    end_pending: bool,
+}
+
+/// A header block which is continued in `CONTINUATION` frames.
+struct PartialHeaders {
+    stream_id: u32,
+    block: Vec<u8>,
+    end_stream: bool,
+}
+
+/// The requests completed by some received data, along with the stream each was received on.
+/// Requests which cannot be handled, for example because their bodies are too large, are returned as errors
+///   so that an error response can be sent.
+pub type ReceivedRequests = Vec<(u32, Result<Request, RequestError>)>;
+
+impl Connection {
+    /// Creates a new connection with the client at the given address, queueing the server's settings to be sent.
+    ///
+    /// Request bodies are read into memory, so they are limited to the maximum body size if specified.
+    /// The connection's flow control window only allows a limited amount of body data to be buffered, and is
+    ///   reopened as the responses to the requests are sent.
+    pub fn new(address: SocketAddr, max_body_size: Option<usize>) -> Self {
+        let buffer_size = max_body_size
+            .unwrap_or_default()
+            .max(DEFAULT_BUFFER_SIZE)
+            .min(MAX_WINDOW_SIZE as usize) as i64;
+
+        let mut connection = Self {
+            address,
+            max_body_size,
+            input: Vec::new(),
+            output: Vec::new(),
+            preface_received: false,
+            settings_received: false,
+            decoder: Decoder::new(DEFAULT_TABLE_SIZE).with_max_list_size(MAX_HEADER_BLOCK_SIZE),
+            streams: BTreeMap::new(),
+            last_stream_id: 0,
+            continuation: None,
+            peer_max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            peer_initial_window: DEFAULT_WINDOW_SIZE,
+            send_window: DEFAULT_WINDOW_SIZE,
+            receive_window: buffer_size,
+            buffer_size,
+            buffered: 0,
+            unanswered: 0,
+            goaway_sent: false,
+            goaway_received: false,
+            error: false,
+        };
+
+        connection.send(Frame::Settings {
+            ack: false,
+            settings: vec![
+                Setting::MaxConcurrentStreams(MAX_CONCURRENT_STREAMS as u32),
+                Setting::MaxHeaderListSize(MAX_HEADER_BLOCK_SIZE as u32),
+            ],
+        });
+
+        // The connection's window can only be enlarged by a `WINDOW_UPDATE` frame
+        connection.send(Frame::WindowUpdate {
+            stream_id: 0,
+            increment: (buffer_size - DEFAULT_WINDOW_SIZE) as u32,
+        });
+
+        connection
+    }
+
+    /// Processes data received from the client, returning any requests which are now complete.
+    ///
+    /// If the client breaks the protocol, a `GOAWAY` frame is queued and the error is returned, after which the
+    ///   connection should be closed once the output has been written.
+    pub fn receive(&mut self, data: &[u8]) -> Result<ReceivedRequests, ErrorCode> {
+        if self.error {
+            return Err(ErrorCode::ProtocolError);
+        }
+
+        self.input.extend_from_slice(data);
+
+        let mut requests = Vec::new();
+
+        match self.process_input(&mut requests) {
+            Ok(()) => Ok(requests),
+            Err(code) => {
+                self.error = true;
+                self.send(Frame::GoAway {
+                    last_stream_id: self.last_stream_id,
+                    error: code,
+                    debug_data: Vec::new(),
+                });
+
+                Err(code)
+            }
+        }
+    }
+
+    /// Queues the response to the request on the given stream.
+    ///
+    /// If the response has a streamed body, it is returned so that it can be sent with `send_data` as it is read,
+    ///   unless the stream has been closed by the client, in which case it is dropped.
+    ///
+    /// Every request returned by `receive` must be answered with either this or `fail_response`, even if its stream
+    ///   has since been closed, since the client cannot open more streams while too many requests are unanswered.
+    pub fn send_response(
+        &mut self,
+        stream_id: u32,
+        mut response: Response,
+    ) -> Option<StreamedBody> {
+        self.unanswered = self.unanswered.saturating_sub(1);
+
+        if !self.streams.contains_key(&stream_id) || self.error {
+            return None;
+        }
+
+        let mut block = Vec::new();
+        hpack::encode(
+            b":status",
+            u16::from(response.status_code).to_string().as_bytes(),
+            &mut block,
+        );
+
+        for header in response.headers.iter() {
+            let name = header.name.to_string().to_ascii_lowercase();
+
+            if !CONNECTION_HEADERS.contains(&name.as_str()) {
+                hpack::encode(name.as_bytes(), header.value.as_bytes(), &mut block);
+            }
+        }
+
+        let stream_body = response.stream.take();
+        let end_stream = stream_body.is_none() && response.body.is_empty();
+
+        self.send_headers(stream_id, block, end_stream);
+
+        // The request has been handled, so its body no longer counts against the connection's buffer
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        let buffered = std::mem::take(&mut stream.buffered);
+        self.release(buffered);
+
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+
+        if end_stream {
+            stream.local_closed = true;
+        } else if stream_body.is_none() {
+            stream.data.extend(response.body);
+            stream.end_pending = true;
+        }
+
+        self.flush_data();
+
+        stream_body
+    }
+
+    /// Queues part of the streamed body of the response on the given stream, ending the stream if `end` is true.
+    pub fn send_data(&mut self, stream_id: u32, data: &[u8], end: bool) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.data.extend(data);
+            stream.end_pending = end;
+        }
+
+        self.flush_data();
+    }
+
+    /// Returns the amount of response data on the given stream which is waiting for the client to allow it to be
+    ///   sent, or `None` if the stream has been closed.
+    pub fn pending_data(&self, stream_id: u32) -> Option<usize> {
+        self.streams.get(&stream_id).map(|stream| stream.data.len())
+    }
+
+    /// Answers the request on the given stream with an internal error when no response could be generated, for
+    ///   example because the handler panicked.
+    pub fn fail_response(&mut self, stream_id: u32) {
+        self.unanswered = self.unanswered.saturating_sub(1);
+        self.reset(stream_id, ErrorCode::InternalError);
+    }
+
+    /// Returns the number of requests returned by `receive` which have not yet been answered.
+    pub fn unanswered(&self) -> usize {
+        self.unanswered
+    }
+
+    /// Closes the given stream immediately with the given error.
+    pub fn reset(&mut self, stream_id: u32, error: ErrorCode) {
+        if self.remove_stream(stream_id) {
+            self.send(Frame::RstStream { stream_id, error });
+        }
+    }
+
+    /// Starts gracefully shutting down the connection, so that the client opens no more streams.
+    /// Requests which have already been received are still answered.
+    pub fn shutdown(&mut self) {
+        if !self.goaway_sent {
+            self.goaway_sent = true;
+            self.send(Frame::GoAway {
+                last_stream_id: self.last_stream_id,
+                error: ErrorCode::NoError,
+                debug_data: Vec::new(),
+            });
+        }
+    }
+
+    /// Returns true if the connection should be closed once its output has been written, either because of an
+    ///   error or because it has been shut down and every stream is finished.
+    pub fn is_finished(&self) -> bool {
+        self.error || ((self.goaway_sent || self.goaway_received) && self.is_idle())
+    }
+
+    /// Returns true if no streams are open.
+    pub fn is_idle(&self) -> bool {
+        self.streams.is_empty() && self.continuation.is_none()
+    }
+
+    /// Takes the data which is waiting to be written to the client.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Parses and handles every complete frame in the input.
+    fn process_input(&mut self, requests: &mut ReceivedRequests) -> Result<(), ErrorCode> {
+        if !self.preface_received {
+            let length = self.input.len().min(PREFACE.len());
+
+            if self.input[..length] != PREFACE[..length] {
+                return Err(ErrorCode::ProtocolError);
+            }
+
+            if length < PREFACE.len() {
+                return Ok(());
+            }
+
+            self.input.drain(..PREFACE.len());
+            self.preface_received = true;
+        }
+
+        let mut offset = 0;
+
+        while let Some(header) = FrameHeader::parse(&self.input[offset..]) {
+            // No larger frames are allowed since the default maximum frame size is never changed
+            if header.length > DEFAULT_MAX_FRAME_SIZE {
+                return Err(ErrorCode::FrameSizeError);
+            }
+
+            let end = offset + FRAME_HEADER_LENGTH + header.length as usize;
+
+            if self.input.len() < end {
+                break;
+            }
+
+            let frame = Frame::parse(header, &self.input[offset + FRAME_HEADER_LENGTH..end]);
+            offset = end;
+
+            match frame.and_then(|frame| self.handle_frame(frame, requests)) {
+                Ok(()) => (),
+                Err(FrameError::Connection(code)) => return Err(code),
+                Err(FrameError::Stream(stream_id, code)) => {
+                    self.remove_stream(stream_id);
+                    self.send(Frame::RstStream {
+                        stream_id,
+                        error: code,
+                    });
+                }
+            }
+        }
+
+        self.input.drain(..offset);
+
+        Ok(())
+    }
+
+    /// Handles a frame received from the client.
+    fn handle_frame(
+        &mut self,
+        frame: Frame,
+        requests: &mut ReceivedRequests,
+    ) -> Result<(), FrameError> {
+        let protocol_error = FrameError::Connection(ErrorCode::ProtocolError);
+
+        // The client's settings must come first
+        if !self.settings_received {
+            match frame {
+                Frame::Settings { ack: false, .. } => self.settings_received = true,
+                _ => return Err(protocol_error),
+            }
+        }
+
+        // Header blocks must not be interrupted by other frames
+        if let Some(continuation) = &self.continuation {
+            match frame {
+                Frame::Continuation { stream_id, .. } if stream_id == continuation.stream_id => (),
+                _ => return Err(protocol_error),
+            }
+        }
+
+        match frame {
+            Frame::Data {
+                stream_id,
+                data,
+                end_stream,
+                flow_controlled_length,
+            } => self.handle_data(
+                stream_id,
+                data,
+                end_stream,
+                flow_controlled_length,
+                requests,
+            ),
+            Frame::Headers {
+                stream_id,
+                block,
+                end_stream,
+                end_headers,
+                priority,
+            } => {
+                if priority.map(|priority| priority.dependency) == Some(stream_id) {
+                    return Err(FrameError::Stream(stream_id, ErrorCode::ProtocolError));
+                }
+
+                self.continuation = Some(PartialHeaders {
+                    stream_id,
+                    block,
+                    end_stream,
+                });
+
+                match end_headers {
+                    true => self.handle_headers(requests),
+                    false => self.check_header_block_size(),
+                }
+            }
+            Frame::Continuation {
+                block, end_headers, ..
+            } => {
+                match &mut self.continuation {
+                    Some(continuation) => continuation.block.extend(block),
+                    None => return Err(protocol_error),
+                }
+
+                match end_headers {
+                    true => self.handle_headers(requests),
+                    false => self.check_header_block_size(),
+                }
+            }
+            Frame::Priority { .. } => Ok(()),
+            Frame::RstStream { stream_id, .. } => {
+                if stream_id > self.last_stream_id {
+                    return Err(protocol_error);
+                }
+
+                self.remove_stream(stream_id);
+
+                Ok(())
+            }
+            Frame::Settings { ack: true, .. } => Ok(()),
+            Frame::Settings {
+                ack: false,
+                settings,
+            } => {
+                for setting in settings {
+                    self.apply_setting(setting)?;
+                }
+
+                self.send(Frame::Settings {
+                    ack: true,
+                    settings: Vec::new(),
+                });
+                self.flush_data();
+
+                Ok(())
+            }
+            Frame::PushPromise { .. } => Err(protocol_error),
+            Frame::Ping { ack: false, data } => {
+                self.send(Frame::Ping { ack: true, data });
+                Ok(())
+            }
+            Frame::Ping { ack: true, .. } => Ok(()),
+            Frame::GoAway { .. } => {
+                self.goaway_received = true;
+                Ok(())
+            }
+            Frame::WindowUpdate {
+                stream_id,
+                increment,
+            } => self.handle_window_update(stream_id, increment),
+            Frame::Unknown { .. } => Ok(()),
+        }
+    }
+
+    /// Handles a `DATA` frame, adding its data to the body of the request on its stream.
+    fn handle_data(
+        &mut self,
+        stream_id: u32,
+        data: Vec<u8>,
+        end_stream: bool,
+        flow_controlled_length: u32,
+        requests: &mut ReceivedRequests,
+    ) -> Result<(), FrameError> {
+        let length = flow_controlled_length as i64;
+
+        if length > self.receive_window {
+            return Err(FrameError::Connection(ErrorCode::FlowControlError));
+        }
+
+        // The data counts against the connection's buffer until it is released
+        self.receive_window -= length;
+        self.buffered += length;
+
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(stream) => stream,
+            None if stream_id > self.last_stream_id => {
+                return Err(FrameError::Connection(ErrorCode::ProtocolError))
+            }
+            // The stream has already been closed, perhaps before the client knew about it
+            None => {
+                self.release(length);
+                return Ok(());
+            }
+        };
+
+        if stream.remote_closed {
+            self.release(length);
+            return Err(FrameError::Stream(stream_id, ErrorCode::StreamClosed));
+        }
+
+        if length > stream.receive_window {
+            self.release(length);
+            return Err(FrameError::Stream(stream_id, ErrorCode::FlowControlError));
+        }
+
+        stream.receive_window -= length;
+
+        // Bodies which are too large are discarded, since the error has already been returned
+        let released = match stream.request.is_some() {
+            true => {
+                stream.body.extend(data);
+                stream.buffered += length;
+
+                if self
+                    .max_body_size
+                    .is_some_and(|max| stream.body.len() > max)
+                {
+                    stream.request = None;
+                    stream.body = Vec::new();
+                    requests.push((stream_id, Err(RequestError::PayloadTooLarge)));
+                    self.unanswered += 1;
+
+                    std::mem::take(&mut stream.buffered)
+                } else {
+                    0
+                }
+            }
+            false => length,
+        };
+
+        self.release(released);
+
+        if end_stream {
+            return self.end_request(stream_id, requests);
+        }
+
+        // The connection's window limits how much is buffered, so each stream's window is reopened straight away
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+
+        if stream.receive_window <= DEFAULT_WINDOW_SIZE / 2 {
+            let increment = (DEFAULT_WINDOW_SIZE - stream.receive_window) as u32;
+            stream.receive_window = DEFAULT_WINDOW_SIZE;
+
+            self.send(Frame::WindowUpdate {
+                stream_id,
+                increment,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Handles a complete header block, which either opens a stream or contains the trailers of a request body.
+    fn handle_headers(&mut self, requests: &mut ReceivedRequests) -> Result<(), FrameError> {
+        let PartialHeaders {
+            stream_id,
+            block,
+            end_stream,
+        } = self.continuation.take().unwrap();
+
+        // The block must be decoded even if it is ignored, since it may change the state of the decoder
+        let fields = self.decoder.decode(&block).map_err(|e| match e {
+            HpackError::ListSize => FrameError::Connection(ErrorCode::EnhanceYourCalm),
+            _ => FrameError::Connection(ErrorCode::CompressionError),
+        })?;
+
+        if let Some(stream) = self.streams.get(&stream_id) {
+            if stream.remote_closed {
+                return Err(FrameError::Stream(stream_id, ErrorCode::StreamClosed));
+            }
+
+            // Trailers must end the stream and cannot contain pseudo-headers, but are otherwise ignored
+            if !end_stream || fields.iter().any(|(name, _)| name.starts_with(b":")) {
+                return Err(FrameError::Stream(stream_id, ErrorCode::ProtocolError));
+            }
+
+            return self.end_request(stream_id, requests);
+        }
+
+        // Client streams have odd identifiers, which must increase
+        if stream_id % 2 == 0 || stream_id <= self.last_stream_id {
+            return Err(FrameError::Connection(ErrorCode::ProtocolError));
+        }
+
+        // Streams opened after the connection started shutting down are not processed
+        if self.goaway_sent {
+            return Ok(());
+        }
+
+        self.last_stream_id = stream_id;
+
+        // Requests are still being handled after their streams are reset, so they are limited separately
+        if self.streams.len() >= MAX_CONCURRENT_STREAMS || self.unanswered >= MAX_CONCURRENT_STREAMS
+        {
+            return Err(FrameError::Stream(stream_id, ErrorCode::RefusedStream));
+        }
+
+        let request = request_from_fields(fields, self.address)
+            .ok_or(FrameError::Stream(stream_id, ErrorCode::ProtocolError))?;
+
+        let content_length = match request.headers.get(HeaderType::ContentLength) {
+            Some(content_length) => Some(
+                content_length
+                    .parse::<usize>()
+                    .map_err(|_| FrameError::Stream(stream_id, ErrorCode::ProtocolError))?,
+            ),
+            None => None,
+        };
+
+        let too_large = match (content_length, self.max_body_size) {
+            (Some(content_length), Some(max)) => content_length > max,
+            _ => false,
+        };
+
+        if too_large {
+            requests.push((stream_id, Err(RequestError::PayloadTooLarge)));
+            self.unanswered += 1;
+        }
+
+        self.streams.insert(
+            stream_id,
+            Stream {
+                request: if too_large { None } else { Some(request) },
+                body: Vec::new(),
+                content_length,
+                remote_closed: false,
+                local_closed: false,
+                send_window: self.peer_initial_window,
+                receive_window: DEFAULT_WINDOW_SIZE,
+                buffered: 0,
+                data: VecDeque::new(),
+                end_pending: false,
+            },
+        );
+
+        match end_stream {
+            true => self.end_request(stream_id, requests),
+            false => Ok(()),
+        }
+    }
+
+    /// Handles the end of the request on the given stream, returning it unless its body was discarded.
+    fn end_request(
+        &mut self,
+        stream_id: u32,
+        requests: &mut ReceivedRequests,
+    ) -> Result<(), FrameError> {
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        stream.remote_closed = true;
+
+        let mut request = match stream.request.take() {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+
+        let body = std::mem::take(&mut stream.body);
+
+        match stream.content_length {
+            Some(content_length) if content_length != body.len() => {
+                return Err(FrameError::Stream(stream_id, ErrorCode::ProtocolError));
+            }
+            Some(_) => request.content = Some(body),
+            None if !body.is_empty() => {
+                request
+                    .headers
+                    .add(HeaderType::ContentLength, body.len().to_string());
+                request.content = Some(body);
+            }
+            None => (),
+        }
+
+        requests.push((stream_id, Ok(request)));
+        self.unanswered += 1;
+
+        Ok(())
+    }
+
+    /// Applies a setting received from the client.
+    fn apply_setting(&mut self, setting: Setting) -> Result<(), FrameError> {
+        match setting {
+            Setting::EnablePush(value) if value > 1 => {
+                Err(FrameError::Connection(ErrorCode::ProtocolError))
+            }
+            Setting::InitialWindowSize(size) => {
+                let size = size as i64;
+
+                if size > MAX_WINDOW_SIZE {
+                    return Err(FrameError::Connection(ErrorCode::FlowControlError));
+                }
+
+                // The change applies to the windows of every open stream
+                let delta = size - self.peer_initial_window;
+                self.peer_initial_window = size;
+
+                for stream in self.streams.values_mut() {
+                    stream.send_window += delta;
+
+                    if stream.send_window > MAX_WINDOW_SIZE {
+                        return Err(FrameError::Connection(ErrorCode::FlowControlError));
+                    }
+                }
+
+                Ok(())
+            }
+            Setting::MaxFrameSize(size) => {
+                if !(DEFAULT_MAX_FRAME_SIZE..=MAX_ALLOWED_FRAME_SIZE).contains(&size) {
+                    return Err(FrameError::Connection(ErrorCode::ProtocolError));
+                }
+
+                self.peer_max_frame_size = size;
+
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Handles a `WINDOW_UPDATE` frame, allowing more data to be sent on the connection or a stream.
+    fn handle_window_update(&mut self, stream_id: u32, increment: u32) -> Result<(), FrameError> {
+        let increment = increment as i64;
+
+        if stream_id == 0 {
+            if increment == 0 {
+                return Err(FrameError::Connection(ErrorCode::ProtocolError));
+            }
+
+            self.send_window += increment;
+
+            if self.send_window > MAX_WINDOW_SIZE {
+                return Err(FrameError::Connection(ErrorCode::FlowControlError));
+            }
+        } else {
+            let stream = match self.streams.get_mut(&stream_id) {
+                Some(stream) => stream,
+                None if stream_id > self.last_stream_id => {
+                    return Err(FrameError::Connection(ErrorCode::ProtocolError))
+                }
+                None => return Ok(()),
+            };
+
+            if increment == 0 {
+                return Err(FrameError::Stream(stream_id, ErrorCode::ProtocolError));
+            }
+
+            stream.send_window += increment;
+
+            if stream.send_window > MAX_WINDOW_SIZE {
+                return Err(FrameError::Stream(stream_id, ErrorCode::FlowControlError));
+            }
+        }
+
+        self.flush_data();
+
+        Ok(())
+    }
+
+    /// Checks that a header block which is still being continued is not too large.
+    fn check_header_block_size(&self) -> Result<(), FrameError> {
+        match &self.continuation {
+            Some(continuation) if continuation.block.len() > MAX_HEADER_BLOCK_SIZE => {
+                Err(FrameError::Connection(ErrorCode::EnhanceYourCalm))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Queues a header block, splitting it into `CONTINUATION` frames if it is larger than the client allows.
+    fn send_headers(&mut self, stream_id: u32, block: Vec<u8>, end_stream: bool) {
+        let mut chunks = block.chunks(self.peer_max_frame_size as usize).peekable();
+        let first = chunks.next().unwrap_or(&[]).to_vec();
+
+        self.send(Frame::Headers {
+            stream_id,
+            block: first,
+            end_stream,
+            end_headers: chunks.peek().is_none(),
+            priority: None,
+        });
+
+        while let Some(chunk) = chunks.next() {
+            let end_headers = chunks.peek().is_none();
+
+            self.send(Frame::Continuation {
+                stream_id,
+                block: chunk.to_vec(),
+                end_headers,
+            });
+        }
+    }
+
+    /// Sends as much of the queued response data as flow control allows, closing streams whose responses have
+    ///   been completely sent.
+    fn flush_data(&mut self) {
+        let mut finished = Vec::new();
+
+        for (&stream_id, stream) in self.streams.iter_mut() {
+            loop {
+                let length = (stream.data.len() as i64)
+                    .min(stream.send_window)
+                    .min(self.send_window)
+                    .min(self.peer_max_frame_size as i64)
+                    .max(0) as usize;
+
+                let end_stream = stream.end_pending && length == stream.data.len();
+
+                if length == 0 && !end_stream {
+                    break;
+                }
+
+                let data: Vec<u8> = stream.data.drain(..length).collect();
+                stream.send_window -= length as i64;
+                self.send_window -= length as i64;
+
+                Frame::Data {
+                    stream_id,
+                    data,
+                    end_stream,
+                    flow_controlled_length: length as u32,
+                }
+                .write_to(&mut self.output);
+
+                if end_stream {
+                    stream.end_pending = false;
+                    stream.local_closed = true;
+                    break;
+                }
+            }
+
+            if stream.local_closed {
+                finished.push(stream_id);
+            }
+        }
+
+        for stream_id in finished {
+            let stream = self.streams.remove(&stream_id).unwrap();
+            self.release(stream.buffered);
+
+            // The response is complete, so the rest of the request is not needed
+            if !stream.remote_closed {
+                self.send(Frame::RstStream {
+                    stream_id,
+                    error: ErrorCode::NoError,
+                });
+            }
+        }
+    }
+
+    /// Closes the given stream, releasing any of its body data which is still buffered.
+    /// Returns false if the stream was not open.
+    fn remove_stream(&mut self, stream_id: u32) -> bool {
+        match self.streams.remove(&stream_id) {
+            Some(stream) => {
+                self.release(stream.buffered);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Releases body data from the connection's buffer, reopening its window once half of it is available.
+    fn release(&mut self, length: i64) {
+        self.buffered -= length;
+
+        let increment = self.buffer_size - self.buffered - self.receive_window;
+
+        if increment > 0 && self.receive_window <= self.buffer_size / 2 {
+            self.send(Frame::WindowUpdate {
+                stream_id: 0,
+                increment: increment as u32,
+            });
+            self.receive_window += increment;
+        }
+    }
+
+    /// Queues a frame to be sent.
+    fn send(&mut self, frame: Frame) {
+        frame.write_to(&mut self.output);
+    }
+}
+
+/// Creates a request from the fields of a header block, or returns `None` if the request is malformed.
+fn request_from_fields(fields: Vec<HeaderField>, address: SocketAddr) -> Option<Request> {
+    let mut method = None;
+    let mut path: Option<String> = None;
+    let mut authority = None;
+    let mut scheme = None;
+    let mut headers = Headers::new();
+    let mut cookies = Vec::new();
+
+    for (name, value) in fields {
+        let name = String::from_utf8(name).ok()?;
+        let value = String::from_utf8(value).ok()?;
+
+        if value.contains(['\r', '\n', '\0']) {
+            return None;
+        }
+
+        // Pseudo-headers must come before every other field, and each may only appear once
+        if let Some(pseudo_header) = name.strip_prefix(':') {
+            let field = match pseudo_header {
+                "method" => &mut method,
+                "path" => &mut path,
+                "authority" => &mut authority,
+                "scheme" => &mut scheme,
+                _ => return None,
+            };
+
+            if field.is_some() || !headers.is_empty() || !cookies.is_empty() {
+                return None;
+            }
+
+            *field = Some(value);
+            continue;
+        }
+
+        if name.is_empty()
+            || name.bytes().any(|b| b.is_ascii_uppercase())
+            || CONNECTION_HEADERS.contains(&name.as_str())
+            || (name == "te" && value != "trailers")
+        {
+            return None;
+        }
+
+        // Cookies may be split into multiple fields, which are joined back together
+        match name.as_str() {
+            "cookie" => cookies.push(value),
+            _ => headers.add(name.as_str(), value),
+        }
+    }
+
+    if !cookies.is_empty() {
+        headers.add(HeaderType::Cookie, cookies.join("; "));
+    }
+
+    if let Some(authority) = authority {
+        if headers.get(HeaderType::Host).is_none() {
+            headers.add(HeaderType::Host, authority);
+        }
+    }
+
+    // `CONNECT` requests have no path or scheme, but are not supported
+    let method = Method::from_name(&method?).ok()?;
+    scheme?;
+    let path = path?;
+
+    if !path.starts_with('/') && path != "*" {
+        return None;
+    }
+
+    let (uri, query) = match path.split_once('?') {
+        Some((uri, query)) => (uri.to_string(), query.to_string()),
+        None => (path, String::new()),
+    };
+
+    let address = Address::from_headers(&headers, address).ok()?;
+
+    Some(Request {
+        method,
+        uri,
+        query,
+        params: HashMap::new(),
+        version: "HTTP/2.0".to_string(),
+        headers,
+        trailers: Headers::new(),
+        content: None,
+        address,
+        peer_identity: None,
+        // HTTP/2 is only negotiated over TLS
+        https: true,
+    })
+}
@@ -0,0 +1,601 @@
+//! Provides the frames which make up an HTTP/2 connection, as defined in RFC 9113 section 6.
+
+/// The length of the header at the start of every frame.
+pub const FRAME_HEADER_LENGTH: usize = 9;
+
+/// The largest frame payload which every peer must accept, until it allows larger frames with its settings.
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16384;
+
+/// The largest frame payload which a peer can allow with its settings.
+pub const MAX_ALLOWED_FRAME_SIZE: u32 = 16777215;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+/// Represents the header at the start of every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// The length of the frame's payload.
+    pub length: u32,
+    /// The type of the frame.
+    pub kind: u8,
+    /// The flags of the frame, whose meaning depends on its type.
+    pub flags: u8,
+    /// The stream the frame belongs to, or zero if it applies to the whole connection.
+    pub stream_id: u32,
+}
+
+/// Represents a frame of an HTTP/2 connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// Part of the body of a request or response.
+    Data {
+        /// The stream the data belongs to.
+        stream_id: u32,
+        /// The data, without any padding.
+        data: Vec<u8>,
+        /// Whether this is the last frame of the body.
+        end_stream: bool,
+        /// The length of the frame's payload including padding, all of which counts towards flow control.
+        flow_controlled_length: u32,
+    },
+    /// Opens a stream, or sends the trailers of a body, with the first part of a header block.
+    Headers {
+        /// The stream the headers belong to.
+        stream_id: u32,
+        /// The first part of the header block.
+        block: Vec<u8>,
+        /// Whether the stream has no body, or these are the trailers after it.
+        end_stream: bool,
+        /// Whether the header block is complete without any `CONTINUATION` frames.
+        end_headers: bool,
+        /// The priority of the stream, if specified.
+        priority: Option<Priority>,
+    },
+    /// Suggests a priority for a stream.
+    Priority {
+        /// The stream whose priority is suggested.
+        stream_id: u32,
+        /// The suggested priority.
+        priority: Priority,
+    },
+    /// Immediately closes a stream.
+    RstStream {
+        /// The stream to close.
+        stream_id: u32,
+        /// The reason the stream was closed.
+        error: ErrorCode,
+    },
+    /// Sends the sender's settings, or acknowledges the peer's.
+    Settings {
+        /// Whether this acknowledges the peer's settings, in which case there are no settings.
+        ack: bool,
+        /// The settings which have changed.
+        settings: Vec<Setting>,
+    },
+    /// Reserves a stream for a response which the server sends without a request.
+    PushPromise {
+        /// The stream of the request which the pushed response is associated with.
+        stream_id: u32,
+        /// The stream reserved for the pushed response.
+        promised_stream_id: u32,
+        /// The first part of the header block of the request which the pushed response is for.
+        block: Vec<u8>,
+        /// Whether the header block is complete without any `CONTINUATION` frames.
+        end_headers: bool,
+    },
+    /// Measures the round-trip time or checks that the connection is still working.
+    Ping {
+        /// Whether this is the response to a ping.
+        ack: bool,
+        /// Data which is sent back in the response.
+        data: [u8; 8],
+    },
+    /// Starts shutting down the connection.
+    GoAway {
+        /// The last stream which the sender has processed or may process.
+        last_stream_id: u32,
+        /// The reason the connection is being shut down.
+        error: ErrorCode,
+        /// Additional information for debugging.
+        debug_data: Vec<u8>,
+    },
+    /// Allows the peer to send more data on a stream or on the whole connection.
+    WindowUpdate {
+        /// The stream whose window is increased, or zero for the connection's window.
+        stream_id: u32,
+        /// How many more bytes the peer may send.
+        increment: u32,
+    },
+    /// Continues a header block.
+    Continuation {
+        /// The stream the headers belong to.
+        stream_id: u32,
+        /// The next part of the header block.
+        block: Vec<u8>,
+        /// Whether this is the last part of the header block.
+        end_headers: bool,
+    },
+    /// A frame of an unknown type, which is ignored.
+    Unknown {
+        /// The type of the frame.
+        kind: u8,
+        /// The stream the frame belongs to.
+        stream_id: u32,
+    },
+}
+
+/// Represents the priority of a stream.
+/// Priorities are deprecated, so they are parsed to validate them but otherwise ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Priority {
+    /// The stream this stream depends on.
+    pub dependency: u32,
+    /// Whether this stream becomes the only dependency of its parent.
+    pub exclusive: bool,
+    /// The weight of the stream, from 1 to 256, minus one.
+    pub weight: u8,
+}
+
+/// Represents a setting sent in a `SETTINGS` frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    /// The maximum size of the sender's HPACK dynamic table.
+    HeaderTableSize(u32),
+    /// Whether the server may push responses, sent by clients.
+    EnablePush(u32),
+    /// The maximum number of streams the sender allows the peer to open at once.
+    MaxConcurrentStreams(u32),
+    /// The initial flow control window of each stream for data sent to the sender.
+    InitialWindowSize(u32),
+    /// The largest frame payload the sender accepts.
+    MaxFrameSize(u32),
+    /// The largest header list the sender is prepared to accept.
+    MaxHeaderListSize(u32),
+    /// A setting which is not known, which must be ignored.
+    Unknown(u16, u32),
+}
+
+/// Represents the error codes sent in `RST_STREAM` and `GOAWAY` frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The stream or connection closed normally.
+    NoError,
+    /// The peer broke the protocol.
+    ProtocolError,
+    /// An unexpected internal error occurred.
+    InternalError,
+    /// The peer broke the flow control protocol.
+    FlowControlError,
+    /// Settings were not acknowledged in time.
+    SettingsTimeout,
+    /// A frame was received after the stream was half-closed.
+    StreamClosed,
+    /// A frame had an invalid size.
+    FrameSizeError,
+    /// The stream was refused before any processing happened.
+    RefusedStream,
+    /// The stream is no longer needed.
+    Cancel,
+    /// The state of header compression could not be maintained.
+    CompressionError,
+    /// The connection for a `CONNECT` request was reset or closed.
+    ConnectError,
+    /// The peer is generating excessive load.
+    EnhanceYourCalm,
+    /// The transport does not meet the minimum security requirements.
+    InadequateSecurity,
+    /// The request must be made over HTTP/1.1.
+    Http11Required,
+    /// An error code which is not known.
+    Unknown(u32),
+}
+
+/// An error found while parsing a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// An error which affects the whole connection, which must be closed.
+    Connection(ErrorCode),
+    /// An error which only affects the given stream, which must be reset.
+    Stream(u32, ErrorCode),
+}
+
+impl FrameHeader {
+    /// Parses a frame header from the start of the buffer, or returns `None` if the buffer is too short.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < FRAME_HEADER_LENGTH {
+            return None;
+        }
+
+        Some(Self {
+            length: u32::from_be_bytes([0, buf[0], buf[1], buf[2]]),
+            kind: buf[3],
+            flags: buf[4],
+            stream_id: read_u32(&buf[5..9]) & 0x7fffffff,
+        })
+    }
+
+    /// Serialises the frame header, appending it to the buffer.
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.length.to_be_bytes()[1..]);
+        buf.push(self.kind);
+        buf.push(self.flags);
+        buf.extend_from_slice(&self.stream_id.to_be_bytes());
+    }
+}
+
+impl Frame {
+    /// Parses the payload of a frame with the given header.
+    pub fn parse(header: FrameHeader, payload: &[u8]) -> Result<Self, FrameError> {
+        let stream_id = header.stream_id;
+        let flags = header.flags;
+        let protocol_error = FrameError::Connection(ErrorCode::ProtocolError);
+        let frame_size_error = FrameError::Connection(ErrorCode::FrameSizeError);
+
+        // Frames either always belong to a stream or never do
+        let stream_frame = matches!(
+            header.kind,
+            DATA | HEADERS | PRIORITY | RST_STREAM | PUSH_PROMISE | CONTINUATION
+        );
+        let connection_frame = matches!(header.kind, SETTINGS | PING | GOAWAY);
+
+        if (stream_frame && stream_id == 0) || (connection_frame && stream_id != 0) {
+            return Err(protocol_error);
+        }
+
+        match header.kind {
+            DATA => {
+                let data = remove_padding(flags, payload)?;
+
+                Ok(Self::Data {
+                    stream_id,
+                    data: data.to_vec(),
+                    end_stream: flags & END_STREAM != 0,
+                    flow_controlled_length: header.length,
+                })
+            }
+            HEADERS => {
+                let mut block = remove_padding(flags, payload)?;
+                let mut priority = None;
+
+                if flags & PRIORITY_FLAG != 0 {
+                    if block.len() < 5 {
+                        return Err(frame_size_error);
+                    }
+
+                    priority = Some(Priority::parse(&block[..5]));
+                    block = &block[5..];
+                }
+
+                Ok(Self::Headers {
+                    stream_id,
+                    block: block.to_vec(),
+                    end_stream: flags & END_STREAM != 0,
+                    end_headers: flags & END_HEADERS != 0,
+                    priority,
+                })
+            }
+            PRIORITY => match payload.len() {
+                5 => Ok(Self::Priority {
+                    stream_id,
+                    priority: Priority::parse(payload),
+                }),
+                _ => Err(FrameError::Stream(stream_id, ErrorCode::FrameSizeError)),
+            },
+            RST_STREAM => match payload.len() {
+                4 => Ok(Self::RstStream {
+                    stream_id,
+                    error: ErrorCode::from(read_u32(payload)),
+                }),
+                _ => Err(frame_size_error),
+            },
+            SETTINGS => {
+                let ack = flags & ACK != 0;
+
+                if !payload.len().is_multiple_of(6) || (ack && !payload.is_empty()) {
+                    return Err(frame_size_error);
+                }
+
+                let settings = payload
+                    .chunks(6)
+                    .map(|setting| {
+                        let id = u16::from_be_bytes([setting[0], setting[1]]);
+                        Setting::new(id, read_u32(&setting[2..]))
+                    })
+                    .collect();
+
+                Ok(Self::Settings { ack, settings })
+            }
+            PUSH_PROMISE => {
+                let block = remove_padding(flags, payload)?;
+
+                if block.len() < 4 {
+                    return Err(frame_size_error);
+                }
+
+                Ok(Self::PushPromise {
+                    stream_id,
+                    promised_stream_id: read_u32(block) & 0x7fffffff,
+                    block: block[4..].to_vec(),
+                    end_headers: flags & END_HEADERS != 0,
+                })
+            }
+            PING => match payload.len() {
+                8 => {
+                    let mut data = [0; 8];
+                    data.copy_from_slice(payload);
+
+                    Ok(Self::Ping {
+                        ack: flags & ACK != 0,
+                        data,
+                    })
+                }
+                _ => Err(frame_size_error),
+            },
+            GOAWAY => match payload.len() {
+                0..=7 => Err(frame_size_error),
+                _ => Ok(Self::GoAway {
+                    last_stream_id: read_u32(payload) & 0x7fffffff,
+                    error: ErrorCode::from(read_u32(&payload[4..])),
+                    debug_data: payload[8..].to_vec(),
+                }),
+            },
+            WINDOW_UPDATE => match payload.len() {
+                4 => Ok(Self::WindowUpdate {
+                    stream_id,
+                    increment: read_u32(payload) & 0x7fffffff,
+                }),
+                _ => Err(frame_size_error),
+            },
+            CONTINUATION => Ok(Self::Continuation {
+                stream_id,
+                block: payload.to_vec(),
+                end_headers: flags & END_HEADERS != 0,
+            }),
+            kind => Ok(Self::Unknown { kind, stream_id }),
+        }
+    }
+
+    /// Serialises the frame, appending it to the buffer. Frames are never padded.
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+
+        let (kind, flags, stream_id) = match self {
+            Self::Data {
+                stream_id,
+                data,
+                end_stream,
+                ..
+            } => {
+                payload.extend_from_slice(data);
+                (DATA, flag(*end_stream, END_STREAM), *stream_id)
+            }
+            Self::Headers {
+                stream_id,
+                block,
+                end_stream,
+                end_headers,
+                priority,
+            } => {
+                if let Some(priority) = priority {
+                    priority.write_to(&mut payload);
+                }
+
+                payload.extend_from_slice(block);
+
+                let flags = flag(*end_stream, END_STREAM)
+                    | flag(*end_headers, END_HEADERS)
+                    | flag(priority.is_some(), PRIORITY_FLAG);
+
+                (HEADERS, flags, *stream_id)
+            }
+            Self::Priority {
+                stream_id,
+                priority,
+            } => {
+                priority.write_to(&mut payload);
+                (PRIORITY, 0, *stream_id)
+            }
+            Self::RstStream { stream_id, error } => {
+                payload.extend_from_slice(&u32::from(*error).to_be_bytes());
+                (RST_STREAM, 0, *stream_id)
+            }
+            Self::Settings { ack, settings } => {
+                for setting in settings {
+                    let (id, value) = setting.id_and_value();
+                    payload.extend_from_slice(&id.to_be_bytes());
+                    payload.extend_from_slice(&value.to_be_bytes());
+                }
+
+                (SETTINGS, flag(*ack, ACK), 0)
+            }
+            Self::PushPromise {
+                stream_id,
+                promised_stream_id,
+                block,
+                end_headers,
+            } => {
+                payload.extend_from_slice(&promised_stream_id.to_be_bytes());
+                payload.extend_from_slice(block);
+                (PUSH_PROMISE, flag(*end_headers, END_HEADERS), *stream_id)
+            }
+            Self::Ping { ack, data } => {
+                payload.extend_from_slice(data);
+                (PING, flag(*ack, ACK), 0)
+            }
+            Self::GoAway {
+                last_stream_id,
+                error,
+                debug_data,
+            } => {
+                payload.extend_from_slice(&last_stream_id.to_be_bytes());
+                payload.extend_from_slice(&u32::from(*error).to_be_bytes());
+                payload.extend_from_slice(debug_data);
+                (GOAWAY, 0, 0)
+            }
+            Self::WindowUpdate {
+                stream_id,
+                increment,
+            } => {
+                payload.extend_from_slice(&increment.to_be_bytes());
+                (WINDOW_UPDATE, 0, *stream_id)
+            }
+            Self::Continuation {
+                stream_id,
+                block,
+                end_headers,
+            } => {
+                payload.extend_from_slice(block);
+                (CONTINUATION, flag(*end_headers, END_HEADERS), *stream_id)
+            }
+            Self::Unknown { kind, stream_id } => (*kind, 0, *stream_id),
+        };
+
+        FrameHeader {
+            length: payload.len() as u32,
+            kind,
+            flags,
+            stream_id,
+        }
+        .write_to(buf);
+
+        buf.extend(payload);
+    }
+}
+
+impl Priority {
+    fn parse(buf: &[u8]) -> Self {
+        let dependency = read_u32(buf);
+
+        Self {
+            dependency: dependency & 0x7fffffff,
+            exclusive: dependency & 0x80000000 != 0,
+            weight: buf[4],
+        }
+    }
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        let exclusive = match self.exclusive {
+            true => 0x80000000,
+            false => 0,
+        };
+
+        buf.extend_from_slice(&(self.dependency | exclusive).to_be_bytes());
+        buf.push(self.weight);
+    }
+}
+
+impl Setting {
+    fn new(id: u16, value: u32) -> Self {
+        match id {
+            0x1 => Self::HeaderTableSize(value),
+            0x2 => Self::EnablePush(value),
+            0x3 => Self::MaxConcurrentStreams(value),
+            0x4 => Self::InitialWindowSize(value),
+            0x5 => Self::MaxFrameSize(value),
+            0x6 => Self::MaxHeaderListSize(value),
+            id => Self::Unknown(id, value),
+        }
+    }
+
+    fn id_and_value(&self) -> (u16, u32) {
+        match *self {
+            Self::HeaderTableSize(value) => (0x1, value),
+            Self::EnablePush(value) => (0x2, value),
+            Self::MaxConcurrentStreams(value) => (0x3, value),
+            Self::InitialWindowSize(value) => (0x4, value),
+            Self::MaxFrameSize(value) => (0x5, value),
+            Self::MaxHeaderListSize(value) => (0x6, value),
+            Self::Unknown(id, value) => (id, value),
+        }
+    }
+}
+
+impl From<u32> for ErrorCode {
+    fn from(code: u32) -> Self {
+        match code {
+            0x0 => Self::NoError,
+            0x1 => Self::ProtocolError,
+            0x2 => Self::InternalError,
+            0x3 => Self::FlowControlError,
+            0x4 => Self::SettingsTimeout,
+            0x5 => Self::StreamClosed,
+            0x6 => Self::FrameSizeError,
+            0x7 => Self::RefusedStream,
+            0x8 => Self::Cancel,
+            0x9 => Self::CompressionError,
+            0xa => Self::ConnectError,
+            0xb => Self::EnhanceYourCalm,
+            0xc => Self::InadequateSecurity,
+            0xd => Self::Http11Required,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::NoError => 0x0,
+            ErrorCode::ProtocolError => 0x1,
+            ErrorCode::InternalError => 0x2,
+            ErrorCode::FlowControlError => 0x3,
+            ErrorCode::SettingsTimeout => 0x4,
+            ErrorCode::StreamClosed => 0x5,
+            ErrorCode::FrameSizeError => 0x6,
+            ErrorCode::RefusedStream => 0x7,
+            ErrorCode::Cancel => 0x8,
+            ErrorCode::CompressionError => 0x9,
+            ErrorCode::ConnectError => 0xa,
+            ErrorCode::EnhanceYourCalm => 0xb,
+            ErrorCode::InadequateSecurity => 0xc,
+            ErrorCode::Http11Required => 0xd,
+            ErrorCode::Unknown(code) => code,
+        }
+    }
+}
+
+/// Removes the padding from the payload of a frame if it is padded.
+fn remove_padding(flags: u8, payload: &[u8]) -> Result<&[u8], FrameError> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+
+    let (&pad_length, rest) = payload
+        .split_first()
+        .ok_or(FrameError::Connection(ErrorCode::FrameSizeError))?;
+
+    // The padding must be shorter than the rest of the payload
+    if pad_length as usize > rest.len() {
+        return Err(FrameError::Connection(ErrorCode::ProtocolError));
+    }
+
+    Ok(&rest[..rest.len() - pad_length as usize])
+}
+
+/// Reads a big-endian 32-bit integer from the start of the buffer.
+fn read_u32(buf: &[u8]) -> u32 {
+    u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+/// Returns the given flag if the condition is true.
+fn flag(condition: bool, flag: u8) -> u8 {
+    match condition {
+        true => flag,
+        false => 0,
+    }
+}
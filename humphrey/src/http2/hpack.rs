@@ -0,0 +1,362 @@
+//! Provides HPACK, the format used to compress the headers of HTTP/2 requests and responses, as defined in
+//!   RFC 7541.
+
+use crate::http2::huffman;
+
+use std::collections::VecDeque;
+
+/// The entries of the static table, which are indexed from 1.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// The default maximum size of the dynamic table, which is used until the peer changes it.
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// The size each entry takes up in the dynamic table in addition to its name and value.
+const ENTRY_OVERHEAD: usize = 32;
+
+/// Represents a header field, which is a name and value.
+/// These are bytes since HPACK does not require them to be valid UTF-8.
+pub type HeaderField = (Vec<u8>, Vec<u8>);
+
+/// An error which occurred while decoding a header block.
+/// Every error is a connection error, since the state of the decoder can no longer be relied upon.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HpackError {
+    /// The block ended in the middle of a representation.
+    Truncated,
+    /// An integer was too large to be represented.
+    Integer,
+    /// An index referred to an entry which does not exist.
+    Index,
+    /// A Huffman-encoded string could not be decoded.
+    Huffman,
+    /// A dynamic table size update was larger than allowed, or was not at the start of the block.
+    TableSize,
+    /// The decoded header list was larger than the maximum header list size.
+    ListSize,
+}
+
+/// Decodes the header blocks received on a connection, keeping track of the dynamic table which the peer's
+///   encoder adds fields to.
+pub struct Decoder {
+    table: DynamicTable,
+    max_size: usize,
+    max_list_size: usize,
+}
+
+/// The dynamic table of an HPACK decoder, with the newest entries first.
+struct DynamicTable {
+    entries: VecDeque<HeaderField>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Decoder {
+    /// Creates a new decoder whose dynamic table may be up to the given size, which must match the
+    ///   `SETTINGS_HEADER_TABLE_SIZE` setting sent to the peer.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            table: DynamicTable::new(max_size),
+            max_size,
+            max_list_size: usize::MAX,
+        }
+    }
+
+    /// Limits the size of decoded header lists, which should match the `SETTINGS_MAX_HEADER_LIST_SIZE` setting
+    ///   sent to the peer.
+    ///
+    /// Without this, a small block which refers to a large table entry many times could decode to a huge list.
+    pub fn with_max_list_size(mut self, max_list_size: usize) -> Self {
+        self.max_list_size = max_list_size;
+        self
+    }
+
+    /// Decodes a complete header block into its fields, in order.
+    ///
+    /// The size of the list is counted as it is decoded in the same way as table entries, and decoding stops as
+    ///   soon as it is larger than the maximum.
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<HeaderField>, HpackError> {
+        let mut fields = Vec::new();
+        let mut list_size: usize = 0;
+        let mut buf = block;
+
+        while let Some(&first) = buf.first() {
+            let field = if first & 0x80 != 0 {
+                // Indexed header field
+                let index = decode_integer(&mut buf, 7)?;
+                self.get(index)?
+            } else if first & 0x40 != 0 {
+                // Literal header field with incremental indexing
+                let field = self.decode_literal(&mut buf, 6)?;
+                self.table.insert(field.clone());
+                field
+            } else if first & 0x20 != 0 {
+                // Dynamic table size update, which may only appear before the first field
+                let size = decode_integer(&mut buf, 5)?;
+
+                if size > self.max_size || !fields.is_empty() {
+                    return Err(HpackError::TableSize);
+                }
+
+                self.table.set_max_size(size);
+                continue;
+            } else {
+                // Literal header field without indexing or never indexed
+                self.decode_literal(&mut buf, 4)?
+            };
+
+            list_size = list_size.saturating_add(field.0.len() + field.1.len() + ENTRY_OVERHEAD);
+
+            if list_size > self.max_list_size {
+                return Err(HpackError::ListSize);
+            }
+
+            fields.push(field);
+        }
+
+        Ok(fields)
+    }
+
+    /// Gets the field at the given index of the combined static and dynamic tables.
+    fn get(&self, index: usize) -> Result<HeaderField, HpackError> {
+        match index {
+            0 => Err(HpackError::Index),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.as_bytes().to_vec(), value.as_bytes().to_vec()))
+            }
+            _ => self
+                .table
+                .entries
+                .get(index - 62)
+                .cloned()
+                .ok_or(HpackError::Index),
+        }
+    }
+
+    /// Decodes a literal field whose name is either indexed with the given prefix or a literal.
+    fn decode_literal(&self, buf: &mut &[u8], prefix: u8) -> Result<HeaderField, HpackError> {
+        let name = match decode_integer(buf, prefix)? {
+            0 => decode_string(buf)?,
+            index => self.get(index)?.0,
+        };
+
+        let value = decode_string(buf)?;
+
+        Ok((name, value))
+    }
+}
+
+impl DynamicTable {
+    fn new(max_size: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Adds an entry, evicting the oldest entries to make room for it.
+    /// An entry larger than the table empties the table without being added.
+    fn insert(&mut self, field: HeaderField) {
+        let size = field.0.len() + field.1.len() + ENTRY_OVERHEAD;
+
+        self.evict(self.max_size.saturating_sub(size));
+
+        if size <= self.max_size {
+            self.size += size;
+            self.entries.push_front(field);
+        }
+    }
+
+    /// Changes the maximum size of the table, evicting entries until they fit.
+    fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict(max_size);
+    }
+
+    /// Evicts the oldest entries until the table is no larger than the given size.
+    fn evict(&mut self, size: usize) {
+        while self.size > size {
+            let (name, value) = self.entries.pop_back().unwrap();
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+}
+
+/// Encodes a header field, appending it to the buffer.
+///
+/// Fields are never added to the dynamic table, so encoding requires no state. Fields in the static table are
+///   indexed, and strings are Huffman-encoded when this makes them shorter.
+pub fn encode(name: &[u8], value: &[u8], buf: &mut Vec<u8>) {
+    let mut name_index = 0;
+
+    for (index, (static_name, static_value)) in STATIC_TABLE.iter().enumerate() {
+        if static_name.as_bytes() == name {
+            if static_value.as_bytes() == value {
+                encode_integer(index + 1, 7, 0x80, buf);
+                return;
+            }
+
+            if name_index == 0 {
+                name_index = index + 1;
+            }
+        }
+    }
+
+    // Literal header field without indexing
+    encode_integer(name_index, 4, 0x00, buf);
+
+    if name_index == 0 {
+        encode_string(name, buf);
+    }
+
+    encode_string(value, buf);
+}
+
+/// Decodes an integer with the given prefix length in bits, advancing the buffer past it.
+fn decode_integer(buf: &mut &[u8], prefix: u8) -> Result<usize, HpackError> {
+    let (&first, rest) = buf.split_first().ok_or(HpackError::Truncated)?;
+    *buf = rest;
+
+    let max_prefix = (1usize << prefix) - 1;
+    let mut value = first as usize & max_prefix;
+
+    if value < max_prefix {
+        return Ok(value);
+    }
+
+    let mut shift = 0;
+
+    loop {
+        let (&byte, rest) = buf.split_first().ok_or(HpackError::Truncated)?;
+        *buf = rest;
+
+        // Integers are limited to 28 bits, which is plenty for any length or index
+        if shift > 21 {
+            return Err(HpackError::Integer);
+        }
+
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+/// Encodes an integer with the given prefix length in bits, setting the bits before the prefix in the first byte
+///   to those of `flags`.
+fn encode_integer(value: usize, prefix: u8, flags: u8, buf: &mut Vec<u8>) {
+    let max_prefix = (1usize << prefix) - 1;
+
+    if value < max_prefix {
+        buf.push(flags | value as u8);
+        return;
+    }
+
+    buf.push(flags | max_prefix as u8);
+
+    let mut value = value - max_prefix;
+
+    while value >= 0x80 {
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+
+    buf.push(value as u8);
+}
+
+/// Decodes a string, which may be Huffman-encoded, advancing the buffer past it.
+fn decode_string(buf: &mut &[u8]) -> Result<Vec<u8>, HpackError> {
+    let huffman = buf.first().ok_or(HpackError::Truncated)? & 0x80 != 0;
+    let length = decode_integer(buf, 7)?;
+
+    if length > buf.len() {
+        return Err(HpackError::Truncated);
+    }
+
+    let (data, rest) = buf.split_at(length);
+    *buf = rest;
+
+    match huffman {
+        true => huffman::decode(data).map_err(|_| HpackError::Huffman),
+        false => Ok(data.to_vec()),
+    }
+}
+
+/// Encodes a string, using the Huffman code if it is shorter.
+fn encode_string(data: &[u8], buf: &mut Vec<u8>) {
+    let encoded_len = huffman::encoded_len(data);
+
+    if encoded_len < data.len() {
+        encode_integer(encoded_len, 7, 0x80, buf);
+        huffman::encode(data, buf);
+    } else {
+        encode_integer(data.len(), 7, 0x00, buf);
+        buf.extend_from_slice(data);
+    }
+}
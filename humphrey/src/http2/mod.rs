@@ -0,0 +1,84 @@
+//! Provides support for HTTP/2, which is negotiated with ALPN when clients connect over TLS.
+//!
+//! Requests received over HTTP/2 are handled by the same handlers as those received over HTTP/1, so apps work
+//!   unchanged. Each request is received on its own stream, and many streams share one connection.
+
+pub mod connection;
+pub mod frame;
+pub mod hpack;
+
+mod huffman;
+
+use crate::http::date::DateTime;
+use crate::http::headers::HeaderType;
+use crate::http::response::Response;
+use crate::http::status::StatusCode;
+use crate::monitor::event::{Event, EventType};
+
+use std::net::SocketAddr;
+
+/// The size of the chunks in which streamed response bodies are sent.
+pub(crate) const CHUNK_SIZE: usize = 16384;
+
+/// How often a connection waiting for data checks whether the app is shutting down.
+#[cfg(not(feature = "tokio"))]
+pub(crate) const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How often a connection whose requests are being handled checks whether their responses are ready.
+#[cfg(not(feature = "tokio"))]
+pub(crate) const RESPONSE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Adds the headers which are generated automatically to a response to be sent over HTTP/2, and removes its body
+///   if it is a response to a `HEAD` request.
+pub(crate) fn prepare_response(response: &mut Response, head: bool) {
+    if response.headers.get(HeaderType::Server).is_none() {
+        response.headers.add(HeaderType::Server, "Humphrey");
+    }
+
+    if response.headers.get(HeaderType::Date).is_none() {
+        response
+            .headers
+            .add(HeaderType::Date, DateTime::now().to_string());
+    }
+
+    // `304 Not Modified` responses never have a body, so the length of the unsent content is unknown
+    if response.headers.get(HeaderType::ContentLength).is_none()
+        && response.status_code != StatusCode::NotModified
+    {
+        if let Some(length) = response.content_length() {
+            response
+                .headers
+                .add(HeaderType::ContentLength, length.to_string());
+        }
+    }
+
+    // Responses to `HEAD` requests have the same headers as those to `GET` requests, but no body
+    if head {
+        response.body.clear();
+        response.stream = None;
+    }
+}
+
+/// Creates the monitor event for a response sent over HTTP/2, with the URI of the request if it could be handled.
+pub(crate) fn served_event(addr: SocketAddr, status: StatusCode, uri: Option<&str>) -> Event {
+    let status_str: &str = status.into();
+
+    let event = match status {
+        StatusCode::OK => Event::new(EventType::RequestServedSuccess),
+        _ => Event::new(EventType::RequestServedError),
+    };
+
+    match uri {
+        Some(uri) => event.with_peer(addr).with_info(format!(
+            "{} {} {} (HTTP/2)",
+            u16::from(status),
+            status_str,
+            uri
+        )),
+        None => event.with_peer(addr).with_info(format!(
+            "{} {} (HTTP/2)",
+            u16::from(status),
+            status_str
+        )),
+    }
+}
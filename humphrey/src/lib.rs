@@ -21,13 +21,20 @@ pub use crate::tokio::*;
 pub mod stream;
 
 pub mod client;
+pub mod deflate;
 pub mod http;
+#[cfg(feature = "tls")]
+pub mod http2;
 pub mod krauss;
+pub mod listener;
 pub mod monitor;
 pub mod percent;
 pub mod route;
 pub mod thread;
 
+#[cfg(feature = "tls")]
+pub mod tls;
+
 #[cfg(test)]
 mod tests;
 
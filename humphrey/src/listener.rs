@@ -0,0 +1,163 @@
+//! Provides the listeners on which an app accepts connections.
+
+use crate::http::headers::HeaderType;
+use crate::http::{Request, Response, StatusCode};
+
+#[cfg(feature = "tls")]
+use crate::tls::ClientVerifier;
+#[cfg(feature = "tls")]
+use rustls::server::ResolvesServerCert;
+#[cfg(feature = "tls")]
+use rustls::ServerConfig;
+
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+/// Represents an address on which an app accepts connections, along with how the connections are handled.
+///
+/// Every listener of an app shares its routes, so one app can serve the same routes over both HTTP and HTTPS.
+///
+/// ## Example
+/// ```
+/// let app: App<()> = App::new()
+///     .with_stateless_route("/", home)
+///     .with_cert("cert.pem", "key.pem")
+///     .with_listener(Listener::https("0.0.0.0:443"))
+///     .with_listener(Listener::redirect("0.0.0.0:80", 443))
+///     .with_listener(Listener::http("127.0.0.1:8080"));
+///
+/// app.run_listeners()?;
+/// ```
+#[derive(Clone)]
+pub struct Listener {
+    pub(crate) addr: String,
+    pub(crate) mode: ListenerMode,
+}
+
+/// How the connections to a listener are handled.
+#[derive(Clone)]
+pub(crate) enum ListenerMode {
+    /// Requests are handled by the app over plain HTTP.
+    Http,
+    /// Requests are handled by the app over HTTPS, using the listener's certificates and client authentication if
+    ///   they are set, or the app's otherwise.
+    #[cfg(feature = "tls")]
+    Https {
+        cert_resolver: Option<Arc<dyn ResolvesServerCert>>,
+        client_verifier: Option<ClientVerifier>,
+    },
+    /// Requests are redirected to HTTPS on the given port.
+    Redirect(u16),
+}
+
+/// How a connection which has been accepted is handled, once the TLS configuration of its listener is known.
+#[derive(Clone)]
+pub(crate) enum ConnectionMode {
+    /// Requests are handled by the app over plain HTTP.
+    Http,
+    /// Requests are handled by the app over HTTPS with the given configuration.
+    #[cfg(feature = "tls")]
+    Https(Arc<ServerConfig>),
+    /// Requests are redirected to HTTPS on the given port.
+    Redirect(u16),
+}
+
+impl Listener {
+    /// Creates a listener which serves the app over plain HTTP on the given address.
+    pub fn http(addr: impl AsRef<str>) -> Self {
+        Self {
+            addr: addr.as_ref().to_string(),
+            mode: ListenerMode::Http,
+        }
+    }
+
+    /// Creates a listener which serves the app over HTTPS on the given address.
+    ///
+    /// The app's certificate and client authentication are used unless the listener has its own.
+    #[cfg(feature = "tls")]
+    pub fn https(addr: impl AsRef<str>) -> Self {
+        Self {
+            addr: addr.as_ref().to_string(),
+            mode: ListenerMode::Https {
+                cert_resolver: None,
+                client_verifier: None,
+            },
+        }
+    }
+
+    /// Creates a listener which redirects every request on the given address to the same URL over HTTPS on the
+    ///   given port.
+    pub fn redirect(addr: impl AsRef<str>, https_port: u16) -> Self {
+        Self {
+            addr: addr.as_ref().to_string(),
+            mode: ListenerMode::Redirect(https_port),
+        }
+    }
+
+    /// Sets the resolver which selects the certificate for connections to this HTTPS listener, instead of using
+    ///   the app's certificate.
+    ///
+    /// ## Panics
+    /// This function will panic if the listener does not use HTTPS.
+    #[cfg(feature = "tls")]
+    pub fn with_cert_resolver(mut self, resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        match &mut self.mode {
+            ListenerMode::Https { cert_resolver, .. } => *cert_resolver = Some(resolver),
+            _ => panic!("Certificates can only be set on HTTPS listeners"),
+        }
+
+        self
+    }
+
+    /// Sets the verifier used to authenticate clients of this HTTPS listener with TLS client certificates, instead
+    ///   of using the app's client authentication.
+    ///
+    /// ## Panics
+    /// This function will panic if the listener does not use HTTPS.
+    #[cfg(feature = "tls")]
+    pub fn with_client_auth(mut self, verifier: ClientVerifier) -> Self {
+        match &mut self.mode {
+            ListenerMode::Https {
+                client_verifier, ..
+            } => *client_verifier = Some(verifier),
+            _ => panic!("Client authentication can only be set on HTTPS listeners"),
+        }
+
+        self
+    }
+
+    /// Gets the address of the listener.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+}
+
+/// Creates the response which redirects the request to the same URL over HTTPS on the given port.
+pub(crate) fn https_redirect(request: &Request, https_port: u16) -> Response {
+    if let Some(host) = request.headers.get(&HeaderType::Host) {
+        // Removes the port of the insecure listener from the host, keeping IPv6 addresses intact
+        let host = match host.rfind(':') {
+            Some(index) if !host[index..].contains(']') => &host[..index],
+            _ => host,
+        };
+
+        let mut location = match https_port {
+            443 => format!("https://{}{}", host, request.uri),
+            port => format!("https://{}:{}{}", host, port, request.uri),
+        };
+
+        if !request.query.is_empty() {
+            location.push('?');
+            location.push_str(&request.query);
+        }
+
+        Response::empty(StatusCode::MovedPermanently)
+            .with_header(HeaderType::Location, location)
+            .with_header(HeaderType::Connection, "Close")
+    } else {
+        Response::empty(StatusCode::OK)
+            .with_bytes(b"<h1>Please access over HTTPS</h1>")
+            .with_header(HeaderType::ContentLength, "33")
+            .with_header(HeaderType::Connection, "Close")
+    }
+}
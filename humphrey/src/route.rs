@@ -1,14 +1,18 @@
 //! Provides functionality for handling app routes.
 
 use crate::app::{
-    PathAwareRequestHandler, RequestHandler, StatelessRequestHandler, WebsocketHandler,
+    Middleware, PathAwareRequestHandler, RequestHandler, StatelessRequestHandler,
+    StreamingRequestHandler, WebsocketHandler,
 };
 use crate::http::cors::Cors;
+use crate::http::method::Method;
 use crate::krauss;
 use crate::percent::PercentDecode;
 
+use std::collections::HashMap;
 use std::fs::metadata;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Represents a sub-app to run for a specific host.
 pub struct SubApp<State> {
@@ -21,16 +25,30 @@ pub struct SubApp<State> {
     /// The CORS configuration for this subapp.
     /// If not specified, it is down to the individual routes to specify CORS configuration.
     pub cors: Option<Cors>,
+    /// The middleware which runs for every request to this subapp, in order.
+    pub middleware: Vec<Arc<dyn Middleware<State>>>,
 }
 
 /// Encapsulates a route and its handler.
 pub struct RouteHandler<State> {
     /// The route that this handler will match.
     pub route: String,
+    /// The method that this handler will match, or `None` to match any method.
+    pub method: Option<Method>,
     /// The handler to run when the route is matched.
-    pub handler: Box<dyn RequestHandler<State>>,
+    pub handler: Handler<State>,
     /// The CORS configuration for the route.
     pub cors: Cors,
+    /// The middleware which runs for requests to this route, in order, after that of the subapp.
+    pub middleware: Vec<Arc<dyn Middleware<State>>>,
+}
+
+/// Represents the handler of a route.
+pub enum Handler<State> {
+    /// A handler which is passed the request with its body already read into memory.
+    Buffered(Box<dyn RequestHandler<State>>),
+    /// A handler which reads the request body from the connection as it arrives.
+    Streaming(Box<dyn StreamingRequestHandler<State>>),
 }
 
 /// Encapsulates a route and its WebSocket handler.
@@ -41,6 +59,19 @@ pub struct WebsocketRouteHandler<State> {
     pub handler: Box<dyn WebsocketHandler<State>>,
 }
 
+/// The result of matching a request against the routes of a sub-app.
+pub(crate) struct RouteMatch<'a, State> {
+    /// The handler for the request's method, if there is one.
+    ///
+    /// For `OPTIONS` requests, this is any handler of the route, so that its CORS configuration can be used
+    ///   when the request is answered automatically.
+    pub handler: Option<&'a RouteHandler<State>>,
+    /// The methods which the route can handle, used for the `Allow` header.
+    pub allowed_methods: Vec<Method>,
+    /// The parameters extracted from the path.
+    pub params: HashMap<String, String>,
+}
+
 impl<State> Default for SubApp<State> {
     fn default() -> Self {
         SubApp {
@@ -48,6 +79,7 @@ impl<State> Default for SubApp<State> {
             routes: Vec::new(),
             websocket_routes: Vec::new(),
             cors: None,
+            middleware: Vec::new(),
         }
     }
 }
@@ -66,8 +98,10 @@ impl<State> SubApp<State> {
     {
         self.routes.push(RouteHandler {
             route: route.to_string(),
-            handler: Box::new(handler),
+            method: None,
+            handler: Handler::Buffered(Box::new(handler)),
             cors: self.cors.as_ref().map_or(Cors::default(), |c| c.clone()),
+            middleware: Vec::new(),
         });
         self
     }
@@ -83,8 +117,10 @@ impl<State> SubApp<State> {
     {
         self.routes.push(RouteHandler {
             route: route.to_string(),
-            handler: Box::new(move |request, _| handler.serve(request)),
+            method: None,
+            handler: Handler::Buffered(Box::new(move |request, _| handler.serve(request))),
             cors: self.cors.as_ref().map_or(Cors::default(), |c| c.clone()),
+            middleware: Vec::new(),
         });
         self
     }
@@ -98,12 +134,99 @@ impl<State> SubApp<State> {
     {
         self.routes.push(RouteHandler {
             route: route.to_string(),
-            handler: Box::new(move |request, state| handler.serve(request, state, route)),
+            method: None,
+            handler: Handler::Buffered(Box::new(move |request, state| {
+                handler.serve(request, state, route)
+            })),
+            cors: self.cors.as_ref().map_or(Cors::default(), |c| c.clone()),
+            middleware: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds a route and associated streaming handler to the sub-app.
+    /// Routes can include wildcards, for example `/upload/*`.
+    /// The request body is not read into memory, but is instead passed to the handler to be read
+    ///   from the connection as it arrives. The app's maximum body size does not apply to it.
+    pub fn with_streaming_route<T>(mut self, route: &str, handler: T) -> Self
+    where
+        T: StreamingRequestHandler<State> + 'static,
+    {
+        self.routes.push(RouteHandler {
+            route: route.to_string(),
+            method: None,
+            handler: Handler::Streaming(Box::new(handler)),
             cors: self.cors.as_ref().map_or(Cors::default(), |c| c.clone()),
+            middleware: Vec::new(),
         });
         self
     }
 
+    /// Adds a route and associated handler to the sub-app which only matches requests with the given method.
+    /// Routes can include wildcards, for example `/blog/*`.
+    ///
+    /// If requests are made to the route with a method which has no handler, they are rejected with
+    ///   `405 Method Not Allowed`. `HEAD` requests are handled by the `GET` handler if there is no `HEAD` handler,
+    ///   and `OPTIONS` requests are answered automatically if there is no `OPTIONS` handler.
+    pub fn with_method_route<T>(mut self, method: Method, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.routes.push(RouteHandler {
+            route: route.to_string(),
+            method: Some(method),
+            handler: Handler::Buffered(Box::new(handler)),
+            cors: self.cors.as_ref().map_or(Cors::default(), |c| c.clone()),
+            middleware: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds a route and associated handler to the sub-app which only matches `GET` and `HEAD` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_get<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Get, route, handler)
+    }
+
+    /// Adds a route and associated handler to the sub-app which only matches `POST` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_post<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Post, route, handler)
+    }
+
+    /// Adds a route and associated handler to the sub-app which only matches `PUT` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_put<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Put, route, handler)
+    }
+
+    /// Adds a route and associated handler to the sub-app which only matches `PATCH` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_patch<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Patch, route, handler)
+    }
+
+    /// Adds a route and associated handler to the sub-app which only matches `DELETE` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_delete<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Delete, route, handler)
+    }
+
     /// Adds a WebSocket route and associated handler to the sub-app.
     /// Routes can include wildcards, for example `/ws/*`.
     /// The handler is passed the stream, state, and the request which triggered its calling.
@@ -145,22 +268,252 @@ impl<State> SubApp<State> {
 
         self
     }
+
+    /// Adds middleware to the sub-app, which runs for every request to it.
+    ///
+    /// Middleware runs in the order it was added. See the `Middleware` trait for more details.
+    pub fn with_middleware<T>(mut self, middleware: T) -> Self
+    where
+        T: Middleware<State> + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Adds middleware to a given route, which runs after the sub-app's middleware.
+    ///
+    /// The route must already have been added, since the middleware is attached to its handler.
+    pub fn with_route_middleware<T>(mut self, route: &str, middleware: T) -> Self
+    where
+        T: Middleware<State> + 'static,
+    {
+        let middleware: Arc<dyn Middleware<State>> = Arc::new(middleware);
+
+        self.routes.iter_mut().for_each(|r| {
+            if r.route == route {
+                r.middleware.push(middleware.clone());
+            }
+        });
+
+        self
+    }
+
+    /// Finds the handler for the given method at the most specific route which matches the path.
+    ///
+    /// Returns `None` if no route matches the path, and a match without a handler if the route cannot handle the method.
+    pub(crate) fn find_handler(
+        &self,
+        path: &str,
+        method: &Method,
+    ) -> Option<RouteMatch<'_, State>> {
+        let (index, params) = find_route(self.routes.iter().map(|route| &route.route), path)?;
+        let route = &self.routes[index].route;
+        let handlers: Vec<&RouteHandler<State>> = self
+            .routes
+            .iter()
+            .filter(|handler| &handler.route == route)
+            .collect();
+
+        let find_method = |method: &Method| {
+            handlers
+                .iter()
+                .find(|handler| handler.method.as_ref() == Some(method))
+                .copied()
+        };
+
+        // Handlers for a specific method take precedence over those for any method
+        let handler = find_method(method)
+            .or_else(|| match method {
+                Method::Head => find_method(&Method::Get),
+                _ => None,
+            })
+            .or_else(|| {
+                handlers
+                    .iter()
+                    .find(|handler| handler.method.is_none())
+                    .copied()
+            })
+            .or_else(|| match method {
+                Method::Options => handlers.first().copied(),
+                _ => None,
+            });
+
+        let allowed_methods = if handlers.iter().any(|handler| handler.method.is_none()) {
+            ALL_METHODS.to_vec()
+        } else {
+            ALL_METHODS
+                .iter()
+                .filter(|allowed| {
+                    find_method(allowed).is_some()
+                        || (**allowed == Method::Head && find_method(&Method::Get).is_some())
+                        || **allowed == Method::Options
+                })
+                .cloned()
+                .collect()
+        };
+
+        Some(RouteMatch {
+            handler,
+            allowed_methods,
+            params,
+        })
+    }
 }
 
+/// Every method which can be routed, in the order they are listed in the `Allow` header.
+const ALL_METHODS: [Method; 7] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Patch,
+    Method::Delete,
+    Method::Options,
+];
+
 /// An object that can represent a route, currently only `String`.
+///
+/// Routes can contain wildcards, for example `/blog/*`, named parameters which match a single segment of the path,
+///   for example `/users/:id`, and a trailing catch-all parameter which matches the rest of the path, if any,
+///   for example `/files/*:path`.
 pub trait Route {
     /// Returns true if the given route matches the path.
     fn route_matches(&self, route: &str) -> bool;
+
+    /// Returns the parameters extracted from the path if the given route matches it, or `None` if it does not.
+    fn route_params(&self, route: &str) -> Option<HashMap<String, String>>;
 }
 
 impl Route for String {
-    /// Checks whether this route matches the given one, respecting its own wildcards only.
+    /// Checks whether this route matches the given one, respecting its own wildcards and parameters only.
     /// For example, `/blog/*` will match `/blog/my-first-post` but not the other way around.
     fn route_matches(&self, route: &str) -> bool {
-        krauss::wildcard_match(self, route)
+        self.route_params(route).is_some()
+    }
+
+    /// Matches the given path against this route segment by segment, extracting its parameters.
+    /// For example, `/users/:id` will match `/users/123` with the parameter `id` set to `123`.
+    ///
+    /// Routes without parameters are matched as a whole with `krauss::wildcard_match`, so their wildcards can
+    ///   match across multiple segments.
+    fn route_params(&self, route: &str) -> Option<HashMap<String, String>> {
+        let mut params = HashMap::new();
+
+        if !self.split('/').any(is_parameter) {
+            return krauss::wildcard_match(self, route).then_some(params);
+        }
+
+        let route_segments: Vec<&str> = self.split('/').collect();
+        let path_segments: Vec<&str> = route.split('/').collect();
+
+        for (i, segment) in route_segments.iter().enumerate() {
+            // A trailing catch-all matches the rest of the path, even if there is none
+            if let Some(name) = catch_all_name(segment) {
+                if i == route_segments.len() - 1 {
+                    let rest = path_segments.get(i..).unwrap_or_default().join("/");
+
+                    if !name.is_empty() {
+                        params.insert(name.to_string(), decode_param(&rest));
+                    }
+
+                    return Some(params);
+                }
+            }
+
+            let path_segment = path_segments.get(i)?;
+
+            if let Some(name) = segment.strip_prefix(':') {
+                if path_segment.is_empty() {
+                    return None;
+                }
+
+                params.insert(name.to_string(), decode_param(path_segment));
+            } else if !krauss::wildcard_match(segment, path_segment) {
+                return None;
+            }
+        }
+
+        (route_segments.len() == path_segments.len()).then_some(params)
+    }
+}
+
+/// Checks whether a segment of a route is a named parameter or catch-all.
+fn is_parameter(segment: &str) -> bool {
+    segment.starts_with(':') || catch_all_name(segment).is_some_and(|name| !name.is_empty())
+}
+
+/// Gets the name of a catch-all segment, which is empty if it is unnamed, or `None` if it is not a catch-all.
+///
+/// Named catch-alls are written `*:name`, so that wildcard segments such as `*png` are still matched as globs.
+fn catch_all_name(segment: &str) -> Option<&str> {
+    match segment.strip_prefix('*')? {
+        "" => Some(""),
+        rest => rest.strip_prefix(':').filter(|name| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }),
     }
 }
 
+/// Percent-decodes the value of a parameter, leaving it unchanged if it is not valid.
+fn decode_param(value: &str) -> String {
+    value
+        .percent_decode()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .unwrap_or_else(|| value.to_string())
+}
+
+/// Calculates the specificity of a route, which is compared segment by segment to choose between routes
+///   which both match a path.
+///
+/// Literal segments are the most specific, followed by segments containing wildcards, then named parameters,
+///   then catch-alls, including segments which consist only of a wildcard. A route which ends is more specific
+///   than one which continues with a catch-all, so `/users/:id` is chosen over `/users/:id/*:rest` for `/users/1`.
+fn specificity(route: &str) -> Vec<u8> {
+    route
+        .split('/')
+        .map(|segment| {
+            if catch_all_name(segment).is_some() {
+                0
+            } else if segment.starts_with(':') {
+                2
+            } else if segment.contains('*') {
+                3
+            } else {
+                4
+            }
+        })
+        .chain(std::iter::once(1))
+        .collect()
+}
+
+/// Finds the most specific of the given routes which matches the path, returning its index and the
+///   parameters extracted from the path.
+///
+/// Routes which are equally specific are chosen in the order they were added.
+pub fn find_route<'a>(
+    routes: impl Iterator<Item = &'a String>,
+    path: &str,
+) -> Option<(usize, HashMap<String, String>)> {
+    let mut best: Option<(usize, HashMap<String, String>, Vec<u8>)> = None;
+
+    for (index, route) in routes.enumerate() {
+        if let Some(params) = route.route_params(path) {
+            let specificity = specificity(route);
+
+            let more_specific = match &best {
+                Some(best) => best.2 < specificity,
+                None => true,
+            };
+
+            if more_specific {
+                best = Some((index, params, specificity));
+            }
+        }
+    }
+
+    best.map(|(index, params, _)| (index, params))
+}
+
 /// A located file or directory path.
 pub enum LocatedPath {
     /// A directory was located.
@@ -5,6 +5,8 @@
 #[cfg(feature = "tls")]
 use rustls::ServerConnection;
 
+use crate::http::identity::PeerIdentity;
+
 use std::io::{Error, Read, Write};
 use std::net::SocketAddr;
 use std::time::Duration;
@@ -102,4 +104,35 @@ impl Stream {
             Stream::Tls(stream) => stream.sock.set_nonblocking(false),
         }
     }
+
+    /// Returns true if the stream is encrypted with TLS.
+    pub fn is_tls(&self) -> bool {
+        match self {
+            Stream::Tcp(_) => false,
+            #[cfg(feature = "tls")]
+            Stream::Tls(_) => true,
+        }
+    }
+
+    /// Returns true if the remote peer negotiated HTTP/2 with ALPN when connecting over TLS.
+    pub fn is_http2(&self) -> bool {
+        match self {
+            Stream::Tcp(_) => false,
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.conn.alpn_protocol() == Some(b"h2"),
+        }
+    }
+
+    /// Returns the identity of the remote peer if it authenticated with a TLS client certificate.
+    pub fn peer_identity(&self) -> Option<PeerIdentity> {
+        match self {
+            Stream::Tcp(_) => None,
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream
+                .conn
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| PeerIdentity::from_der(&cert.0)),
+        }
+    }
 }
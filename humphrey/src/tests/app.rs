@@ -0,0 +1,213 @@
+use crate::app::{ConnectionConfig, ErrorHandler};
+use crate::http::cors::Cors;
+use crate::http::headers::HeaderType;
+use crate::http::method::Method;
+use crate::http::{Request, Response, StatusCode};
+use crate::monitor::MonitorConfig;
+use crate::route::SubApp;
+use crate::stream::Stream;
+use crate::App;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+/// Finds a port which is not in use.
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Connects to the given port, waiting for the app to start listening if necessary.
+pub fn connect(port: u16) -> TcpStream {
+    loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+/// Runs the app in the background on a free port, sends it the request and returns the connection.
+pub fn send(app: App, request: &str) -> TcpStream {
+    let port = free_port();
+
+    spawn(move || app.run(("127.0.0.1", port)).unwrap());
+
+    let mut stream = connect(port);
+    stream.write_all(request.as_bytes()).unwrap();
+    stream
+}
+
+/// Runs the app in the background on a free port, sends it the request and returns the response.
+pub fn serve(app: App, request: &str) -> Response {
+    Response::from_stream(&mut send(app, request)).unwrap()
+}
+
+fn handler(name: &'static str) -> impl Fn(Request, Arc<()>) -> Response {
+    move |_, _| Response::new(StatusCode::OK, name)
+}
+
+fn app() -> App {
+    App::new_with_config(2, ())
+        .with_get("/users/:id", handler("get"))
+        .with_delete("/users/:id", handler("delete"))
+        .with_route("/any", handler("any"))
+        .with_cors(Cors::wildcard())
+}
+
+#[test]
+fn test_method_routing() {
+    let response = serve(app(), "GET /users/1 HTTP/1.1\r\n\r\n");
+    assert_eq!(response.body, b"get");
+
+    let response = serve(app(), "DELETE /users/1 HTTP/1.1\r\n\r\n");
+    assert_eq!(response.body, b"delete");
+
+    let response = serve(app(), "PUT /any HTTP/1.1\r\n\r\n");
+    assert_eq!(response.body, b"any");
+}
+
+#[test]
+fn test_method_not_allowed() {
+    let response = serve(app(), "POST /users/1 HTTP/1.1\r\n\r\n");
+
+    assert_eq!(response.status_code, StatusCode::MethodNotAllowed);
+    assert_eq!(
+        response.headers.get(HeaderType::Allow),
+        Some("GET, HEAD, DELETE, OPTIONS")
+    );
+
+    let response = serve(app(), "POST /missing HTTP/1.1\r\n\r\n");
+    assert_eq!(response.status_code, StatusCode::NotFound);
+}
+
+#[test]
+fn test_automatic_options() {
+    let response = serve(
+        app(),
+        "OPTIONS /users/1 HTTP/1.1\r\nOrigin: https://example.com\r\nAccess-Control-Request-Method: DELETE\r\n\r\n",
+    );
+
+    assert_eq!(response.status_code, StatusCode::NoContent);
+    assert_eq!(
+        response.headers.get(HeaderType::Allow),
+        Some("GET, HEAD, DELETE, OPTIONS")
+    );
+    assert_eq!(
+        response.headers.get(HeaderType::AccessControlAllowMethods),
+        Some("GET, HEAD, DELETE, OPTIONS")
+    );
+    assert_eq!(
+        response.headers.get(HeaderType::AccessControlAllowOrigin),
+        Some("*")
+    );
+
+    let app = app().with_method_route(Method::Options, "/users/:id", handler("options"));
+    let response = serve(app, "OPTIONS /users/1 HTTP/1.1\r\n\r\n");
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"options");
+}
+
+#[test]
+fn test_head() {
+    // The connection is closed after the response, so everything sent can be read
+    let mut response = String::new();
+    send(app(), "HEAD /users/1 HTTP/1.1\r\n\r\n")
+        .read_to_string(&mut response)
+        .unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nContent-Length: 3\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    let app = app().with_method_route(Method::Head, "/users/:id", handler("head"));
+    let mut response = String::new();
+    send(app, "HEAD /users/1 HTTP/1.1\r\n\r\n")
+        .read_to_string(&mut response)
+        .unwrap();
+
+    assert!(response.contains("\r\nContent-Length: 4\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+}
+
+#[test]
+fn test_graceful_shutdown() {
+    let port = free_port();
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let app: App = App::new_with_config(2, ())
+        .with_route("/", handler("fast"))
+        .with_route("/slow", |_, _| {
+            sleep(Duration::from_millis(300));
+            Response::new(StatusCode::OK, "slow")
+        })
+        .with_shutdown(shutdown.clone())
+        .with_drain_timeout(Some(Duration::from_secs(5)));
+
+    let server = spawn(move || app.run(("127.0.0.1", port)).unwrap());
+
+    let mut idle = connect(port);
+    idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    idle.write_all(b"GET / HTTP/1.1\r\nConnection: Keep-Alive\r\n\r\n")
+        .unwrap();
+
+    let response = Response::from_stream(&mut idle).unwrap();
+    assert_eq!(response.body, b"fast");
+    assert_eq!(
+        response.headers.get(HeaderType::Connection),
+        Some("Keep-Alive")
+    );
+
+    let mut busy = connect(port);
+    busy.write_all(b"GET /slow HTTP/1.1\r\nConnection: Keep-Alive\r\n\r\n")
+        .unwrap();
+
+    // Shut down while the slow request is being handled
+    sleep(Duration::from_millis(100));
+    shutdown.store(true, Ordering::Relaxed);
+
+    let response = Response::from_stream(&mut busy).unwrap();
+    assert_eq!(response.body, b"slow");
+    assert_eq!(response.headers.get(HeaderType::Connection), Some("Close"));
+
+    // The idle keep-alive connection is closed without a response
+    assert_eq!(idle.read(&mut [0; 1]).unwrap(), 0);
+
+    server.join().unwrap();
+    assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+}
+
+#[test]
+fn test_custom_connection_handler() {
+    fn connection_handler(
+        mut stream: Stream,
+        _: Arc<Vec<SubApp<()>>>,
+        _: Arc<SubApp<()>>,
+        _: Arc<ErrorHandler>,
+        _: Arc<()>,
+        _: MonitorConfig,
+        config: &ConnectionConfig,
+    ) {
+        let body = format!("{:?} {:?}", config.timeout, config.max_body_size);
+        let response = Response::new(StatusCode::OK, body);
+        let bytes: Vec<u8> = response.into();
+        stream.write_all(&bytes).unwrap();
+    }
+
+    let app: App = App::new_with_config(2, ())
+        .with_connection_timeout(Some(Duration::from_secs(1)))
+        .with_max_body_size(Some(10))
+        .with_custom_connection_handler(connection_handler);
+
+    let mut response = String::new();
+    send(app, "").read_to_string(&mut response).unwrap();
+    assert!(response.contains("\r\n\r\nSome(1s) Some(10)"));
+}
@@ -0,0 +1,123 @@
+use crate::http::headers::HeaderType;
+use crate::http::{Response, StatusCode};
+use crate::listener::Listener;
+use crate::App;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::sleep;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    let app: App = App::new()
+        .with_route("/", |_, _| async { Response::new(StatusCode::OK, "fast") })
+        .with_route("/slow", |_, _| async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Response::new(StatusCode::OK, "slow")
+        })
+        .with_shutdown(shutdown_rx)
+        .with_drain_timeout(Some(Duration::from_secs(5)));
+
+    let client = tokio::task::spawn_blocking(move || {
+        let connect = || loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) => sleep(Duration::from_millis(10)),
+            }
+        };
+
+        let mut idle = connect();
+        idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        idle.write_all(b"GET / HTTP/1.1\r\nConnection: Keep-Alive\r\n\r\n")
+            .unwrap();
+
+        let response = Response::from_stream(&mut idle).unwrap();
+        assert_eq!(response.body, b"fast");
+
+        let mut busy = connect();
+        busy.write_all(b"GET /slow HTTP/1.1\r\nConnection: Keep-Alive\r\n\r\n")
+            .unwrap();
+
+        // Shut down while the slow request is being handled
+        sleep(Duration::from_millis(100));
+        shutdown_tx.send(()).unwrap();
+
+        let response = Response::from_stream(&mut busy).unwrap();
+        assert_eq!(response.body, b"slow");
+        assert_eq!(response.headers.get(HeaderType::Connection), Some("Close"));
+
+        // The idle keep-alive connection is closed without a response
+        assert_eq!(idle.read(&mut [0; 1]).unwrap(), 0);
+    });
+
+    app.run(("127.0.0.1", port)).await.unwrap();
+    client.await.unwrap();
+
+    assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+}
+
+#[tokio::test]
+async fn test_multiple_listeners() {
+    let ports: Vec<u16> = (0..2)
+        .map(|_| {
+            TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port()
+        })
+        .collect();
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    let app: App = App::new()
+        .with_route("/*", |_, _| async {
+            Response::new(StatusCode::OK, "shared")
+        })
+        .with_listener(Listener::http(format!("127.0.0.1:{}", ports[0])))
+        .with_listener(Listener::redirect(format!("127.0.0.1:{}", ports[1]), 443))
+        .with_shutdown(shutdown_rx);
+
+    let cloned_ports = ports.clone();
+    let client = tokio::task::spawn_blocking(move || {
+        let get = |port: u16| {
+            let mut stream = loop {
+                match TcpStream::connect(("127.0.0.1", port)) {
+                    Ok(stream) => break stream,
+                    Err(_) => sleep(Duration::from_millis(10)),
+                }
+            };
+
+            stream
+                .write_all(b"GET /page HTTP/1.1\r\nHost: example.com\r\n\r\n")
+                .unwrap();
+            Response::from_stream(&mut stream).unwrap()
+        };
+
+        assert_eq!(get(cloned_ports[0]).body, b"shared");
+        assert_eq!(
+            get(cloned_ports[1]).headers.get(HeaderType::Location),
+            Some("https://example.com/page")
+        );
+
+        shutdown_tx.send(()).unwrap();
+    });
+
+    app.run_listeners().await.unwrap();
+    client.await.unwrap();
+
+    for port in ports {
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+#![allow(unused_imports)]
+use crate::deflate::{adler32, crc32, deflate, gzip, zlib};
+use crate::http::compression::{apply_compression, is_compressible, Encoding};
+use crate::http::headers::HeaderType;
+use crate::http::{Request, Response, StatusCode};
+use crate::tests::mock_request;
+
+const LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// A minimal decoder for DEFLATE streams consisting of a single block with fixed Huffman codes,
+///   which is what the encoder produces.
+fn inflate_fixed(data: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let mut read_bits = |count: u32| -> usize {
+        let mut value = 0;
+        for i in 0..count {
+            let bit = (data[position / 8] >> (position % 8)) & 1;
+            value |= (bit as usize) << i;
+            position += 1;
+        }
+        value
+    };
+
+    assert_eq!(read_bits(1), 1);
+    assert_eq!(read_bits(2), 1);
+
+    let mut output: Vec<u8> = Vec::new();
+
+    loop {
+        // Fixed Huffman codes are packed most significant bit first
+        let mut code = 0;
+        let mut length = 0;
+        let symbol = loop {
+            code = (code << 1) | read_bits(1);
+            length += 1;
+
+            match (length, code) {
+                (7, 0..=0x17) => break code + 256,
+                (8, 0x30..=0xbf) => break code - 0x30,
+                (8, 0xc0..=0xc7) => break code - 0xc0 + 280,
+                (9, 0x190..=0x1ff) => break code - 0x190 + 144,
+                (9, _) => panic!("invalid code"),
+                _ => (),
+            }
+        };
+
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => break,
+            _ => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] + read_bits(LENGTH_EXTRA_BITS[index]);
+
+                let mut distance_code = 0;
+                for _ in 0..5 {
+                    distance_code = (distance_code << 1) | read_bits(1);
+                }
+                let distance =
+                    DISTANCE_BASE[distance_code] + read_bits(DISTANCE_EXTRA_BITS[distance_code]);
+
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+
+    output
+}
+
+fn test_data() -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for i in 0..2000 {
+        data.extend(format!("{{\"id\": {}, \"name\": \"item {}\"}},", i, i % 7).as_bytes());
+    }
+
+    data.extend((0..=255).collect::<Vec<u8>>());
+    data
+}
+
+fn request_with_headers(headers: &[(HeaderType, &str)]) -> Request {
+    let mut request = Request {
+        uri: "/api/items".into(),
+        ..mock_request()
+    };
+
+    for (name, value) in headers {
+        request.headers.add(name, value);
+    }
+
+    request
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(adler32(b""), 1);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+}
+
+#[test]
+fn test_deflate_round_trip() {
+    let data = test_data();
+    let compressed = deflate(&data);
+
+    assert!(compressed.len() < data.len() / 4);
+    assert_eq!(inflate_fixed(&compressed), data);
+
+    assert_eq!(inflate_fixed(&deflate(b"")), b"");
+    assert_eq!(inflate_fixed(&deflate(b"a")), b"a");
+    assert_eq!(inflate_fixed(&deflate(&[0; 100000])), vec![0; 100000]);
+}
+
+#[test]
+fn test_gzip_and_zlib_formats() {
+    let data = test_data();
+
+    let gzipped = gzip(&data);
+    assert_eq!(&gzipped[..3], &[0x1f, 0x8b, 0x08]);
+    assert_eq!(inflate_fixed(&gzipped[10..gzipped.len() - 8]), data);
+    assert_eq!(
+        &gzipped[gzipped.len() - 8..gzipped.len() - 4],
+        &crc32(&data).to_le_bytes()
+    );
+    assert_eq!(
+        &gzipped[gzipped.len() - 4..],
+        &(data.len() as u32).to_le_bytes()
+    );
+
+    let zlibbed = zlib(&data);
+    assert_eq!(((zlibbed[0] as u16) << 8 | zlibbed[1] as u16) % 31, 0);
+    assert_eq!(inflate_fixed(&zlibbed[2..zlibbed.len() - 4]), data);
+    assert_eq!(&zlibbed[zlibbed.len() - 4..], &adler32(&data).to_be_bytes());
+}
+
+#[test]
+fn test_negotiate_encoding() {
+    assert_eq!(
+        Encoding::negotiate("gzip, deflate, br"),
+        Some(Encoding::Gzip)
+    );
+    assert_eq!(Encoding::negotiate("deflate"), Some(Encoding::Deflate));
+    assert_eq!(
+        Encoding::negotiate("gzip;q=0.5, deflate;q=0.8"),
+        Some(Encoding::Deflate)
+    );
+    assert_eq!(Encoding::negotiate("gzip;q=0, *"), Some(Encoding::Deflate));
+    assert_eq!(Encoding::negotiate("*;q=0"), None);
+    assert_eq!(Encoding::negotiate("br, identity"), None);
+    assert_eq!(Encoding::negotiate(""), None);
+}
+
+#[test]
+fn test_is_compressible() {
+    assert!(is_compressible("text/html"));
+    assert!(is_compressible("application/json; charset=utf-8"));
+    assert!(is_compressible("image/svg+xml"));
+    assert!(!is_compressible("image/png"));
+    assert!(!is_compressible("application/zip"));
+}
+
+#[test]
+fn test_apply_compression() {
+    let data = test_data();
+    let request = request_with_headers(&[(HeaderType::AcceptEncoding, "gzip, deflate")]);
+    let response = Response::new(StatusCode::OK, data.clone())
+        .with_header(HeaderType::ContentType, "application/json")
+        .with_header(HeaderType::ETag, "\"abc\"");
+
+    let response = apply_compression(&request, response, 1024);
+
+    assert_eq!(
+        response.headers.get(HeaderType::ContentEncoding),
+        Some("gzip")
+    );
+    assert_eq!(
+        response.headers.get(HeaderType::Vary),
+        Some("Accept-Encoding")
+    );
+    assert_eq!(response.headers.get(HeaderType::ETag), Some("W/\"abc\""));
+    assert_eq!(response.body, gzip(&data));
+
+    let request = request_with_headers(&[]);
+    let response = Response::new(StatusCode::OK, data.clone())
+        .with_header(HeaderType::ContentType, "application/json")
+        .with_header(HeaderType::Vary, "Origin");
+
+    let response = apply_compression(&request, response, 1024);
+
+    assert_eq!(response.headers.get(HeaderType::ContentEncoding), None);
+    assert_eq!(
+        response.headers.get(HeaderType::Vary),
+        Some("Origin, Accept-Encoding")
+    );
+    assert_eq!(response.body, data);
+}
+
+#[test]
+fn test_compression_ignored() {
+    let data = test_data();
+    let request = request_with_headers(&[(HeaderType::AcceptEncoding, "gzip")]);
+
+    let too_small = apply_compression(
+        &request,
+        Response::new(StatusCode::OK, b"hello").with_header(HeaderType::ContentType, "text/plain"),
+        1024,
+    );
+    assert_eq!(too_small.headers.get(HeaderType::ContentEncoding), None);
+    assert_eq!(too_small.headers.get(HeaderType::Vary), None);
+
+    let not_compressible = apply_compression(
+        &request,
+        Response::new(StatusCode::OK, data.clone())
+            .with_header(HeaderType::ContentType, "image/png"),
+        1024,
+    );
+    assert_eq!(
+        not_compressible.headers.get(HeaderType::ContentEncoding),
+        None
+    );
+    assert_eq!(not_compressible.body, data);
+
+    let already_encoded = apply_compression(
+        &request,
+        Response::new(StatusCode::OK, data.clone())
+            .with_header(HeaderType::ContentType, "text/plain")
+            .with_header(HeaderType::ContentEncoding, "br"),
+        1024,
+    );
+    assert_eq!(already_encoded.body, data);
+}
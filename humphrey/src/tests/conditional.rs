@@ -0,0 +1,141 @@
+#![allow(unused_imports)]
+use crate::http::conditional::{apply_conditional, Validators};
+use crate::http::headers::HeaderType;
+use crate::http::method::Method;
+use crate::http::{Request, Response, StatusCode};
+use crate::tests::mock_request;
+
+fn request_with_headers(method: Method, headers: &[(HeaderType, &str)]) -> Request {
+    let mut request = Request {
+        method,
+        uri: "/index.html".into(),
+        ..mock_request()
+    };
+
+    for (name, value) in headers {
+        request.headers.add(name, value);
+    }
+
+    request
+}
+
+fn full_response() -> Response {
+    Response::new(StatusCode::OK, b"<h1>Hello</h1>")
+        .with_header(HeaderType::ContentType, "text/html")
+        .with_header(HeaderType::CacheControl, "max-age=60")
+}
+
+fn validators() -> Validators {
+    Validators {
+        etag: Some("\"abc-e\"".into()),
+        last_modified: Some(1628437415),
+    }
+}
+
+#[test]
+fn test_validators_added() {
+    let request = request_with_headers(Method::Get, &[]);
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"<h1>Hello</h1>");
+    assert_eq!(response.headers.get(HeaderType::ETag), Some("\"abc-e\""));
+    assert_eq!(
+        response.headers.get(HeaderType::LastModified),
+        Some("Sun, 08 Aug 2021 15:43:35 GMT")
+    );
+}
+
+#[test]
+fn test_if_none_match() {
+    let request = request_with_headers(
+        Method::Get,
+        &[(HeaderType::IfNoneMatch, "\"xyz\", W/\"abc-e\"")],
+    );
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::NotModified);
+    assert!(response.body.is_empty());
+    assert_eq!(response.headers.get(HeaderType::ETag), Some("\"abc-e\""));
+    assert_eq!(
+        response.headers.get(HeaderType::CacheControl),
+        Some("max-age=60")
+    );
+    assert_eq!(response.headers.get(HeaderType::ContentType), None);
+
+    let request = request_with_headers(Method::Get, &[(HeaderType::IfNoneMatch, "*")]);
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::NotModified);
+
+    let request = request_with_headers(Method::Head, &[(HeaderType::IfNoneMatch, "*")]);
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::NotModified);
+
+    let request = request_with_headers(Method::Get, &[(HeaderType::IfNoneMatch, "\"xyz\"")]);
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::OK);
+}
+
+#[test]
+fn test_if_modified_since() {
+    let request = request_with_headers(
+        Method::Get,
+        &[(HeaderType::IfModifiedSince, "Sun, 08 Aug 2021 15:43:35 GMT")],
+    );
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::NotModified);
+    assert_eq!(
+        response.headers.get(HeaderType::LastModified),
+        Some("Sun, 08 Aug 2021 15:43:35 GMT")
+    );
+
+    let request = request_with_headers(
+        Method::Get,
+        &[(HeaderType::IfModifiedSince, "Sun, 08 Aug 2021 15:43:34 GMT")],
+    );
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let request = request_with_headers(Method::Get, &[(HeaderType::IfModifiedSince, "yesterday")]);
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::OK);
+}
+
+#[test]
+fn test_if_none_match_takes_precedence() {
+    let request = request_with_headers(
+        Method::Get,
+        &[
+            (HeaderType::IfNoneMatch, "\"xyz\""),
+            (HeaderType::IfModifiedSince, "Sun, 08 Aug 2021 15:43:35 GMT"),
+        ],
+    );
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::OK);
+}
+
+#[test]
+fn test_conditional_ignored() {
+    let request = request_with_headers(Method::Post, &[(HeaderType::IfNoneMatch, "*")]);
+    let response = apply_conditional(&request, full_response(), &validators());
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.headers.get(HeaderType::ETag), Some("\"abc-e\""));
+
+    let request = request_with_headers(Method::Get, &[(HeaderType::IfNoneMatch, "*")]);
+    let response = apply_conditional(
+        &request,
+        Response::empty(StatusCode::NotFound),
+        &validators(),
+    );
+
+    assert_eq!(response.status_code, StatusCode::NotFound);
+    assert_eq!(response.headers.get(HeaderType::ETag), None);
+}
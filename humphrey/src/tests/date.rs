@@ -1,5 +1,7 @@
 #[allow(unused_imports)]
-use crate::http::date::DateTime;
+use crate::http::date::{DateTime, DateTimeError};
+
+use std::convert::TryFrom;
 
 #[test]
 fn test_date_from_timestamp() {
@@ -34,3 +36,56 @@ fn test_date_from_timestamp() {
     assert_eq!(output_4, expected_output_4);
     assert_eq!(output_5, expected_output_5);
 }
+
+#[test]
+fn test_date_from_string() {
+    let imf_fixdate = DateTime::try_from("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+    let rfc_850 = DateTime::try_from("Sunday, 06-Nov-94 08:49:37 GMT").unwrap();
+    let asctime = DateTime::try_from("Sun Nov  6 08:49:37 1994").unwrap();
+
+    assert_eq!(imf_fixdate.get_timestamp(), 784111777);
+    assert_eq!(rfc_850.get_timestamp(), 784111777);
+    assert_eq!(asctime.get_timestamp(), 784111777);
+
+    let leap_day = DateTime::try_from("Sat, 29 Feb 2020 12:34:56 GMT").unwrap();
+    let before_epoch = DateTime::try_from("Fri, 28 Sep 1066 10:12:51 GMT").unwrap();
+
+    assert_eq!(leap_day.get_timestamp(), 1582979696);
+    assert_eq!(before_epoch.get_timestamp(), -28504100829);
+}
+
+#[test]
+fn test_invalid_date_from_string() {
+    assert!(matches!(
+        DateTime::try_from("Sun, 06 Nov 1994 08:49:37"),
+        Err(DateTimeError)
+    ));
+    assert!(matches!(
+        DateTime::try_from("Sun, 06 Foo 1994 08:49:37 GMT"),
+        Err(DateTimeError)
+    ));
+    assert!(matches!(
+        DateTime::try_from("Sun, 06 Nov 1994 25:49:37 GMT"),
+        Err(DateTimeError)
+    ));
+    assert!(matches!(
+        DateTime::try_from("Fri, 31 Feb 2020 12:00:00 GMT"),
+        Err(DateTimeError)
+    ));
+    assert!(matches!(
+        DateTime::try_from("Sun, 06 Nov 1994 -1:49:37 GMT"),
+        Err(DateTimeError)
+    ));
+    assert!(matches!(
+        DateTime::try_from("Sun, 06 Nov 10000 08:49:37 GMT"),
+        Err(DateTimeError)
+    ));
+    assert!(matches!(
+        DateTime::try_from("Sun, 06 Nov 99999999999999999 08:49:37 GMT"),
+        Err(DateTimeError)
+    ));
+    assert!(matches!(
+        DateTime::try_from("Sun Nov  6 08:49:37 -9223372036854775808"),
+        Err(DateTimeError)
+    ));
+}
@@ -0,0 +1,246 @@
+use crate::http::form::{FileContent, FormData, FormError, Multipart};
+use crate::http::headers::HeaderType;
+use crate::http::method::Method;
+use crate::http::Request;
+use crate::tests::mock_request;
+
+const BODY: &[u8] = b"preamble\r\n\
+--boundary\r\n\
+Content-Disposition: form-data; name=\"title\"\r\n\
+\r\n\
+Hello, world!\r\n\
+--boundary\r\n\
+Content-Disposition: form-data; name=\"tag\"\r\n\
+\r\n\
+a\r\n\
+--boundary\r\n\
+Content-Disposition: form-data; name=\"tag\"\r\n\
+\r\n\
+b\r\n\
+--boundary\r\n\
+Content-Disposition: form-data; name=\"upload\"; filename=\"a;b.txt\"\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+line one\r\n--boundar\r\nline two\r\n\
+--boundary--\r\n\
+epilogue";
+
+fn request(content_type: &str, content: &[u8]) -> Request {
+    let mut request = Request {
+        method: Method::Post,
+        content: Some(content.to_vec()),
+        ..mock_request()
+    };
+
+    request.headers.add(HeaderType::ContentType, content_type);
+    request
+}
+
+#[test]
+fn test_query_params() {
+    let mut request = request("text/plain", b"");
+    request.query = "a=1&b=hello+world&a=2&c=%26%3D&flag&&d=".into();
+
+    let query = request.query_params();
+
+    assert_eq!(query.get("a"), Some("1"));
+    assert_eq!(query.get_all("a"), vec!["1", "2"]);
+    assert_eq!(query.get("b"), Some("hello world"));
+    assert_eq!(query.get("c"), Some("&="));
+    assert_eq!(query.get("flag"), Some(""));
+    assert_eq!(query.get("d"), Some(""));
+    assert_eq!(query.get("e"), None);
+    assert!(query.contains("flag"));
+    assert_eq!(query.len(), 6);
+}
+
+#[test]
+fn test_form() {
+    let request = request(
+        "application/x-www-form-urlencoded; charset=utf-8",
+        b"name=Humphrey+Server&lang=en%2DGB&lang=fr",
+    );
+
+    let form = request.form().unwrap();
+
+    assert_eq!(form.get("name"), Some("Humphrey Server"));
+    assert_eq!(form.get_all("lang"), vec!["en-GB", "fr"]);
+    assert_eq!(
+        form.iter().collect::<Vec<_>>(),
+        vec![
+            ("name", "Humphrey Server"),
+            ("lang", "en-GB"),
+            ("lang", "fr")
+        ]
+    );
+}
+
+#[test]
+fn test_form_wrong_content_type() {
+    let request = request("application/json", b"{}");
+
+    assert_eq!(request.form(), Err(FormError::UnsupportedContentType));
+    assert_eq!(request.multipart(), Err(FormError::UnsupportedContentType));
+}
+
+#[test]
+fn test_multipart() {
+    let request = request("multipart/form-data; boundary=\"boundary\"", BODY);
+
+    let multipart = request.multipart().unwrap();
+
+    let mut fields = FormData::new();
+    fields.add("title", "Hello, world!");
+    fields.add("tag", "a");
+    fields.add("tag", "b");
+
+    assert_eq!(multipart.fields, fields);
+    assert_eq!(multipart.files.len(), 1);
+
+    let file = &multipart.files[0];
+    assert_eq!(file.name, "upload");
+    assert_eq!(file.filename, "a;b.txt");
+    assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(
+        file.content,
+        FileContent::Memory(b"line one\r\n--boundar\r\nline two".to_vec())
+    );
+}
+
+#[test]
+fn test_multipart_invalid() {
+    let truncated = &BODY[..BODY.len() - 20];
+    assert_eq!(
+        Multipart::parse(truncated, "boundary"),
+        Err(FormError::Invalid)
+    );
+
+    let no_disposition = b"--boundary\r\nContent-Type: text/plain\r\n\r\nhi\r\n--boundary--";
+    assert_eq!(
+        Multipart::parse(no_disposition, "boundary"),
+        Err(FormError::Invalid)
+    );
+
+    let request = request("multipart/form-data", BODY);
+    assert_eq!(request.multipart(), Err(FormError::Invalid));
+}
+
+#[test]
+fn test_multipart_limits() {
+    let field = "a".repeat(1024 * 1024 + 1);
+    let body = format!(
+        "--boundary\r\nContent-Disposition: form-data; name=\"large\"\r\n\r\n{}\r\n--boundary--",
+        field
+    );
+    assert_eq!(
+        Multipart::parse(body.as_bytes(), "boundary"),
+        Err(FormError::Invalid)
+    );
+
+    let part = "--boundary\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\na\r\n";
+    let body = format!("{}--boundary--", part.repeat(1024));
+    assert_eq!(
+        Multipart::parse(body.as_bytes(), "boundary").map(|multipart| multipart.fields.len()),
+        Ok(1024)
+    );
+
+    let body = format!("{}--boundary--", part.repeat(1025));
+    assert_eq!(
+        Multipart::parse(body.as_bytes(), "boundary"),
+        Err(FormError::Invalid)
+    );
+}
+
+#[cfg(not(feature = "tokio"))]
+#[test]
+fn test_multipart_streaming_invalid() {
+    use crate::http::request::{BodyLength, RequestBody};
+
+    let directory = std::env::temp_dir().join(format!("humphrey-form-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    // The file is written before the invalid part is reached
+    let body = b"--boundary\r\n\
+Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n\
+\r\n\
+content\r\n\
+--boundary\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+no disposition\r\n\
+--boundary--\r\n";
+
+    let request = request("multipart/form-data; boundary=boundary", b"");
+    let mut reader = &body[..];
+    let mut body = RequestBody::new(&mut reader, BodyLength::Fixed(body.len() as u64));
+
+    assert_eq!(
+        Multipart::from_body(&request, &mut body, &directory),
+        Err(FormError::Invalid)
+    );
+    assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 0);
+
+    std::fs::remove_dir(&directory).unwrap();
+}
+
+#[cfg(not(feature = "tokio"))]
+#[test]
+fn test_multipart_streaming() {
+    use crate::http::request::RequestBody;
+    use crate::http::{Response, StatusCode};
+    use crate::tests::app::serve;
+    use crate::App;
+
+    use std::sync::Arc;
+
+    // Large enough to be split across several reads, including inside the closing boundary.
+    let data: String = (0..100_000)
+        .map(|i| (b'a' + (i % 26) as u8) as char)
+        .collect();
+
+    let body = format!(
+        "--boundary\r\n\
+Content-Disposition: form-data; name=\"title\"\r\n\
+\r\n\
+Large\r\n\
+--boundary\r\n\
+Content-Disposition: form-data; name=\"upload\"; filename=\"large.txt\"\r\n\
+\r\n\
+{}\r\n\
+--boundary--\r\n",
+        data
+    );
+
+    let handler = |request: Request, body: &mut RequestBody, _: Arc<()>| {
+        let multipart = Multipart::from_body(&request, body, std::env::temp_dir()).unwrap();
+
+        match &multipart.files[0].content {
+            FileContent::Disk(path) => {
+                let content = std::fs::read(path).unwrap();
+                std::fs::remove_file(path).unwrap();
+
+                let mut response = Response::new(StatusCode::OK, content);
+                response
+                    .headers
+                    .add("X-Title", multipart.fields.get("title").unwrap());
+                response
+            }
+            FileContent::Memory(_) => Response::empty(StatusCode::InternalError),
+        }
+    };
+
+    let app: App = App::new_with_config(1, ()).with_streaming_route("/upload", handler);
+
+    let response = serve(
+        app,
+        &format!(
+            "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=boundary\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ),
+    );
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.headers.get("X-Title"), Some("Large"));
+    assert_eq!(response.body, data.as_bytes());
+}
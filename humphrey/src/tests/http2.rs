@@ -0,0 +1,1008 @@
+use crate::http::headers::HeaderType;
+use crate::http::method::Method;
+use crate::http::request::RequestError;
+use crate::http::{Request, Response, StatusCode};
+use crate::http2::connection::{Connection, PREFACE};
+use crate::http2::frame::{ErrorCode, Frame, FrameError, FrameHeader, Priority, Setting};
+use crate::http2::hpack::{self, Decoder, HpackError, DEFAULT_TABLE_SIZE};
+use crate::listener::Listener;
+use crate::tls::CertResolver;
+
+use rustls::{Certificate, ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use rustls_pemfile::certs;
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[cfg(not(feature = "tokio"))]
+use crate::App;
+#[cfg(not(feature = "tokio"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(feature = "tokio"))]
+use std::thread::spawn;
+
+const CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/certs/");
+
+fn hex(s: &str) -> Vec<u8> {
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+fn fields(fields: &[(&str, &str)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    fields
+        .iter()
+        .map(|(name, value)| (name.as_bytes().to_vec(), value.as_bytes().to_vec()))
+        .collect()
+}
+
+fn encode(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut block = Vec::new();
+
+    for (name, value) in fields {
+        hpack::encode(name.as_bytes(), value.as_bytes(), &mut block);
+    }
+
+    block
+}
+
+pub fn headers(stream_id: u32, fields: &[(&str, &str)], end_stream: bool) -> Frame {
+    Frame::Headers {
+        stream_id,
+        block: encode(fields),
+        end_stream,
+        end_headers: true,
+        priority: None,
+    }
+}
+
+pub fn data(stream_id: u32, data: &[u8], end_stream: bool) -> Frame {
+    Frame::Data {
+        stream_id,
+        data: data.to_vec(),
+        end_stream,
+        flow_controlled_length: data.len() as u32,
+    }
+}
+
+pub fn get(path: &str) -> Vec<(&str, &str)> {
+    vec![
+        (":method", "GET"),
+        (":scheme", "https"),
+        (":path", path),
+        (":authority", "localhost"),
+    ]
+}
+
+pub fn write_frames(frames: &[Frame]) -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    for frame in frames {
+        frame.write_to(&mut buf);
+    }
+
+    buf
+}
+
+fn parse_frames(mut buf: &[u8]) -> Vec<Frame> {
+    let mut frames = Vec::new();
+
+    while let Some(header) = FrameHeader::parse(buf) {
+        let end = 9 + header.length as usize;
+        frames.push(Frame::parse(header, &buf[9..end]).unwrap());
+        buf = &buf[end..];
+    }
+
+    assert!(buf.is_empty());
+
+    frames
+}
+
+/// Finds a free port to listen on.
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Creates an HTTPS listener on the given port with the test certificate.
+pub fn https_listener(port: u16) -> Listener {
+    let resolver = CertResolver::new()
+        .with_default_cert(
+            format!("{}localhost.pem", CERTS),
+            format!("{}localhost.key", CERTS),
+        )
+        .unwrap();
+
+    Listener::https(format!("127.0.0.1:{}", port)).with_cert_resolver(Arc::new(resolver))
+}
+
+/// Connects to the server on the given port, negotiating HTTP/2 and sending the client's preface and settings.
+pub fn connect(port: u16) -> StreamOwned<ClientConnection, TcpStream> {
+    let mut reader = BufReader::new(File::open(format!("{}ca.pem", CERTS)).unwrap());
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(&Certificate(certs(&mut reader).unwrap().remove(0)))
+        .unwrap();
+
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+
+    let connection =
+        ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap();
+
+    let socket = loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(10)),
+        }
+    };
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let mut stream = StreamOwned::new(connection, socket);
+
+    let mut preface = PREFACE.to_vec();
+    preface.extend(write_frames(&[Frame::Settings {
+        ack: false,
+        settings: Vec::new(),
+    }]));
+    stream.write_all(&preface).unwrap();
+
+    assert_eq!(stream.conn.alpn_protocol(), Some(&b"h2"[..]));
+
+    stream
+}
+
+/// Reads the next frame from the server, keeping any data after it in the buffer.
+pub fn read_frame(
+    stream: &mut StreamOwned<ClientConnection, TcpStream>,
+    buf: &mut Vec<u8>,
+) -> Frame {
+    loop {
+        if let Some(header) = FrameHeader::parse(buf) {
+            let end = 9 + header.length as usize;
+
+            if buf.len() >= end {
+                let frame = Frame::parse(header, &buf[9..end]).unwrap();
+                buf.drain(..end);
+
+                return frame;
+            }
+        }
+
+        let mut chunk = [0; 4096];
+        let length = stream.read(&mut chunk).unwrap();
+        assert_ne!(length, 0);
+        buf.extend(&chunk[..length]);
+    }
+}
+
+/// Opens a connection with the client's preface and settings, discarding the server's settings.
+fn open(max_body_size: Option<usize>) -> Connection {
+    let mut connection = Connection::new("1.2.3.4:5678".parse().unwrap(), max_body_size);
+
+    let mut input = PREFACE.to_vec();
+    input.extend(write_frames(&[Frame::Settings {
+        ack: false,
+        settings: Vec::new(),
+    }]));
+
+    assert!(connection.receive(&input).unwrap().is_empty());
+    connection.take_output();
+
+    connection
+}
+
+/// Sends the frames to the connection, returning the requests it received.
+fn receive(connection: &mut Connection, frames: &[Frame]) -> Vec<(u32, Request)> {
+    connection
+        .receive(&write_frames(frames))
+        .unwrap()
+        .into_iter()
+        .map(|(stream_id, request)| (stream_id, request.unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_hpack_decode() {
+    // RFC 7541 Appendix C.3, requests without Huffman coding
+    let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+
+    assert_eq!(
+        decoder.decode(&hex("828684410f7777772e6578616d706c652e636f6d")),
+        Ok(fields(&[
+            (":method", "GET"),
+            (":scheme", "http"),
+            (":path", "/"),
+            (":authority", "www.example.com"),
+        ]))
+    );
+    assert_eq!(
+        decoder.decode(&hex("828684be58086e6f2d6361636865")),
+        Ok(fields(&[
+            (":method", "GET"),
+            (":scheme", "http"),
+            (":path", "/"),
+            (":authority", "www.example.com"),
+            ("cache-control", "no-cache"),
+        ]))
+    );
+    assert_eq!(
+        decoder.decode(&hex(
+            "828785bf400a637573746f6d2d6b65790c637573746f6d2d76616c7565"
+        )),
+        Ok(fields(&[
+            (":method", "GET"),
+            (":scheme", "https"),
+            (":path", "/index.html"),
+            (":authority", "www.example.com"),
+            ("custom-key", "custom-value"),
+        ]))
+    );
+}
+
+#[test]
+fn test_hpack_huffman_decode() {
+    // RFC 7541 Appendix C.4, requests with Huffman coding
+    let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+
+    assert_eq!(
+        decoder.decode(&hex("828684418cf1e3c2e5f23a6ba0ab90f4ff")),
+        Ok(fields(&[
+            (":method", "GET"),
+            (":scheme", "http"),
+            (":path", "/"),
+            (":authority", "www.example.com"),
+        ]))
+    );
+    assert_eq!(
+        decoder.decode(&hex("828684be5886a8eb10649cbf")),
+        Ok(fields(&[
+            (":method", "GET"),
+            (":scheme", "http"),
+            (":path", "/"),
+            (":authority", "www.example.com"),
+            ("cache-control", "no-cache"),
+        ]))
+    );
+    assert_eq!(
+        decoder.decode(&hex("828785bf408825a849e95ba97d7f8925a849e95bb8e8b4bf")),
+        Ok(fields(&[
+            (":method", "GET"),
+            (":scheme", "https"),
+            (":path", "/index.html"),
+            (":authority", "www.example.com"),
+            ("custom-key", "custom-value"),
+        ]))
+    );
+}
+
+#[test]
+fn test_hpack_encode() {
+    // Fields in the static table are indexed
+    assert_eq!(encode(&[(":method", "GET")]), vec![0x82]);
+    assert_eq!(encode(&[(":status", "200")]), vec![0x88]);
+
+    let original = [
+        (":status", "404"),
+        ("content-type", "text/html; charset=utf-8"),
+        ("x-custom", "value with spaces and symbols !#$%&'*+-.^_`|~"),
+        ("x-empty", ""),
+        ("x-unicode", "caf\u{e9}"),
+    ];
+
+    let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+    assert_eq!(decoder.decode(&encode(&original)), Ok(fields(&original)));
+}
+
+#[test]
+fn test_hpack_errors() {
+    let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+
+    assert_eq!(decoder.decode(&[0x80]), Err(HpackError::Index));
+    assert_eq!(decoder.decode(&[0xbe]), Err(HpackError::Index));
+    assert_eq!(
+        decoder.decode(&[0x40, 0x05, b'a']),
+        Err(HpackError::Truncated)
+    );
+    assert_eq!(
+        decoder.decode(&[0x3f, 0xe2, 0x1f]),
+        Err(HpackError::TableSize)
+    );
+    assert_eq!(
+        decoder.decode(&[0x40, 0x81, 0xff]),
+        Err(HpackError::Huffman)
+    );
+
+    // A single large entry referenced many times
+    let mut block = vec![0x40, 0x01, b'a', 0x64];
+    block.extend_from_slice(&[b'b'; 100]);
+    block.extend_from_slice(&[0xbe; 10]);
+
+    let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE).with_max_list_size(1024);
+    assert_eq!(decoder.decode(&block), Err(HpackError::ListSize));
+    assert_eq!(decoder.decode(&block[..block.len() - 4]).unwrap().len(), 7);
+}
+
+#[test]
+fn test_frame_round_trip() {
+    let frames = [
+        data(1, b"hello", true),
+        Frame::Headers {
+            stream_id: 3,
+            block: vec![0x82, 0x86],
+            end_stream: false,
+            end_headers: false,
+            priority: Some(Priority {
+                dependency: 1,
+                exclusive: true,
+                weight: 16,
+            }),
+        },
+        Frame::RstStream {
+            stream_id: 5,
+            error: ErrorCode::Cancel,
+        },
+        Frame::Settings {
+            ack: false,
+            settings: vec![
+                Setting::InitialWindowSize(1048576),
+                Setting::Unknown(0x99, 1),
+            ],
+        },
+        Frame::Ping {
+            ack: true,
+            data: *b"12345678",
+        },
+        Frame::GoAway {
+            last_stream_id: 7,
+            error: ErrorCode::EnhanceYourCalm,
+            debug_data: b"slow down".to_vec(),
+        },
+        Frame::WindowUpdate {
+            stream_id: 0,
+            increment: 1000,
+        },
+        Frame::Continuation {
+            stream_id: 3,
+            block: vec![0x84],
+            end_headers: true,
+        },
+    ];
+
+    assert_eq!(parse_frames(&write_frames(&frames)), frames);
+}
+
+#[test]
+fn test_frame_errors() {
+    let parse = |kind, flags, stream_id, payload: &[u8]| {
+        let header = FrameHeader {
+            length: payload.len() as u32,
+            kind,
+            flags,
+            stream_id,
+        };
+
+        Frame::parse(header, payload)
+    };
+
+    // `DATA` frames must belong to a stream, and `SETTINGS` frames must not
+    assert_eq!(
+        parse(0x0, 0, 0, b"data"),
+        Err(FrameError::Connection(ErrorCode::ProtocolError))
+    );
+    assert_eq!(
+        parse(0x4, 0, 1, &[]),
+        Err(FrameError::Connection(ErrorCode::ProtocolError))
+    );
+
+    // Padding must be shorter than the payload
+    assert_eq!(
+        parse(0x0, 0x8, 1, &[5, b'a']),
+        Err(FrameError::Connection(ErrorCode::ProtocolError))
+    );
+
+    assert_eq!(
+        parse(0x4, 0x1, 0, &[0; 6]),
+        Err(FrameError::Connection(ErrorCode::FrameSizeError))
+    );
+    assert_eq!(
+        parse(0x6, 0, 0, &[0; 7]),
+        Err(FrameError::Connection(ErrorCode::FrameSizeError))
+    );
+    assert_eq!(
+        parse(0x2, 0, 1, &[0; 4]),
+        Err(FrameError::Stream(1, ErrorCode::FrameSizeError))
+    );
+
+    // Frames of unknown types are ignored
+    assert_eq!(
+        parse(0xfa, 0, 1, b"ignored"),
+        Ok(Frame::Unknown {
+            kind: 0xfa,
+            stream_id: 1
+        })
+    );
+}
+
+#[test]
+fn test_connection_preface() {
+    let mut connection = Connection::new("1.2.3.4:5678".parse().unwrap(), None);
+
+    // The preface and settings may arrive in several parts
+    let mut input = PREFACE.to_vec();
+    input.extend(write_frames(&[Frame::Settings {
+        ack: false,
+        settings: vec![Setting::MaxFrameSize(32768)],
+    }]));
+
+    for part in input.chunks(5) {
+        assert!(connection.receive(part).unwrap().is_empty());
+    }
+
+    assert_eq!(
+        parse_frames(&connection.take_output()),
+        vec![
+            Frame::Settings {
+                ack: false,
+                settings: vec![
+                    Setting::MaxConcurrentStreams(100),
+                    Setting::MaxHeaderListSize(65536),
+                ],
+            },
+            Frame::WindowUpdate {
+                stream_id: 0,
+                increment: 16 * 1024 * 1024 - 65535,
+            },
+            Frame::Settings {
+                ack: true,
+                settings: Vec::new(),
+            },
+        ]
+    );
+
+    // Clients which do not speak HTTP/2 are sent a `GOAWAY` frame
+    let mut connection = Connection::new("1.2.3.4:5678".parse().unwrap(), None);
+    connection.take_output();
+
+    assert_eq!(
+        connection.receive(b"GET / HTTP/1.1\r\n\r\n").err(),
+        Some(ErrorCode::ProtocolError)
+    );
+    assert_eq!(
+        parse_frames(&connection.take_output()),
+        vec![Frame::GoAway {
+            last_stream_id: 0,
+            error: ErrorCode::ProtocolError,
+            debug_data: Vec::new(),
+        }]
+    );
+    assert!(connection.is_finished());
+}
+
+#[test]
+fn test_connection_request() {
+    let mut connection = open(None);
+
+    let mut fields = get("/page?a=1&b=2");
+    fields.extend([("cookie", "a=1"), ("accept", "*/*"), ("cookie", "b=2")]);
+
+    let requests = receive(&mut connection, &[headers(1, &fields, true)]);
+    assert_eq!(requests.len(), 1);
+
+    let (stream_id, request) = &requests[0];
+    assert_eq!(*stream_id, 1);
+    assert_eq!(request.method, Method::Get);
+    assert_eq!(request.uri, "/page");
+    assert_eq!(request.query, "a=1&b=2");
+    assert_eq!(request.version, "HTTP/2.0");
+    assert!(request.https);
+    assert_eq!(request.headers.get(HeaderType::Host), Some("localhost"));
+    assert_eq!(request.headers.get(HeaderType::Accept), Some("*/*"));
+    assert_eq!(request.headers.get(HeaderType::Cookie), Some("a=1; b=2"));
+    assert_eq!(request.content, None);
+
+    let response = Response::empty(StatusCode::OK)
+        .with_header(HeaderType::ContentType, "text/plain")
+        .with_header(HeaderType::Connection, "keep-alive")
+        .with_bytes("Hello, world!");
+    assert!(connection.send_response(1, response).is_none());
+
+    let output = parse_frames(&connection.take_output());
+    assert_eq!(output.len(), 2);
+
+    match &output[0] {
+        Frame::Headers {
+            stream_id: 1,
+            block,
+            end_stream: false,
+            end_headers: true,
+            ..
+        } => {
+            // Headers specific to HTTP/1 connections are not sent
+            let fields = Decoder::new(DEFAULT_TABLE_SIZE).decode(block).unwrap();
+            assert_eq!(
+                fields,
+                self::fields(&[(":status", "200"), ("content-type", "text/plain")])
+            );
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+
+    assert_eq!(output[1], data(1, b"Hello, world!", true));
+    assert!(connection.is_idle());
+}
+
+#[test]
+fn test_connection_request_body() {
+    let mut connection = open(None);
+
+    let fields = [
+        (":method", "POST"),
+        (":scheme", "https"),
+        (":path", "/upload"),
+        (":authority", "localhost"),
+    ];
+
+    let requests = receive(
+        &mut connection,
+        &[
+            headers(1, &fields, false),
+            data(1, b"Hello, ", false),
+            data(1, b"world!", true),
+        ],
+    );
+
+    // Bodies without a `Content-Length` header are given one
+    let request = &requests[0].1;
+    assert_eq!(request.method, Method::Post);
+    assert_eq!(request.content.as_deref(), Some(&b"Hello, world!"[..]));
+    assert_eq!(request.headers.get(HeaderType::ContentLength), Some("13"));
+
+    // Bodies which do not match their `Content-Length` header are rejected
+    let mut fields = fields.to_vec();
+    fields.push(("content-length", "100"));
+
+    let requests = receive(
+        &mut connection,
+        &[headers(3, &fields, false), data(3, b"too short", true)],
+    );
+
+    assert!(requests.is_empty());
+    assert_eq!(
+        parse_frames(&connection.take_output()),
+        vec![Frame::RstStream {
+            stream_id: 3,
+            error: ErrorCode::ProtocolError
+        }]
+    );
+}
+
+#[test]
+fn test_connection_buffer() {
+    let mut connection = open(None);
+
+    let fields = [
+        (":method", "POST"),
+        (":scheme", "https"),
+        (":path", "/upload"),
+        (":authority", "localhost"),
+    ];
+
+    receive(&mut connection, &[headers(1, &fields, false)]);
+
+    for _ in 0..1024 {
+        receive(&mut connection, &[data(1, &[0; 16384], false)]);
+    }
+
+    let requests = receive(&mut connection, &[data(1, b"", true)]);
+    assert_eq!(
+        requests[0].1.content.as_ref().map(Vec::len),
+        Some(16 * 1024 * 1024)
+    );
+
+    // The connection's window is only reopened once the request has been handled
+    let connection_updates = |connection: &mut Connection| -> Vec<u32> {
+        parse_frames(&connection.take_output())
+            .into_iter()
+            .filter_map(|frame| match frame {
+                Frame::WindowUpdate {
+                    stream_id: 0,
+                    increment,
+                } => Some(increment),
+                _ => None,
+            })
+            .collect()
+    };
+
+    assert!(connection_updates(&mut connection).is_empty());
+
+    connection.send_response(1, Response::empty(StatusCode::OK));
+    assert_eq!(connection_updates(&mut connection), vec![16 * 1024 * 1024]);
+}
+
+#[test]
+fn test_connection_max_body_size() {
+    let mut connection = open(Some(10));
+
+    let fields = [
+        (":method", "POST"),
+        (":scheme", "https"),
+        (":path", "/upload"),
+        (":authority", "localhost"),
+    ];
+
+    // Bodies are rejected as soon as they are known to be too large
+    let mut with_length = fields.to_vec();
+    with_length.push(("content-length", "11"));
+
+    let requests = connection
+        .receive(&write_frames(&[headers(1, &with_length, false)]))
+        .unwrap();
+    assert!(matches!(
+        requests[..],
+        [(1, Err(RequestError::PayloadTooLarge))]
+    ));
+
+    let requests = connection
+        .receive(&write_frames(&[
+            headers(3, &fields, false),
+            data(3, b"0123456789", false),
+        ]))
+        .unwrap();
+    assert!(requests.is_empty());
+
+    let requests = connection
+        .receive(&write_frames(&[data(3, b"a", false), data(3, b"b", true)]))
+        .unwrap();
+    assert!(matches!(
+        requests[..],
+        [(3, Err(RequestError::PayloadTooLarge))]
+    ));
+}
+
+#[test]
+fn test_connection_malformed_requests() {
+    let mut connection = open(None);
+
+    let mut uppercase = get("/");
+    uppercase.push(("Accept", "*/*"));
+
+    let mut connection_header = get("/");
+    connection_header.push(("connection", "keep-alive"));
+
+    let mut late_pseudo_header = vec![("accept", "*/*")];
+    late_pseudo_header.extend(get("/"));
+
+    let missing_path = [(":method", "GET"), (":scheme", "https")];
+
+    let requests = receive(
+        &mut connection,
+        &[
+            headers(1, &uppercase, true),
+            headers(3, &connection_header, true),
+            headers(5, &late_pseudo_header, true),
+            headers(7, &missing_path, true),
+        ],
+    );
+
+    // Malformed requests only reset their own stream
+    assert!(requests.is_empty());
+    assert_eq!(
+        parse_frames(&connection.take_output()),
+        [1, 3, 5, 7]
+            .iter()
+            .map(|&stream_id| Frame::RstStream {
+                stream_id,
+                error: ErrorCode::ProtocolError
+            })
+            .collect::<Vec<_>>()
+    );
+
+    assert_eq!(
+        receive(&mut connection, &[headers(9, &get("/"), true)]).len(),
+        1
+    );
+}
+
+#[test]
+fn test_connection_flow_control() {
+    let mut connection = open(None);
+    receive(&mut connection, &[headers(1, &get("/"), true)]);
+
+    let response = Response::empty(StatusCode::OK).with_bytes(vec![b'a'; 100000]);
+    connection.send_response(1, response);
+
+    let sent = |connection: &mut Connection| -> usize {
+        parse_frames(&connection.take_output())
+            .iter()
+            .map(|frame| match frame {
+                Frame::Data { data, .. } => data.len(),
+                _ => 0,
+            })
+            .sum()
+    };
+
+    // Only the initial window is sent until the client allows more
+    assert_eq!(sent(&mut connection), 65535);
+    assert_eq!(connection.pending_data(1), Some(100000 - 65535));
+
+    // Both the connection and the stream must allow more data to be sent
+    receive(
+        &mut connection,
+        &[Frame::WindowUpdate {
+            stream_id: 1,
+            increment: 10000,
+        }],
+    );
+    assert_eq!(sent(&mut connection), 0);
+
+    receive(
+        &mut connection,
+        &[Frame::WindowUpdate {
+            stream_id: 0,
+            increment: 50000,
+        }],
+    );
+    assert_eq!(sent(&mut connection), 10000);
+
+    receive(
+        &mut connection,
+        &[Frame::Settings {
+            ack: false,
+            settings: vec![Setting::InitialWindowSize(1000000)],
+        }],
+    );
+    assert_eq!(sent(&mut connection), 100000 - 75535);
+    assert_eq!(connection.pending_data(1), None);
+}
+
+#[test]
+fn test_connection_errors() {
+    // Streams opened by the client must have odd identifiers
+    let mut connection = open(None);
+    assert_eq!(
+        connection
+            .receive(&write_frames(&[headers(2, &get("/"), true)]))
+            .err(),
+        Some(ErrorCode::ProtocolError)
+    );
+    assert!(connection.is_finished());
+
+    // Header blocks must not be interrupted by other frames
+    let mut connection = open(None);
+    let block = encode(&get("/"));
+    assert_eq!(
+        connection
+            .receive(&write_frames(&[
+                Frame::Headers {
+                    stream_id: 1,
+                    block: block[..2].to_vec(),
+                    end_stream: true,
+                    end_headers: false,
+                    priority: None,
+                },
+                Frame::Ping {
+                    ack: false,
+                    data: [0; 8],
+                },
+            ]))
+            .err(),
+        Some(ErrorCode::ProtocolError)
+    );
+
+    // Header blocks which cannot be decoded break the state of the decoder
+    let mut connection = open(None);
+    assert_eq!(
+        connection
+            .receive(&write_frames(&[Frame::Headers {
+                stream_id: 1,
+                block: vec![0x80],
+                end_stream: true,
+                end_headers: true,
+                priority: None,
+            }]))
+            .err(),
+        Some(ErrorCode::CompressionError)
+    );
+
+    // Flow control windows must not grow beyond the largest allowed size
+    let mut connection = open(None);
+    let frames = [Frame::WindowUpdate {
+        stream_id: 0,
+        increment: 0x7fffffff,
+    }];
+    assert_eq!(
+        connection.receive(&write_frames(&frames)).err(),
+        Some(ErrorCode::FlowControlError)
+    );
+}
+
+#[test]
+fn test_connection_shutdown() {
+    let mut connection = open(None);
+    receive(&mut connection, &[headers(1, &get("/"), true)]);
+
+    connection.shutdown();
+    assert!(!connection.is_finished());
+
+    // Streams opened after the shutdown are ignored
+    assert!(receive(&mut connection, &[headers(3, &get("/"), true)]).is_empty());
+
+    connection.send_response(1, Response::empty(StatusCode::NoContent));
+    assert!(connection.is_finished());
+
+    let output = parse_frames(&connection.take_output());
+    assert_eq!(
+        output[0],
+        Frame::GoAway {
+            last_stream_id: 1,
+            error: ErrorCode::NoError,
+            debug_data: Vec::new(),
+        }
+    );
+    assert!(matches!(
+        output[1],
+        Frame::Headers {
+            stream_id: 1,
+            end_stream: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_connection_rapid_reset() {
+    let mut connection = open(None);
+    let mut received = Vec::new();
+
+    // Resetting a stream closes it but does not stop its request being handled, so the client cannot make the
+    //   server handle an unlimited number of requests by resetting them as soon as they are sent
+    for stream_id in (1..1000).step_by(2) {
+        let requests = receive(
+            &mut connection,
+            &[
+                headers(stream_id, &get("/"), true),
+                Frame::RstStream {
+                    stream_id,
+                    error: ErrorCode::Cancel,
+                },
+            ],
+        );
+
+        received.extend(requests.into_iter().map(|(stream_id, _)| stream_id));
+        assert!(connection.unanswered() <= 100);
+    }
+
+    assert_eq!(received.len(), 100);
+    assert!(connection.is_idle());
+    assert!(
+        parse_frames(&connection.take_output()).contains(&Frame::RstStream {
+            stream_id: 999,
+            error: ErrorCode::RefusedStream,
+        })
+    );
+
+    // Answering requests allows more to be received, even though their streams were reset
+    connection.send_response(received[0], Response::empty(StatusCode::OK));
+    connection.fail_response(received[1]);
+    assert_eq!(connection.unanswered(), 98);
+    assert!(connection.take_output().is_empty());
+
+    let requests = receive(
+        &mut connection,
+        &[
+            headers(1001, &get("/"), true),
+            headers(1003, &get("/"), true),
+        ],
+    );
+    assert_eq!(requests.len(), 2);
+    assert_eq!(connection.unanswered(), 100);
+}
+
+#[test]
+#[cfg(not(feature = "tokio"))]
+fn test_http2_app() {
+    let port = free_port();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    let slow_released = released.clone();
+
+    let app: App = App::new_with_config(2, ())
+        .with_route("/*", |request: Request, _| {
+            Response::new(
+                StatusCode::OK,
+                format!("{} {}", request.version, request.uri),
+            )
+        })
+        .with_post("/echo", |request: Request, _| {
+            Response::new(StatusCode::OK, request.content.unwrap_or_default())
+        })
+        .with_route("/slow", move |_, _| {
+            while !slow_released.load(Ordering::Relaxed) {
+                sleep(Duration::from_millis(10));
+            }
+
+            Response::new(StatusCode::OK, "slow")
+        })
+        .with_listener(https_listener(port))
+        .with_shutdown(shutdown.clone());
+
+    let server = spawn(move || app.run_listeners().unwrap());
+
+    let mut stream = connect(port);
+
+    let post = [
+        (":method", "POST"),
+        (":scheme", "https"),
+        (":path", "/echo"),
+        (":authority", "localhost"),
+    ];
+
+    // Every request is sent before any response is received, and the slow one does not hold up the others
+    stream
+        .write_all(&write_frames(&[
+            headers(1, &get("/slow"), true),
+            headers(3, &get("/page"), true),
+            headers(5, &post, false),
+            data(5, b"echo", true),
+        ]))
+        .unwrap();
+
+    let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+    let mut bodies = [Vec::new(), Vec::new(), Vec::new()];
+    let mut finished = 0;
+    let mut buf = Vec::new();
+
+    while finished < 3 {
+        if finished == 2 {
+            assert!(bodies[0].is_empty());
+            released.store(true, Ordering::Relaxed);
+        }
+
+        match read_frame(&mut stream, &mut buf) {
+            Frame::Headers {
+                block, end_stream, ..
+            } => {
+                let fields = decoder.decode(&block).unwrap();
+                assert_eq!(fields[0], (b":status".to_vec(), b"200".to_vec()));
+                assert!(!end_stream);
+            }
+            Frame::Data {
+                stream_id,
+                data,
+                end_stream,
+                ..
+            } => {
+                bodies[stream_id as usize / 2].extend(data);
+
+                if end_stream {
+                    finished += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    assert_eq!(bodies[0], b"slow");
+    assert_eq!(bodies[1], b"HTTP/2.0 /page");
+    assert_eq!(bodies[2], b"echo");
+
+    shutdown.store(true, Ordering::Relaxed);
+    server.join().unwrap();
+}
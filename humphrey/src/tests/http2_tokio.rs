@@ -0,0 +1,347 @@
+use crate::http::{Request, Response, StatusCode};
+use crate::http2::frame::{ErrorCode, Frame};
+use crate::http2::hpack::{Decoder, DEFAULT_TABLE_SIZE};
+use crate::tests::http2::{
+    connect, data, free_port, get, headers, https_listener, read_frame, write_frames,
+};
+use crate::App;
+
+use rustls::{ClientConnection, StreamOwned};
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+type Client = StreamOwned<ClientConnection, TcpStream>;
+
+/// The status and body of each response, or the error code of each reset stream.
+type Responses = HashMap<u32, Result<(String, Vec<u8>), ErrorCode>>;
+
+fn app(port: u16, started: Arc<AtomicUsize>) -> App {
+    App::new()
+        .with_route("/*", |request: Request, _| async move {
+            Response::new(
+                StatusCode::OK,
+                format!("{} {}", request.version, request.uri),
+            )
+        })
+        .with_post("/echo", |request: Request, _| async move {
+            Response::new(StatusCode::OK, request.content.unwrap_or_default())
+        })
+        .with_route("/large", |_, _| async {
+            Response::new(StatusCode::OK, vec![b'a'; 100000])
+        })
+        .with_route("/slow", |_, _| async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Response::new(StatusCode::OK, "slow")
+        })
+        .with_route("/wait", move |_, _| {
+            started.fetch_add(1, Ordering::SeqCst);
+
+            async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                Response::empty(StatusCode::NoContent)
+            }
+        })
+        .with_route("/panic", |_, _| async {
+            if true {
+                panic!("handler panicked");
+            }
+
+            Response::empty(StatusCode::OK)
+        })
+        .with_listener(https_listener(port))
+        .with_drain_timeout(Some(Duration::from_secs(5)))
+}
+
+/// Runs the app while the client runs on another thread.
+///
+/// The app shuts down when the client sends on the given sender, or drops it by finishing or panicking.
+async fn run<F>(app: App, client: F)
+where
+    F: FnOnce(oneshot::Sender<()>) + Send + 'static,
+{
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let client = tokio::task::spawn_blocking(move || client(shutdown_tx));
+
+    app.with_shutdown(shutdown_rx)
+        .run_listeners()
+        .await
+        .unwrap();
+    client.await.unwrap();
+}
+
+/// Reads frames from the server until the given number of streams have been answered.
+fn responses(
+    stream: &mut Client,
+    buf: &mut Vec<u8>,
+    decoder: &mut Decoder,
+    count: usize,
+) -> Responses {
+    let mut statuses: HashMap<u32, String> = HashMap::new();
+    let mut bodies: HashMap<u32, Vec<u8>> = HashMap::new();
+    let mut responses = Responses::new();
+
+    while responses.len() < count {
+        let (stream_id, end_stream) = match read_frame(stream, buf) {
+            Frame::Headers {
+                stream_id,
+                block,
+                end_stream,
+                ..
+            } => {
+                let fields = decoder.decode(&block).unwrap();
+                assert_eq!(fields[0].0, b":status");
+
+                let status = String::from_utf8(fields[0].1.clone()).unwrap();
+                statuses.insert(stream_id, status);
+
+                (stream_id, end_stream)
+            }
+            Frame::Data {
+                stream_id,
+                data,
+                end_stream,
+                ..
+            } => {
+                bodies.entry(stream_id).or_default().extend(data);
+
+                (stream_id, end_stream)
+            }
+            Frame::RstStream { stream_id, error } => {
+                responses.insert(stream_id, Err(error));
+                continue;
+            }
+            _ => continue,
+        };
+
+        if end_stream {
+            let status = statuses.remove(&stream_id).unwrap();
+            let body = bodies.remove(&stream_id).unwrap_or_default();
+            responses.insert(stream_id, Ok((status, body)));
+        }
+    }
+
+    responses
+}
+
+/// Pings the server, returning the frames it sent before the acknowledgement.
+fn ping(stream: &mut Client, buf: &mut Vec<u8>) -> Vec<Frame> {
+    let frame = Frame::Ping {
+        ack: false,
+        data: *b"humphrey",
+    };
+    stream.write_all(&write_frames(&[frame])).unwrap();
+
+    let mut frames = Vec::new();
+
+    loop {
+        match read_frame(stream, buf) {
+            Frame::Ping { ack: true, data } if &data == b"humphrey" => return frames,
+            frame => frames.push(frame),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_http2_app() {
+    let port = free_port();
+
+    run(app(port, Arc::default()), move |_| {
+        let mut stream = connect(port);
+        let mut buf = Vec::new();
+        let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+
+        let post = [
+            (":method", "POST"),
+            (":scheme", "https"),
+            (":path", "/echo"),
+            (":authority", "localhost"),
+        ];
+
+        // The slow request does not hold up the others
+        stream
+            .write_all(&write_frames(&[
+                headers(1, &get("/slow"), true),
+                headers(3, &get("/page"), true),
+                headers(5, &post, false),
+                data(5, b"echo", true),
+            ]))
+            .unwrap();
+
+        let first = responses(&mut stream, &mut buf, &mut decoder, 2);
+        assert_eq!(first[&3], Ok(("200".into(), b"HTTP/2.0 /page".to_vec())));
+        assert_eq!(first[&5], Ok(("200".into(), b"echo".to_vec())));
+
+        let last = responses(&mut stream, &mut buf, &mut decoder, 1);
+        assert_eq!(last[&1], Ok(("200".into(), b"slow".to_vec())));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_http2_flow_control() {
+    let port = free_port();
+
+    run(app(port, Arc::default()), move |_| {
+        let mut stream = connect(port);
+        let mut buf = Vec::new();
+
+        stream
+            .write_all(&write_frames(&[headers(1, &get("/large"), true)]))
+            .unwrap();
+
+        // Only the initial window is sent until the client allows more
+        let mut received = 0;
+
+        while received < 65535 {
+            if let Frame::Data { data, .. } = read_frame(&mut stream, &mut buf) {
+                received += data.len();
+            }
+        }
+
+        assert_eq!(received, 65535);
+        assert!(!ping(&mut stream, &mut buf)
+            .iter()
+            .any(|frame| matches!(frame, Frame::Data { .. })));
+
+        stream
+            .write_all(&write_frames(&[
+                Frame::WindowUpdate {
+                    stream_id: 0,
+                    increment: 100000,
+                },
+                Frame::WindowUpdate {
+                    stream_id: 1,
+                    increment: 100000,
+                },
+            ]))
+            .unwrap();
+
+        loop {
+            if let Frame::Data {
+                data, end_stream, ..
+            } = read_frame(&mut stream, &mut buf)
+            {
+                received += data.len();
+
+                if end_stream {
+                    break;
+                }
+            }
+        }
+
+        assert_eq!(received, 100000);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_http2_shutdown() {
+    let port = free_port();
+
+    run(app(port, Arc::default()), move |shutdown| {
+        let mut stream = connect(port);
+        let mut buf = Vec::new();
+        let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+
+        stream
+            .write_all(&write_frames(&[headers(1, &get("/slow"), true)]))
+            .unwrap();
+        ping(&mut stream, &mut buf);
+
+        // Shut down while the slow request is being handled
+        shutdown.send(()).unwrap();
+
+        assert_eq!(
+            read_frame(&mut stream, &mut buf),
+            Frame::GoAway {
+                last_stream_id: 1,
+                error: ErrorCode::NoError,
+                debug_data: Vec::new(),
+            }
+        );
+
+        // The current request is still answered before the connection is closed
+        let responses = responses(&mut stream, &mut buf, &mut decoder, 1);
+        assert_eq!(responses[&1], Ok(("200".into(), b"slow".to_vec())));
+
+        assert!(buf.is_empty());
+        assert_eq!(stream.read(&mut [0; 1]).unwrap_or(0), 0);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_http2_handler_panic() {
+    let port = free_port();
+
+    run(app(port, Arc::default()), move |_| {
+        let mut stream = connect(port);
+        let mut buf = Vec::new();
+        let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+
+        stream
+            .write_all(&write_frames(&[
+                headers(1, &get("/panic"), true),
+                headers(3, &get("/page"), true),
+            ]))
+            .unwrap();
+
+        // The stream whose handler panicked is reset, and the connection carries on
+        let responses = responses(&mut stream, &mut buf, &mut decoder, 2);
+        assert_eq!(responses[&1], Err(ErrorCode::InternalError));
+        assert_eq!(
+            responses[&3],
+            Ok(("200".into(), b"HTTP/2.0 /page".to_vec()))
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_http2_rapid_reset() {
+    let port = free_port();
+    let started = Arc::new(AtomicUsize::new(0));
+    let client_started = started.clone();
+
+    run(app(port, started), move |_| {
+        let mut stream = connect(port);
+        let mut buf = Vec::new();
+
+        // Every request is reset as soon as it is sent, but is still handled
+        for stream_id in (1..300).step_by(2) {
+            stream
+                .write_all(&write_frames(&[
+                    headers(stream_id, &get("/wait"), true),
+                    Frame::RstStream {
+                        stream_id,
+                        error: ErrorCode::Cancel,
+                    },
+                ]))
+                .unwrap();
+        }
+
+        let refused = ping(&mut stream, &mut buf)
+            .iter()
+            .filter(|frame| {
+                matches!(
+                    frame,
+                    Frame::RstStream {
+                        error: ErrorCode::RefusedStream,
+                        ..
+                    }
+                )
+            })
+            .count();
+
+        assert_eq!(refused, 50);
+        assert!(client_started.load(Ordering::SeqCst) <= 100);
+    })
+    .await;
+}
@@ -0,0 +1,131 @@
+use crate::http::headers::HeaderType;
+use crate::http::json::JsonError;
+use crate::http::method::Method;
+use crate::http::response::IntoResponse;
+use crate::http::{Request, Response, StatusCode};
+use crate::tests::mock_request;
+
+use humphrey_json::prelude::*;
+use humphrey_json::Value;
+
+#[derive(Debug, PartialEq, Eq)]
+struct User {
+    name: String,
+    age: u8,
+}
+
+json_map! {
+    User,
+    name => "name",
+    age => "age"
+}
+
+fn request(content_type: Option<&str>, content: &str) -> Request {
+    let mut request = Request {
+        method: Method::Post,
+        content: Some(content.as_bytes().to_vec()),
+        ..mock_request()
+    };
+
+    if let Some(content_type) = content_type {
+        request.headers.add(HeaderType::ContentType, content_type);
+    }
+
+    request
+}
+
+#[test]
+fn test_request_json() {
+    let valid = request(
+        Some("application/json; charset=utf-8"),
+        r#"{"name": "Humphrey", "age": 3}"#,
+    );
+
+    assert_eq!(
+        valid.json::<User>(),
+        Ok(User {
+            name: "Humphrey".into(),
+            age: 3
+        })
+    );
+
+    let suffixed = request(Some("application/vnd.api+json"), "[1, 2]");
+    assert_eq!(suffixed.json::<Vec<u8>>(), Ok(vec![1, 2]));
+}
+
+#[test]
+fn test_request_json_errors() {
+    let missing = request(None, "{}");
+    assert_eq!(
+        missing.json::<Value>(),
+        Err(JsonError::UnsupportedMediaType)
+    );
+
+    let wrong = request(Some("text/plain"), "{}");
+    assert_eq!(wrong.json::<Value>(), Err(JsonError::UnsupportedMediaType));
+
+    let invalid = request(Some("application/json"), "{");
+    assert_eq!(invalid.json::<Value>(), Err(JsonError::Invalid));
+
+    let mismatched = request(Some("application/json"), r#"{"name": 1}"#);
+    assert_eq!(mismatched.json::<User>(), Err(JsonError::Invalid));
+
+    assert_eq!(
+        JsonError::UnsupportedMediaType.into_response().status_code,
+        StatusCode::UnsupportedMediaType
+    );
+    assert_eq!(
+        JsonError::Invalid.into_response().status_code,
+        StatusCode::BadRequest
+    );
+}
+
+#[test]
+fn test_response_json() {
+    let user = User {
+        name: "Humphrey".into(),
+        age: 3,
+    };
+
+    let response = Response::json(&user);
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.headers.get(HeaderType::ContentType),
+        Some("application/json")
+    );
+    assert_eq!(response.body, br#"{"name":"Humphrey","age":3}"#);
+}
+
+#[cfg(not(feature = "tokio"))]
+#[test]
+fn test_json_handler() {
+    use crate::tests::app::serve;
+    use crate::App;
+
+    use std::sync::Arc;
+
+    fn handler(request: Request, _: Arc<()>) -> Result<User, JsonError> {
+        let mut user: User = request.json()?;
+        user.age += 1;
+        Ok(user)
+    }
+
+    let app = || App::new_with_config(1, ()).with_route("/", handler);
+
+    let response = serve(
+        app(),
+        "POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 27\r\n\r\n{\"name\":\"Humphrey\",\"age\":3}",
+    );
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, br#"{"name":"Humphrey","age":4}"#);
+
+    let response = serve(
+        app(),
+        "POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 1\r\n\r\n{",
+    );
+    assert_eq!(response.status_code, StatusCode::BadRequest);
+
+    let response = serve(app(), "POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}");
+    assert_eq!(response.status_code, StatusCode::UnsupportedMediaType);
+}
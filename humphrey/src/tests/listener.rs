@@ -0,0 +1,187 @@
+use crate::http::headers::HeaderType;
+use crate::http::{Request, Response, StatusCode};
+use crate::listener::{https_redirect, Listener};
+use crate::tests::app::{connect, free_port};
+use crate::tests::mock_request;
+use crate::App;
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::Duration;
+
+fn get(port: u16, host: &str) -> Response {
+    let mut stream = connect(port);
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(format!("GET /page?a=1 HTTP/1.1\r\nHost: {}\r\n\r\n", host).as_bytes())
+        .unwrap();
+
+    Response::from_stream(&mut stream).unwrap()
+}
+
+fn request(host: Option<&str>) -> Request {
+    let mut request = Request {
+        uri: "/page".into(),
+        query: "a=1".into(),
+        ..mock_request()
+    };
+
+    if let Some(host) = host {
+        request.headers.add(HeaderType::Host, host);
+    }
+
+    request
+}
+
+#[test]
+fn test_multiple_listeners() {
+    let first_port = free_port();
+    let second_port = free_port();
+    let redirect_port = free_port();
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let app: App = App::new_with_config(2, ())
+        .with_route("/*", |request: Request, _| {
+            Response::new(StatusCode::OK, request.address.origin_addr.to_string())
+        })
+        .with_listener(Listener::http(format!("127.0.0.1:{}", first_port)))
+        .with_listener(Listener::http(format!("127.0.0.1:{}", second_port)))
+        .with_listener(Listener::redirect(
+            format!("127.0.0.1:{}", redirect_port),
+            8443,
+        ))
+        .with_shutdown(shutdown.clone());
+
+    let server = spawn(move || app.run_listeners().unwrap());
+
+    // Both listeners share the same routes
+    for port in [first_port, second_port] {
+        let response = get(port, "example.com");
+        assert_eq!(response.status_code, StatusCode::OK);
+        assert_eq!(response.body, b"127.0.0.1");
+    }
+
+    let response = get(redirect_port, &format!("example.com:{}", redirect_port));
+    assert_eq!(response.status_code, StatusCode::MovedPermanently);
+    assert_eq!(
+        response.headers.get(HeaderType::Location),
+        Some("https://example.com:8443/page?a=1")
+    );
+
+    shutdown.store(true, Ordering::Relaxed);
+    server.join().unwrap();
+
+    for port in [first_port, second_port, redirect_port] {
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+}
+
+#[test]
+fn test_no_listeners() {
+    let app: App = App::new();
+    assert!(app.run_listeners().is_err());
+}
+
+#[test]
+fn test_https_redirect() {
+    let location = |host, port| {
+        let response = https_redirect(&request(host), port);
+        assert_eq!(response.status_code, StatusCode::MovedPermanently);
+        response
+            .headers
+            .get(HeaderType::Location)
+            .unwrap()
+            .to_string()
+    };
+
+    assert_eq!(
+        location(Some("example.com"), 443),
+        "https://example.com/page?a=1"
+    );
+    assert_eq!(
+        location(Some("example.com:80"), 443),
+        "https://example.com/page?a=1"
+    );
+    assert_eq!(
+        location(Some("example.com:8080"), 8443),
+        "https://example.com:8443/page?a=1"
+    );
+    assert_eq!(location(Some("[::1]"), 443), "https://[::1]/page?a=1");
+    assert_eq!(
+        location(Some("[::1]:8080"), 8443),
+        "https://[::1]:8443/page?a=1"
+    );
+
+    let response = https_redirect(&request(None), 443);
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"<h1>Please access over HTTPS</h1>");
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn test_https_listener() {
+    use crate::tls::CertResolver;
+
+    use rustls::{Certificate, ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+    use rustls_pemfile::certs;
+
+    use std::convert::TryInto;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    let certs_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/certs/");
+    let http_port = free_port();
+    let https_port = free_port();
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let resolver = CertResolver::new()
+        .with_default_cert(
+            format!("{}localhost.pem", certs_dir),
+            format!("{}localhost.key", certs_dir),
+        )
+        .unwrap();
+
+    let app: App = App::new_with_config(2, ())
+        .with_route("/*", |request: Request, _| {
+            Response::new(StatusCode::OK, request.uri.clone())
+        })
+        .with_listener(Listener::http(format!("127.0.0.1:{}", http_port)))
+        .with_listener(
+            Listener::https(format!("127.0.0.1:{}", https_port))
+                .with_cert_resolver(Arc::new(resolver)),
+        )
+        .with_shutdown(shutdown.clone());
+
+    let server = spawn(move || app.run_listeners().unwrap());
+
+    let mut reader = BufReader::new(File::open(format!("{}ca.pem", certs_dir)).unwrap());
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(&Certificate(certs(&mut reader).unwrap().remove(0)))
+        .unwrap();
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connection =
+        ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap();
+
+    let mut stream = StreamOwned::new(connection, connect(https_port));
+    stream
+        .write_all(b"GET /secure HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+
+    let response = Response::from_stream(&mut stream).unwrap();
+    assert_eq!(response.body, b"/secure");
+
+    assert_eq!(get(http_port, "localhost").body, b"/page");
+
+    shutdown.store(true, Ordering::Relaxed);
+    server.join().unwrap();
+}
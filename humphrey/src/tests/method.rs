@@ -8,6 +8,8 @@ fn test_from_name() {
     assert_eq!(Method::from_name("POST"), Ok(Method::Post));
     assert_eq!(Method::from_name("PUT"), Ok(Method::Put));
     assert_eq!(Method::from_name("DELETE"), Ok(Method::Delete));
+    assert_eq!(Method::from_name("HEAD"), Ok(Method::Head));
+    assert_eq!(Method::from_name("PATCH"), Ok(Method::Patch));
     assert_eq!(Method::from_name("get"), Err(RequestError::Request));
     assert_eq!(Method::from_name("method"), Err(RequestError::Request));
     assert_eq!(Method::from_name(""), Err(RequestError::Request));
@@ -0,0 +1,134 @@
+use crate::app::Middleware;
+use crate::http::headers::{HeaderType, Headers};
+use crate::http::{Request, Response, StatusCode};
+use crate::tests::app::serve;
+use crate::{App, SubApp};
+
+use std::sync::Arc;
+
+/// Records the order in which middleware runs in the `X-Before` request header and the `X-After` response header.
+struct Tag(&'static str);
+
+impl Middleware<()> for Tag {
+    fn before(&self, request: &mut Request, _: Arc<()>) -> Option<Response> {
+        append(&mut request.headers, "X-Before", self.0);
+        None
+    }
+
+    fn after(&self, _: &Request, response: &mut Response, _: Arc<()>) {
+        append(&mut response.headers, "X-After", self.0);
+    }
+}
+
+/// Responds directly to requests without an `Authorization` header.
+struct RequireAuthorization;
+
+impl Middleware<()> for RequireAuthorization {
+    fn before(&self, request: &mut Request, _: Arc<()>) -> Option<Response> {
+        match request.headers.get(HeaderType::Authorization) {
+            Some(_) => None,
+            None => Some(Response::new(StatusCode::Unauthorized, "Unauthorized")),
+        }
+    }
+
+    fn after(&self, _: &Request, response: &mut Response, _: Arc<()>) {
+        append(&mut response.headers, "X-After", "auth");
+    }
+}
+
+fn append(headers: &mut Headers, name: &str, value: &str) {
+    let name = HeaderType::from(name);
+
+    match headers.get_mut(&name) {
+        Some(existing) => {
+            existing.push(',');
+            existing.push_str(value);
+        }
+        None => headers.add(name, value),
+    }
+}
+
+fn handler(request: Request, _: Arc<()>) -> Response {
+    Response::new(
+        StatusCode::OK,
+        request.headers.get("X-Before").unwrap_or(""),
+    )
+}
+
+fn app() -> App {
+    App::new_with_config(2, ())
+        .with_middleware(Tag("app"))
+        .with_route("/", handler)
+        .with_route("/private", handler)
+        .with_route_middleware("/private", RequireAuthorization)
+        .with_route_middleware("/private", Tag("route"))
+        .with_host(
+            "example.com",
+            SubApp::new()
+                .with_middleware(Tag("host"))
+                .with_route("/", handler)
+                .with_route_middleware("/", Tag("route")),
+        )
+}
+
+#[test]
+fn test_middleware_order() {
+    let response = serve(app(), "GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"app,host,route");
+    assert_eq!(response.headers.get("X-After"), Some("route,host,app"));
+
+    let response = serve(
+        app(),
+        "GET /private HTTP/1.1\r\nHost: localhost\r\nAuthorization: yes\r\n\r\n",
+    );
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"app,route");
+    assert_eq!(response.headers.get("X-After"), Some("route,auth,app"));
+}
+
+#[test]
+fn test_middleware_short_circuit() {
+    let response = serve(app(), "GET /private HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert_eq!(response.status_code, StatusCode::Unauthorized);
+    assert_eq!(response.body, b"Unauthorized");
+    assert_eq!(response.headers.get("X-After"), Some("app"));
+}
+
+#[test]
+fn test_middleware_not_found() {
+    let response = serve(app(), "GET /missing HTTP/1.1\r\nHost: example.com\r\n\r\n");
+
+    assert_eq!(response.status_code, StatusCode::NotFound);
+    assert_eq!(response.headers.get("X-After"), Some("host,app"));
+}
+
+#[test]
+fn test_middleware_before_body() {
+    // The body is never sent, so the response can only be generated if it is not read
+    let response = serve(
+        app(),
+        "POST /private HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1024\r\n\r\n",
+    );
+
+    assert_eq!(response.status_code, StatusCode::Unauthorized);
+    assert_eq!(response.headers.get(HeaderType::Connection), Some("Close"));
+}
+
+#[test]
+fn test_middleware_options() {
+    let response = serve(app(), "OPTIONS / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    assert_eq!(response.status_code, StatusCode::NoContent);
+    assert_eq!(response.headers.get("X-After"), Some("app"));
+
+    let response = serve(
+        app(),
+        "OPTIONS /private HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+
+    assert_eq!(response.status_code, StatusCode::Unauthorized);
+}
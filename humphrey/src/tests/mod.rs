@@ -1,14 +1,61 @@
 #[cfg(not(feature = "tokio"))]
+pub mod app;
+#[cfg(not(feature = "tokio"))]
+pub mod listener;
+#[cfg(not(feature = "tokio"))]
+pub mod middleware;
+#[cfg(not(feature = "tokio"))]
 pub mod request;
 
+#[cfg(feature = "tokio")]
+pub mod app_tokio;
 #[cfg(feature = "tokio")]
 pub mod request_tokio;
 
 pub mod client;
+pub mod compression;
+pub mod conditional;
 pub mod date;
+pub mod form;
+#[cfg(feature = "tls")]
+pub mod http2;
+#[cfg(all(feature = "tls", feature = "tokio"))]
+pub mod http2_tokio;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod krauss;
 pub mod method;
 pub mod mock_stream;
 pub mod percent;
+pub mod pool;
+pub mod proxy;
+pub mod range;
 pub mod response;
+pub mod route;
 pub mod status;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+use crate::http::address::Address;
+use crate::http::headers::Headers;
+use crate::http::method::Method;
+use crate::http::Request;
+
+use std::collections::HashMap;
+
+/// Creates a `GET /` request from `1.2.3.4:5678` with no headers, for tests to change as they need.
+pub fn mock_request() -> Request {
+    Request {
+        method: Method::Get,
+        uri: "/".into(),
+        query: "".into(),
+        params: HashMap::new(),
+        version: "HTTP/1.1".into(),
+        headers: Headers::new(),
+        trailers: Headers::new(),
+        content: None,
+        address: Address::new("1.2.3.4:5678").unwrap(),
+        peer_identity: None,
+        https: false,
+    }
+}
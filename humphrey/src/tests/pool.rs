@@ -0,0 +1,132 @@
+use crate::client::Client;
+use crate::http::headers::HeaderType;
+use crate::http::pool::ConnectionPool;
+use crate::http::{Request, StatusCode};
+use crate::tests::mock_request;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+/// Starts a server which responds to every request on a connection, closing the connection after the first
+///   response if `close` is set, and counts the connections it accepts.
+fn backend(close: bool) -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+
+    spawn(move || {
+        for stream in listener.incoming() {
+            counter.fetch_add(1, Ordering::SeqCst);
+
+            spawn(move || {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                loop {
+                    let mut line = String::new();
+
+                    loop {
+                        line.clear();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        } else if line == "\r\n" {
+                            break;
+                        }
+                    }
+
+                    stream
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                        .unwrap();
+
+                    if close {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    (addr, connections)
+}
+
+fn request() -> Request {
+    let mut request = mock_request();
+    request.headers.add(HeaderType::Host, "localhost");
+    request.headers.add(HeaderType::Connection, "close");
+
+    request
+}
+
+#[test]
+fn test_pool_reuses_connections() {
+    let (addr, connections) = backend(false);
+    let pool = ConnectionPool::new();
+
+    for _ in 0..3 {
+        let response = pool.send(addr, request()).unwrap();
+        assert_eq!(response.status_code, StatusCode::OK);
+        assert_eq!(response.body, b"ok");
+    }
+
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+    assert_eq!(pool.idle_connections(addr), 1);
+
+    pool.clear();
+    assert_eq!(pool.idle_connections(addr), 0);
+}
+
+#[test]
+fn test_pool_stale_connections() {
+    let (addr, connections) = backend(true);
+    let pool = ConnectionPool::new();
+
+    for _ in 0..3 {
+        let response = pool.send(addr, request()).unwrap();
+        assert_eq!(response.body, b"ok");
+
+        // Give the connection time to be closed by the backend while it is idle
+        sleep(Duration::from_millis(50));
+    }
+
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_pool_idle_limits() {
+    let (addr, connections) = backend(false);
+    let pool = ConnectionPool::new().with_max_idle(0);
+
+    for _ in 0..2 {
+        pool.send(addr, request()).unwrap();
+    }
+
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    assert_eq!(pool.idle_connections(addr), 0);
+
+    let pool = ConnectionPool::new().with_idle_timeout(Duration::from_millis(50));
+    pool.send(addr, request()).unwrap();
+    sleep(Duration::from_millis(100));
+    pool.send(addr, request()).unwrap();
+
+    assert_eq!(connections.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn test_client_pool() {
+    let (addr, connections) = backend(false);
+    let pool = Arc::new(ConnectionPool::new());
+    let client = Client::new().with_connection_pool(pool.clone());
+
+    for _ in 0..2 {
+        let response = client.request(addr, request()).unwrap();
+        assert_eq!(response.body, b"ok");
+    }
+
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+    assert_eq!(pool.idle_connections(addr), 1);
+}
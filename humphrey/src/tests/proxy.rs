@@ -0,0 +1,162 @@
+use crate::http::address::Address;
+use crate::http::headers::{HeaderType, Headers};
+use crate::http::proxy::{
+    forward_request, strip_hop_by_hop_headers, try_proxy_request, HostRewrite, ProxyHeaders,
+};
+use crate::http::{Request, StatusCode};
+use crate::tests::mock_request;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::channel;
+use std::thread::spawn;
+use std::time::Duration;
+
+fn request(address: &str, headers: &[(&str, &str)]) -> Request {
+    let mut request = Request {
+        address: Address::new(address).unwrap(),
+        ..mock_request()
+    };
+
+    for (name, value) in headers {
+        request.headers.add(*name, value);
+    }
+
+    request
+}
+
+#[test]
+fn test_strip_hop_by_hop_headers() {
+    let mut headers = Headers::new();
+    headers.add(
+        HeaderType::Connection,
+        "keep-alive, X-Secret, Content-Length",
+    );
+    headers.add(HeaderType::Connection, "Upgrade");
+    headers.add("Keep-Alive", "timeout=5");
+    headers.add("Proxy-Authorization", "Basic abc");
+    headers.add("TE", "trailers");
+    headers.add(HeaderType::Upgrade, "websocket");
+    headers.add("x-secret", "123");
+    headers.add(HeaderType::ContentLength, "0");
+    headers.add(HeaderType::Host, "example.com");
+
+    strip_hop_by_hop_headers(&mut headers);
+
+    let mut expected = Headers::new();
+    expected.add(HeaderType::ContentLength, "0");
+    expected.add(HeaderType::Host, "example.com");
+
+    assert_eq!(headers, expected);
+}
+
+#[test]
+fn test_forward_request() {
+    let mut request = request(
+        "1.2.3.4:5678",
+        &[
+            ("Host", "example.com"),
+            ("Connection", "keep-alive"),
+            ("X-Forwarded-For", "9.9.9.9"),
+            ("X-Forwarded-Proto", "http"),
+            ("Via", "1.0 cdn"),
+        ],
+    );
+    request.https = true;
+
+    let forwarded = forward_request(&request, &ProxyHeaders::default(), "127.0.0.1:8000");
+
+    assert_eq!(forwarded.headers.get(HeaderType::Connection), None);
+    assert_eq!(forwarded.headers.get(HeaderType::Host), Some("example.com"));
+    assert_eq!(
+        forwarded.headers.get("X-Forwarded-For"),
+        Some("9.9.9.9, 1.2.3.4")
+    );
+    assert_eq!(
+        forwarded.headers.get_all("X-Forwarded-Proto"),
+        vec!["https"]
+    );
+    assert_eq!(
+        forwarded.headers.get("X-Forwarded-Host"),
+        Some("example.com")
+    );
+    assert_eq!(
+        forwarded.headers.get(HeaderType::Via),
+        Some("1.0 cdn, 1.1 humphrey")
+    );
+    assert_eq!(forwarded.headers.get(HeaderType::Forwarded), None);
+}
+
+#[test]
+fn test_forward_request_options() {
+    let request = request("[2001:db8::1]:5678", &[("Host", "example.com")]);
+
+    let headers = ProxyHeaders {
+        x_forwarded: false,
+        forwarded: true,
+        via: None,
+        host: HostRewrite::Target,
+    };
+
+    let forwarded = forward_request(&request, &headers, "127.0.0.1:8000");
+
+    assert_eq!(
+        forwarded.headers.get(HeaderType::Forwarded),
+        Some("for=\"[2001:db8::1]\";host=\"example.com\";proto=http")
+    );
+    assert_eq!(
+        forwarded.headers.get(HeaderType::Host),
+        Some("127.0.0.1:8000")
+    );
+    assert_eq!(forwarded.headers.get("X-Forwarded-For"), None);
+    assert_eq!(forwarded.headers.get(HeaderType::Via), None);
+
+    let headers = ProxyHeaders {
+        host: HostRewrite::Custom("internal.example.com".into()),
+        ..ProxyHeaders::default()
+    };
+
+    let forwarded = forward_request(&request, &headers, "127.0.0.1:8000");
+
+    assert_eq!(
+        forwarded.headers.get_all(HeaderType::Host),
+        vec!["internal.example.com"]
+    );
+    assert_eq!(
+        forwarded.headers.get("X-Forwarded-Host"),
+        Some("example.com")
+    );
+}
+
+#[test]
+fn test_try_proxy_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = channel();
+
+    spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = String::new();
+
+        while !head.ends_with("\r\n\r\n") {
+            reader.read_line(&mut head).unwrap();
+        }
+
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .unwrap();
+        tx.send(head).unwrap();
+    });
+
+    let request = request("1.2.3.4:5678", &[("Host", "example.com")]);
+    let response = try_proxy_request(&request, addr, Duration::from_secs(5)).unwrap();
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    // The library proxy adds the same forwarding headers as `forward_request`
+    let head = rx.recv().unwrap();
+    assert!(head.contains("x-forwarded-for: 1.2.3.4\r\n"));
+    assert!(head.contains("x-forwarded-proto: http\r\n"));
+    assert!(head.contains("x-forwarded-host: example.com\r\n"));
+    assert!(head.contains("Via: 1.1 humphrey\r\n"));
+}
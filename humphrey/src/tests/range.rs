@@ -0,0 +1,296 @@
+#![allow(unused_imports)]
+use crate::http::headers::HeaderType;
+use crate::http::range::{apply_range, parse_range_header, ByteRange};
+use crate::http::{Request, Response, StatusCode};
+use crate::tests::mock_request;
+
+fn request_with_headers(headers: &[(HeaderType, &str)]) -> Request {
+    let mut request = Request {
+        uri: "/video.mp4".into(),
+        ..mock_request()
+    };
+
+    for (name, value) in headers {
+        request.headers.add(name, value);
+    }
+
+    request
+}
+
+fn full_response() -> Response {
+    Response::new(StatusCode::OK, b"0123456789")
+        .with_header(HeaderType::ContentType, "text/plain")
+        .with_header(HeaderType::ETag, "\"abc\"")
+}
+
+#[test]
+fn test_parse_range_header() {
+    assert_eq!(
+        parse_range_header("bytes=0-4", 10),
+        Some(vec![ByteRange { start: 0, end: 4 }])
+    );
+    assert_eq!(
+        parse_range_header("bytes=5-", 10),
+        Some(vec![ByteRange { start: 5, end: 9 }])
+    );
+    assert_eq!(
+        parse_range_header("bytes=-3", 10),
+        Some(vec![ByteRange { start: 7, end: 9 }])
+    );
+    assert_eq!(
+        parse_range_header("bytes=8-100, -20", 10),
+        Some(vec![
+            ByteRange { start: 8, end: 9 },
+            ByteRange { start: 0, end: 9 }
+        ])
+    );
+    assert_eq!(parse_range_header("bytes=10-20", 10), Some(vec![]));
+    assert_eq!(parse_range_header("bytes=-0", 10), Some(vec![]));
+    assert_eq!(parse_range_header("bytes=5-4", 10), None);
+    assert_eq!(parse_range_header("bytes=a-b", 10), None);
+    assert_eq!(parse_range_header("bytes=", 10), None);
+    assert_eq!(parse_range_header("items=0-4", 10), None);
+}
+
+#[test]
+fn test_no_range() {
+    let response = apply_range(&request_with_headers(&[]), full_response());
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"0123456789");
+    assert_eq!(
+        response.headers.get(HeaderType::AcceptRanges),
+        Some("bytes")
+    );
+}
+
+#[test]
+fn test_single_range() {
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=2-5")]);
+    let response = apply_range(&request, full_response());
+
+    assert_eq!(response.status_code, StatusCode::PartialContent);
+    assert_eq!(response.body, b"2345");
+    assert_eq!(
+        response.headers.get(HeaderType::ContentRange),
+        Some("bytes 2-5/10")
+    );
+    assert_eq!(
+        response.headers.get(HeaderType::ContentType),
+        Some("text/plain")
+    );
+}
+
+#[test]
+fn test_multiple_ranges() {
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=0-1, 8-")]);
+    let response = apply_range(&request, full_response());
+
+    assert_eq!(response.status_code, StatusCode::PartialContent);
+    assert_eq!(response.headers.get(HeaderType::ContentRange), None);
+
+    let content_type = response.headers.get(HeaderType::ContentType).unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap();
+
+    let expected_body = format!(
+        "--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{0}--\r\n",
+        boundary
+    );
+
+    assert_eq!(response.body, expected_body.as_bytes());
+}
+
+#[test]
+fn test_overlapping_ranges() {
+    // Repeated ranges are only sent once
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=0-,0-,0-,0-")]);
+    let response = apply_range(&request, full_response());
+
+    assert_eq!(response.status_code, StatusCode::PartialContent);
+    assert_eq!(response.body, b"0123456789");
+    assert_eq!(
+        response.headers.get(HeaderType::ContentRange),
+        Some("bytes 0-9/10")
+    );
+
+    // Overlapping and adjacent ranges are merged, leaving separate ranges in order
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=8-, 2-3, 0-1, 1-2, -1")]);
+    let response = apply_range(&request, full_response());
+
+    let content_type = response.headers.get(HeaderType::ContentType).unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap();
+
+    let expected_body = format!(
+        "--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-3/10\r\n\r\n0123\r\n--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{0}--\r\n",
+        boundary
+    );
+
+    assert_eq!(response.body, expected_body.as_bytes());
+}
+
+#[test]
+fn test_unsatisfiable_range() {
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=20-")]);
+    let response = apply_range(&request, full_response());
+
+    assert_eq!(
+        response.status_code,
+        StatusCode::RequestedRangeNotSatisfiable
+    );
+    assert!(response.body.is_empty());
+    assert_eq!(
+        response.headers.get(HeaderType::ContentRange),
+        Some("bytes */10")
+    );
+}
+
+#[test]
+fn test_if_range() {
+    let request = request_with_headers(&[
+        (HeaderType::Range, "bytes=0-1"),
+        (HeaderType::IfRange, "\"abc\""),
+    ]);
+    let response = apply_range(&request, full_response());
+    assert_eq!(response.status_code, StatusCode::PartialContent);
+    assert_eq!(response.body, b"01");
+
+    let request = request_with_headers(&[
+        (HeaderType::Range, "bytes=0-1"),
+        (HeaderType::IfRange, "\"def\""),
+    ]);
+    let response = apply_range(&request, full_response());
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body, b"0123456789");
+
+    let request = request_with_headers(&[
+        (HeaderType::Range, "bytes=0-1"),
+        (HeaderType::IfRange, "W/\"abc\""),
+    ]);
+    let response = apply_range(&request, full_response());
+    assert_eq!(response.status_code, StatusCode::OK);
+}
+
+#[test]
+fn test_range_ignored_for_errors() {
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=0-1")]);
+    let response = apply_range(&request, Response::new(StatusCode::NotFound, b"not found"));
+
+    assert_eq!(response.status_code, StatusCode::NotFound);
+    assert_eq!(response.body, b"not found");
+    assert_eq!(response.headers.get(HeaderType::AcceptRanges), None);
+}
+
+#[test]
+#[cfg(not(feature = "tokio"))]
+fn test_file_range() {
+    use crate::http::range::apply_file_range;
+    use std::io::Read;
+
+    let path = std::env::temp_dir().join(format!("humphrey-range-{}", std::process::id()));
+    std::fs::write(&path, b"0123456789").unwrap();
+
+    let read_body = |response: Response| {
+        let mut body = Vec::new();
+        let stream = response.stream.unwrap();
+        stream.source.take(1000).read_to_end(&mut body).unwrap();
+        assert_eq!(stream.length, Some(body.len() as u64));
+        body
+    };
+
+    let file_response = || {
+        Response::empty(StatusCode::OK)
+            .with_header(HeaderType::ContentType, "text/plain")
+            .with_header(HeaderType::ETag, "\"abc\"")
+    };
+
+    // Only the requested range is read from the file
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=2-4")]);
+    let response = apply_file_range(&request, file_response(), &path).unwrap();
+
+    assert_eq!(response.status_code, StatusCode::PartialContent);
+    assert_eq!(
+        response.headers.get(HeaderType::ContentRange),
+        Some("bytes 2-4/10")
+    );
+    assert_eq!(read_body(response), b"234");
+
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=0-1, 8-")]);
+    let response = apply_file_range(&request, file_response(), &path).unwrap();
+
+    let content_type = response.headers.get(HeaderType::ContentType).unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap()
+        .to_string();
+
+    let expected_body = format!(
+        "--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--{0}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{0}--\r\n",
+        boundary
+    );
+
+    assert_eq!(read_body(response), expected_body.as_bytes());
+
+    // The whole file is streamed if the client's copy is outdated
+    let request = request_with_headers(&[
+        (HeaderType::Range, "bytes=2-4"),
+        (HeaderType::IfRange, "\"def\""),
+    ]);
+    let response = apply_file_range(&request, file_response(), &path).unwrap();
+
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(read_body(response), b"0123456789");
+
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=20-")]);
+    let response = apply_file_range(&request, file_response(), &path).unwrap();
+
+    assert_eq!(
+        response.status_code,
+        StatusCode::RequestedRangeNotSatisfiable
+    );
+    assert_eq!(
+        response.headers.get(HeaderType::ContentRange),
+        Some("bytes */10")
+    );
+
+    std::fs::remove_file(&path).unwrap();
+
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=2-4")]);
+    assert!(apply_file_range(&request, file_response(), &path).is_err());
+}
+
+#[tokio::test]
+#[cfg(feature = "tokio")]
+async fn test_file_range() {
+    use crate::http::range::apply_file_range;
+    use tokio::io::AsyncReadExt;
+
+    let path = std::env::temp_dir().join(format!("humphrey-range-tokio-{}", std::process::id()));
+    std::fs::write(&path, b"0123456789").unwrap();
+
+    let request = request_with_headers(&[(HeaderType::Range, "bytes=0-1, 8-")]);
+    let response = apply_file_range(&request, Response::empty(StatusCode::OK), &path).unwrap();
+
+    let content_type = response.headers.get(HeaderType::ContentType).unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap()
+        .to_string();
+
+    let expected_body = format!(
+        "--{0}\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--{0}\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{0}--\r\n",
+        boundary
+    );
+
+    let mut stream = response.stream.unwrap();
+    let mut body = Vec::new();
+    stream.source.read_to_end(&mut body).await.unwrap();
+
+    assert_eq!(body, expected_body.as_bytes());
+    assert_eq!(stream.length, Some(body.len() as u64));
+
+    std::fs::remove_file(&path).unwrap();
+}
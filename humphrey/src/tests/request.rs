@@ -3,11 +3,13 @@ use crate::http::address::Address;
 use crate::http::cookie::Cookie;
 use crate::http::headers::{Header, HeaderType, Headers};
 use crate::http::method::Method;
+use crate::http::request::{BodyLength, RequestBody, RequestError};
 use crate::http::Request;
+use crate::tests::mock_request;
 use crate::tests::mock_stream::MockStream;
 
-use std::collections::{BTreeMap, VecDeque};
-use std::io::Read;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{BufReader, Read};
 use std::iter::FromIterator;
 use std::net::{SocketAddr, ToSocketAddrs};
 
@@ -50,13 +52,10 @@ fn test_cookie_request() {
 #[test]
 fn test_bytes_from_request() {
     let mut test_data = Request {
-        method: Method::Get,
         uri: "/test".into(),
         query: "foo=bar".into(),
-        version: "HTTP/1.1".into(),
-        headers: Headers::new(),
         content: Some(b"this is a test".to_vec()),
-        address: Address::new("1.2.3.4:5678").unwrap(),
+        ..mock_request()
     };
 
     test_data.headers.add(HeaderType::ContentLength, "14");
@@ -97,3 +96,198 @@ fn test_proxied_request_from_stream() {
 
     assert_eq!(request.headers, expected_headers);
 }
+
+#[test]
+fn test_request_body_from_stream() {
+    let test_data =
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 14\r\n\r\nthis is a test";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let request = Request::from_stream(&mut stream, "1.2.3.4:5678".parse().unwrap()).unwrap();
+
+    assert_eq!(request.method, Method::Post);
+    assert_eq!(request.content, Some(b"this is a test".to_vec()));
+}
+
+#[test]
+fn test_truncated_request_body() {
+    let test_data =
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 20\r\n\r\nthis is a test";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let request = Request::from_stream(&mut stream, "1.2.3.4:5678".parse().unwrap());
+
+    assert_eq!(request.unwrap_err(), RequestError::Stream);
+}
+
+#[test]
+fn test_request_body_max_size() {
+    let test_data =
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 14\r\n\r\nthis is a test";
+    let addr: SocketAddr = "1.2.3.4:5678".parse().unwrap();
+
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().skip(1).cloned()));
+    let mut reader = BufReader::new(&mut stream);
+    let mut request = Request::head_from_reader(&mut reader, addr, b'P').unwrap();
+    assert_eq!(
+        request.read_body(&mut reader, Some(13)),
+        Err(RequestError::PayloadTooLarge)
+    );
+    assert_eq!(request.content, None);
+
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().skip(1).cloned()));
+    let mut reader = BufReader::new(&mut stream);
+    let mut request = Request::head_from_reader(&mut reader, addr, b'P').unwrap();
+    assert_eq!(request.read_body(&mut reader, Some(14)), Ok(()));
+    assert_eq!(request.content, Some(b"this is a test".to_vec()));
+}
+
+#[test]
+fn test_streamed_request_body() {
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 14\r\n\r\nthis is a testGET / HTTP/1.1\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().skip(1).cloned()));
+    let mut reader = BufReader::new(&mut stream);
+    let request =
+        Request::head_from_reader(&mut reader, "1.2.3.4:5678".parse().unwrap(), b'P').unwrap();
+
+    assert_eq!(request.content, None);
+    assert_eq!(request.body_length(), Ok(BodyLength::Fixed(14)));
+
+    let mut body = RequestBody::new(&mut reader, BodyLength::Fixed(14));
+    let mut first_buf = [0; 4];
+    body.read_exact(&mut first_buf).unwrap();
+    assert_eq!(&first_buf, b"this");
+    assert!(!body.is_finished());
+
+    let mut rest = Vec::new();
+    body.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b" is a test");
+    assert!(body.is_finished());
+
+    let mut next_request = Vec::new();
+    reader.read_to_end(&mut next_request).unwrap();
+    assert_eq!(next_request, b"GET / HTTP/1.1\r\n\r\n");
+}
+
+#[test]
+fn test_chunked_request_from_stream() {
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n7;ext=foo\r\nthis is\r\n7\r\n a test\r\n0\r\nX-Checksum: 1234\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let request = Request::from_stream(&mut stream, "1.2.3.4:5678".parse().unwrap()).unwrap();
+
+    assert_eq!(request.content, Some(b"this is a test".to_vec()));
+
+    let mut expected_headers: Headers = Headers::new();
+    expected_headers.add(HeaderType::Host, "localhost");
+    expected_headers.add(HeaderType::ContentLength, "14");
+
+    let mut expected_trailers: Headers = Headers::new();
+    expected_trailers.add("X-Checksum", "1234");
+
+    assert_eq!(request.headers, expected_headers);
+    assert_eq!(request.trailers, expected_trailers);
+}
+
+#[test]
+fn test_chunked_request_max_size() {
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nthis is\r\n7\r\n a test\r\n0\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().skip(1).cloned()));
+    let mut reader = BufReader::new(&mut stream);
+    let mut request =
+        Request::head_from_reader(&mut reader, "1.2.3.4:5678".parse().unwrap(), b'P').unwrap();
+
+    assert_eq!(
+        request.read_body(&mut reader, Some(10)),
+        Err(RequestError::PayloadTooLarge)
+    );
+}
+
+#[test]
+fn test_chunked_request_size_overflow() {
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nthis is\r\nffffffffffffffff\r\n a test\r\n0\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().skip(1).cloned()));
+    let mut reader = BufReader::new(&mut stream);
+    let mut request =
+        Request::head_from_reader(&mut reader, "1.2.3.4:5678".parse().unwrap(), b'P').unwrap();
+
+    assert_eq!(
+        request.read_body(&mut reader, Some(10)),
+        Err(RequestError::PayloadTooLarge)
+    );
+}
+
+#[test]
+fn test_invalid_chunked_request() {
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nthis is\r\n0\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let request = Request::from_stream(&mut stream, "1.2.3.4:5678".parse().unwrap());
+
+    assert_eq!(request.unwrap_err(), RequestError::Request);
+
+    // Chunk size lines are limited in length, even when most of the line is extensions
+    let mut test_data =
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n1;".to_vec();
+    test_data.extend(vec![b'a'; 5000]);
+    test_data.extend(b"\r\na\r\n0\r\n\r\n");
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let request = Request::from_stream(&mut stream, "1.2.3.4:5678".parse().unwrap());
+
+    assert_eq!(request.unwrap_err(), RequestError::Request);
+
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: gzip\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let request = Request::from_stream(&mut stream, "1.2.3.4:5678".parse().unwrap());
+
+    assert_eq!(request.unwrap_err(), RequestError::Request);
+}
+
+#[test]
+fn test_streamed_chunked_request_body() {
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nthis is\r\n7\r\n a test\r\n0\r\nX-Checksum: 1234\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().skip(1).cloned()));
+    let mut reader = BufReader::new(&mut stream);
+    let request =
+        Request::head_from_reader(&mut reader, "1.2.3.4:5678".parse().unwrap(), b'P').unwrap();
+
+    assert_eq!(request.body_length(), Ok(BodyLength::Chunked));
+
+    let mut body = RequestBody::new(&mut reader, BodyLength::Chunked);
+    let mut content = Vec::new();
+    body.read_to_end(&mut content).unwrap();
+
+    assert_eq!(content, b"this is a test");
+    assert!(body.is_finished());
+    assert_eq!(body.trailers().get("X-Checksum"), Some("1234"));
+
+    let mut next_request = Vec::new();
+    reader.read_to_end(&mut next_request).unwrap();
+    assert_eq!(next_request, b"GET / HTTP/1.1\r\n\r\n");
+}
+
+#[test]
+fn test_chunked_request_trailer_limits() {
+    let addr: SocketAddr = "1.2.3.4:5678".parse().unwrap();
+    let head = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n";
+
+    let mut test_data = head.to_vec();
+    test_data.extend(format!("X-Long: {}\r\n\r\n", "a".repeat(10000)).bytes());
+    let mut stream = MockStream::with_data(VecDeque::from(test_data));
+    assert_eq!(
+        Request::from_stream(&mut stream, addr).unwrap_err(),
+        RequestError::PayloadTooLarge
+    );
+
+    let mut test_data = head.to_vec();
+    test_data.extend("X-Many: a\r\n".repeat(101).bytes());
+    test_data.extend(b"\r\n");
+    let mut stream = MockStream::with_data(VecDeque::from(test_data));
+    assert_eq!(
+        Request::from_stream(&mut stream, addr).unwrap_err(),
+        RequestError::PayloadTooLarge
+    );
+
+    let mut test_data = head.to_vec();
+    test_data.extend("X-Many: a\r\n".repeat(100).bytes());
+    test_data.extend(b"\r\n");
+    let mut stream = MockStream::with_data(VecDeque::from(test_data));
+    let request = Request::from_stream(&mut stream, addr).unwrap();
+    assert_eq!(request.trailers.len(), 100);
+}
@@ -4,10 +4,12 @@ use crate::http::address::Address;
 use crate::http::cookie::Cookie;
 use crate::http::headers::{Header, HeaderType, Headers};
 use crate::http::method::Method;
+use crate::http::request::RequestError;
 use crate::http::Request;
+use crate::tests::mock_request;
 use crate::tests::mock_stream::MockStream;
 
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::io::Read;
 use std::iter::FromIterator;
 use std::net::{SocketAddr, ToSocketAddrs};
@@ -60,13 +62,10 @@ fn test_cookie_request() {
 #[test]
 fn test_bytes_from_request() {
     let mut test_data = Request {
-        method: Method::Get,
         uri: "/test".into(),
         query: "foo=bar".into(),
-        version: "HTTP/1.1".into(),
-        headers: Headers::new(),
         content: Some(b"this is a test".to_vec()),
-        address: Address::new("1.2.3.4:5678").unwrap(),
+        ..mock_request()
     };
 
     test_data.headers.add(HeaderType::ContentLength, "14");
@@ -110,3 +109,78 @@ fn test_proxied_request_from_stream() {
 
     assert_eq!(request.headers, expected_headers);
 }
+
+#[test]
+fn test_request_body_max_size() {
+    let test_data =
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 14\r\n\r\nthis is a test";
+    let addr: SocketAddr = "1.2.3.4:5678".parse().unwrap();
+
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let mut reader = tokio::io::BufReader::new(&mut stream);
+    let mut request = block_on(Request::head_from_reader(&mut reader, addr)).unwrap();
+    assert_eq!(
+        block_on(request.read_body(&mut reader, Some(13))),
+        Err(RequestError::PayloadTooLarge)
+    );
+    assert_eq!(request.content, None);
+
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let mut reader = tokio::io::BufReader::new(&mut stream);
+    let mut request = block_on(Request::head_from_reader(&mut reader, addr)).unwrap();
+    assert_eq!(block_on(request.read_body(&mut reader, Some(14))), Ok(()));
+    assert_eq!(request.content, Some(b"this is a test".to_vec()));
+}
+
+#[test]
+fn test_chunked_request_from_stream() {
+    let test_data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n7;ext=foo\r\nthis is\r\n7\r\n a test\r\n0\r\nX-Checksum: 1234\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let request = block_on(Request::from_stream(
+        &mut stream,
+        "1.2.3.4:5678".parse().unwrap(),
+    ))
+    .unwrap();
+
+    assert_eq!(request.content, Some(b"this is a test".to_vec()));
+
+    let mut expected_headers: Headers = Headers::new();
+    expected_headers.add(HeaderType::Host, "localhost");
+    expected_headers.add(HeaderType::ContentLength, "14");
+
+    let mut expected_trailers: Headers = Headers::new();
+    expected_trailers.add("X-Checksum", "1234");
+
+    assert_eq!(request.headers, expected_headers);
+    assert_eq!(request.trailers, expected_trailers);
+}
+
+#[test]
+fn test_chunked_request_trailer_limits() {
+    let addr: SocketAddr = "1.2.3.4:5678".parse().unwrap();
+    let head = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n";
+
+    let mut test_data = head.to_vec();
+    test_data.extend(format!("X-Long: {}\r\n\r\n", "a".repeat(10000)).bytes());
+    let mut stream = MockStream::with_data(VecDeque::from(test_data));
+    assert_eq!(
+        block_on(Request::from_stream(&mut stream, addr)).unwrap_err(),
+        RequestError::PayloadTooLarge
+    );
+
+    let mut test_data = head.to_vec();
+    test_data.extend("X-Many: a\r\n".repeat(101).bytes());
+    test_data.extend(b"\r\n");
+    let mut stream = MockStream::with_data(VecDeque::from(test_data));
+    assert_eq!(
+        block_on(Request::from_stream(&mut stream, addr)).unwrap_err(),
+        RequestError::PayloadTooLarge
+    );
+
+    let mut test_data = head.to_vec();
+    test_data.extend("X-Many: a\r\n".repeat(100).bytes());
+    test_data.extend(b"\r\n");
+    let mut stream = MockStream::with_data(VecDeque::from(test_data));
+    let request = block_on(Request::from_stream(&mut stream, addr)).unwrap();
+    assert_eq!(request.trailers.len(), 100);
+}
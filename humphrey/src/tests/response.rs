@@ -79,3 +79,58 @@ fn test_response_from_stream() {
     expected_headers.add(HeaderType::ContentLength, "51");
     assert_eq!(response.headers, expected_headers);
 }
+
+#[test]
+fn test_response_head_from_stream() {
+    let test_data = b"HTTP/1.1 200 OK\r\nContent-Length: 51\r\n\r\n";
+    let mut stream = MockStream::with_data(VecDeque::from_iter(test_data.iter().cloned()));
+    let response = Response::head_from_stream(&mut stream).unwrap();
+
+    assert!(response.body.is_empty());
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.headers.get(HeaderType::ContentLength), Some("51"));
+}
+
+#[test]
+#[cfg(not(feature = "tokio"))]
+fn test_streamed_response() {
+    let source = std::io::Cursor::new(b"Hello, world!".to_vec());
+    let response = Response::empty(StatusCode::OK)
+        .with_header(HeaderType::ContentLength, "13")
+        .with_stream(source, Some(13));
+
+    assert_eq!(response.content_length(), Some(13));
+
+    let mut bytes: Vec<u8> = Vec::new();
+    response.write_to(&mut bytes).unwrap();
+
+    let expected_bytes: Vec<u8> =
+        b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\nHello, world!".to_vec();
+
+    assert_eq!(bytes, expected_bytes);
+}
+
+#[test]
+#[cfg(not(feature = "tokio"))]
+fn test_chunked_streamed_response() {
+    let source = std::io::Cursor::new(b"Hello, world!".to_vec());
+    let response = Response::empty(StatusCode::OK)
+        .with_header(HeaderType::TransferEncoding, "chunked")
+        .with_stream(source, None);
+
+    assert_eq!(response.content_length(), None);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    response.write_to(&mut bytes).unwrap();
+
+    let expected_bytes: Vec<u8> =
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nD\r\nHello, world!\r\n0\r\n\r\n"
+            .to_vec();
+
+    assert_eq!(bytes, expected_bytes);
+
+    let mut stream = MockStream::with_data(VecDeque::from_iter(bytes.iter().cloned()));
+    let parsed = Response::from_stream(&mut stream).unwrap();
+
+    assert_eq!(parsed.body, b"Hello, world!".to_vec());
+}
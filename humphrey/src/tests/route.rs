@@ -0,0 +1,101 @@
+use crate::route::{find_route, Route};
+
+use std::collections::HashMap;
+
+fn params(pairs: &[(&str, &str)]) -> Option<HashMap<String, String>> {
+    Some(
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    )
+}
+
+fn find(routes: &[&str], path: &str) -> Option<(usize, HashMap<String, String>)> {
+    let routes: Vec<String> = routes.iter().map(|route| route.to_string()).collect();
+    find_route(routes.iter(), path)
+}
+
+#[test]
+fn test_named_params() {
+    let route = "/users/:id/posts/:post_id".to_string();
+
+    assert_eq!(
+        route.route_params("/users/42/posts/7"),
+        params(&[("id", "42"), ("post_id", "7")])
+    );
+    assert_eq!(
+        route.route_params("/users/jo%20bloggs/posts/7"),
+        params(&[("id", "jo bloggs"), ("post_id", "7")])
+    );
+    assert_eq!(route.route_params("/users/42/posts"), None);
+    assert_eq!(route.route_params("/users//posts/7"), None);
+    assert_eq!(route.route_params("/users/42/posts/7/comments"), None);
+    assert_eq!(route.route_params("/accounts/42/posts/7"), None);
+}
+
+#[test]
+fn test_catch_all_params() {
+    let route = "/files/*:path".to_string();
+
+    assert_eq!(
+        route.route_params("/files/docs/readme.md"),
+        params(&[("path", "docs/readme.md")])
+    );
+    assert_eq!(route.route_params("/files/"), params(&[("path", "")]));
+    assert_eq!(route.route_params("/files"), params(&[("path", "")]));
+    assert_eq!(route.route_params("/filesystem"), None);
+
+    let route = "/users/:id/*".to_string();
+
+    assert_eq!(
+        route.route_params("/users/42/anything/else"),
+        params(&[("id", "42")])
+    );
+}
+
+#[test]
+fn test_wildcard_routes_unchanged() {
+    assert_eq!("/blog/*".to_string().route_params("/blog/a/b"), params(&[]));
+    assert_eq!("/*.html".to_string().route_params("/a/b.html"), params(&[]));
+    assert!("*".to_string().route_matches("/anything"));
+    assert!(!"/blog/*".to_string().route_matches("/about"));
+
+    // Wildcards followed by a suffix are globs, not catch-alls
+    assert_eq!(
+        "/images/*png".to_string().route_params("/images/cat.png"),
+        params(&[])
+    );
+    assert_eq!("/*html".to_string().route_params("/a/b.html"), params(&[]));
+    assert!(!"/images/*png".to_string().route_matches("/images/cat.jpg"));
+    assert!(!"/*html".to_string().route_matches("/a/b.css"));
+}
+
+#[test]
+fn test_route_specificity() {
+    let routes = [
+        "/*",
+        "/users/:id",
+        "/users/me",
+        "/users/*",
+        "/users/:id/*:rest",
+    ];
+
+    assert_eq!(find(&routes, "/users/me"), Some((2, params(&[]).unwrap())));
+    assert_eq!(
+        find(&routes, "/users/42"),
+        Some((1, params(&[("id", "42")]).unwrap()))
+    );
+    assert_eq!(
+        find(&routes, "/users/42/posts"),
+        Some((4, params(&[("id", "42"), ("rest", "posts")]).unwrap()))
+    );
+    assert_eq!(find(&routes, "/about"), Some((0, params(&[]).unwrap())));
+    assert_eq!(find(&["/blog"], "/about"), None);
+
+    // Equally specific routes are chosen in the order they were added
+    assert_eq!(
+        find(&["/:a", "/:b"], "/x"),
+        Some((0, params(&[("a", "x")]).unwrap()))
+    );
+}
@@ -0,0 +1,287 @@
+use crate::http::identity::{AltName, PeerIdentity};
+use crate::tls::{load_certified_key, CertError, CertResolver, ClientAuth, ClientVerifier};
+
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerConfig,
+    ServerConnection,
+};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+
+use std::convert::TryInto;
+use std::fs::{copy, create_dir_all, remove_dir_all, write, File};
+use std::io::BufReader;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+const CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/certs/");
+
+fn cert_path(name: &str) -> String {
+    format!("{}{}.pem", CERTS, name)
+}
+
+fn key_path(name: &str) -> String {
+    format!("{}{}.key", CERTS, name)
+}
+
+fn read_cert(path: &str) -> Certificate {
+    let mut reader = BufReader::new(File::open(path).unwrap());
+    Certificate(certs(&mut reader).unwrap().remove(0))
+}
+
+fn resolver() -> CertResolver {
+    CertResolver::new()
+        .with_default_cert(cert_path("localhost"), key_path("localhost"))
+        .unwrap()
+        .with_host_cert(
+            "ONE.example.com",
+            cert_path("one.example.com"),
+            key_path("one.example.com"),
+        )
+        .unwrap()
+        .with_host_cert(
+            "*.two.example.com",
+            cert_path("two.example.com"),
+            key_path("two.example.com"),
+        )
+        .unwrap()
+}
+
+fn read_key(path: &str) -> PrivateKey {
+    let mut reader = BufReader::new(File::open(path).unwrap());
+    PrivateKey(pkcs8_private_keys(&mut reader).unwrap().remove(0))
+}
+
+fn client_config(client_cert: Option<&str>) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add(&read_cert(&cert_path("ca"))).unwrap();
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    match client_cert {
+        Some(name) => builder
+            .with_client_auth_cert(vec![read_cert(&cert_path(name))], read_key(&key_path(name)))
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    }
+}
+
+/// Performs a TLS handshake in memory with the given server name, returning the connections once it is complete.
+fn connect(
+    client_config: ClientConfig,
+    server_config: ServerConfig,
+    server_name: &str,
+) -> Result<(ClientConnection, ServerConnection), rustls::Error> {
+    let mut client =
+        ClientConnection::new(Arc::new(client_config), server_name.try_into().unwrap()).unwrap();
+    let mut server = ServerConnection::new(Arc::new(server_config)).unwrap();
+
+    while client.is_handshaking() || server.is_handshaking() {
+        let mut buf = Vec::new();
+
+        while client.wants_write() {
+            client.write_tls(&mut buf).unwrap();
+        }
+        server.read_tls(&mut buf.as_slice()).unwrap();
+        server.process_new_packets()?;
+
+        buf.clear();
+
+        while server.wants_write() {
+            server.write_tls(&mut buf).unwrap();
+        }
+        client.read_tls(&mut buf.as_slice()).unwrap();
+        client.process_new_packets()?;
+    }
+
+    Ok((client, server))
+}
+
+/// Performs a TLS handshake in memory with the given server name, returning the certificate sent by the server.
+fn handshake(resolver: Arc<CertResolver>, server_name: &str) -> Certificate {
+    let server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+
+    let (client, server) = connect(client_config(None), server_config, server_name).unwrap();
+
+    assert_eq!(server.server_name(), Some(server_name));
+
+    client.peer_certificates().unwrap()[0].clone()
+}
+
+/// Performs a TLS handshake in memory with client authentication, returning the identity of the client.
+fn client_auth_handshake(
+    client_auth: ClientAuth,
+    client_cert: Option<&str>,
+) -> Result<Option<PeerIdentity>, rustls::Error> {
+    let verifier = ClientVerifier::from_ca_file(cert_path("client-ca"), client_auth).unwrap();
+    let server_config = verifier
+        .config_builder()
+        .with_cert_resolver(Arc::new(resolver()));
+
+    let (_, server) = connect(client_config(client_cert), server_config, "localhost")?;
+
+    Ok(server
+        .peer_certificates()
+        .map(|certs| PeerIdentity::from_der(&certs[0].0).unwrap()))
+}
+
+#[test]
+fn test_cert_resolver() {
+    let resolver = resolver();
+    let cert = |host| resolver.cert_for(host).unwrap().cert[0].clone();
+
+    assert_eq!(
+        cert(Some("one.example.com")),
+        read_cert(&cert_path("one.example.com"))
+    );
+    assert_eq!(
+        cert(Some("a.TWO.example.com")),
+        read_cert(&cert_path("two.example.com"))
+    );
+    assert_eq!(
+        cert(Some("example.com")),
+        read_cert(&cert_path("localhost"))
+    );
+    assert_eq!(cert(None), read_cert(&cert_path("localhost")));
+
+    assert!(CertResolver::new().cert_for(Some("localhost")).is_none());
+    assert_eq!(resolver.paths().len(), 6);
+}
+
+#[test]
+fn test_sni_handshake() {
+    let resolver = Arc::new(
+        CertResolver::new()
+            .with_default_cert(cert_path("localhost"), key_path("localhost"))
+            .unwrap()
+            .with_host_cert(
+                "one.example.com",
+                cert_path("one.example.com"),
+                key_path("one.example.com"),
+            )
+            .unwrap()
+            .with_host_cert(
+                "two.example.com",
+                cert_path("two.example.com"),
+                key_path("two.example.com"),
+            )
+            .unwrap(),
+    );
+
+    for name in ["localhost", "one.example.com", "two.example.com"] {
+        assert_eq!(
+            handshake(resolver.clone(), name),
+            read_cert(&cert_path(name))
+        );
+    }
+}
+
+#[test]
+fn test_cert_reload() {
+    let dir = std::env::temp_dir().join(format!("humphrey-tls-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+
+    let cert = dir.join("cert.pem").to_string_lossy().to_string();
+    let key = dir.join("key.pem").to_string_lossy().to_string();
+
+    copy(cert_path("localhost"), &cert).unwrap();
+    copy(key_path("localhost"), &key).unwrap();
+
+    let resolver = CertResolver::new().with_default_cert(&cert, &key).unwrap();
+    let current = || resolver.cert_for(None).unwrap().cert[0].clone();
+
+    // Renewed certificates are only used once the resolver is reloaded
+    copy(cert_path("one.example.com"), &cert).unwrap();
+    copy(key_path("one.example.com"), &key).unwrap();
+    assert_eq!(current(), read_cert(&cert_path("localhost")));
+
+    assert_eq!(resolver.reload(), Ok(()));
+    assert_eq!(current(), read_cert(&cert_path("one.example.com")));
+
+    // Invalid certificates are not used
+    write(&cert, "not a certificate").unwrap();
+    assert_eq!(resolver.reload(), Err(CertError::Certificate(cert.clone())));
+    assert_eq!(current(), read_cert(&cert_path("one.example.com")));
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_load_errors() {
+    let missing = format!("{}missing.pem", CERTS);
+
+    assert_eq!(
+        load_certified_key(&missing, key_path("localhost")).err(),
+        Some(CertError::File(missing))
+    );
+    assert_eq!(
+        load_certified_key(key_path("localhost"), key_path("localhost")).err(),
+        Some(CertError::Certificate(key_path("localhost")))
+    );
+    assert_eq!(
+        load_certified_key(cert_path("localhost"), cert_path("localhost")).err(),
+        Some(CertError::Key(cert_path("localhost")))
+    );
+}
+
+#[test]
+fn test_peer_identity() {
+    let identity = PeerIdentity::from_der(&read_cert(&cert_path("client")).0).unwrap();
+
+    assert_eq!(identity.subject, "CN=client,OU=Services,O=Humphrey,C=GB");
+    assert_eq!(identity.common_name.as_deref(), Some("client"));
+    assert_eq!(identity.issuer, "CN=Humphrey Test Client CA,O=Humphrey");
+    assert_eq!(
+        identity.alt_names,
+        vec![
+            AltName::Dns("client.example.com".into()),
+            AltName::Uri("spiffe://example.com/client".into()),
+            AltName::Email("client@example.com".into()),
+            AltName::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        ]
+    );
+    assert_eq!(
+        identity.dns_names().collect::<Vec<_>>(),
+        vec!["client.example.com"]
+    );
+    assert_eq!(
+        identity.uris().collect::<Vec<_>>(),
+        vec!["spiffe://example.com/client"]
+    );
+
+    assert!(PeerIdentity::from_der(b"not a certificate").is_none());
+}
+
+#[test]
+fn test_client_auth() {
+    let identity = client_auth_handshake(ClientAuth::Required, Some("client")).unwrap();
+    assert_eq!(
+        identity.unwrap().subject,
+        "CN=client,OU=Services,O=Humphrey,C=GB"
+    );
+
+    // Clients without a certificate are only allowed when authentication is optional
+    assert!(client_auth_handshake(ClientAuth::Required, None).is_err());
+    assert_eq!(client_auth_handshake(ClientAuth::Optional, None), Ok(None));
+
+    // Certificates which are not signed by the client CA are rejected
+    assert!(client_auth_handshake(ClientAuth::Optional, Some("localhost")).is_err());
+}
+
+#[test]
+fn test_client_verifier_errors() {
+    let missing = format!("{}missing.pem", CERTS);
+
+    assert_eq!(
+        ClientVerifier::from_ca_file(&missing, ClientAuth::Required).err(),
+        Some(CertError::File(missing))
+    );
+    assert_eq!(
+        ClientVerifier::from_ca_file(key_path("client"), ClientAuth::Required).err(),
+        Some(CertError::Certificate(key_path("client")))
+    );
+}
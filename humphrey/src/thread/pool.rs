@@ -4,10 +4,11 @@ use crate::monitor::event::EventType;
 use crate::monitor::MonitorConfig;
 use crate::thread::recovery::{PanicMarker, RecoveryThread};
 
+use std::cell::RefCell;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
-use std::thread::{Builder, JoinHandle};
-use std::time::Instant;
+use std::thread::{sleep, Builder, JoinHandle};
+use std::time::{Duration, Instant};
 
 /// The number of milliseconds a task can be waiting in the pool before the pool is considered overloaded.
 const OVERLOAD_THRESHOLD: u128 = 100;
@@ -20,6 +21,18 @@ pub struct ThreadPool {
     recovery_thread: Option<RecoveryThread>,
     tx: Sender<Message>,
     monitor: Option<MonitorConfig>,
+    drain_timeout: Option<Duration>,
+}
+
+/// A handle to a thread pool, which can be used to give it tasks from its own threads.
+#[derive(Clone)]
+pub struct ThreadPoolHandle {
+    tx: Sender<Message>,
+}
+
+thread_local! {
+    /// The pool which gave the current thread its task.
+    static CURRENT_POOL: RefCell<Option<ThreadPoolHandle>> = const { RefCell::new(None) };
 }
 
 /// Represents a single worker thread in the thread pool
@@ -67,6 +80,7 @@ impl ThreadPool {
             recovery_thread: None,
             tx: channel().0,
             monitor: None,
+            drain_timeout: None,
         }
     }
 
@@ -104,16 +118,54 @@ impl ThreadPool {
     }
 
     /// Stops the thread pool.
+    ///
+    /// Tasks which have already been given to the pool are still run. If a drain timeout has been set,
+    ///   this waits for up to that long for them to finish, otherwise it returns immediately.
     pub fn stop(&mut self) {
         if let Some(ref recovery_thread) = self.recovery_thread {
             if let Err(err) = recovery_thread.tx.send(None) { println!("{err}") }
         }
         self.recovery_thread = None;
-        if let Err(err) = self.tx.send(Message::Shutdown) { println!("{err}") }
+
+        // Every thread stops after receiving one shutdown message, which it only does after the tasks before it
+        // If sending fails, every thread has already stopped, so there is nothing left to do
+        for _ in 0..self.thread_count {
+            if self.tx.send(Message::Shutdown).is_err() {
+                break;
+            }
+        }
+
+        if let Some(drain_timeout) = self.drain_timeout {
+            let start = Instant::now();
+
+            while start.elapsed() < drain_timeout && !self.is_finished() {
+                sleep(Duration::from_millis(10));
+            }
+        }
+
         self.monitor = None;
         self.started = false;
     }
 
+    /// Sets the maximum amount of time `stop` waits for running tasks to finish.
+    ///
+    /// Threads which are still running after this time are left to finish in the background.
+    pub fn set_drain_timeout(&mut self, drain_timeout: Option<Duration>) {
+        self.drain_timeout = drain_timeout;
+    }
+
+    /// Returns true if every thread in the pool has stopped.
+    fn is_finished(&self) -> bool {
+        self.threads
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|thread| match &thread.os_thread {
+                Some(handle) => handle.is_finished(),
+                None => true,
+            })
+    }
+
     /// Register a monitor for the thread pool.
     pub fn register_monitor(&mut self, monitor: MonitorConfig) {
         self.monitor = Some(monitor);
@@ -129,19 +181,59 @@ impl ThreadPool {
     {
         assert!(self.started);
 
-        let boxed_task = Box::new(task);
+        let boxed_task = self.handle().enter(task);
         let time_into_pool = Instant::now();
         self.tx
             .send(Message::Function(boxed_task, time_into_pool))
             .unwrap();
     }
 
+    /// Gets a handle to the thread pool, which is only valid until the pool is stopped or restarted.
+    pub fn handle(&self) -> ThreadPoolHandle {
+        ThreadPoolHandle {
+            tx: self.tx.clone(),
+        }
+    }
+
     /// Returns the configured number of threads.
     pub fn thread_count(&self) -> usize {
         self.thread_count
     }
 }
 
+impl ThreadPoolHandle {
+    /// Gets a handle to the pool which is running the current task, or `None` if the current thread is not in a
+    ///   thread pool.
+    pub fn current() -> Option<Self> {
+        CURRENT_POOL.with(|pool| pool.borrow().clone())
+    }
+
+    /// Executes a task in the thread pool.
+    ///
+    /// If the pool has been stopped, the task is never run.
+    pub fn execute<F>(&self, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let boxed_task = self.clone().enter(task);
+        let time_into_pool = Instant::now();
+        self.tx
+            .send(Message::Function(boxed_task, time_into_pool))
+            .ok();
+    }
+
+    /// Wraps the task so that the pool is the current pool while it runs.
+    fn enter<F>(self, task: F) -> Task
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        Box::new(move || {
+            CURRENT_POOL.with(|pool| *pool.borrow_mut() = Some(self));
+            task()
+        })
+    }
+}
+
 impl Thread {
     /// Creates a new thread.
     pub fn new(
@@ -0,0 +1,335 @@
+//! Provides certificate handling for TLS, including selecting certificates by the requested host name and
+//!   verifying client certificates.
+
+use crate::krauss::wildcard_match;
+
+use rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientHello,
+    ResolvesServerCert, WantsServerCert,
+};
+use rustls::sign::{any_supported_type, CertifiedKey};
+use rustls::{Certificate, ConfigBuilder, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::{read_all, Item};
+
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+
+/// Selects the certificate for each TLS connection using the host name sent by the client with SNI
+///   (Server Name Indication), so that one server can serve multiple hosts with different certificates.
+///
+/// Host names are matched against wildcard patterns such as `*.example.com` in the order the certificates were
+///   added. The default certificate is used when no pattern matches or the client does not send a host name.
+///
+/// The certificates are read from disk when they are added, and can be read again with `reload` to pick up
+///   renewed certificates without restarting the server.
+///
+/// ## Example
+/// ```
+/// let resolver = CertResolver::new()
+///     .with_default_cert("cert.pem", "key.pem")?
+///     .with_host_cert("*.example.com", "example.pem", "example.key")?;
+///
+/// let app = App::new().with_cert_resolver(Arc::new(resolver));
+/// ```
+#[derive(Default)]
+pub struct CertResolver {
+    certs: RwLock<ResolverCerts>,
+}
+
+/// The certificates currently used by a resolver.
+#[derive(Clone, Default)]
+struct ResolverCerts {
+    default: Option<LoadedCert>,
+    hosts: Vec<(String, LoadedCert)>,
+}
+
+/// A certificate and key which have been read from disk, along with the paths they were read from.
+#[derive(Clone)]
+struct LoadedCert {
+    cert_path: String,
+    key_path: String,
+    key: Arc<CertifiedKey>,
+}
+
+/// Verifies TLS client certificates against a set of trusted CAs, so that clients can authenticate with them.
+///
+/// ## Example
+/// ```
+/// let verifier = ClientVerifier::from_ca_file("ca.pem", ClientAuth::Required)?;
+///
+/// let app = App::new()
+///     .with_cert("cert.pem", "key.pem")
+///     .with_client_auth(verifier);
+/// ```
+#[derive(Clone)]
+pub struct ClientVerifier {
+    roots: RootCertStore,
+    client_auth: ClientAuth,
+}
+
+/// Whether clients must authenticate with a TLS client certificate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Clients must send a certificate signed by a trusted CA, otherwise the handshake fails.
+    Required,
+    /// Clients are asked for a certificate but may connect without one. Certificates which are sent must still
+    ///   be signed by a trusted CA.
+    Optional,
+}
+
+/// An error which occurred while loading a certificate or key.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CertError {
+    /// The file at the given path could not be read.
+    File(String),
+    /// The file at the given path does not contain a certificate.
+    Certificate(String),
+    /// The file at the given path does not contain a supported private key.
+    Key(String),
+}
+
+impl CertResolver {
+    /// Creates a new resolver without any certificates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the certificate used when no host pattern matches the requested host name.
+    pub fn with_default_cert(
+        self,
+        cert_path: impl AsRef<str>,
+        key_path: impl AsRef<str>,
+    ) -> Result<Self, CertError> {
+        let cert = LoadedCert::load(cert_path.as_ref(), key_path.as_ref())?;
+        self.certs.write().unwrap().default = Some(cert);
+
+        Ok(self)
+    }
+
+    /// Adds a certificate for the host names matching the given wildcard pattern.
+    pub fn with_host_cert(
+        self,
+        host: impl AsRef<str>,
+        cert_path: impl AsRef<str>,
+        key_path: impl AsRef<str>,
+    ) -> Result<Self, CertError> {
+        let cert = LoadedCert::load(cert_path.as_ref(), key_path.as_ref())?;
+        self.certs
+            .write()
+            .unwrap()
+            .hosts
+            .push((host.as_ref().to_ascii_lowercase(), cert));
+
+        Ok(self)
+    }
+
+    /// Reads every certificate and key from disk again.
+    ///
+    /// The new certificates are only used if all of them could be loaded, otherwise the current certificates are
+    ///   kept and the error is returned. Connections which are already open are not affected.
+    pub fn reload(&self) -> Result<(), CertError> {
+        let current = self.certs.read().unwrap().clone();
+
+        let default = match &current.default {
+            Some(cert) => Some(cert.reload()?),
+            None => None,
+        };
+
+        let hosts = current
+            .hosts
+            .iter()
+            .map(|(host, cert)| Ok((host.clone(), cert.reload()?)))
+            .collect::<Result<_, CertError>>()?;
+
+        *self.certs.write().unwrap() = ResolverCerts { default, hosts };
+
+        Ok(())
+    }
+
+    /// Replaces the certificates of this resolver with those of another, for example after the configuration
+    ///   specifying them has changed.
+    pub fn replace(&self, other: CertResolver) {
+        *self.certs.write().unwrap() = other.certs.into_inner().unwrap();
+    }
+
+    /// Gets the paths of every certificate and key file used by this resolver.
+    pub fn paths(&self) -> Vec<String> {
+        let certs = self.certs.read().unwrap();
+
+        certs
+            .default
+            .iter()
+            .chain(certs.hosts.iter().map(|(_, cert)| cert))
+            .flat_map(|cert| [cert.cert_path.clone(), cert.key_path.clone()])
+            .collect()
+    }
+
+    /// Gets the certificate which would be used for the given host name, or `None` if there is no suitable
+    ///   certificate.
+    pub fn cert_for(&self, host: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let certs = self.certs.read().unwrap();
+
+        host.map(|host| host.to_ascii_lowercase())
+            .and_then(|host| {
+                certs
+                    .hosts
+                    .iter()
+                    .find(|(pattern, _)| wildcard_match(pattern, &host))
+                    .map(|(_, cert)| cert)
+            })
+            .or(certs.default.as_ref())
+            .map(|cert| cert.key.clone())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.cert_for(client_hello.server_name())
+    }
+}
+
+impl ClientVerifier {
+    /// Creates a verifier which trusts the CAs in the given PEM file.
+    pub fn from_ca_file(
+        ca_path: impl AsRef<str>,
+        client_auth: ClientAuth,
+    ) -> Result<Self, CertError> {
+        let ca_path = ca_path.as_ref();
+        let mut roots = RootCertStore::empty();
+
+        for item in read_pem(ca_path)? {
+            if let Item::X509Certificate(cert) = item {
+                roots
+                    .add(&Certificate(cert))
+                    .map_err(|_| CertError::Certificate(ca_path.to_string()))?;
+            }
+        }
+
+        if roots.is_empty() {
+            return Err(CertError::Certificate(ca_path.to_string()));
+        }
+
+        Ok(Self { roots, client_auth })
+    }
+
+    /// Gets whether clients must authenticate.
+    pub fn client_auth(&self) -> ClientAuth {
+        self.client_auth
+    }
+
+    /// Starts building a server configuration which authenticates clients with this verifier.
+    pub(crate) fn config_builder(&self) -> ConfigBuilder<ServerConfig, WantsServerCert> {
+        let builder = ServerConfig::builder().with_safe_defaults();
+
+        match self.client_auth {
+            ClientAuth::Required => builder.with_client_cert_verifier(
+                AllowAnyAuthenticatedClient::new(self.roots.clone()).boxed(),
+            ),
+            ClientAuth::Optional => builder.with_client_cert_verifier(
+                AllowAnyAnonymousOrAuthenticatedClient::new(self.roots.clone()).boxed(),
+            ),
+        }
+    }
+}
+
+impl LoadedCert {
+    /// Loads the certificate chain and private key from the given files.
+    fn load(cert_path: &str, key_path: &str) -> Result<Self, CertError> {
+        let key = load_certified_key(cert_path, key_path)?;
+
+        Ok(Self {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            key: Arc::new(key),
+        })
+    }
+
+    /// Loads the certificate and key again from the same files.
+    fn reload(&self) -> Result<Self, CertError> {
+        Self::load(&self.cert_path, &self.key_path)
+    }
+}
+
+/// Creates the server configuration which selects certificates with the given resolver and, if a verifier is
+///   given, authenticates clients with it.
+///
+/// If `http2` is true, clients may negotiate HTTP/2 with ALPN, and otherwise they must use HTTP/1.1.
+pub(crate) fn server_config(
+    cert_resolver: Arc<dyn ResolvesServerCert>,
+    client_verifier: Option<&ClientVerifier>,
+    http2: bool,
+) -> Arc<ServerConfig> {
+    let builder = match client_verifier {
+        Some(verifier) => verifier.config_builder(),
+        None => ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth(),
+    };
+
+    let mut config = builder.with_cert_resolver(cert_resolver);
+
+    // Clients which do not use ALPN, or do not support HTTP/2, use HTTP/1.1
+    if http2 {
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    }
+
+    Arc::new(config)
+}
+
+/// Loads a certificate chain and its private key from PEM files.
+///
+/// The certificate file may contain intermediate certificates after the server's certificate. The key may be a
+///   PKCS#8 or PKCS#1 (RSA) key.
+pub fn load_certified_key(
+    cert_path: impl AsRef<str>,
+    key_path: impl AsRef<str>,
+) -> Result<CertifiedKey, CertError> {
+    let cert_path = cert_path.as_ref();
+    let key_path = key_path.as_ref();
+
+    let certs: Vec<Certificate> = read_pem(cert_path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(cert) => Some(Certificate(cert)),
+            _ => None,
+        })
+        .collect();
+
+    if certs.is_empty() {
+        return Err(CertError::Certificate(cert_path.to_string()));
+    }
+
+    let key = read_pem(key_path)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| CertError::Key(key_path.to_string()))?;
+
+    let signing_key = any_supported_type(&key).map_err(|_| CertError::Key(key_path.to_string()))?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Reads every item from a PEM file.
+fn read_pem(path: &str) -> Result<Vec<Item>, CertError> {
+    let file = File::open(path).map_err(|_| CertError::File(path.to_string()))?;
+
+    read_all(&mut BufReader::new(file)).map_err(|_| CertError::File(path.to_string()))
+}
+
+impl Display for CertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CertError::File(path) => write!(f, "Could not read {}", path),
+            CertError::Certificate(path) => write!(f, "No certificate found in {}", path),
+            CertError::Key(path) => write!(f, "No supported private key found in {}", path),
+        }
+    }
+}
+
+impl Error for CertError {}
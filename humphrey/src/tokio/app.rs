@@ -2,26 +2,54 @@
 
 #![allow(clippy::new_without_default)]
 
-use crate::http::cors::Cors;
+use crate::http::compression::apply_compression;
+use crate::http::cors::{join_methods, Cors};
 use crate::http::date::DateTime;
 use crate::http::headers::HeaderType;
 use crate::http::method::Method;
-use crate::http::request::{Request, RequestError};
+use crate::http::request::{pipe_body, BodyLength, Request, RequestBody, RequestError};
 use crate::http::response::Response;
 use crate::http::status::StatusCode;
 use crate::krauss::wildcard_match;
+use crate::listener::{https_redirect, ConnectionMode, Listener, ListenerMode};
 use crate::monitor::event::{Event, EventType};
 use crate::monitor::MonitorConfig;
-use crate::route::{Route, RouteHandler, SubApp};
+use crate::route::{find_route, Handler, RouteHandler, SubApp};
 use crate::stream::Stream;
 
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, watch};
 
+#[cfg(feature = "tls")]
+use crate::http::response::StreamedBody;
+#[cfg(feature = "tls")]
+use crate::http2::connection::Connection;
+#[cfg(feature = "tls")]
+use crate::http2::frame::ErrorCode;
+#[cfg(feature = "tls")]
+use crate::http2::{prepare_response, served_event, CHUNK_SIZE};
+#[cfg(feature = "tls")]
+use crate::tls::{server_config, ClientVerifier};
+#[cfg(feature = "tls")]
+use futures::FutureExt;
+#[cfg(feature = "tls")]
+use rustls::server::ResolvesServerCert;
 #[cfg(feature = "tls")]
 use rustls::ServerConfig;
+#[cfg(feature = "tls")]
+use std::collections::HashMap;
+#[cfg(feature = "tls")]
+use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::panic::AssertUnwindSafe;
+#[cfg(feature = "tls")]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 /// Represents the Humphrey app.
 ///
@@ -38,12 +66,25 @@ where
     state: Arc<State>,
     monitor: MonitorConfig,
     connection_condition: ConnectionCondition<State>,
+    max_body_size: Option<usize>,
+    compression: Option<usize>,
+    middleware: Vec<Arc<dyn Middleware<State>>>,
+    shutdown: Option<Shutdown>,
+    drain_timeout: Option<Duration>,
+    listeners: Vec<Listener>,
     #[cfg(feature = "tls")]
     tls_config: Option<Arc<ServerConfig>>,
     #[cfg(feature = "tls")]
+    client_verifier: Option<ClientVerifier>,
+    #[cfg(feature = "tls")]
     force_https: bool,
+    #[cfg(feature = "tls")]
+    http2: bool,
 }
 
+/// Represents a future which completes when the app should shut down.
+pub type Shutdown = Pin<Box<dyn Future<Output = ()> + Send>>;
+
 /// Represents a function able to calculate whether a connection will be accepted.
 pub type ConnectionCondition<State> = fn(&mut TcpStream, Arc<State>) -> bool;
 
@@ -94,10 +135,20 @@ where
             state: Arc::new(State::default()),
             monitor: MonitorConfig::default(),
             connection_condition: |_, _| true,
+            max_body_size: None,
+            compression: None,
+            middleware: Vec::new(),
+            shutdown: None,
+            drain_timeout: None,
+            listeners: Vec::new(),
             #[cfg(feature = "tls")]
             tls_config: None,
             #[cfg(feature = "tls")]
+            client_verifier: None,
+            #[cfg(feature = "tls")]
             force_https: false,
+            #[cfg(feature = "tls")]
+            http2: true,
         }
     }
 
@@ -110,10 +161,20 @@ where
             state: Arc::new(state),
             monitor: MonitorConfig::default(),
             connection_condition: |_, _| true,
+            max_body_size: None,
+            compression: None,
+            middleware: Vec::new(),
+            shutdown: None,
+            drain_timeout: None,
+            listeners: Vec::new(),
             #[cfg(feature = "tls")]
             tls_config: None,
             #[cfg(feature = "tls")]
+            client_verifier: None,
+            #[cfg(feature = "tls")]
             force_https: false,
+            #[cfg(feature = "tls")]
+            http2: true,
         }
     }
 
@@ -124,57 +185,8 @@ where
         A: ToSocketAddrs,
     {
         let socket = TcpListener::bind(addr).await?;
-        let subapps = Arc::new(self.subapps);
-        let default_subapp = Arc::new(self.default_subapp);
-        let error_handler = Arc::new(self.error_handler);
 
-        loop {
-            match socket.accept().await {
-                Ok((mut stream, _)) => {
-                    let cloned_state = self.state.clone();
-
-                    // Check that the client is allowed to connect
-                    if (self.connection_condition)(&mut stream, cloned_state) {
-                        let cloned_state = self.state.clone();
-                        let cloned_monitor = self.monitor.clone();
-                        let cloned_subapps = subapps.clone();
-                        let cloned_default_subapp = default_subapp.clone();
-                        let cloned_error_handler = error_handler.clone();
-
-                        cloned_monitor.send(
-                            Event::new(EventType::ConnectionSuccess)
-                                .with_peer_result(stream.peer_addr()),
-                        );
-
-                        // Spawn a new thread to handle the connection
-                        tokio::spawn(async move {
-                            cloned_monitor.send(
-                                Event::new(EventType::ThreadPoolProcessStarted)
-                                    .with_peer_result(stream.peer_addr()),
-                            );
-
-                            client_handler(
-                                Stream::Tcp(stream),
-                                cloned_subapps,
-                                cloned_default_subapp,
-                                cloned_error_handler,
-                                cloned_state,
-                                cloned_monitor,
-                            )
-                            .await
-                        });
-                    } else {
-                        self.monitor.send(
-                            Event::new(EventType::ConnectionDenied)
-                                .with_peer_result(stream.peer_addr()),
-                        );
-                    }
-                }
-                Err(e) => self
-                    .monitor
-                    .send(Event::new(EventType::ConnectionError).with_info(e.to_string())),
-            }
-        }
+        self.serve(vec![(socket, ConnectionMode::Http)]).await
     }
 
     /// Securely runs the Humphrey app on the given socket address.
@@ -184,81 +196,197 @@ where
     where
         A: ToSocketAddrs,
     {
-        use tokio_rustls::TlsAcceptor;
-
         let socket = TcpListener::bind(addr).await?;
+        let https_port = socket.local_addr()?.port();
+        let mut listeners = vec![(
+            socket,
+            ConnectionMode::Https(self.tls_server_config(None, None)),
+        )];
+
+        if self.force_https {
+            listeners.push(
+                self.bind(&Listener::redirect("0.0.0.0:80", https_port))
+                    .await?,
+            );
+        }
+
+        self.serve(listeners).await
+    }
+
+    /// Runs the Humphrey app on every listener added with `with_listener`, sharing the routes between them.
+    /// This function will only return if a fatal error is thrown such as a port being in use or a TLS certificate
+    ///   being invalid.
+    pub async fn run_listeners(self) -> Result<(), HumphreyError> {
+        if self.listeners.is_empty() {
+            return Err("No listeners were added to the app".into());
+        }
+
+        let mut listeners = Vec::with_capacity(self.listeners.len());
+
+        for listener in &self.listeners {
+            listeners.push(self.bind(listener).await?);
+        }
+
+        self.serve(listeners).await
+    }
+
+    /// Binds the socket of a listener, returning it along with how its connections are handled.
+    async fn bind(
+        &self,
+        listener: &Listener,
+    ) -> Result<(TcpListener, ConnectionMode), HumphreyError> {
+        let socket = TcpListener::bind(&listener.addr).await?;
+        Ok((socket, self.connection_mode(&listener.mode)))
+    }
+
+    /// Accepts connections on the given listeners until the app shuts down, handling them according to the
+    ///   listener they were accepted on.
+    async fn serve(
+        mut self,
+        listeners: Vec<(TcpListener, ConnectionMode)>,
+    ) -> Result<(), HumphreyError> {
+        self.apply_middleware();
+
         let subapps = Arc::new(self.subapps);
         let default_subapp = Arc::new(self.default_subapp);
         let error_handler = Arc::new(self.error_handler);
-        let tls_config = self.tls_config.expect("TLS certificate not supplied");
+        let (draining, _) = watch::channel(false);
+        let mut shutdown = self
+            .shutdown
+            .take()
+            .unwrap_or_else(|| Box::pin(std::future::pending()));
 
-        if self.force_https {
-            let cloned_monitor = self.monitor.clone();
+        // Each listener accepts connections in its own task, passing them to this task to be checked and handled
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut accept_tasks = Vec::with_capacity(listeners.len());
 
-            tokio::spawn(async move {
-                force_https_thread(cloned_monitor).await.unwrap_or(());
-            });
+        for (socket, mode) in listeners {
+            let tx = tx.clone();
+            let monitor = self.monitor.clone();
+
+            accept_tasks.push(tokio::spawn(accept_connections(socket, mode, tx, monitor)));
         }
 
-        let acceptor = TlsAcceptor::from(tls_config);
+        drop(tx);
 
         loop {
-            match socket.accept().await {
-                Ok((mut sock, _)) => {
-                    let cloned_state = self.state.clone();
-
-                    // Check that the client is allowed to connect
-                    if (self.connection_condition)(&mut sock, cloned_state) {
-                        let cloned_state = self.state.clone();
-                        let cloned_subapps = subapps.clone();
-                        let cloned_default_subapp = default_subapp.clone();
-                        let cloned_error_handler = error_handler.clone();
-                        let cloned_monitor = self.monitor.clone();
-                        let cloned_acceptor = acceptor.clone();
-
-                        cloned_monitor.send(
-                            Event::new(EventType::ConnectionSuccess)
-                                .with_peer_result(sock.peer_addr()),
-                        );
-
-                        // Spawn a new thread to handle the connection
-                        tokio::spawn(async move {
-                            cloned_monitor.send(
-                                Event::new(EventType::ThreadPoolProcessStarted)
-                                    .with_peer_result(sock.peer_addr()),
-                            );
-
-                            match cloned_acceptor.accept(sock).await {
-                                Ok(tls_stream) => {
-                                    let stream = Stream::Tls(tls_stream);
-
-                                    client_handler(
-                                        stream,
-                                        cloned_subapps,
-                                        cloned_default_subapp,
-                                        cloned_error_handler,
-                                        cloned_state,
-                                        cloned_monitor,
-                                    )
-                                    .await
+            let accepted = tokio::select! {
+                accepted = rx.recv() => accepted,
+                _ = &mut shutdown => break,
+            };
+
+            let (mut stream, mode) = match accepted {
+                Some(accepted) => accepted,
+                None => break,
+            };
+
+            let cloned_state = self.state.clone();
+
+            // Check that the client is allowed to connect
+            if (self.connection_condition)(&mut stream, cloned_state) {
+                let cloned_state = self.state.clone();
+                let cloned_monitor = self.monitor.clone();
+                let cloned_subapps = subapps.clone();
+                let cloned_default_subapp = default_subapp.clone();
+                let cloned_error_handler = error_handler.clone();
+                let cloned_max_body_size = self.max_body_size;
+                let cloned_compression = self.compression;
+                let cloned_draining = draining.subscribe();
+
+                cloned_monitor.send(
+                    Event::new(EventType::ConnectionSuccess).with_peer_result(stream.peer_addr()),
+                );
+
+                // Spawn a new thread to handle the connection
+                tokio::spawn(async move {
+                    cloned_monitor.send(
+                        Event::new(EventType::ThreadPoolProcessStarted)
+                            .with_peer_result(stream.peer_addr()),
+                    );
+
+                    let stream = match mode {
+                        ConnectionMode::Http => Stream::Tcp(stream),
+                        #[cfg(feature = "tls")]
+                        ConnectionMode::Https(config) => {
+                            match tokio_rustls::TlsAcceptor::from(config).accept(stream).await {
+                                Ok(tls_stream) => Stream::Tls(tls_stream),
+                                Err(e) => {
+                                    cloned_monitor.send(
+                                        Event::new(EventType::ConnectionError)
+                                            .with_info(e.to_string()),
+                                    );
+                                    return;
                                 }
-                                Err(e) => cloned_monitor.send(
-                                    Event::new(EventType::ConnectionError).with_info(e.to_string()),
-                                ),
                             }
-                        });
-                    } else {
-                        self.monitor.send(
-                            Event::new(EventType::ConnectionDenied)
-                                .with_peer_result(sock.peer_addr()),
-                        );
-                    }
-                }
-                Err(e) => self
-                    .monitor
-                    .send(Event::new(EventType::ConnectionError).with_info(e.to_string())),
+                        }
+                        ConnectionMode::Redirect(https_port) => {
+                            redirect_to_https(stream, https_port, cloned_monitor).await;
+                            return;
+                        }
+                    };
+
+                    client_handler(
+                        stream,
+                        cloned_subapps,
+                        cloned_default_subapp,
+                        cloned_error_handler,
+                        cloned_state,
+                        cloned_monitor,
+                        cloned_max_body_size,
+                        cloned_compression,
+                        cloned_draining,
+                    )
+                    .await
+                });
+            } else {
+                self.monitor.send(
+                    Event::new(EventType::ConnectionDenied).with_peer_result(stream.peer_addr()),
+                );
             }
         }
+
+        // Stop accepting connections, then let the current requests finish and close idle connections
+        for task in accept_tasks {
+            task.abort();
+            task.await.ok();
+        }
+
+        drain(draining, self.drain_timeout).await;
+
+        Ok(())
+    }
+
+    /// Sets a future which stops the app when it completes, for example `tokio::signal::ctrl_c`.
+    ///
+    /// The app then stops accepting connections and closes idle keep-alive connections.
+    ///   Requests which are already being handled are allowed to finish for up to the drain timeout,
+    ///   set with `with_drain_timeout`.
+    pub fn with_shutdown<F>(mut self, shutdown: F) -> Self
+    where
+        F: Future + Send + 'static,
+    {
+        self.shutdown = Some(Box::pin(async move {
+            shutdown.await;
+        }));
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for requests which are being handled to finish when the app shuts down.
+    ///   Defaults to `None`, in which case `run` returns without waiting and the connections continue in the background.
+    ///
+    /// Connections are closed once their current request has been answered.
+    pub fn with_drain_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Adds a listener on which the app accepts connections when it is run with `run_listeners`.
+    ///
+    /// Listeners can serve the app over HTTP or HTTPS, or redirect to HTTPS, and every listener shares the app's
+    ///   routes.
+    pub fn with_listener(mut self, listener: Listener) -> Self {
+        self.listeners.push(listener);
+        self
     }
 
     /// Sets the default state for the server.
@@ -320,6 +448,77 @@ where
         self
     }
 
+    /// Adds a route and associated streaming handler to the server.
+    /// Routes can include wildcards, for example `/upload/*`.
+    /// The request body is not read into memory, but is instead passed to the handler to be read
+    ///   from the connection as it arrives. The maximum body size does not apply to it.
+    pub fn with_streaming_route<T>(mut self, route: &str, handler: T) -> Self
+    where
+        T: StreamingRequestHandler<State> + 'static,
+    {
+        self.default_subapp = self.default_subapp.with_streaming_route(route, handler);
+        self
+    }
+
+    /// Adds a route and associated handler to the server which only matches requests with the given method.
+    /// Routes can include wildcards, for example `/blog/*`.
+    ///
+    /// If requests are made to the route with a method which has no handler, they are rejected with
+    ///   `405 Method Not Allowed`. `HEAD` requests are handled by the `GET` handler if there is no `HEAD` handler,
+    ///   and `OPTIONS` requests are answered automatically if there is no `OPTIONS` handler.
+    pub fn with_method_route<T>(mut self, method: Method, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.default_subapp = self.default_subapp.with_method_route(method, route, handler);
+        self
+    }
+
+    /// Adds a route and associated handler to the server which only matches `GET` and `HEAD` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_get<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Get, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `POST` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_post<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Post, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `PUT` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_put<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Put, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `PATCH` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_patch<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Patch, route, handler)
+    }
+
+    /// Adds a route and associated handler to the server which only matches `DELETE` requests.
+    /// See `with_method_route` for more details.
+    pub fn with_delete<T>(self, route: &str, handler: T) -> Self
+    where
+        T: RequestHandler<State> + 'static,
+    {
+        self.with_method_route(Method::Delete, route, handler)
+    }
+
     /// Adds a WebSocket route and associated handler to the server.
     /// Routes can include wildcards, for example `/ws/*`.
     /// The handler is passed the stream, state, and the request which triggered its calling.
@@ -350,6 +549,48 @@ where
         self
     }
 
+    /// Sets the maximum size of a request body in bytes, or `None` for no limit. Defaults to `None`.
+    ///
+    /// Requests with larger bodies are rejected with `413 Payload Too Large` before the body is read.
+    /// This does not apply to routes with streaming handlers, which read the body themselves.
+    /// Bodies received over HTTP/2 are always read into memory, so the limit applies to every route.
+    pub fn with_max_body_size(mut self, max_body_size: Option<usize>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Sets the minimum size in bytes of response bodies to compress, or `None` to disable compression. Defaults to `None`.
+    ///
+    /// Responses are compressed with gzip or deflate, depending on the client's `Accept-Encoding` header,
+    ///   if their MIME type is compressible. See `humphrey::http::compression` for more details.
+    pub fn with_compression(mut self, min_size: Option<usize>) -> Self {
+        self.compression = min_size;
+        self
+    }
+
+    /// Adds middleware to the app, which runs for every request to every host.
+    ///
+    /// App middleware runs before the middleware of sub-apps and routes, in the order it was added.
+    ///   See the `Middleware` trait for more details.
+    pub fn with_middleware<T>(mut self, middleware: T) -> Self
+    where
+        T: Middleware<State> + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Adds middleware to the specified route, which runs after the app's middleware.
+    ///
+    /// The route must already have been added, since the middleware is attached to its handler.
+    pub fn with_route_middleware<T>(mut self, route: &str, middleware: T) -> Self
+    where
+        T: Middleware<State> + 'static,
+    {
+        self.default_subapp = self.default_subapp.with_route_middleware(route, middleware);
+        self
+    }
+
     /// Sets the CORS configuration for the app.
     ///
     /// This overrides the CORS configuration for existing and future individual routes.
@@ -370,8 +611,8 @@ where
 
     /// Sets whether HTTPS should be forced on all connections. Defaults to false.
     ///
-    /// If this is set to true, a background thread will be spawned when `run_tls` is called to send
-    ///   redirect responses to all insecure requests on port 80.
+    /// If this is set to true, `run_tls` also listens on port 80 and sends redirect responses to all insecure
+    ///   requests. To redirect from a different port, use `run_listeners` with a `Listener::redirect` listener.
     #[cfg(feature = "tls")]
     pub fn with_forced_https(mut self, forced: bool) -> Self {
         self.force_https = forced;
@@ -417,6 +658,93 @@ where
         self
     }
 
+    /// Sets the TLS configuration for the server to select the certificate for each connection using the given
+    ///   resolver, for example a `CertResolver` which selects the certificate by the requested host name.
+    ///
+    /// This, or `with_cert`, **must** be called before `run_tls` is called.
+    #[cfg(feature = "tls")]
+    pub fn with_cert_resolver(mut self, resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        let config = Arc::new(
+            ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_cert_resolver(resolver),
+        );
+
+        self.tls_config = Some(config);
+
+        self
+    }
+
+    /// Sets the verifier used to authenticate clients with TLS client certificates signed by a trusted CA.
+    ///
+    /// The verified identity of the client is available to handlers as `request.peer_identity`.
+    #[cfg(feature = "tls")]
+    pub fn with_client_auth(mut self, verifier: ClientVerifier) -> Self {
+        self.client_verifier = Some(verifier);
+        self
+    }
+
+    /// Sets whether clients connecting over TLS may use HTTP/2, which they negotiate with ALPN. Defaults to true.
+    ///
+    /// Clients which do not support HTTP/2 use HTTP/1.1 either way. WebSocket routes are only available over
+    ///   HTTP/1.1, so clients which support HTTP/2 connect to them using a separate HTTP/1.1 connection.
+    #[cfg(feature = "tls")]
+    pub fn with_http2(mut self, http2: bool) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    /// Adds the app's middleware to the start of every sub-app's middleware, so that it runs first.
+    fn apply_middleware(&mut self) {
+        for subapp in self
+            .subapps
+            .iter_mut()
+            .chain(std::iter::once(&mut self.default_subapp))
+        {
+            subapp.middleware.splice(0..0, self.middleware.iter().cloned());
+        }
+    }
+
+    /// Gets how connections to a listener are handled, using the app's TLS configuration unless the listener has
+    ///   its own.
+    fn connection_mode(&self, mode: &ListenerMode) -> ConnectionMode {
+        match mode {
+            ListenerMode::Http => ConnectionMode::Http,
+            #[cfg(feature = "tls")]
+            ListenerMode::Https {
+                cert_resolver,
+                client_verifier,
+            } => ConnectionMode::Https(
+                self.tls_server_config(cert_resolver.clone(), client_verifier.as_ref()),
+            ),
+            ListenerMode::Redirect(https_port) => ConnectionMode::Redirect(*https_port),
+        }
+    }
+
+    /// Gets the TLS configuration for the server, using the app's certificate and client authentication unless
+    ///   others are given.
+    #[cfg(feature = "tls")]
+    fn tls_server_config(
+        &self,
+        cert_resolver: Option<Arc<dyn ResolvesServerCert>>,
+        client_verifier: Option<&ClientVerifier>,
+    ) -> Arc<ServerConfig> {
+        let cert_resolver = cert_resolver.unwrap_or_else(|| {
+            self.tls_config
+                .as_ref()
+                .expect("TLS certificate not supplied")
+                .cert_resolver
+                .clone()
+        });
+
+        server_config(
+            cert_resolver,
+            client_verifier.or(self.client_verifier.as_ref()),
+            self.http2,
+        )
+    }
+
     /// Gets a reference to the app's state.
     /// This should only be used in the main thread, as the state is passed to request handlers otherwise.
     pub fn get_state(&self) -> Arc<State> {
@@ -424,6 +752,16 @@ where
     }
 }
 
+/// Signals every connection that the app is shutting down, then waits for them to close for up to the timeout.
+async fn drain(draining: watch::Sender<bool>, timeout: Option<Duration>) {
+    draining.send_replace(true);
+
+    // Every connection holds a receiver, so the channel is closed once they have all finished
+    if let Some(timeout) = timeout {
+        tokio::time::timeout(timeout, draining.closed()).await.ok();
+    }
+}
+
 /// Handles a connection with a client.
 /// The connection will be opened upon the first request and closed as soon as a request is
 ///   received without the `Connection: Keep-Alive` header.
@@ -435,7 +773,12 @@ async fn client_handler<State>(
     error_handler: Arc<ErrorHandler>,
     state: Arc<State>,
     monitor: MonitorConfig,
-) {
+    max_body_size: Option<usize>,
+    compression: Option<usize>,
+    mut draining: watch::Receiver<bool>,
+) where
+    State: Send + Sync + 'static,
+{
     let addr = if let Ok(addr) = stream.peer_addr() {
         addr
     } else {
@@ -444,9 +787,48 @@ async fn client_handler<State>(
         return;
     };
 
+    // Clients which negotiated HTTP/2 use it for the whole connection
+    #[cfg(feature = "tls")]
+    if stream.is_http2() {
+        http2_handler(
+            stream,
+            addr,
+            subapps,
+            default_subapp,
+            *error_handler,
+            state,
+            &monitor,
+            max_body_size,
+            compression,
+            draining,
+        )
+        .await;
+
+        monitor.send(Event::new(EventType::ConnectionClosed).with_peer(addr));
+        return;
+    }
+
     loop {
-        // Parses the request from the stream
-        let request = Request::from_stream(&mut stream, addr).await;
+        let mut reader = BufReader::new(&mut stream);
+
+        // Wait for the next request, unless the app starts shutting down first
+        let shutting_down = tokio::select! {
+            biased;
+            _ = reader.fill_buf() => false,
+            _ = draining.wait_for(|draining| *draining) => true,
+        };
+
+        if shutting_down {
+            break;
+        }
+
+        // Parses the head of the request from the stream
+        let mut request = Request::head_from_reader(&mut reader, addr).await;
+
+        if let Ok(request) = &mut request {
+            request.peer_identity = reader.get_ref().peer_identity();
+            request.https = reader.get_ref().is_tls();
+        }
 
         let cloned_state = state.clone();
 
@@ -455,6 +837,7 @@ async fn client_handler<State>(
             if req.headers.get(&HeaderType::Upgrade) == Some("websocket") {
                 monitor.send(Event::new(EventType::WebsocketConnectionRequested).with_peer(addr));
 
+                drop(reader);
                 call_websocket_handler(req, &subapps, &default_subapp, cloned_state, stream).await;
 
                 monitor.send(Event::new(EventType::WebsocketConnectionClosed).with_peer(addr));
@@ -462,8 +845,13 @@ async fn client_handler<State>(
             }
         }
 
+        let (subapp, handler, allowed_methods) = match &mut request {
+            Ok(request) => get_handler(request, &subapps, &default_subapp),
+            Err(_) => (default_subapp.as_ref(), None, Vec::new()),
+        };
+
         // Get the keep alive information from the request before it is consumed by the handler
-        let keep_alive = if let Ok(request) = &request {
+        let mut keep_alive = if let Ok(request) = &request {
             if let Some(connection) = request.headers.get(&HeaderType::Connection) {
                 connection.to_ascii_lowercase() == "keep-alive"
             } else {
@@ -474,50 +862,45 @@ async fn client_handler<State>(
         };
 
         // Generate the response based on the handlers
-        let response = match &request {
-            Ok(request) if request.method == Method::Options => {
-                let handler = get_handler(request, &subapps, &default_subapp);
-
-                match handler {
-                    Some(handler) => {
-                        let mut response = Response::empty(StatusCode::NoContent)
-                            .with_header(HeaderType::Date, DateTime::now().to_string())
-                            .with_header(HeaderType::Server, "Humphrey")
-                            .with_header(
-                                HeaderType::Connection,
-                                match keep_alive {
-                                    true => "Keep-Alive",
-                                    false => "Close",
-                                },
-                            );
-
-                        handler.cors.set_headers(&mut response.headers);
-
-                        response
-                    }
-                    None => error_handler(StatusCode::NotFound),
-                }
-            }
+        let handled = match request {
             Ok(request) => {
-                let handler = get_handler(request, &subapps, &default_subapp);
-
-                let mut response = match handler {
-                    Some(handler) => {
-                        let mut response: Response =
-                            handler.handler.serve(request.clone(), state.clone()).await;
+                route_response(
+                    request,
+                    &mut reader,
+                    subapp,
+                    handler,
+                    &allowed_methods,
+                    *error_handler,
+                    &state,
+                    max_body_size,
+                    compression,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
 
-                        handler.cors.set_headers(&mut response.headers);
+        let (request, response) = match handled {
+            Ok((request, mut response, body_read)) => {
+                // If the body was not read, it is still on the connection, so it cannot be reused
+                if !body_read {
+                    keep_alive = false;
+                }
 
-                        response
-                    }
-                    None => error_handler(StatusCode::NotFound),
-                };
+                // Connections are closed after their current request once the app starts shutting down
+                if *draining.borrow() {
+                    keep_alive = false;
+                }
 
                 // Automatically generate required headers
                 match response.headers.get_mut(HeaderType::Connection) {
                     Some(_) => (),
                     None => {
-                        if let Some(connection) = &request.headers.get(&HeaderType::Connection) {
+                        if !keep_alive {
+                            response.headers.add(HeaderType::Connection, "Close");
+                        } else if let Some(connection) =
+                            &request.headers.get(&HeaderType::Connection)
+                        {
                             response.headers.add(HeaderType::Connection, connection);
                         } else {
                             response.headers.add(HeaderType::Connection, "Close");
@@ -543,33 +926,66 @@ async fn client_handler<State>(
 
                 match response.headers.get_mut(HeaderType::ContentLength) {
                     Some(_) => (),
-                    None => {
-                        response
-                            .headers
-                            .add(HeaderType::ContentLength, response.body.len().to_string());
-                    }
+                    // `304 Not Modified` responses never have a body, so the length of the unsent content is unknown
+                    None if response.status_code == StatusCode::NotModified => (),
+                    // `204 No Content` responses must not have a `Content-Length` header
+                    None if response.status_code == StatusCode::NoContent => (),
+                    None => match response.content_length() {
+                        Some(length) => {
+                            response
+                                .headers
+                                .add(HeaderType::ContentLength, length.to_string());
+                        }
+                        None if request.version == "HTTP/1.0" => {
+                            // Chunked encoding is not supported, so the end of the body is marked by closing the connection
+                            keep_alive = false;
+                            response.headers.remove(HeaderType::Connection);
+                            response.headers.add(HeaderType::Connection, "Close");
+                        }
+                        None => {
+                            if response.headers.get(HeaderType::TransferEncoding).is_none() {
+                                response
+                                    .headers
+                                    .add(HeaderType::TransferEncoding, "chunked");
+                            }
+                        }
+                    },
+                }
+
+                // Responses to `HEAD` requests have the same headers as those to `GET` requests, but no body
+                if request.method == Method::Head {
+                    response.body.clear();
+                    response.stream = None;
                 }
 
                 // Set HTTP version
                 response.version = request.version.clone();
 
-                response
+                (Ok(request), response)
+            }
+            Err(e) => {
+                let response = match e {
+                    RequestError::Request => error_handler(StatusCode::BadRequest),
+                    RequestError::Timeout => error_handler(StatusCode::RequestTimeout),
+                    RequestError::PayloadTooLarge => {
+                        error_handler(StatusCode::RequestEntityTooLarge)
+                            .with_header(HeaderType::Connection, "Close")
+                    }
+                    RequestError::Disconnected => return,
+                    RequestError::Stream => {
+                        return monitor.send(Event::new(EventType::RequestServedError))
+                    }
+                };
+
+                (Err(e), response)
             }
-            Err(e) => match e {
-                RequestError::Request => error_handler(StatusCode::BadRequest),
-                RequestError::Timeout => error_handler(StatusCode::RequestTimeout),
-                RequestError::Disconnected => return,
-                RequestError::Stream => {
-                    return monitor.send(Event::new(EventType::RequestServedError))
-                }
-            },
         };
 
         // Write the response to the stream
+        drop(reader);
         let status = response.status_code;
-        let response_bytes: Vec<u8> = response.into();
 
-        if let Err(e) = stream.write_all(&response_bytes).await {
+        if let Err(e) = response.write_to(&mut stream).await {
             monitor.send(
                 Event::new(EventType::RequestServedError)
                     .with_peer(addr)
@@ -609,8 +1025,8 @@ async fn client_handler<State>(
             }
         }
 
-        // If the request specified to keep the connection open, respect this
-        if !keep_alive {
+        // If the request specified to keep the connection open, respect this unless the app is shutting down
+        if !keep_alive || *draining.borrow() {
             break;
         }
 
@@ -620,43 +1036,440 @@ async fn client_handler<State>(
     monitor.send(Event::new(EventType::ConnectionClosed).with_peer(addr));
 }
 
-/// Gets the correct handler for the given request.
+/// Gets the most specific handler for the given request, along with the sub-app it belongs to and the methods
+///   allowed at its route, and sets the request's parameters from its route.
+///
+/// If no route matches, the sub-app for the request's host is returned, or the default sub-app if there is none.
+///   If a route matches but cannot handle the request's method, no handler is returned, but the allowed methods are.
 pub(crate) fn get_handler<'a, State>(
-    request: &'a Request,
+    request: &mut Request,
     subapps: &'a [SubApp<State>],
     default_subapp: &'a SubApp<State>,
-) -> Option<&'a RouteHandler<State>> {
+) -> (
+    &'a SubApp<State>,
+    Option<&'a RouteHandler<State>>,
+    Vec<Method>,
+) {
+    let mut host_subapp = None;
+
     // Iterate over the sub-apps and find the one which matches the host
     if let Some(host) = request.headers.get(&HeaderType::Host) {
         if let Some(subapp) = subapps
             .iter()
             .find(|subapp| wildcard_match(&subapp.host, host))
         {
-            // If the sub-app has a handler for this route, call it
-            if let Some(handler) = subapp
-                .routes // Get the routes of the sub-app
-                .iter() // Iterate over the routes
-                .find(|route| route.route.route_matches(&request.uri))
-            // Find the route that matches
-            {
-                return Some(handler);
+            // If the sub-app has a route for this path, use it
+            if let Some(route) = subapp.find_handler(&request.uri, &request.method) {
+                request.params = route.params;
+                return (subapp, route.handler, route.allowed_methods);
             }
+
+            host_subapp = Some(subapp);
         }
     }
 
     // If no sub-app was found, try to use the handler on the default sub-app
-    if let Some(handler) = default_subapp
-        .routes
+    if let Some(route) = default_subapp.find_handler(&request.uri, &request.method) {
+        request.params = route.params;
+        return (default_subapp, route.handler, route.allowed_methods);
+    }
+
+    (host_subapp.unwrap_or(default_subapp), None, Vec::new())
+}
+
+/// Generates the automatic response to a CORS preflight or other `OPTIONS` request for a route with no handler
+///   specifically for them.
+pub(crate) fn preflight_response<State>(
+    handler: &RouteHandler<State>,
+    allowed_methods: &[Method],
+) -> Response {
+    let mut response = Response::empty(StatusCode::NoContent)
+        .with_header(HeaderType::Date, DateTime::now().to_string())
+        .with_header(HeaderType::Server, "Humphrey")
+        .with_header(HeaderType::Allow, join_methods(allowed_methods));
+
+    handler
+        .cors
+        .set_preflight_headers(&mut response.headers, allowed_methods);
+
+    response
+}
+
+/// Generates the response to a request using the given handler, running the middleware around it and
+///   compressing the response if enabled.
+///
+/// The middleware runs before the body is read, so it can reject requests without reading their bodies. The body
+///   is then read from the reader, unless the handler streams it. Returns the request as modified by the middleware,
+///   the response, and whether the whole body was read, or an error if the body could not be read.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn route_response<State, R>(
+    mut request: Request,
+    reader: &mut R,
+    subapp: &SubApp<State>,
+    handler: Option<&RouteHandler<State>>,
+    allowed_methods: &[Method],
+    error_handler: ErrorHandler,
+    state: &Arc<State>,
+    max_body_size: Option<usize>,
+    compression: Option<usize>,
+) -> Result<(Request, Response, bool), RequestError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    // The body length is taken before the middleware runs, since it may change the headers
+    let body_length = request.body_length().ok();
+    let mut body_read = true;
+
+    // `OPTIONS` requests are answered automatically unless the route has a handler specifically for them
+    let preflight = handler.is_some_and(|handler| {
+        request.method == Method::Options && handler.method != Some(Method::Options)
+    });
+
+    let streaming = !preflight
+        && matches!(
+            handler,
+            Some(RouteHandler {
+                handler: Handler::Streaming(_),
+                ..
+            })
+        );
+
+    let middleware: Vec<&Arc<dyn Middleware<State>>> = subapp
+        .middleware
         .iter()
-        .find(|route| route.route.route_matches(&request.uri))
-    {
-        return Some(handler);
+        .chain(handler.iter().flat_map(|handler| handler.middleware.iter()))
+        .collect();
+
+    // Run the middleware in order until one of them responds directly, if any
+    let mut early_response = None;
+    let mut middleware_run = 0;
+
+    for middleware in &middleware {
+        early_response = middleware.before(&mut request, state.clone()).await;
+
+        if early_response.is_some() {
+            break;
+        }
+
+        middleware_run += 1;
+    }
+
+    // Read the body of the request unless the middleware responded directly or the handler will stream it
+    if early_response.is_none() && !streaming {
+        match request.read_body(reader, max_body_size).await {
+            Ok(()) => (),
+            Err(RequestError::PayloadTooLarge) => {
+                early_response = Some(
+                    error_handler(StatusCode::RequestEntityTooLarge)
+                        .with_header(HeaderType::Connection, "Close"),
+                );
+            }
+            Err(e) => return Err(e),
+        }
     }
 
-    None
+    let mut response = match (early_response, handler) {
+        (Some(response), _) => {
+            // If the middleware responded directly, the body has not been read
+            if request.content.is_none() && body_length != Some(BodyLength::Fixed(0)) {
+                body_read = false;
+            }
+
+            response
+        }
+        (None, Some(handler)) if preflight => preflight_response(handler, allowed_methods),
+        (None, Some(handler)) => match &handler.handler {
+            Handler::Buffered(handler) => handler.serve(request.clone(), state.clone()).await,
+            Handler::Streaming(handler) => match body_length {
+                Some(length) => {
+                    let (body, writer) = RequestBody::new();
+                    let (response, finished) = tokio::join!(
+                        handler.serve(request.clone(), body, state.clone()),
+                        pipe_body(reader, writer, length)
+                    );
+
+                    body_read = finished;
+
+                    response
+                }
+                None => {
+                    body_read = false;
+                    error_handler(StatusCode::BadRequest)
+                }
+            },
+        },
+        (None, None) if allowed_methods.is_empty() => error_handler(StatusCode::NotFound),
+        (None, None) => error_handler(StatusCode::MethodNotAllowed)
+            .with_header(HeaderType::Allow, join_methods(allowed_methods)),
+    };
+
+    match handler {
+        Some(handler) if !preflight => handler.cors.set_headers(&mut response.headers),
+        _ => (),
+    }
+
+    // Run the middleware which ran before the handler again in reverse order
+    for middleware in middleware[..middleware_run].iter().rev() {
+        middleware
+            .after(&request, &mut response, state.clone())
+            .await;
+    }
+
+    if let Some(min_size) = compression {
+        response = apply_compression(&request, response, min_size);
+    }
+
+    Ok((request, response, body_read))
 }
 
-/// Calls the correct WebSocket handler for the given request.
+/// Handles a connection with a client which negotiated HTTP/2.
+///
+/// Each request is handled in its own task, and the responses are sent as quickly as the client's flow control
+///   allows.
+#[cfg(feature = "tls")]
+#[allow(clippy::too_many_arguments)]
+async fn http2_handler<State>(
+    mut stream: Stream,
+    addr: SocketAddr,
+    subapps: Arc<Vec<SubApp<State>>>,
+    default_subapp: Arc<SubApp<State>>,
+    error_handler: ErrorHandler,
+    state: Arc<State>,
+    monitor: &MonitorConfig,
+    max_body_size: Option<usize>,
+    compression: Option<usize>,
+    mut draining: watch::Receiver<bool>,
+) where
+    State: Send + Sync + 'static,
+{
+    let peer_identity = stream.peer_identity();
+    let mut connection = Connection::new(addr, max_body_size);
+    let (response_tx, mut response_rx) = mpsc::unbounded_channel();
+    let (chunk_tx, mut chunk_rx) = mpsc::unbounded_channel();
+    let mut bodies: HashMap<u32, BodyReader> = HashMap::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut shutdown_sent = false;
+
+    loop {
+        // Ask for the next part of every streamed body which the client is ready for
+        bodies.retain(|stream_id, body| {
+            let pending = match connection.pending_data(*stream_id) {
+                Some(pending) => pending,
+                None => return false,
+            };
+
+            if !body.requested && pending < CHUNK_SIZE {
+                body.requested = body.next.send(()).is_ok();
+            }
+
+            true
+        });
+
+        let output = connection.take_output();
+
+        if !output.is_empty() && stream.write_all(&output).await.is_err() {
+            break;
+        }
+
+        if connection.is_finished() {
+            break;
+        }
+
+        tokio::select! {
+            read = stream.read(&mut buf) => {
+                let length = match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(length) => length,
+                };
+
+                // After an error, the connection is finished once the `GOAWAY` frame has been sent
+                if let Ok(requests) = connection.receive(&buf[..length]) {
+                    for (stream_id, request) in requests {
+                        let request = request.map(|mut request| {
+                            request.peer_identity = peer_identity.clone();
+                            request
+                        });
+
+                        let subapps = subapps.clone();
+                        let default_subapp = default_subapp.clone();
+                        let state = state.clone();
+                        let response_tx = response_tx.clone();
+
+                        tokio::spawn(async move {
+                            let response = AssertUnwindSafe(http2_response(
+                                request,
+                                &subapps,
+                                &default_subapp,
+                                error_handler,
+                                &state,
+                                compression,
+                            ))
+                            .catch_unwind()
+                            .await;
+
+                            // The stream is reset if the handler panics
+                            response_tx.send((stream_id, response.ok())).ok();
+                        });
+                    }
+                }
+            }
+            Some((stream_id, response)) = response_rx.recv() => {
+                let (response, uri) = match response {
+                    Some(response) => response,
+                    None => {
+                        connection.fail_response(stream_id);
+                        continue;
+                    }
+                };
+
+                monitor.send(served_event(addr, response.status_code, uri.as_deref()));
+
+                if let Some(body) = connection.send_response(stream_id, response) {
+                    let (next, next_rx) = mpsc::unbounded_channel();
+                    tokio::spawn(read_body(stream_id, body, next_rx, chunk_tx.clone()));
+
+                    bodies.insert(stream_id, BodyReader { next, requested: false });
+                }
+            }
+            Some((stream_id, chunk)) = chunk_rx.recv() => {
+                if let Some(body) = bodies.get_mut(&stream_id) {
+                    body.requested = false;
+                }
+
+                match chunk {
+                    BodyChunk::Data(data) => connection.send_data(stream_id, &data, false),
+                    BodyChunk::End => {
+                        bodies.remove(&stream_id);
+                        connection.send_data(stream_id, &[], true);
+                    }
+                    BodyChunk::Failed => {
+                        bodies.remove(&stream_id);
+                        connection.reset(stream_id, ErrorCode::InternalError);
+                    }
+                }
+            }
+            _ = draining.wait_for(|draining| *draining), if !shutdown_sent => {
+                // Current requests are still answered, but the client may not send any more
+                shutdown_sent = true;
+                connection.shutdown();
+            }
+        }
+    }
+}
+
+/// Generates the response to a request received over HTTP/2, returning it along with the URI of the request if
+///   it could be handled.
+///
+/// The body has already been received, so it is read from memory.
+#[cfg(feature = "tls")]
+async fn http2_response<State>(
+    request: Result<Request, RequestError>,
+    subapps: &[SubApp<State>],
+    default_subapp: &SubApp<State>,
+    error_handler: ErrorHandler,
+    state: &Arc<State>,
+    compression: Option<usize>,
+) -> (Response, Option<String>) {
+    let mut request = match request {
+        Ok(request) => request,
+        Err(e) => {
+            let status = match e {
+                RequestError::PayloadTooLarge => StatusCode::RequestEntityTooLarge,
+                _ => StatusCode::BadRequest,
+            };
+
+            let mut response = error_handler(status);
+            prepare_response(&mut response, false);
+
+            return (response, None);
+        }
+    };
+
+    let (subapp, handler, allowed_methods) = get_handler(&mut request, subapps, default_subapp);
+    let body = request.content.take().unwrap_or_default();
+
+    let (request, mut response) = match route_response(
+        request,
+        &mut body.as_slice(),
+        subapp,
+        handler,
+        &allowed_methods,
+        error_handler,
+        state,
+        None,
+        compression,
+    )
+    .await
+    {
+        Ok((request, response, _)) => (request, response),
+        Err(_) => {
+            let mut response = error_handler(StatusCode::BadRequest);
+            prepare_response(&mut response, false);
+
+            return (response, None);
+        }
+    };
+
+    prepare_response(&mut response, request.method == Method::Head);
+
+    (response, Some(request.uri))
+}
+
+/// The connection's handle on a task which reads a streamed response body.
+#[cfg(feature = "tls")]
+struct BodyReader {
+    next: mpsc::UnboundedSender<()>,
+    requested: bool,
+}
+
+/// Part of a streamed response body, read by a `read_body` task.
+#[cfg(feature = "tls")]
+enum BodyChunk {
+    Data(Vec<u8>),
+    End,
+    Failed,
+}
+
+/// Reads a streamed response body in chunks, reading each chunk when the connection asks for it so that no more of
+///   the body is held in memory than the client is ready for.
+#[cfg(feature = "tls")]
+async fn read_body(
+    stream_id: u32,
+    mut body: StreamedBody,
+    mut next: mpsc::UnboundedReceiver<()>,
+    chunks: mpsc::UnboundedSender<(u32, BodyChunk)>,
+) {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+
+    while next.recv().await.is_some() {
+        let to_read = match body.length {
+            Some(remaining) => remaining.min(CHUNK_SIZE as u64) as usize,
+            None => CHUNK_SIZE,
+        };
+
+        let chunk = match to_read {
+            0 => BodyChunk::End,
+            _ => match body.source.read(&mut buf[..to_read]).await {
+                // A body which ends before its length cannot be completed
+                Ok(0) if body.length.is_some() => BodyChunk::Failed,
+                Ok(0) => BodyChunk::End,
+                Ok(read) => {
+                    body.length = body.length.map(|remaining| remaining - read as u64);
+                    BodyChunk::Data(buf[..read].to_vec())
+                }
+                Err(_) => BodyChunk::Failed,
+            },
+        };
+
+        let last = !matches!(chunk, BodyChunk::Data(_));
+
+        if chunks.send((stream_id, chunk)).is_err() || last {
+            break;
+        }
+    }
+}
+
+/// Calls the most specific WebSocket handler for the given request.
 async fn call_websocket_handler<State>(
     request: &Request,
     subapps: &[SubApp<State>],
@@ -664,6 +1477,8 @@ async fn call_websocket_handler<State>(
     state: Arc<State>,
     stream: Stream,
 ) {
+    let mut request = request.clone();
+
     // Iterate over the sub-apps and find the one which matches the host
     if let Some(host) = request.headers.get(&HeaderType::Host) {
         if let Some(subapp) = subapps
@@ -671,55 +1486,67 @@ async fn call_websocket_handler<State>(
             .find(|subapp| wildcard_match(&subapp.host, host))
         {
             // If the sub-app has a handler for this route, call it
-            if let Some(handler) = subapp
-                .websocket_routes // Get the WebSocket routes of the sub-app
-                .iter() // Iterate over the routes
-                .find(|route| route.route.route_matches(&request.uri))
-            {
-                handler.handler.serve(request.clone(), stream, state).await;
+            if let Some((index, params)) = find_route(
+                subapp.websocket_routes.iter().map(|route| &route.route),
+                &request.uri,
+            ) {
+                request.params = params;
+                subapp.websocket_routes[index]
+                    .handler
+                    .serve(request, stream, state).await;
                 return;
             }
         }
     }
 
     // If no sub-app was found, try to use the handler on the default sub-app
-    if let Some(handler) = default_subapp
-        .websocket_routes
-        .iter()
-        .find(|route| route.route.route_matches(&request.uri))
-    {
-        handler.handler.serve(request.clone(), stream, state).await
+    if let Some((index, params)) = find_route(
+        default_subapp.websocket_routes.iter().map(|route| &route.route),
+        &request.uri,
+    ) {
+        request.params = params;
+        default_subapp.websocket_routes[index]
+            .handler
+            .serve(request, stream, state).await
     }
 }
 
-#[cfg(feature = "tls")]
-async fn force_https_thread(monitor: MonitorConfig) -> Result<(), Box<dyn std::error::Error>> {
-    let socket = TcpListener::bind("0.0.0.0:80").await?;
+/// Accepts connections on the listener, passing them to the app until it stops receiving them.
+async fn accept_connections(
+    socket: TcpListener,
+    mode: ConnectionMode,
+    tx: mpsc::UnboundedSender<(TcpStream, ConnectionMode)>,
+    monitor: MonitorConfig,
+) {
+    loop {
+        match socket.accept().await {
+            Ok((stream, _)) => {
+                if tx.send((stream, mode.clone())).is_err() {
+                    break;
+                }
+            }
+            Err(e) => monitor.send(Event::new(EventType::ConnectionError).with_info(e.to_string())),
+        }
+    }
+}
 
-    while let Ok((mut stream, addr)) = socket.accept().await {
-        let request = Request::from_stream(&mut stream, addr).await?;
+/// Responds to a request on an insecure listener with a redirect to the same URL over HTTPS.
+async fn redirect_to_https(mut stream: TcpStream, https_port: u16, monitor: MonitorConfig) {
+    let addr = if let Ok(addr) = stream.peer_addr() {
+        addr
+    } else {
+        monitor.send(EventType::StreamDisconnectedWhileWaiting);
 
-        let response = if let Some(host) = request.headers.get(&HeaderType::Host) {
-            Response::empty(StatusCode::MovedPermanently)
-                .with_header(
-                    HeaderType::Location,
-                    format!("https://{}{}", host, request.uri),
-                )
-                .with_header(HeaderType::Connection, "Close")
-        } else {
-            Response::empty(StatusCode::OK)
-                .with_bytes(b"<h1>Please access over HTTPS</h1>")
-                .with_header(HeaderType::ContentLength, "33")
-                .with_header(HeaderType::Connection, "Close")
-        };
+        return;
+    };
 
-        let response_bytes: Vec<u8> = response.into();
-        stream.write_all(&response_bytes).await?;
+    if let Ok(request) = Request::from_stream(&mut stream, addr).await {
+        let response = https_redirect(&request, https_port);
 
-        monitor.send(Event::new(EventType::HTTPSRedirect).with_peer(addr));
+        if response.write_to(&mut stream).await.is_ok() {
+            monitor.send(Event::new(EventType::HTTPSRedirect).with_peer(addr));
+        }
     }
-
-    Ok(())
 }
 
 /// The default error handler for every Humphrey app.
@@ -1,6 +1,9 @@
+use crate::http::request::RequestBody;
+use crate::http::response::IntoResponse;
 use crate::http::{Request, Response};
 use crate::stream::Stream;
 
+use futures::FutureExt;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -31,7 +34,7 @@ where
 }
 
 /// Represents a function able to handle a request.
-/// It is passed the request as well as the app's state, and must return a response.
+/// It is passed the request as well as the app's state, and must return a response or another type which implements `IntoResponse`.
 ///
 /// ## Example
 /// The most basic request handler would be as follows:
@@ -48,22 +51,60 @@ pub trait RequestHandler<State>: Send + Sync {
         state: Arc<State>,
     ) -> Pin<Box<dyn Future<Output = Response> + Send>>;
 }
-impl<F, Fut, State> RequestHandler<State> for F
+impl<F, Fut, R, State> RequestHandler<State> for F
 where
     F: Fn(Request, Arc<State>) -> Fut + Send + Sync,
-    Fut: Future<Output = Response> + Send + 'static,
+    Fut: Future<Output = R> + Send + 'static,
+    R: IntoResponse + 'static,
 {
     fn serve(
         &self,
         request: Request,
         state: Arc<State>,
     ) -> Pin<Box<dyn Future<Output = Response> + Send>> {
-        Box::pin(self(request, state))
+        Box::pin(self(request, state).map(IntoResponse::into_response))
+    }
+}
+
+/// Represents a function able to handle a request whose body is read from the connection as it arrives.
+/// It is passed the request, its body and the app's state, and must return a response or another type which implements `IntoResponse`.
+/// The `content` field of the request will always be `None`.
+///
+/// ## Example
+/// A streaming request handler which counts the bytes uploaded would be as follows:
+/// ```
+/// async fn handler(_: Request, mut body: RequestBody, _: Arc<()>) -> Response {
+///     let length = tokio::io::copy(&mut body, &mut tokio::io::sink()).await.unwrap();
+///     Response::new(StatusCode::OK, format!("Received {} bytes", length))
+/// }
+/// ```
+pub trait StreamingRequestHandler<State>: Send + Sync {
+    #[allow(missing_docs)]
+    fn serve(
+        &self,
+        request: Request,
+        body: RequestBody,
+        state: Arc<State>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send>>;
+}
+impl<F, Fut, R, State> StreamingRequestHandler<State> for F
+where
+    F: Fn(Request, RequestBody, Arc<State>) -> Fut + Send + Sync,
+    Fut: Future<Output = R> + Send + 'static,
+    R: IntoResponse + 'static,
+{
+    fn serve(
+        &self,
+        request: Request,
+        body: RequestBody,
+        state: Arc<State>,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+        Box::pin(self(request, body, state).map(IntoResponse::into_response))
     }
 }
 
 /// Represents a function able to handle a request.
-/// It is passed only the request, and must return a response.
+/// It is passed only the request, and must return a response or another type which implements `IntoResponse`.
 /// If you want access to the app's state, consider using the `RequestHandler` trait instead.
 ///
 /// ## Example
@@ -77,13 +118,14 @@ pub trait StatelessRequestHandler<State>: Send + Sync {
     #[allow(missing_docs)]
     fn serve(&self, request: Request) -> Pin<Box<dyn Future<Output = Response> + Send>>;
 }
-impl<F, Fut, State> StatelessRequestHandler<State> for F
+impl<F, Fut, R, State> StatelessRequestHandler<State> for F
 where
     F: Fn(Request) -> Fut + Send + Sync,
-    Fut: Future<Output = Response> + Send + 'static,
+    Fut: Future<Output = R> + Send + 'static,
+    R: IntoResponse + 'static,
 {
     fn serve(&self, request: Request) -> Pin<Box<dyn Future<Output = Response> + Send>> {
-        Box::pin(self(request))
+        Box::pin(self(request).map(IntoResponse::into_response))
     }
 }
 
@@ -120,3 +162,56 @@ where
         Box::pin(self(request, state, route))
     }
 }
+
+/// Represents middleware, which runs around the handlers of an app, sub-app or route.
+///
+/// `before` is called before the request is handled, and can modify the request or respond to it directly,
+///   in which case the handler and any inner middleware are skipped. It runs before the body of the request is
+///   read, so `content` is always `None`, and requests which are rejected never have their bodies read.
+///   `after` is called before the response is sent, and can modify it.
+///
+/// App middleware runs first, followed by sub-app middleware and then route middleware, with `after` being
+///   called in the reverse order. If middleware responds directly, `after` is still called on the middleware
+///   which ran before it.
+///
+/// ## Example
+/// Middleware which rejects requests without an `Authorization` header would be as follows:
+/// ```
+/// struct RequireAuthorization;
+///
+/// impl Middleware<()> for RequireAuthorization {
+///     fn before<'a>(
+///         &'a self,
+///         request: &'a mut Request,
+///         _: Arc<()>,
+///     ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+///         Box::pin(async move {
+///             match request.headers.get(HeaderType::Authorization) {
+///                 Some(_) => None,
+///                 None => Some(Response::empty(StatusCode::Unauthorized)),
+///             }
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware<State>: Send + Sync {
+    /// Called before the request is handled.
+    /// Returning a response skips the handler and sends the response instead.
+    fn before<'a>(
+        &'a self,
+        _request: &'a mut Request,
+        _state: Arc<State>,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        Box::pin(async { None })
+    }
+
+    /// Called after the request has been handled, before the response is sent.
+    fn after<'a>(
+        &'a self,
+        _request: &'a Request,
+        _response: &'a mut Response,
+        _state: Arc<State>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(async {})
+    }
+}
@@ -4,8 +4,10 @@
 // When they are stabilised, this code will look a lot nicer.
 
 use crate::app::{error_handler, PathAwareRequestHandler, RequestHandler};
+use crate::http::conditional::{apply_conditional, Validators};
 use crate::http::headers::HeaderType;
 use crate::http::mime::MimeType;
+use crate::http::range::{apply_file_range, apply_range};
 use crate::http::{Request, Response, StatusCode};
 use crate::route::{try_find_path, LocatedPath};
 
@@ -14,7 +16,7 @@ use tokio::io::AsyncReadExt;
 
 use futures::Future;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -32,25 +34,13 @@ struct FileServer {
 }
 
 impl<S> RequestHandler<S> for FileServer {
-    fn serve(&self, _: Request, _: Arc<S>) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+    fn serve(&self, request: Request, _: Arc<S>) -> Pin<Box<dyn Future<Output = Response> + Send>> {
         let path_buf = self.path_buf.clone();
 
         Box::pin(async move {
-            if let Ok(mut file) = File::open(&path_buf).await {
-                let mut buf = Vec::new();
-                if file.read_to_end(&mut buf).await.is_ok() {
-                    return if let Some(extension) = &path_buf.extension() {
-                        Response::new(StatusCode::OK, buf).with_header(
-                            HeaderType::ContentType,
-                            MimeType::from_extension(extension.to_str().unwrap()).to_string(),
-                        )
-                    } else {
-                        Response::new(StatusCode::OK, buf)
-                    };
-                }
-            }
-
-            error_handler(StatusCode::NotFound)
+            file_response(&request, &path_buf)
+                .await
+                .unwrap_or_else(|| error_handler(StatusCode::NotFound))
         })
     }
 }
@@ -83,21 +73,9 @@ impl<S> RequestHandler<S> for FilePathServer {
 
             let path_buf = PathBuf::from(path);
 
-            if let Ok(mut file) = File::open(&path_buf).await {
-                let mut buf = Vec::new();
-                if file.read_to_end(&mut buf).await.is_ok() {
-                    return if let Some(extension) = path_buf.extension() {
-                        Response::new(StatusCode::OK, buf).with_header(
-                            HeaderType::ContentType,
-                            MimeType::from_extension(extension.to_str().unwrap()).to_string(),
-                        )
-                    } else {
-                        Response::new(StatusCode::OK, buf)
-                    };
-                }
-            }
-
-            error_handler(StatusCode::NotFound)
+            file_response(&request, &path_buf)
+                .await
+                .unwrap_or_else(|| error_handler(StatusCode::NotFound))
         })
     }
 }
@@ -137,24 +115,9 @@ impl<S> PathAwareRequestHandler<S> for DirServer {
                 match located {
                     LocatedPath::Directory => Response::empty(StatusCode::MovedPermanently)
                         .with_header(HeaderType::Location, format!("{}/", &request.uri)),
-                    LocatedPath::File(path) => {
-                        if let Ok(mut file) = File::open(&path).await {
-                            let mut buf = Vec::new();
-                            if file.read_to_end(&mut buf).await.is_ok() {
-                                return if let Some(extension) = path.extension() {
-                                    Response::new(StatusCode::OK, buf).with_header(
-                                        HeaderType::ContentType,
-                                        MimeType::from_extension(extension.to_str().unwrap())
-                                            .to_string(),
-                                    )
-                                } else {
-                                    Response::new(StatusCode::OK, buf)
-                                };
-                            }
-                        }
-
-                        error_handler(StatusCode::InternalError)
-                    }
+                    LocatedPath::File(path) => file_response(&request, &path)
+                        .await
+                        .unwrap_or_else(|| error_handler(StatusCode::InternalError)),
                 }
             } else {
                 error_handler(StatusCode::NotFound)
@@ -179,3 +142,42 @@ impl<S> RequestHandler<S> for RedirectServer {
         Box::pin(async move { Response::redirect(location) })
     }
 }
+
+/// Reads the file at the given path into a response, respecting any conditional headers and range requested.
+/// Returns `None` if the file could not be read.
+///
+/// When a range is requested, only that range is streamed from the file rather than reading all of it.
+async fn file_response(request: &Request, path: &Path) -> Option<Response> {
+    let mut file = File::open(path).await.ok()?;
+    let validators = Validators::from_metadata(&file.metadata().await.ok()?);
+
+    let response = match path.extension() {
+        Some(extension) => Response::empty(StatusCode::OK).with_header(
+            HeaderType::ContentType,
+            MimeType::from_extension(extension.to_str().unwrap()).to_string(),
+        ),
+        None => Response::empty(StatusCode::OK),
+    };
+
+    let response = apply_conditional(request, response, &validators);
+
+    if response.status_code != StatusCode::OK {
+        return Some(response);
+    }
+
+    if request.headers.get(&HeaderType::Range).is_some() {
+        // Opening and seeking the file blocks, so it is done away from the runtime's threads
+        let request = request.clone();
+        let path = path.to_path_buf();
+
+        return tokio::task::spawn_blocking(move || apply_file_range(&request, response, &path))
+            .await
+            .ok()?
+            .ok();
+    }
+
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).await.ok()?;
+
+    Some(apply_range(request, response.with_bytes(buf)))
+}
@@ -23,7 +23,7 @@ pub fn init(
     let mut watcher = raw_watcher(tx)?;
     let mut watched_routes = Vec::new();
 
-    for route in &state.config.default_host.routes {
+    for route in &state.config().default_host.routes {
         match route.route_type {
             RouteType::File | RouteType::Directory => {
                 let path = PathBuf::from(route.path.as_ref().unwrap()).canonicalize()?;